@@ -0,0 +1,8 @@
+//! Embeds the Windows application manifest, which marks ico2img as DPI-aware, in the binary.
+
+fn main() {
+    // the target, not the host running the build script, decides whether to embed it
+    if std::env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("windows") {
+        embed_resource::compile("windows/ico2img.rc", embed_resource::NONE);
+    }
+}
@@ -0,0 +1,114 @@
+//! Color adjustments, for theming icons.
+
+use crate::{error::Result, Color, Ico2imgError};
+use image::RgbaImage;
+use std::str::FromStr;
+
+/// A color laid over the luminance of an image, parsed from `RRGGBB[:STRENGTH]`, with an
+/// optional leading `#`. The strength goes from 0 (no tint) to 1 (the default, full tint).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tint {
+    pub color: Color,
+    pub strength: f32,
+}
+
+impl FromStr for Tint {
+    type Err = Ico2imgError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (color, strength) = match s.split_once(':') {
+            Some((color, strength)) => {
+                let strength: f32 = strength.parse().map_err(|_| {
+                    Ico2imgError::InvalidArgument(format!("Invalid tint strength {}.", strength))
+                })?;
+                (color, strength)
+            }
+            None => (s, 1.0),
+        };
+
+        if !(0.0..=1.0).contains(&strength) {
+            return Err(Ico2imgError::InvalidArgument(format!(
+                "Invalid tint strength {}, expected a value from 0 to 1.",
+                strength
+            )));
+        }
+
+        Ok(Tint {
+            color: color.parse()?,
+            strength,
+        })
+    }
+}
+
+/// Multiplies the luminance of every pixel of `image` by the tint color, blended with the
+/// original pixel according to the strength of the tint. Alpha is kept as it is.
+pub(crate) fn tint(image: &mut RgbaImage, tint: Tint) {
+    let color = [tint.color.r, tint.color.g, tint.color.b];
+    for pixel in image.pixels_mut() {
+        let [r, g, b, _] = pixel.0;
+        let luminance = (0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32) / 255.0;
+        for (channel, &tint_channel) in pixel.0[..3].iter_mut().zip(&color) {
+            let tinted = luminance * tint_channel as f32;
+            let blended = *channel as f32 + (tinted - *channel as f32) * tint.strength;
+            *channel = blended.round().clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+/// Rotates the hue of every pixel of `image` by `degrees`. Gray pixels, which have no hue, are
+/// left unchanged, and so is alpha.
+pub(crate) fn hue_shift(image: &mut RgbaImage, degrees: f32) {
+    for pixel in image.pixels_mut() {
+        let [r, g, b, _] = pixel.0;
+        if r == g && g == b {
+            continue;
+        }
+
+        let (h, s, l) = rgb_to_hsl(r, g, b);
+        let (r, g, b) = hsl_to_rgb((h + degrees).rem_euclid(360.0), s, l);
+        pixel.0[..3].copy_from_slice(&[r, g, b]);
+    }
+}
+
+/// Converts a color to hue (in degrees), saturation and lightness (from 0 to 1).
+fn rgb_to_hsl(r: u8, g: u8, b: u8) -> (f32, f32, f32) {
+    let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let l = (max + min) / 2.0;
+
+    let delta = max - min;
+    if delta == 0.0 {
+        return (0.0, 0.0, l);
+    }
+
+    let s = delta / (1.0 - (2.0 * l - 1.0).abs());
+    let h = if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+
+    (h, s, l)
+}
+
+/// Converts hue (in degrees), saturation and lightness (from 0 to 1) to a color.
+fn hsl_to_rgb(h: f32, s: f32, l: f32) -> (u8, u8, u8) {
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let x = c * (1.0 - ((h / 60.0).rem_euclid(2.0) - 1.0).abs());
+    let m = l - c / 2.0;
+
+    let (r, g, b) = match h as u32 {
+        0..=59 => (c, x, 0.0),
+        60..=119 => (x, c, 0.0),
+        120..=179 => (0.0, c, x),
+        180..=239 => (0.0, x, c),
+        240..=299 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+
+    let channel = |v: f32| ((v + m) * 255.0).round().clamp(0.0, 255.0) as u8;
+    (channel(r), channel(g), channel(b))
+}
@@ -0,0 +1,43 @@
+//! Alignment of the content of images, for entries whose icon isn't centered the same way.
+
+use crate::{Gravity, Rect};
+use image::{imageops, DynamicImage, RgbaImage};
+
+/// Returns the smallest rectangle holding every pixel of `image` that isn't fully transparent,
+/// or `None` if the whole image is transparent.
+pub fn find_content_bbox(image: &RgbaImage) -> Option<Rect> {
+    let (mut left, mut top) = (u32::MAX, u32::MAX);
+    let (mut right, mut bottom) = (0, 0);
+    for (x, y, pixel) in image.enumerate_pixels() {
+        if pixel[3] != 0 {
+            left = left.min(x);
+            top = top.min(y);
+            right = right.max(x);
+            bottom = bottom.max(y);
+        }
+    }
+
+    (left != u32::MAX).then(|| Rect {
+        x: left,
+        y: top,
+        width: right - left + 1,
+        height: bottom - top + 1,
+    })
+}
+
+/// Arguments:
+///   - image: The image to align.
+///   - bbox: The content of the image, as returned by [`find_content_bbox`].
+///   - anchor: Where to move the content to.
+///
+/// Returns an image of the same size, with the content of `bbox` moved to `anchor`, such as
+/// centered with [`Gravity::Center`], and the rest transparent.
+pub fn align_to_anchor(image: DynamicImage, bbox: Rect, anchor: Gravity) -> DynamicImage {
+    let (width, height) = (image.width(), image.height());
+    let content = image.crop_imm(bbox.x, bbox.y, bbox.width, bbox.height);
+    let (x, y) = anchor.position((bbox.width, bbox.height), (width, height));
+
+    let mut aligned = RgbaImage::new(width, height);
+    imageops::replace(&mut aligned, &content.to_rgba8(), x, y);
+    DynamicImage::ImageRgba8(aligned)
+}
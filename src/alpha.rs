@@ -0,0 +1,49 @@
+//! Statistics of the alpha channel of decoded images, to tell icons that use transparency from
+//! fully opaque ones.
+
+use image::RgbaImage;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Statistics of the alpha channel of an image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub struct AlphaStats {
+    /// Whether any pixel has an alpha lower than 255.
+    pub has_transparency: bool,
+    /// The percentage of fully transparent pixels, from 0 to 100.
+    pub transparent_percent: f64,
+    pub min_alpha: u8,
+    pub mean_alpha: f64,
+}
+
+impl AlphaStats {
+    /// Returns the statistics of the alpha channel of `image`. An empty image counts as opaque.
+    pub fn from_image(image: &RgbaImage) -> AlphaStats {
+        let (mut transparent, mut min_alpha, mut total) = (0u64, u8::MAX, 0u64);
+        for pixel in image.pixels() {
+            let alpha = pixel[3];
+            transparent += (alpha == 0) as u64;
+            min_alpha = min_alpha.min(alpha);
+            total += alpha as u64;
+        }
+
+        let count = image.width() as u64 * image.height() as u64;
+        if count == 0 {
+            return AlphaStats {
+                has_transparency: false,
+                transparent_percent: 0.0,
+                min_alpha: u8::MAX,
+                mean_alpha: u8::MAX as f64,
+            };
+        }
+        AlphaStats {
+            has_transparency: min_alpha < u8::MAX,
+            transparent_percent: transparent as f64 * 100.0 / count as f64,
+            min_alpha,
+            mean_alpha: total as f64 / count as f64,
+        }
+    }
+}
@@ -0,0 +1,136 @@
+//! Support for ANI animated cursors.
+//!
+//! ANI files are RIFF containers of type `ACON`. The `anih` chunk holds the animation header, the
+//! optional `rate` and `seq ` chunks the display rate and frame of each step, and the `fram` list
+//! one `icon` chunk per frame, each a complete ICO or CUR file. Frames are read as-is, so that
+//! they can go through the same pipeline as regular ICO files.
+
+use anyhow::{anyhow, Result};
+use std::{fs, path::Path};
+
+/// Set in the flags of the `anih` chunk when the frames are ICO or CUR files, rather than raw
+/// bitmaps.
+const AF_ICON: u32 = 0x1;
+
+/// A frame of an ANI file.
+pub struct Frame {
+    /// The number of entries in the frame.
+    pub len: usize,
+    /// How long the frame is shown, in jiffies (1/60 of a second). When the frame is shown by
+    /// several steps of the animation, this is the rate of the first of them.
+    pub rate: u32,
+    /// The frame, as an ICO or CUR file.
+    ico: Vec<u8>,
+}
+
+impl Frame {
+    /// Returns the frame as the bytes of an ICO or CUR file.
+    pub fn into_ico(self) -> Vec<u8> {
+        self.ico
+    }
+}
+
+/// Returns whether the file at `path` is an ANI file, judging from its extension.
+pub fn is_ani(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("ani"))
+}
+
+/// Arguments:
+///   - path: The path to the ANI file.
+///
+/// Returns the frames of the ANI file, in the order they're stored in.
+pub fn read_frames(path: &Path) -> Result<Vec<Frame>> {
+    let data = fs::read(path)?;
+    if data.len() < 12 || !data.starts_with(b"RIFF") || data[8..12] != *b"ACON" {
+        return Err(anyhow!("Invalid ANI file: missing RIFF ACON header."));
+    }
+    let end = (8 + read_u32(&data, 4)? as usize).min(data.len());
+
+    let mut header = None;
+    let mut rates = None;
+    let mut sequence = None;
+    let mut icons = Vec::new();
+    for (id, chunk) in chunks(&data[12..end])? {
+        match &id {
+            b"anih" => header = Some(chunk),
+            b"rate" => rates = Some(read_u32s(chunk)?),
+            b"seq " => sequence = Some(read_u32s(chunk)?),
+            b"LIST" if chunk.starts_with(b"fram") => {
+                for (id, icon) in chunks(&chunk[4..])? {
+                    if id == *b"icon" {
+                        icons.push(icon);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let header = header.ok_or_else(|| anyhow!("Invalid ANI file: missing anih chunk."))?;
+    let steps = read_u32(header, 8)? as usize;
+    let default_rate = read_u32(header, 28)?;
+    if read_u32(header, 32)? & AF_ICON == 0 {
+        return Err(anyhow!(
+            "Unsupported ANI file: the frames are raw bitmaps rather than icons."
+        ));
+    }
+
+    icons
+        .into_iter()
+        .enumerate()
+        .map(|(index, icon)| {
+            // the first step showing the frame, which is the frame itself without a sequence
+            let step = match sequence {
+                Some(ref sequence) => sequence.iter().position(|&frame| frame as usize == index),
+                None => Some(index),
+            };
+            let rate = step
+                .filter(|&step| step < steps)
+                .and_then(|step| rates.as_ref()?.get(step).copied())
+                .unwrap_or(default_rate);
+            Ok(Frame {
+                len: read_u16(icon, 4)? as usize,
+                rate,
+                ico: icon.to_vec(),
+            })
+        })
+        .collect()
+}
+
+/// Splits `data` into RIFF chunks, returning the ID and data of each one.
+fn chunks(mut data: &[u8]) -> Result<Vec<([u8; 4], &[u8])>> {
+    let mut chunks = Vec::new();
+    while data.len() >= 8 {
+        let id = [data[0], data[1], data[2], data[3]];
+        let len = read_u32(data, 4)? as usize;
+        let chunk = data.get(8..8 + len).ok_or_else(|| {
+            anyhow!(
+                "Invalid ANI file: chunk {} is out of bounds.",
+                String::from_utf8_lossy(&id)
+            )
+        })?;
+        chunks.push((id, chunk));
+
+        // chunks are padded to an even length
+        data = data.get(8 + len + len % 2..).unwrap_or_default();
+    }
+    Ok(chunks)
+}
+
+fn read_u32s(data: &[u8]) -> Result<Vec<u32>> {
+    (0..data.len() / 4)
+        .map(|index| read_u32(data, 4 * index))
+        .collect()
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        .ok_or_else(|| anyhow!("Invalid ANI file: unexpected end of data."))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .ok_or_else(|| anyhow!("Invalid ANI file: unexpected end of data."))
+}
@@ -0,0 +1,244 @@
+//! Discovery of ICO files when the input is a directory.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+use walkdir::WalkDir;
+
+/// Arguments:
+///   - dir: The directory to search.
+///   - recursive: Whether to search subdirectories.
+///   - max_depth: How many levels of subdirectories to search at most, if limited.
+///   - follow_symlinks: Whether to follow symbolic links.
+///
+/// Returns the paths to the ICO and CUR files in `dir`, sorted by file name within each
+/// directory. Every file is returned once, even if several symbolic links lead to it.
+pub fn find_icon_files(
+    dir: &Path,
+    recursive: bool,
+    max_depth: Option<usize>,
+    follow_symlinks: bool,
+) -> Vec<PathBuf> {
+    let max_depth = if recursive {
+        max_depth.map_or(usize::MAX, |depth| depth.saturating_add(1))
+    } else {
+        1
+    };
+
+    let mut visited = HashSet::new();
+    let mut files = Vec::new();
+    for entry in WalkDir::new(dir)
+        .follow_links(follow_symlinks)
+        .max_depth(max_depth)
+        .sort_by_file_name()
+    {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                crate::warning::warn(format_args!("skipping {}", e));
+                continue;
+            }
+        };
+
+        if !entry.file_type().is_file() || !is_icon_file(entry.path()) {
+            continue;
+        }
+
+        // canonical paths identify files reached through different symbolic links
+        let canonical = fs::canonicalize(entry.path()).unwrap_or_else(|_| entry.path().into());
+        if visited.insert(canonical) {
+            files.push(entry.into_path());
+        }
+    }
+
+    files
+}
+
+/// Returns whether the file at `path` is an ICO or CUR file, judging from its extension.
+fn is_icon_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| {
+        ext.eq_ignore_ascii_case("ico") || ext.eq_ignore_ascii_case("cur")
+    })
+}
+
+/// Arguments:
+///   - files: The ICO files found in `input_dir`, as returned by [`find_icon_files`].
+///   - input_dir: The directory searched.
+///   - mirror_structure: Whether the outputs are written to the same subdirectories as their
+///     files, so that only files in the same directory can collide.
+///
+/// Returns the groups of files whose outputs would have the same name, as indices in `files`.
+/// Names are compared ignoring case, as some file systems do.
+pub fn colliding_names(
+    files: &[PathBuf],
+    input_dir: &Path,
+    mirror_structure: bool,
+) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut group_of: HashMap<(String, String), usize> = HashMap::new();
+    for (index, file) in files.iter().enumerate() {
+        let key = output_key(file, input_dir, mirror_structure, &stem(file));
+        match group_of.get(&key) {
+            Some(&group) => groups[group].push(index),
+            None => {
+                group_of.insert(key, groups.len());
+                groups.push(vec![index]);
+            }
+        }
+    }
+
+    groups.retain(|group| group.len() > 1);
+    groups
+}
+
+/// Returns the stem of the output of each file, which is the stem of the file unless it
+/// collides with others, as found by [`colliding_names`]. Colliding stems are prefixed with as
+/// many of the parent directories of their files, relative to `input_dir`, as needed to tell
+/// them apart, such as `a_app` and `b_app` for `a/app.ico` and `b/app.ico`. A number is added
+/// to the stems that are still the same, such as those of `app.ico` and `app.cur`. The same
+/// files are always given the same stems.
+pub fn output_stems(files: &[PathBuf], input_dir: &Path, mirror_structure: bool) -> Vec<String> {
+    let mut stems: Vec<String> = files.iter().map(|file| stem(file)).collect();
+
+    if !mirror_structure {
+        for group in colliding_names(files, input_dir, false) {
+            let parents: Vec<Vec<String>> = group
+                .iter()
+                .map(|&index| relative_parents(&files[index], input_dir))
+                .collect();
+            let max_depth = parents.iter().map(Vec::len).max().unwrap_or(0);
+
+            for depth in 1..=max_depth {
+                let prefixed: Vec<String> = group
+                    .iter()
+                    .zip(&parents)
+                    .map(|(&index, parents)| {
+                        let start = parents.len().saturating_sub(depth);
+                        let mut parts = parents[start..].to_vec();
+                        parts.push(stem(&files[index]));
+                        parts.join("_")
+                    })
+                    .collect();
+                let distinct: HashSet<String> =
+                    prefixed.iter().map(|stem| stem.to_lowercase()).collect();
+                let done = distinct.len() == group.len() || depth == max_depth;
+                for (&index, stem) in group.iter().zip(prefixed) {
+                    stems[index] = stem;
+                }
+                if done {
+                    break;
+                }
+            }
+        }
+    }
+
+    // stems that are still the same, or that now collide with the stem of another file, are
+    // numbered in the order of the files
+    let mut taken = HashSet::new();
+    for (index, file) in files.iter().enumerate() {
+        let mut stem = stems[index].clone();
+        let mut number = 2;
+        while !taken.insert(output_key(file, input_dir, mirror_structure, &stem)) {
+            stem = format!("{}_{}", stems[index], number);
+            number += 1;
+        }
+        stems[index] = stem;
+    }
+
+    stems
+}
+
+/// Returns the stem of `file`.
+fn stem(file: &Path) -> String {
+    file.file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Returns the names of the parent directories of `file`, relative to `input_dir`.
+fn relative_parents(file: &Path, input_dir: &Path) -> Vec<String> {
+    file.strip_prefix(input_dir)
+        .ok()
+        .and_then(Path::parent)
+        .map(|dir| {
+            dir.iter()
+                .map(|name| name.to_string_lossy().to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Returns what identifies the output of `file` named `stem`: the directory it's written to,
+/// relative to the output directory, and its lowercase stem.
+fn output_key(
+    file: &Path,
+    input_dir: &Path,
+    mirror_structure: bool,
+    stem: &str,
+) -> (String, String) {
+    let dir = if mirror_structure {
+        relative_parents(file, input_dir).join("/").to_lowercase()
+    } else {
+        String::new()
+    };
+    (dir, stem.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(names: &[&str]) -> Vec<PathBuf> {
+        names
+            .iter()
+            .map(|name| Path::new("in").join(name))
+            .collect()
+    }
+
+    #[test]
+    fn three_way_collisions_are_grouped() {
+        let files = paths(&["a/app.ico", "b/app.ico", "c/APP.ico", "c/other.ico"]);
+        assert_eq!(
+            colliding_names(&files, Path::new("in"), false),
+            [vec![0, 1, 2]]
+        );
+        // mirrored outputs only collide within a directory
+        assert!(colliding_names(&files, Path::new("in"), true).is_empty());
+    }
+
+    #[test]
+    fn three_way_collisions_are_prefixed_with_parents() {
+        let files = paths(&["a/app.ico", "b/app.ico", "c/app.ico"]);
+        assert_eq!(
+            output_stems(&files, Path::new("in"), false),
+            ["a_app", "b_app", "c_app"]
+        );
+
+        // only as many parents as needed are added
+        let files = paths(&["x/a/app.ico", "y/a/app.ico", "z/b/app.ico"]);
+        assert_eq!(
+            output_stems(&files, Path::new("in"), false),
+            ["x_a_app", "y_a_app", "z_b_app"]
+        );
+    }
+
+    #[test]
+    fn three_way_collisions_in_one_directory_are_numbered() {
+        let files = paths(&["app.cur", "app.ico", "APP.ico"]);
+        let stems = output_stems(&files, Path::new("in"), false);
+        assert_eq!(stems, ["app", "app_2", "APP_3"]);
+        assert_eq!(output_stems(&files, Path::new("in"), true), stems);
+    }
+
+    #[test]
+    fn prefixed_stems_colliding_with_other_files_are_numbered() {
+        let files = paths(&["a/app.ico", "b/app.ico", "c/app.ico", "a_app.ico"]);
+        assert_eq!(
+            output_stems(&files, Path::new("in"), false),
+            ["a_app", "b_app", "c_app", "a_app_2"]
+        );
+    }
+}
@@ -0,0 +1,116 @@
+//! Reading of the images added to ICO files, with the alpha channel of 32-bit BMP images, which
+//! the `image` crate drops from BMP files with a plain `BITMAPINFOHEADER`.
+
+use anyhow::{anyhow, Result};
+use image::{DynamicImage, ImageFormat, RgbaImage};
+use std::{fs, path::Path, str::FromStr};
+
+/// Size of the file header of BMP files, before the DIB header.
+const FILE_HEADER_LEN: usize = 14;
+/// `BI_RGB`, uncompressed pixels.
+const BI_RGB: u32 = 0;
+/// `BI_BITFIELDS`, uncompressed pixels laid out by color masks.
+const BI_BITFIELDS: u32 = 3;
+
+/// How the fourth byte of the pixels of 32-bit BMP images is read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BmpAlpha {
+    /// As alpha, even if every pixel ends up fully transparent.
+    Force,
+    /// As alpha if the decoded image is opaque but some of these bytes aren't zero, which
+    /// happens when the decoder ignored them.
+    Auto,
+    /// Not at all, so the image is opaque.
+    Ignore,
+}
+
+impl FromStr for BmpAlpha {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "force" => Ok(BmpAlpha::Force),
+            "auto" => Ok(BmpAlpha::Auto),
+            "ignore" => Ok(BmpAlpha::Ignore),
+            _ => Err(anyhow!(
+                "Invalid BMP alpha mode {}, expected force, auto or ignore.",
+                s
+            )),
+        }
+    }
+}
+
+/// Returns the image at `path`, reading the alpha channel of 32-bit BMP images according to
+/// `alpha`. Other images are read as [`image::open`] does.
+pub fn open_image(path: &Path, alpha: BmpAlpha) -> Result<DynamicImage> {
+    let data = fs::read(path)?;
+    if !data.starts_with(b"BM") {
+        return Ok(image::open(path)?);
+    }
+
+    let image = image::load_from_memory_with_format(&data, ImageFormat::Bmp)?;
+    let image = match alpha {
+        BmpAlpha::Ignore => DynamicImage::ImageRgb8(image.to_rgb8()),
+        BmpAlpha::Force => read_bgra(&data).map_or(image, DynamicImage::ImageRgba8),
+        BmpAlpha::Auto if is_opaque(&image) => match read_bgra(&data) {
+            // a fourth byte of zero everywhere is padding rather than alpha
+            Some(rgba) if rgba.pixels().any(|pixel| pixel[3] != 0) => {
+                DynamicImage::ImageRgba8(rgba)
+            }
+            _ => image,
+        },
+        BmpAlpha::Auto => image,
+    };
+    Ok(image)
+}
+
+/// Returns whether every pixel of `image` is fully opaque.
+fn is_opaque(image: &DynamicImage) -> bool {
+    !image.color().has_alpha() || image.to_rgba8().pixels().all(|pixel| pixel[3] == u8::MAX)
+}
+
+/// Returns the pixels of a 32-bit uncompressed BMP file, with the fourth byte of each pixel as
+/// alpha. Returns `None` for other BMP files, and for those whose color masks aren't the usual
+/// BGRA layout.
+fn read_bgra(data: &[u8]) -> Option<RgbaImage> {
+    let u16_at = |pos: usize| Some(u16::from_le_bytes(data.get(pos..pos + 2)?.try_into().ok()?));
+    let u32_at = |pos: usize| Some(u32::from_le_bytes(data.get(pos..pos + 4)?.try_into().ok()?));
+
+    let pixels_offset = u32_at(10)? as usize;
+    let dib = FILE_HEADER_LEN;
+    let width = u32_at(dib + 4)? as i32;
+    let height = u32_at(dib + 8)? as i32;
+    let bits_per_pixel = u16_at(dib + 14)?;
+    let compression = u32_at(dib + 16)?;
+    if bits_per_pixel != 32 || width <= 0 || height == 0 {
+        return None;
+    }
+    match compression {
+        BI_RGB => {}
+        // the masks follow the 40-byte BITMAPINFOHEADER, inside larger headers or after it
+        BI_BITFIELDS => {
+            let masks = (u32_at(dib + 40)?, u32_at(dib + 44)?, u32_at(dib + 48)?);
+            if masks != (0x00FF_0000, 0x0000_FF00, 0x0000_00FF) {
+                return None;
+            }
+        }
+        _ => return None,
+    }
+
+    let (width, rows) = (width as u32, height.unsigned_abs());
+    let row_len = width as usize * 4;
+    let pixels = data.get(pixels_offset..pixels_offset + row_len * rows as usize)?;
+    let mut image = RgbaImage::new(width, rows);
+    for (i, row) in pixels.chunks_exact(row_len).enumerate() {
+        // rows are stored bottom-up unless the height is negative
+        let y = if height > 0 {
+            rows - 1 - i as u32
+        } else {
+            i as u32
+        };
+        for (x, bgra) in row.chunks_exact(4).enumerate() {
+            image.put_pixel(x as u32, y, [bgra[2], bgra[1], bgra[0], bgra[3]].into());
+        }
+    }
+    Some(image)
+}
@@ -0,0 +1,113 @@
+//! The pixel layout of true-color BMP images. The `image` crate writes 24-bit RGB and 32-bit RGBA
+//! BMP images, but not 16-bit RGB565 ones, which embedded displays often load as they are, so
+//! these are written here.
+
+use crate::{error::Result, Ico2imgError};
+use image::RgbaImage;
+use std::str::FromStr;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Alpha below which a pixel is written as black.
+const ALPHA_THRESHOLD: u8 = 128;
+/// Size of the file header of BMP images.
+const FILE_HEADER_LEN: u32 = 14;
+/// Size of the `BITMAPINFOHEADER` of BMP images.
+const INFO_HEADER_LEN: u32 = 40;
+/// `BI_BITFIELDS`, uncompressed pixels laid out by the color masks following the header.
+const BI_BITFIELDS: u32 = 3;
+/// The red, green and blue masks of RGB565 pixels.
+const RGB565_MASKS: [u32; 3] = [0xF800, 0x07E0, 0x001F];
+
+/// The pixel layout of BMP images, set with [`ExtractOptions::bmp_format`].
+///
+/// [`ExtractOptions::bmp_format`]: crate::ExtractOptions::bmp_format
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[non_exhaustive]
+pub enum BmpFormat {
+    /// 24 bits per pixel, 8 for each of red, green and blue.
+    Rgb888,
+    /// 16 bits per pixel, 5 for red, 6 for green and 5 for blue.
+    Rgb565,
+    /// 32 bits per pixel, 8 for each of red, green, blue and alpha.
+    Rgba8888,
+}
+
+impl FromStr for BmpFormat {
+    type Err = Ico2imgError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "rgb888" => Ok(BmpFormat::Rgb888),
+            "rgb565" => Ok(BmpFormat::Rgb565),
+            "rgba8888" => Ok(BmpFormat::Rgba8888),
+            _ => Err(Ico2imgError::InvalidArgument(format!(
+                "Invalid BMP format {}, expected rgb888, rgb565 or rgba8888.",
+                s
+            ))),
+        }
+    }
+}
+
+/// Arguments:
+///   - image: The image to encode.
+///
+/// Returns the image encoded as a 16-bit RGB565 BMP image, with `BI_BITFIELDS` masks. RGB565
+/// has no transparency, so pixels whose alpha is below 128 are written as black and the others
+/// are made opaque.
+pub(crate) fn encode_rgb565_bmp(image: &RgbaImage) -> Vec<u8> {
+    let (width, height) = image.dimensions();
+    // rows are padded to a multiple of 4 bytes
+    let row_len = (width * 2).div_ceil(4) * 4;
+    let pixels_offset = FILE_HEADER_LEN + INFO_HEADER_LEN + 4 * RGB565_MASKS.len() as u32;
+    let file_len = pixels_offset + row_len * height;
+
+    let mut bmp = Vec::with_capacity(file_len as usize);
+    bmp.extend_from_slice(b"BM");
+    bmp.extend_from_slice(&file_len.to_le_bytes());
+    bmp.extend_from_slice(&[0; 4]);
+    bmp.extend_from_slice(&pixels_offset.to_le_bytes());
+
+    bmp.extend_from_slice(&INFO_HEADER_LEN.to_le_bytes());
+    bmp.extend_from_slice(&(width as i32).to_le_bytes());
+    bmp.extend_from_slice(&(height as i32).to_le_bytes());
+    bmp.extend_from_slice(&1u16.to_le_bytes());
+    bmp.extend_from_slice(&16u16.to_le_bytes());
+    // BI_BITFIELDS, the image size, the resolution (72 DPI) and the number of colors used and
+    // important (0, as there's no palette)
+    bmp.extend_from_slice(&BI_BITFIELDS.to_le_bytes());
+    bmp.extend_from_slice(&(row_len * height).to_le_bytes());
+    bmp.extend_from_slice(&2835u32.to_le_bytes());
+    bmp.extend_from_slice(&2835u32.to_le_bytes());
+    bmp.extend_from_slice(&0u32.to_le_bytes());
+    bmp.extend_from_slice(&0u32.to_le_bytes());
+
+    for mask in RGB565_MASKS {
+        bmp.extend_from_slice(&mask.to_le_bytes());
+    }
+
+    // rows are stored bottom-up
+    for y in (0..height).rev() {
+        let start = bmp.len();
+        for x in 0..width {
+            let [r, g, b, a] = image.get_pixel(x, y).0;
+            let pixel = if a < ALPHA_THRESHOLD {
+                0
+            } else {
+                (scale(r, 31) << 11) | (scale(g, 63) << 5) | scale(b, 31)
+            };
+            bmp.extend_from_slice(&pixel.to_le_bytes());
+        }
+        bmp.resize(start + row_len as usize, 0);
+    }
+
+    bmp
+}
+
+/// Returns `channel`, from 0 to 255, scaled to 0 to `max` and rounded to the nearest value.
+fn scale(channel: u8, max: u16) -> u16 {
+    (channel as u16 * max + 127) / 255
+}
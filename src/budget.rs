@@ -0,0 +1,56 @@
+//! Bounding of the memory used by the images being converted in parallel.
+
+use std::{
+    sync::{Condvar, Mutex},
+    time::Duration,
+};
+
+/// An approximate memory budget, shared by the threads converting images, counting the bytes of
+/// the images decoded but not written yet.
+pub struct MemoryBudget {
+    limit: usize,
+    used: Mutex<usize>,
+    released: Condvar,
+}
+
+impl MemoryBudget {
+    /// Returns a budget of `limit` bytes, none of which are used.
+    pub fn new(limit: usize) -> MemoryBudget {
+        MemoryBudget {
+            limit,
+            used: Mutex::new(0),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Allocates `bytes` bytes if they fit in what's left of the budget, or if nothing is
+    /// allocated, so that an image larger than the whole budget can still be converted alone.
+    /// Returns whether they were allocated.
+    pub fn try_allocate(&self, bytes: usize) -> bool {
+        let mut used = self.used.lock().unwrap();
+        if *used == 0 || *used + bytes <= self.limit {
+            *used += bytes;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Allocates `bytes` bytes even if they don't fit in the budget.
+    pub fn force_allocate(&self, bytes: usize) {
+        *self.used.lock().unwrap() += bytes;
+    }
+
+    /// Releases `bytes` bytes allocated before.
+    pub fn release(&self, bytes: usize) {
+        let mut used = self.used.lock().unwrap();
+        *used = used.saturating_sub(bytes);
+        self.released.notify_all();
+    }
+
+    /// Waits until some bytes are released, or at most `timeout`.
+    pub fn wait(&self, timeout: Duration) {
+        let used = self.used.lock().unwrap();
+        drop(self.released.wait_timeout(used, timeout).unwrap());
+    }
+}
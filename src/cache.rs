@@ -0,0 +1,200 @@
+//! Cache of converted images, so that repeated runs over the same ICO files skip decoding and
+//! encoding them.
+//!
+//! Images are stored in the cache directory as `{key}.{ext}`, where the key is a SHA-256 hash of
+//! the path, modification time and size of the ICO file, the index of the entry and the
+//! conversion options. The size and last use of each image are recorded in `cache_index.json`,
+//! to evict the least recently used images when the cache grows over its maximum size.
+
+use anyhow::Result;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::UNIX_EPOCH,
+};
+
+/// Name of the file recording the images of the cache.
+const INDEX_FILE: &str = "cache_index.json";
+
+/// An image of the cache, as recorded in the index.
+struct CachedImage {
+    size: u64,
+    /// The value of the clock of the index when the image was last used.
+    last_used: u64,
+}
+
+/// The images of the cache, by file name, and a clock counting their uses.
+#[derive(Default)]
+struct Index {
+    images: HashMap<String, CachedImage>,
+    clock: u64,
+}
+
+/// A cache directory, shared by the threads converting images.
+pub struct Cache {
+    dir: PathBuf,
+    max_size: Option<u64>,
+    index: Mutex<Index>,
+}
+
+impl Cache {
+    /// Arguments:
+    ///   - dir: The cache directory, which is created if it doesn't exist.
+    ///   - max_size: The size, in bytes, the images of the cache are evicted down to, if limited.
+    ///
+    /// Returns the cache in `dir`. A missing or unreadable index is started over.
+    pub fn open(dir: &Path, max_size: Option<u64>) -> Result<Cache> {
+        fs::create_dir_all(dir)?;
+        let index = fs::read(dir.join(INDEX_FILE))
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .map(|json: Value| read_index(&json))
+            .unwrap_or_default();
+
+        Ok(Cache {
+            dir: dir.to_path_buf(),
+            max_size,
+            index: Mutex::new(index),
+        })
+    }
+
+    /// Arguments:
+    ///   - file: The path to the ICO file.
+    ///   - index: The index of the entry.
+    ///   - options: A description of the conversion options.
+    ///
+    /// Returns the key of the entry converted with `options`, which changes whenever the ICO
+    /// file is modified.
+    pub fn key(file: &Path, index: usize, options: &str) -> Result<String> {
+        let metadata = fs::metadata(file)?;
+        let mtime = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos());
+
+        let mut hasher = Sha256::new();
+        for field in [
+            file.to_string_lossy().to_string(),
+            mtime.to_string(),
+            metadata.len().to_string(),
+            index.to_string(),
+            options.to_string(),
+        ] {
+            hasher.update(field.as_bytes());
+            hasher.update([0]);
+        }
+        Ok(hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect())
+    }
+
+    /// Returns the cached image of `key`, with the extension `ext`, if there's one.
+    pub fn get(&self, key: &str, ext: &str) -> Option<Vec<u8>> {
+        let name = format!("{}.{}", key, ext);
+        let mut index = self.index.lock().unwrap();
+        let Ok(data) = fs::read(self.dir.join(&name)) else {
+            index.images.remove(&name);
+            return None;
+        };
+
+        index.clock += 1;
+        let last_used = index.clock;
+        index.images.insert(
+            name,
+            CachedImage {
+                size: data.len() as u64,
+                last_used,
+            },
+        );
+        Some(data)
+    }
+
+    /// Stores `data` as the image of `key`, with the extension `ext`, then evicts the least
+    /// recently used images until the cache is back under its maximum size.
+    pub fn put(&self, key: &str, ext: &str, data: &[u8]) -> Result<()> {
+        let name = format!("{}.{}", key, ext);
+        let mut index = self.index.lock().unwrap();
+        fs::write(self.dir.join(&name), data)?;
+
+        index.clock += 1;
+        let last_used = index.clock;
+        index.images.insert(
+            name.clone(),
+            CachedImage {
+                size: data.len() as u64,
+                last_used,
+            },
+        );
+
+        let Some(max_size) = self.max_size else {
+            return Ok(());
+        };
+        let mut total: u64 = index.images.values().map(|image| image.size).sum();
+        while total > max_size {
+            // the image just stored is only evicted if it's larger than the cache on its own
+            let oldest = index
+                .images
+                .iter()
+                .filter(|(other, _)| **other != name)
+                .min_by_key(|(_, image)| image.last_used)
+                .map(|(other, _)| other.clone())
+                .unwrap_or_else(|| name.clone());
+            if let Some(image) = index.images.remove(&oldest) {
+                total -= image.size;
+            }
+            let _ = fs::remove_file(self.dir.join(&oldest));
+            if oldest == name {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the index of the cache, to be read by the next run.
+    pub fn save(&self) -> Result<()> {
+        let index = self.index.lock().unwrap();
+        let images: Map<String, Value> = index
+            .images
+            .iter()
+            .map(|(name, image)| {
+                let value = json!({ "size": image.size, "last_used": image.last_used });
+                (name.clone(), value)
+            })
+            .collect();
+
+        let json = json!({ "clock": index.clock, "images": images });
+        fs::write(self.dir.join(INDEX_FILE), serde_json::to_vec(&json)?)?;
+        Ok(())
+    }
+}
+
+/// Returns the index read from its JSON, skipping invalid images.
+fn read_index(json: &Value) -> Index {
+    let images = json["images"]
+        .as_object()
+        .map(|images| {
+            images
+                .iter()
+                .filter_map(|(name, image)| {
+                    let image = CachedImage {
+                        size: image["size"].as_u64()?,
+                        last_used: image["last_used"].as_u64()?,
+                    };
+                    Some((name.clone(), image))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Index {
+        images,
+        clock: json["clock"].as_u64().unwrap_or(0),
+    }
+}
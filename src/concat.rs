@@ -0,0 +1,142 @@
+//! Joining of images side by side, or on top of each other, into a single image.
+
+use image::{
+    imageops::{self, FilterType},
+    DynamicImage, Rgba, RgbaImage,
+};
+
+/// Arguments:
+///   - images: The images to join, from left to right.
+///   - gap: The space between two images, in pixels.
+///   - background: The color of the gaps.
+///
+/// Returns the images side by side, each resized to the height of the tallest one while keeping
+/// its aspect ratio. The images are copied as they are, so their transparent pixels stay
+/// transparent.
+pub fn concat_images_horizontal(
+    images: &[DynamicImage],
+    gap: u32,
+    background: Rgba<u8>,
+) -> DynamicImage {
+    concat(images, gap, background, true)
+}
+
+/// Arguments:
+///   - images: The images to join, from top to bottom.
+///   - gap: The space between two images, in pixels.
+///   - background: The color of the gaps.
+///
+/// Returns the images on top of each other, each resized to the width of the widest one while
+/// keeping its aspect ratio, like [`concat_images_horizontal`] does.
+pub fn concat_images_vertical(
+    images: &[DynamicImage],
+    gap: u32,
+    background: Rgba<u8>,
+) -> DynamicImage {
+    concat(images, gap, background, false)
+}
+
+/// Joins `images` along the horizontal axis if `horizontal` is set, or the vertical one
+/// otherwise. Sizes are handled as their length along that axis and across it.
+fn concat(
+    images: &[DynamicImage],
+    gap: u32,
+    background: Rgba<u8>,
+    horizontal: bool,
+) -> DynamicImage {
+    let lengths = |(width, height): (u32, u32)| {
+        if horizontal {
+            (width, height)
+        } else {
+            (height, width)
+        }
+    };
+    let across = images
+        .iter()
+        .map(|image| lengths((image.width(), image.height())).1)
+        .max()
+        .unwrap_or(0);
+
+    let resized: Vec<RgbaImage> = images
+        .iter()
+        .map(|image| {
+            let image = image.to_rgba8();
+            let (along, current) = lengths(image.dimensions());
+            if current == across || current == 0 {
+                return image;
+            }
+            let along = (along as u64 * across as u64 + current as u64 / 2) / current as u64;
+            let (width, height) = lengths((along.max(1) as u32, across));
+            imageops::resize(&image, width, height, FilterType::Lanczos3)
+        })
+        .collect();
+
+    let gaps = gap * resized.len().saturating_sub(1) as u32;
+    let total = resized
+        .iter()
+        .map(|image| lengths(image.dimensions()).0)
+        .sum::<u32>()
+        + gaps;
+    let (width, height) = lengths((total, across));
+    let mut result = RgbaImage::from_pixel(width, height, background);
+
+    let mut pos = 0;
+    for image in &resized {
+        let (x, y) = if horizontal { (pos, 0) } else { (0, pos) };
+        imageops::replace(&mut result, image, x, y);
+        pos += (lengths(image.dimensions()).0 + gap) as i64;
+    }
+    DynamicImage::ImageRgba8(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GAP_COLOR: Rgba<u8> = Rgba([255, 0, 0, 255]);
+
+    fn solid(width: u32, height: u32, value: u8) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(width, height, Rgba([value; 4])))
+    }
+
+    #[test]
+    fn horizontal_width_is_the_sum_of_widths_and_gaps() {
+        let images = [solid(16, 32, 10), solid(32, 32, 20), solid(8, 32, 30)];
+        for gap in [0, 1, 5] {
+            let joined = concat_images_horizontal(&images, gap, GAP_COLOR);
+            assert_eq!(joined.width(), 16 + 32 + 8 + 2 * gap);
+            assert_eq!(joined.height(), 32);
+        }
+
+        let joined = concat_images_horizontal(&images, 2, GAP_COLOR).to_rgba8();
+        assert_eq!(joined.get_pixel(15, 0).0, [10; 4]);
+        assert_eq!(joined.get_pixel(16, 0), &GAP_COLOR);
+        assert_eq!(joined.get_pixel(17, 31), &GAP_COLOR);
+        assert_eq!(joined.get_pixel(18, 0).0, [20; 4]);
+        assert_eq!(joined.get_pixel(52, 0).0, [30; 4]);
+    }
+
+    #[test]
+    fn horizontal_images_are_resized_to_the_tallest_one() {
+        let images = [solid(16, 16, 10), solid(24, 48, 20)];
+        let joined = concat_images_horizontal(&images, 4, GAP_COLOR);
+        // the first image is scaled to 48x48
+        assert_eq!(joined.width(), 48 + 24 + 4);
+        assert_eq!(joined.height(), 48);
+    }
+
+    #[test]
+    fn vertical_height_is_the_sum_of_heights_and_gaps() {
+        let images = [solid(32, 16, 10), solid(32, 8, 20), solid(16, 16, 30)];
+        let joined = concat_images_vertical(&images, 3, GAP_COLOR);
+        // the last image is scaled to 32x32
+        assert_eq!(joined.width(), 32);
+        assert_eq!(joined.height(), 16 + 8 + 32 + 2 * 3);
+    }
+
+    #[test]
+    fn no_images_join_into_an_empty_image() {
+        let joined = concat_images_horizontal(&[], 4, GAP_COLOR);
+        assert_eq!((joined.width(), joined.height()), (0, 0));
+    }
+}
@@ -0,0 +1,99 @@
+//! Visual comparison of an output image with the one of a previous run.
+
+use anyhow::Result;
+use image::{DynamicImage, GenericImageView, ImageFormat, Rgba, RgbaImage};
+use std::path::Path;
+
+/// Factor the differences are multiplied by in diff images, to make small ones visible.
+const DIFF_SCALE: u8 = 4;
+/// Color of the placeholder written when there's no previous image to compare with.
+const PLACEHOLDER: Rgba<u8> = Rgba([255, 0, 255, 255]);
+
+/// Returns an image holding the absolute difference of `a` and `b` for each channel of each
+/// pixel. If the sizes differ, the result covers both images, and pixels outside of either are
+/// compared with transparent black.
+pub fn compute_diff_image(a: &DynamicImage, b: &DynamicImage) -> RgbaImage {
+    let width = a.width().max(b.width());
+    let height = a.height().max(b.height());
+    let pixel = |image: &DynamicImage, x, y| {
+        if image.in_bounds(x, y) {
+            image.get_pixel(x, y)
+        } else {
+            Rgba([0; 4])
+        }
+    };
+
+    RgbaImage::from_fn(width, height, |x, y| {
+        let (pa, pb) = (pixel(a, x, y), pixel(b, x, y));
+        Rgba([0, 1, 2, 3].map(|c| pa[c].abs_diff(pb[c])))
+    })
+}
+
+/// Arguments:
+///   - image: The image that was just converted.
+///   - previous_path: The path to the same image from a previous run.
+///   - diff_path: The path to write the diff image to.
+///
+/// Writes the differences between `image` and the previous one as an opaque PNG image, with the
+/// color channels scaled up for visibility. If the previous image can't be read, a magenta
+/// placeholder is written instead.
+pub fn write_diff_image(
+    image: &DynamicImage,
+    previous_path: &Path,
+    diff_path: &Path,
+) -> Result<()> {
+    let diff = match image::open(previous_path) {
+        Ok(previous) => {
+            let mut diff = compute_diff_image(image, &previous);
+            for pixel in diff.pixels_mut() {
+                *pixel = Rgba([
+                    pixel[0].saturating_mul(DIFF_SCALE),
+                    pixel[1].saturating_mul(DIFF_SCALE),
+                    pixel[2].saturating_mul(DIFF_SCALE),
+                    255,
+                ]);
+            }
+            diff
+        }
+        Err(_) => RgbaImage::from_pixel(image.width(), image.height(), PLACEHOLDER),
+    };
+
+    diff.save_with_format(diff_path, ImageFormat::Png)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_images_have_no_differences() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(16, 16, |x, y| {
+            Rgba([x as u8 * 16, y as u8 * 16, 128, 200])
+        }));
+        let diff = compute_diff_image(&image, &image.clone());
+        assert_eq!(diff.dimensions(), (16, 16));
+        assert!(diff.pixels().all(|pixel| pixel.0 == [0; 4]));
+    }
+
+    #[test]
+    fn differences_are_absolute_per_channel() {
+        let a = DynamicImage::ImageRgba8(RgbaImage::from_pixel(2, 2, Rgba([10, 200, 0, 255])));
+        let b = DynamicImage::ImageRgba8(RgbaImage::from_pixel(2, 2, Rgba([30, 150, 0, 255])));
+        let diff = compute_diff_image(&a, &b);
+        assert!(diff.pixels().all(|pixel| pixel.0 == [20, 50, 0, 0]));
+        assert_eq!(compute_diff_image(&b, &a), diff);
+    }
+
+    #[test]
+    fn different_sizes_are_compared_with_transparent_black() {
+        let a = DynamicImage::ImageRgba8(RgbaImage::from_pixel(2, 1, Rgba([1, 2, 3, 4])));
+        let b = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 2, Rgba([1, 2, 3, 4])));
+        let diff = compute_diff_image(&a, &b);
+        assert_eq!(diff.dimensions(), (2, 2));
+        assert_eq!(diff.get_pixel(0, 0).0, [0; 4]);
+        assert_eq!(diff.get_pixel(1, 0).0, [1, 2, 3, 4]);
+        assert_eq!(diff.get_pixel(0, 1).0, [1, 2, 3, 4]);
+        assert_eq!(diff.get_pixel(1, 1).0, [0; 4]);
+    }
+}
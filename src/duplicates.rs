@@ -0,0 +1,73 @@
+//! Detection of duplicate entries: entries of the same size whose pixels are identical, or close
+//! enough to look the same.
+
+use image::{imageops, DynamicImage, GrayImage};
+use sha2::{Digest, Sha256};
+
+/// Side of the grayscale thumbnail the average hash is computed from, giving a 64-bit hash.
+const HASH_SIDE: u32 = 8;
+/// Largest fraction of differing bits between the average hashes of two entries for them to be
+/// reported as likely duplicates.
+const DISTANCE_THRESHOLD: f64 = 4.0 / 64.0;
+
+/// Arguments:
+///   - entries: The decoded entries of an ICO file, in order.
+///
+/// Returns the pairs of entries `(a, b)`, with `a < b`, that are likely duplicates, along with
+/// the distance between them: the fraction of differing bits of their average hashes, from 0.0
+/// to 1.0. Only entries of the same size are compared, since ICO files usually hold the same
+/// image at several sizes. Entries whose pixels are identical have a distance of exactly 0.0,
+/// while others only count as duplicates if the distance is at most 4/64.
+pub fn find_duplicate_entries(entries: &[DynamicImage]) -> Vec<(usize, usize, f64)> {
+    let hashes: Vec<([u8; 32], u64)> = entries
+        .iter()
+        .map(|image| (pixels_digest(image), average_hash(image)))
+        .collect();
+
+    let mut duplicates = Vec::new();
+    for a in 0..entries.len() {
+        for b in a + 1..entries.len() {
+            if size(&entries[a]) != size(&entries[b]) {
+                continue;
+            }
+            let distance = if hashes[a].0 == hashes[b].0 {
+                0.0
+            } else {
+                // never 0.0, which is kept for identical pixels
+                (hashes[a].1 ^ hashes[b].1).count_ones().max(1) as f64 / 64.0
+            };
+            if distance <= DISTANCE_THRESHOLD {
+                duplicates.push((a, b, distance));
+            }
+        }
+    }
+    duplicates
+}
+
+fn size(image: &DynamicImage) -> (u32, u32) {
+    (image.width(), image.height())
+}
+
+/// Returns the SHA-256 digest of the RGBA pixels of `image`.
+fn pixels_digest(image: &DynamicImage) -> [u8; 32] {
+    Sha256::digest(image.to_rgba8().as_raw()).into()
+}
+
+/// Returns the average hash of `image`: the image is flattened over black, scaled down to an 8x8
+/// grayscale thumbnail, and each bit tells whether a pixel is brighter than the mean.
+fn average_hash(image: &DynamicImage) -> u64 {
+    let mut rgba = image.to_rgba8();
+    for pixel in rgba.pixels_mut() {
+        let alpha = pixel[3] as u16;
+        for channel in &mut pixel.0[..3] {
+            *channel = (*channel as u16 * alpha / 255) as u8;
+        }
+    }
+    let gray: GrayImage = DynamicImage::ImageRgba8(rgba).to_luma8();
+    let thumbnail = imageops::resize(&gray, HASH_SIDE, HASH_SIDE, imageops::FilterType::Triangle);
+
+    let mean = thumbnail.pixels().map(|p| p[0] as u64).sum::<u64>() / 64;
+    thumbnail.pixels().enumerate().fold(0, |hash, (i, p)| {
+        hash | (u64::from(p[0] as u64 > mean) << i)
+    })
+}
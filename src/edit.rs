@@ -0,0 +1,296 @@
+//! Editing of existing ICO files.
+//!
+//! Entries that aren't touched by an edit are copied as they are, so their data is preserved
+//! byte-for-byte.
+
+use crate::bmp::{self, BmpAlpha};
+use anyhow::{anyhow, Result};
+use ico::{IconDir, IconDirEntry, IconImage};
+use image::{DynamicImage, ImageFormat};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufWriter, Cursor, Write},
+    path::{Path, PathBuf},
+};
+
+/// Maximum width and height of an ICO entry.
+const MAX_SIZE: u32 = 256;
+
+/// Arguments:
+///   - icon_dir: The ICO file to edit.
+///   - images: The paths to the images to add.
+///   - replace: Whether to replace entries with the same size as an added image.
+///   - trust_directory: Whether to use the entry sizes declared in the ICO directory rather than
+///     the sizes of their images.
+///   - bmp_alpha: How to read the alpha channel of 32-bit BMP images.
+///   - allow_oversize: Whether to add images larger than the 256x256 allowed by the ICO format,
+///     instead of failing.
+///
+/// Returns a new icon directory with the images added. Fails if an image has the same size as an
+/// existing entry and `replace` isn't set. Oversize images are stored as PNG, with their size
+/// written as 256 in the directory, as with [`pack_into_ico`].
+pub fn append_images(
+    icon_dir: &IconDir,
+    images: &[PathBuf],
+    replace: bool,
+    trust_directory: bool,
+    bmp_alpha: BmpAlpha,
+    allow_oversize: bool,
+) -> Result<IconDir> {
+    let mut entries = icon_dir.entries().to_vec();
+
+    for path in images {
+        let image = bmp::open_image(path, bmp_alpha)?.to_rgba8();
+        let (width, height) = image.dimensions();
+        if (width > MAX_SIZE || height > MAX_SIZE) && !allow_oversize {
+            return Err(anyhow!(
+                "{} is {}x{}, but ICO entries can be at most {}x{} (use --allow-oversize to add it anyway).",
+                path.display(),
+                width,
+                height,
+                MAX_SIZE,
+                MAX_SIZE
+            ));
+        }
+
+        let entry = IconDirEntry::encode(&IconImage::from_rgba_data(
+            width,
+            height,
+            image.into_raw(),
+        ))?;
+
+        match entries
+            .iter()
+            .position(|e| crate::entry_size(e, trust_directory) == (width, height))
+        {
+            Some(position) if replace => entries[position] = entry,
+            Some(_) => {
+                return Err(anyhow!(
+                    "The ICO file already has a {}x{} entry, use --replace to replace it.",
+                    width,
+                    height
+                ))
+            }
+            None => entries.push(entry),
+        }
+    }
+
+    Ok(rebuild(icon_dir, entries))
+}
+
+/// Arguments:
+///   - icon_dir: The ICO file to edit.
+///   - sizes: The sizes, as `(width, height)`, of the entries to remove.
+///   - trust_directory: Whether to use the entry sizes declared in the ICO directory rather than
+///     the sizes of their images.
+///
+/// Returns a new icon directory without the entries of the given sizes.
+pub fn remove_sizes(icon_dir: &IconDir, sizes: &[(u32, u32)], trust_directory: bool) -> IconDir {
+    for &(width, height) in sizes {
+        if !icon_dir
+            .entries()
+            .iter()
+            .any(|e| crate::entry_size(e, trust_directory) == (width, height))
+        {
+            crate::warning::warn(format_args!(
+                "the ICO file has no {}x{} entry.",
+                width, height
+            ));
+        }
+    }
+
+    let entries = icon_dir
+        .entries()
+        .iter()
+        .filter(|e| !sizes.contains(&crate::entry_size(e, trust_directory)))
+        .cloned()
+        .collect();
+    rebuild(icon_dir, entries)
+}
+
+/// Arguments:
+///   - images: The images to store, in order.
+///   - output_path: The path to the ICO file to write.
+///   - allow_oversize: Whether to store images larger than the 256x256 allowed by the ICO format,
+///     instead of failing.
+///
+/// Writes a new ICO file holding `images`. Images smaller than 256x256 are stored as BMP, larger
+/// ones as PNG. The size of oversize images is written as 256 in the directory, like the tools
+/// that produce such files do.
+pub fn pack_into_ico(
+    images: Vec<DynamicImage>,
+    output_path: &Path,
+    allow_oversize: bool,
+) -> Result<()> {
+    let mut entries = Vec::with_capacity(images.len());
+    for image in images {
+        let (width, height) = (image.width(), image.height());
+        let data = if width < MAX_SIZE && height < MAX_SIZE {
+            let icon_image =
+                IconImage::from_rgba_data(width, height, image.to_rgba8().into_raw());
+            IconDirEntry::encode_as_bmp(&icon_image)?.data().to_vec()
+        } else if (width <= MAX_SIZE && height <= MAX_SIZE) || allow_oversize {
+            // the `ico` crate can't encode images larger than 256x256
+            let mut data = Vec::new();
+            image.write_to(&mut Cursor::new(&mut data), ImageFormat::Png)?;
+            data
+        } else {
+            return Err(anyhow!(
+                "The {}x{} image is too large, ICO entries can be at most {}x{} (use --allow-oversize to write it anyway).",
+                width,
+                height,
+                MAX_SIZE,
+                MAX_SIZE
+            ));
+        };
+        entries.push((width, height, data));
+    }
+
+    let mut writer = BufWriter::new(File::create(output_path)?);
+    write_ico(&entries, &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes an icon file holding 32-bit `entries`, given as their width, height and encoded data.
+/// Unlike the `ico` crate, it doesn't check the sizes of the entries: sizes of 256 pixels and
+/// more are written as 0, which stands for 256.
+fn write_ico(entries: &[(u32, u32, Vec<u8>)], writer: &mut impl Write) -> Result<()> {
+    let count = u16::try_from(entries.len())
+        .map_err(|_| anyhow!("An ICO file can hold at most {} entries.", u16::MAX))?;
+    // reserved, resource type (1 for icons) and number of entries
+    writer.write_all(&[0, 0, 1, 0])?;
+    writer.write_all(&count.to_le_bytes())?;
+
+    let mut offset = 6 + 16 * entries.len() as u32;
+    for (width, height, data) in entries {
+        let size_byte = |size: u32| if size >= MAX_SIZE { 0 } else { size as u8 };
+        // width, height, color count, reserved, color planes and bits per pixel
+        writer.write_all(&[size_byte(*width), size_byte(*height), 0, 0, 1, 0, 32, 0])?;
+        writer.write_all(&(data.len() as u32).to_le_bytes())?;
+        writer.write_all(&offset.to_le_bytes())?;
+        offset += data.len() as u32;
+    }
+
+    for (_, _, data) in entries {
+        writer.write_all(data)?;
+    }
+    Ok(())
+}
+
+/// Arguments:
+///   - dir: The directory holding the images.
+///   - pattern: The pattern the names of the images match, where `*` stands for any text, and
+///     `{width}`, `{height}` and `{index}` for numbers.
+///   - output_ico: The path to the ICO file to write.
+///   - allow_oversize: Whether to store images larger than 256x256, as with [`pack_into_ico`].
+///   - bmp_alpha: How to read the alpha channel of 32-bit BMP images.
+///
+/// Writes a new ICO file holding the images of `dir` whose name matches `pattern`, ordered by
+/// `{index}` if the pattern has one, then by size. Fails if an image isn't of the size given by
+/// its name.
+pub fn pack_images_from_dir(
+    dir: &Path,
+    pattern: &str,
+    output_ico: &Path,
+    allow_oversize: bool,
+    bmp_alpha: BmpAlpha,
+) -> Result<()> {
+    check_pattern(pattern)?;
+
+    let mut images = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let values = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .filter(|_| path.is_file())
+            .and_then(|name| match_pattern(pattern, name));
+        let Some(values) = values else {
+            continue;
+        };
+
+        let image = bmp::open_image(&path, bmp_alpha)
+            .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        let width = values.get("width").copied().unwrap_or(image.width());
+        let height = values.get("height").copied().unwrap_or(image.height());
+        if (width, height) != (image.width(), image.height()) {
+            return Err(anyhow!(
+                "{} is {}x{}, not {}x{} as its name says.",
+                path.display(),
+                image.width(),
+                image.height(),
+                width,
+                height
+            ));
+        }
+        images.push((values.get("index").copied(), image));
+    }
+
+    if images.is_empty() {
+        return Err(anyhow!("No files in {} match {}.", dir.display(), pattern));
+    }
+
+    images.sort_by_key(|(index, image)| (*index, image.width(), image.height()));
+    let images = images.into_iter().map(|(_, image)| image).collect();
+    pack_into_ico(images, output_ico, allow_oversize)
+}
+
+/// Fails if `pattern` has a placeholder other than `{width}`, `{height}` and `{index}`.
+fn check_pattern(pattern: &str) -> Result<()> {
+    let mut rest = pattern;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| anyhow!("Unclosed placeholder in the pattern {}.", pattern))?;
+        let name = &rest[start + 1..start + end];
+        if !["width", "height", "index"].contains(&name) {
+            return Err(anyhow!(
+                "Unknown placeholder {{{}}} in the pattern, expected {{width}}, {{height}} or {{index}}.",
+                name
+            ));
+        }
+        rest = &rest[start + end + 1..];
+    }
+    Ok(())
+}
+
+/// Returns the numbers matched by the placeholders of `pattern` in `name`, by placeholder name,
+/// if `name` matches `pattern`. The pattern must have been checked with `check_pattern`.
+fn match_pattern(pattern: &str, name: &str) -> Option<HashMap<String, u32>> {
+    if let Some(rest) = pattern.strip_prefix('*') {
+        return (0..=name.len())
+            .filter(|&i| name.is_char_boundary(i))
+            .find_map(|i| match_pattern(rest, &name[i..]));
+    }
+
+    if let Some(placeholder) = pattern.strip_prefix('{') {
+        let (key, rest) = placeholder.split_once('}')?;
+        let digits = name.len() - name.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        // numbers can be followed by digits of the pattern, so every length is tried
+        return (1..=digits).rev().find_map(|len| {
+            let value = name[..len].parse().ok()?;
+            let mut values = match_pattern(rest, &name[len..])?;
+            values.insert(key.to_string(), value);
+            Some(values)
+        });
+    }
+
+    match (pattern.chars().next(), name.chars().next()) {
+        (None, None) => Some(HashMap::new()),
+        (Some(p), Some(n)) if p == n => {
+            match_pattern(&pattern[p.len_utf8()..], &name[n.len_utf8()..])
+        }
+        _ => None,
+    }
+}
+
+/// Returns a new icon directory with the same resource type as `icon_dir`, holding `entries`.
+fn rebuild(icon_dir: &IconDir, entries: Vec<IconDirEntry>) -> IconDir {
+    let mut new_dir = IconDir::new(icon_dir.resource_type());
+    for entry in entries {
+        new_dir.add_entry(entry);
+    }
+    new_dir
+}
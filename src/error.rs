@@ -0,0 +1,175 @@
+//! The error type of the library.
+
+use std::{error::Error, fmt, io, path::PathBuf};
+
+/// The errors returned by the library. Its variants may grow, so match them with a wildcard arm.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Ico2imgError {
+    /// The file at this path doesn't exist.
+    FileNotFound(PathBuf),
+    /// Reading a file or a reader failed.
+    Io(io::Error),
+    /// The ICO file is invalid, as described by the message.
+    IcoParseError(String),
+    /// The index of an entry is past the last one, `max`.
+    IndexOutOfBounds { index: usize, max: usize },
+    /// The entry at `index` couldn't be decoded.
+    DecodeError { index: usize, source: String },
+    /// The image couldn't be encoded.
+    EncodeError(String),
+    /// The image couldn't be written to `path`.
+    WriteError { path: PathBuf, source: String },
+    /// An option, or a value parsed from a string, is invalid, as described by the message.
+    InvalidArgument(String),
+}
+
+impl Ico2imgError {
+    /// Returns a stable identifier of the kind of error, such as `decode_error`, for programs
+    /// that tell errors apart without parsing their message.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Ico2imgError::FileNotFound(_) => "file_not_found",
+            Ico2imgError::Io(_) => "io_error",
+            Ico2imgError::IcoParseError(_) => "ico_parse_error",
+            Ico2imgError::IndexOutOfBounds { .. } => "index_out_of_bounds",
+            Ico2imgError::DecodeError { .. } => "decode_error",
+            Ico2imgError::EncodeError(_) => "encode_error",
+            Ico2imgError::WriteError { .. } => "write_error",
+            Ico2imgError::InvalidArgument(_) => "invalid_argument",
+        }
+    }
+
+    /// Returns the index of the entry the error is about, if any.
+    pub fn entry_index(&self) -> Option<usize> {
+        match self {
+            Ico2imgError::IndexOutOfBounds { index, .. }
+            | Ico2imgError::DecodeError { index, .. } => Some(*index),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Ico2imgError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Ico2imgError::FileNotFound(path) => {
+                write!(f, "The file {} doesn't exist.", path.display())
+            }
+            Ico2imgError::Io(e) => write!(f, "{}", e),
+            Ico2imgError::IcoParseError(message) => write!(f, "{}", message),
+            Ico2imgError::IndexOutOfBounds { index, max } => write!(
+                f,
+                "Invalid image index {}, the last entry is {}.",
+                index, max
+            ),
+            Ico2imgError::DecodeError { index, source } => {
+                write!(f, "Failed to decode entry {}: {}", index, source)
+            }
+            Ico2imgError::EncodeError(source) => {
+                write!(f, "Failed to encode the image: {}", source)
+            }
+            Ico2imgError::WriteError { path, source } => {
+                write!(f, "Failed to write {}: {}", path.display(), source)
+            }
+            Ico2imgError::InvalidArgument(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for Ico2imgError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Ico2imgError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Ico2imgError {
+    fn from(e: io::Error) -> Self {
+        Ico2imgError::Io(e)
+    }
+}
+
+/// The result of the functions of the library.
+pub(crate) type Result<T, E = Ico2imgError> = std::result::Result<T, E>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    /// Returns an error of every variant.
+    fn every_error() -> Vec<Ico2imgError> {
+        vec![
+            Ico2imgError::FileNotFound(PathBuf::from("missing.ico")),
+            Ico2imgError::Io(io::Error::other("disk full")),
+            Ico2imgError::IcoParseError("bad header".to_string()),
+            Ico2imgError::IndexOutOfBounds { index: 3, max: 1 },
+            Ico2imgError::DecodeError {
+                index: 2,
+                source: "truncated".to_string(),
+            },
+            Ico2imgError::EncodeError("unsupported".to_string()),
+            Ico2imgError::WriteError {
+                path: PathBuf::from("out.png"),
+                source: "denied".to_string(),
+            },
+            Ico2imgError::InvalidArgument("bad option".to_string()),
+        ]
+    }
+
+    /// Returns the position of the variant of `error` in the enum. The match has no wildcard arm,
+    /// so a new variant doesn't compile until it's added here and to [`every_error`].
+    fn variant(error: &Ico2imgError) -> usize {
+        match error {
+            Ico2imgError::FileNotFound(_) => 0,
+            Ico2imgError::Io(_) => 1,
+            Ico2imgError::IcoParseError(_) => 2,
+            Ico2imgError::IndexOutOfBounds { .. } => 3,
+            Ico2imgError::DecodeError { .. } => 4,
+            Ico2imgError::EncodeError(_) => 5,
+            Ico2imgError::WriteError { .. } => 6,
+            Ico2imgError::InvalidArgument(_) => 7,
+        }
+    }
+
+    #[test]
+    fn every_variant_has_a_unique_code() {
+        let errors = every_error();
+        let variants: Vec<usize> = errors.iter().map(variant).collect();
+        assert_eq!(variants, (0..=7).collect::<Vec<_>>());
+
+        let codes: HashSet<&str> = errors.iter().map(Ico2imgError::code).collect();
+        assert_eq!(codes.len(), errors.len());
+        for code in codes {
+            assert!(
+                code.chars().all(|c| c.is_ascii_lowercase() || c == '_'),
+                "{}",
+                code
+            );
+        }
+    }
+
+    #[test]
+    fn entry_index_of_entry_errors() {
+        let indices: Vec<Option<usize>> = every_error().iter().map(|e| e.entry_index()).collect();
+        assert_eq!(
+            indices,
+            [None, None, None, Some(3), Some(2), None, None, None]
+        );
+    }
+
+    #[test]
+    fn every_variant_has_a_message() {
+        for error in every_error() {
+            assert!(!error.to_string().is_empty(), "{:?}", error);
+        }
+        let error = Ico2imgError::IndexOutOfBounds { index: 3, max: 1 };
+        assert_eq!(
+            error.to_string(),
+            "Invalid image index 3, the last entry is 1."
+        );
+    }
+}
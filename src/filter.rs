@@ -0,0 +1,478 @@
+//! Selection of ICO entries with a predicate over their fields, such as
+//! `bpp == 32 and width >= 48`.
+//!
+//! The grammar of a filter, from the loosest binding to the tightest:
+//!
+//! ``` text
+//! expr       = and ("or" and)*
+//! and        = not ("and" not)*
+//! not        = "not" not | primary
+//! primary    = "(" expr ")" | "alpha" | stored | comparison
+//! stored     = "stored" ("==" | "!=") ("png" | "bmp")
+//! comparison = field op number
+//! field      = "size" | "width" | "height" | "bpp" | "index"
+//! op         = "==" | "!=" | "<" | "<=" | ">" | ">="
+//! ```
+//!
+//! `&&`, `||` and `!` can be used instead of `and`, `or` and `not`, and `=` instead of `==`.
+//! `size` is the largest of the width and height, and `alpha` stands for `bpp == 32`, since
+//! entries are filtered before being decoded.
+
+use anyhow::{anyhow, Result};
+use std::{fmt, str::FromStr};
+
+/// The fields of an entry that a filter can test.
+#[derive(Clone, Copy, Debug)]
+pub struct EntryFields {
+    pub index: usize,
+    pub width: u32,
+    pub height: u32,
+    pub bpp: u16,
+    /// Whether the entry is stored as PNG data, rather than as a BMP image.
+    pub png: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Field {
+    Size,
+    Width,
+    Height,
+    Bpp,
+    Index,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Compare(Field, Op, u64),
+    StoredPng,
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Number(u64),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+/// A parsed `--entry-filter` expression.
+#[derive(Clone, Debug)]
+pub struct EntryFilter {
+    expr: Expr,
+}
+
+impl EntryFilter {
+    /// Returns whether the entry with the given fields is selected by the filter.
+    pub fn matches(&self, fields: &EntryFields) -> bool {
+        self.expr.eval(fields)
+    }
+}
+
+impl FromStr for EntryFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parser = Parser {
+            source: s,
+            tokens: tokenize(s)?,
+            pos: 0,
+        };
+        let expr = parser.expr()?;
+        match parser.peek() {
+            None => Ok(EntryFilter { expr }),
+            Some(token) => Err(parser.error(parser.pos, format!("Unexpected {}", token))),
+        }
+    }
+}
+
+impl Expr {
+    fn eval(&self, fields: &EntryFields) -> bool {
+        match self {
+            Expr::Compare(field, op, value) => {
+                let actual = match field {
+                    Field::Size => fields.width.max(fields.height) as u64,
+                    Field::Width => fields.width as u64,
+                    Field::Height => fields.height as u64,
+                    Field::Bpp => fields.bpp as u64,
+                    Field::Index => fields.index as u64,
+                };
+                match op {
+                    Op::Eq => actual == *value,
+                    Op::Ne => actual != *value,
+                    Op::Lt => actual < *value,
+                    Op::Le => actual <= *value,
+                    Op::Gt => actual > *value,
+                    Op::Ge => actual >= *value,
+                }
+            }
+            Expr::StoredPng => fields.png,
+            Expr::Not(expr) => !expr.eval(fields),
+            Expr::And(a, b) => a.eval(fields) && b.eval(fields),
+            Expr::Or(a, b) => a.eval(fields) || b.eval(fields),
+        }
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "'{}'", word),
+            Token::Number(number) => write!(f, "{}", number),
+            Token::Op(_) => write!(f, "comparison"),
+            Token::And => write!(f, "'and'"),
+            Token::Or => write!(f, "'or'"),
+            Token::Not => write!(f, "'not'"),
+            Token::Open => write!(f, "'('"),
+            Token::Close => write!(f, "')'"),
+        }
+    }
+}
+
+/// Splits a filter into tokens, along with the byte offset of each one in `s`.
+fn tokenize(s: &str) -> Result<Vec<(usize, Token)>> {
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_alphabetic() {
+            let mut word = String::new();
+            while let Some(&(_, c)) = chars.peek().filter(|(_, c)| c.is_ascii_alphanumeric()) {
+                word.push(c);
+                chars.next();
+            }
+            let token = match word.to_lowercase().as_str() {
+                "and" => Token::And,
+                "or" => Token::Or,
+                "not" => Token::Not,
+                _ => Token::Word(word),
+            };
+            tokens.push((start, token));
+        } else if c.is_ascii_digit() {
+            let mut number = String::new();
+            while let Some(&(_, c)) = chars.peek().filter(|(_, c)| c.is_ascii_digit()) {
+                number.push(c);
+                chars.next();
+            }
+            let number = number
+                .parse()
+                .map_err(|_| error_at(s, start, format!("The number {} is too large", number)))?;
+            tokens.push((start, Token::Number(number)));
+        } else {
+            chars.next();
+            let mut next_if_eq = |expected| chars.next_if(|&(_, c)| c == expected).is_some();
+            let token = match c {
+                '(' => Token::Open,
+                ')' => Token::Close,
+                '&' if next_if_eq('&') => Token::And,
+                '|' if next_if_eq('|') => Token::Or,
+                '=' => {
+                    next_if_eq('=');
+                    Token::Op(Op::Eq)
+                }
+                '!' if next_if_eq('=') => Token::Op(Op::Ne),
+                '!' => Token::Not,
+                '<' if next_if_eq('=') => Token::Op(Op::Le),
+                '<' => Token::Op(Op::Lt),
+                '>' if next_if_eq('=') => Token::Op(Op::Ge),
+                '>' => Token::Op(Op::Gt),
+                _ => return Err(error_at(s, start, format!("Unexpected character '{}'", c))),
+            };
+            tokens.push((start, token));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Returns the error `message` about the entry filter `s`, followed by the filter with a caret
+/// under the byte offset `pos`.
+fn error_at(s: &str, pos: usize, message: String) -> anyhow::Error {
+    let column = s[..pos].chars().count();
+    anyhow!(
+        "{} at column {} of the entry filter:\n  {}\n  {}^",
+        message,
+        column + 1,
+        s,
+        " ".repeat(column)
+    )
+}
+
+/// A recursive-descent parser over the tokens of a filter, following the grammar in the module
+/// documentation.
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, token)| token)
+    }
+
+    fn take(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// Returns the error `message`, pointing at the token at `index`, or at the end of the
+    /// filter if there are fewer tokens.
+    fn error(&self, index: usize, message: String) -> anyhow::Error {
+        let pos = self
+            .tokens
+            .get(index)
+            .map_or(self.source.len(), |&(pos, _)| pos);
+        error_at(self.source, pos, message)
+    }
+
+    fn expr(&mut self) -> Result<Expr> {
+        let mut expr = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.take();
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut expr = self.not()?;
+        while self.peek() == Some(&Token::And) {
+            self.take();
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr> {
+        if self.peek() == Some(&Token::Not) {
+            self.take();
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        let start = self.pos;
+        match self.take() {
+            Some(Token::Open) => {
+                let expr = self.expr()?;
+                match self.take() {
+                    Some(Token::Close) => Ok(expr),
+                    _ => Err(self.error(self.pos - 1, "Missing ')'".to_string())),
+                }
+            }
+            Some(Token::Word(word)) => {
+                let field = match word.to_lowercase().as_str() {
+                    "alpha" => return Ok(Expr::Compare(Field::Bpp, Op::Eq, 32)),
+                    "stored" => return self.stored(),
+                    "size" => Field::Size,
+                    "width" => Field::Width,
+                    "height" => Field::Height,
+                    "bpp" => Field::Bpp,
+                    "index" => Field::Index,
+                    _ => {
+                        return Err(self.error(
+                            start,
+                            format!(
+                                "Unknown field '{}', expected size, width, height, bpp, index, \
+                                 stored or alpha",
+                                word
+                            ),
+                        ))
+                    }
+                };
+                let op = match self.take() {
+                    Some(Token::Op(op)) => op,
+                    _ => {
+                        return Err(self.error(
+                            self.pos - 1,
+                            format!("Expected a comparison after '{}'", word),
+                        ))
+                    }
+                };
+                match self.take() {
+                    Some(Token::Number(value)) => Ok(Expr::Compare(field, op, value)),
+                    _ => Err(self.error(
+                        self.pos - 1,
+                        format!("Expected a number to compare '{}' with", word),
+                    )),
+                }
+            }
+            Some(token) => Err(self.error(start, format!("Unexpected {}", token))),
+            None => Err(self.error(start, "Unexpected end".to_string())),
+        }
+    }
+
+    /// Parses the rest of a `stored` comparison, after the field.
+    fn stored(&mut self) -> Result<Expr> {
+        let equal = match self.take() {
+            Some(Token::Op(Op::Eq)) => true,
+            Some(Token::Op(Op::Ne)) => false,
+            _ => {
+                return Err(self.error(
+                    self.pos - 1,
+                    "Expected '==' or '!=' after 'stored'".to_string(),
+                ))
+            }
+        };
+        let png = match self.take() {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case("png") => true,
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case("bmp") => false,
+            _ => {
+                return Err(self.error(
+                    self.pos - 1,
+                    "Expected png or bmp to compare 'stored' with".to_string(),
+                ))
+            }
+        };
+        Ok(if equal == png {
+            Expr::StoredPng
+        } else {
+            Expr::Not(Box::new(Expr::StoredPng))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 16x16 4-bit BMP, a 32x32 32-bit BMP, a 48x32 32-bit BMP and a 256x256 32-bit PNG entry.
+    const ENTRIES: [EntryFields; 4] = [
+        EntryFields {
+            index: 0,
+            width: 16,
+            height: 16,
+            bpp: 4,
+            png: false,
+        },
+        EntryFields {
+            index: 1,
+            width: 32,
+            height: 32,
+            bpp: 32,
+            png: false,
+        },
+        EntryFields {
+            index: 2,
+            width: 48,
+            height: 32,
+            bpp: 32,
+            png: false,
+        },
+        EntryFields {
+            index: 3,
+            width: 256,
+            height: 256,
+            bpp: 32,
+            png: true,
+        },
+    ];
+
+    /// Returns the indices of the entries of [`ENTRIES`] selected by `filter`.
+    fn selected(filter: &str) -> Vec<usize> {
+        let filter: EntryFilter = filter
+            .parse()
+            .unwrap_or_else(|e| panic!("{}: {}", filter, e));
+        ENTRIES
+            .iter()
+            .filter(|fields| filter.matches(fields))
+            .map(|fields| fields.index)
+            .collect()
+    }
+
+    #[test]
+    fn expressions_select_entries() {
+        let table: &[(&str, &[usize])] = &[
+            ("size >= 48", &[2, 3]),
+            ("size>=48", &[2, 3]),
+            ("width == 48", &[2]),
+            ("width = 48", &[2]),
+            ("height != 32", &[0, 3]),
+            ("height < 32", &[0]),
+            ("height <= 32", &[0, 1, 2]),
+            ("width > 32", &[2, 3]),
+            ("bpp == 4", &[0]),
+            ("index == 3", &[3]),
+            ("alpha", &[1, 2, 3]),
+            ("stored == png", &[3]),
+            ("stored != png", &[0, 1, 2]),
+            ("stored == bmp", &[0, 1, 2]),
+            ("stored != bmp", &[3]),
+            ("STORED == PNG", &[3]),
+            ("size >= 48 && bpp == 32 && stored == bmp", &[2]),
+            ("size >= 48 and bpp == 32 and stored == bmp", &[2]),
+            ("index == 0 || index == 3", &[0, 3]),
+            ("index == 0 or index == 3", &[0, 3]),
+            ("!alpha", &[0]),
+            ("not alpha", &[0]),
+            ("!!alpha", &[1, 2, 3]),
+            ("!(size >= 48)", &[0, 1]),
+            ("!size >= 48", &[0, 1]),
+            // && binds tighter than ||
+            ("index == 0 || index == 1 && bpp == 4", &[0]),
+            ("(index == 0 || index == 1) && bpp == 32", &[1]),
+            ("index == 0 && bpp == 32 || index == 3", &[3]),
+            ("index == 0 && (bpp == 32 || index == 3)", &[]),
+            ("((alpha)) && ((width < 48))", &[1]),
+            ("!(stored == png || bpp == 4) && size <= 48", &[1, 2]),
+        ];
+        for &(filter, expected) in table {
+            assert_eq!(selected(filter), expected, "{}", filter);
+        }
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_token() {
+        let table: &[(&str, usize, &str)] = &[
+            ("size >= 48 &&", 14, "Unexpected end"),
+            ("size >=", 8, "Expected a number to compare 'size' with"),
+            ("size 48", 6, "Expected a comparison after 'size'"),
+            ("depth == 32", 1, "Unknown field 'depth'"),
+            ("bpp == 32 && colour", 14, "Unknown field 'colour'"),
+            ("(bpp == 32", 11, "Missing ')'"),
+            ("bpp == 32)", 10, "Unexpected ')'"),
+            ("bpp == 32 alpha", 11, "Unexpected 'alpha'"),
+            ("stored == gif", 11, "Expected png or bmp"),
+            ("stored < png", 8, "Expected '==' or '!='"),
+            ("bpp == 32 & alpha", 11, "Unexpected character '&'"),
+            (
+                "size >= 99999999999999999999",
+                9,
+                "The number 99999999999999999999 is too large",
+            ),
+            ("&& alpha", 1, "Unexpected 'and'"),
+            ("", 1, "Unexpected end"),
+        ];
+        for &(filter, column, message) in table {
+            let error = filter.parse::<EntryFilter>().unwrap_err().to_string();
+            assert!(error.starts_with(message), "{}: {}", filter, error);
+            assert!(
+                error.contains(&format!("at column {} of", column)),
+                "{}: {}",
+                filter,
+                error
+            );
+            let caret = format!("\n  {}^", " ".repeat(column - 1));
+            assert!(error.ends_with(&caret), "{}: {}", filter, error);
+        }
+    }
+}
@@ -0,0 +1,83 @@
+//! ICO files and PNG data built in memory for the unit tests.
+
+use crate::png;
+use ico::IconDir;
+use image::{ImageFormat, Rgba, RgbaImage};
+use std::io::Cursor;
+
+/// A made-up ICC profile. Decoders don't parse profiles, so any bytes do.
+pub(crate) const PROFILE: &[u8] = b"made-up ICC profile, long enough to span a few deflate blocks";
+
+/// Returns a `width`x`height` image with a gradient over all four channels.
+pub(crate) fn gradient(width: u32, height: u32) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| {
+        Rgba([
+            (x * 16) as u8,
+            (y * 16) as u8,
+            (x ^ y) as u8,
+            255 - (x + y) as u8,
+        ])
+    })
+}
+
+/// Returns `image` encoded as PNG.
+pub(crate) fn png_data(image: &RgbaImage) -> Vec<u8> {
+    let mut data = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+        .unwrap();
+    data
+}
+
+/// Returns `image` encoded as PNG, with [`PROFILE`] in an `iCCP` chunk and a `gAMA` chunk.
+pub(crate) fn png_with_profile(image: &RgbaImage) -> Vec<u8> {
+    let mut iccp = b"test\0\0".to_vec();
+    iccp.extend_from_slice(&zlib_stored(PROFILE));
+    png::insert_chunks(
+        &png_data(image),
+        &[
+            &png::chunk(b"iCCP", &iccp),
+            &png::chunk(b"gAMA", &45455u32.to_be_bytes()),
+        ],
+    )
+}
+
+/// Returns `data` as a zlib stream of stored (uncompressed) deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut stream = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = data.chunks(16).collect();
+    for (index, block) in blocks.iter().enumerate() {
+        let len = block.len() as u16;
+        stream.push(u8::from(index + 1 == blocks.len()));
+        stream.extend_from_slice(&len.to_le_bytes());
+        stream.extend_from_slice(&(!len).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    stream.extend_from_slice(&((b << 16) | a).to_be_bytes());
+    stream
+}
+
+/// Returns an ICO file whose entries hold `entries`, the data of PNG or BMP images, as they are.
+pub(crate) fn icon_dir(entries: &[(u32, Vec<u8>)]) -> IconDir {
+    let mut data = vec![0, 0, 1, 0];
+    data.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    let mut offset = 6 + 16 * entries.len();
+    for (size, entry) in entries {
+        // a size of 256 is stored as 0
+        let size = *size as u8;
+        data.extend_from_slice(&[size, size, 0, 0, 1, 0, 32, 0]);
+        data.extend_from_slice(&(entry.len() as u32).to_le_bytes());
+        data.extend_from_slice(&(offset as u32).to_le_bytes());
+        offset += entry.len();
+    }
+    for (_, entry) in entries {
+        data.extend_from_slice(entry);
+    }
+    IconDir::read(Cursor::new(data)).unwrap()
+}
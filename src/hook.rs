@@ -0,0 +1,90 @@
+//! Post-processing of the written images with an external command, such as an optimizer.
+
+use anyhow::{anyhow, Result};
+use std::{
+    fs,
+    path::Path,
+    process::Command,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Interval at which a running command is checked for completion when it has a timeout.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Arguments:
+///   - template: The command to run, where `{}` stands for the path to the image.
+///   - shell: Whether to pass the command to the shell, instead of splitting it into words and
+///     running it directly.
+///   - timeout: The number of seconds after which the command is killed, if limited.
+///   - output: The path to the image to post-process.
+///
+/// Runs the command on `output`, with the directory of `output` as working directory. Fails if
+/// the command can't be run, doesn't succeed or times out.
+pub fn post_process(
+    template: &str,
+    shell: bool,
+    timeout: Option<f64>,
+    output: &Path,
+) -> Result<()> {
+    let path = fs::canonicalize(output)?;
+    let path = path.to_string_lossy();
+
+    let mut command = if shell {
+        let (program, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+        let mut command = Command::new(program);
+        command
+            .arg(flag)
+            .arg(template.replace("{}", &shell_words::quote(&path)));
+        command
+    } else {
+        let words = split_command(template)?;
+        let mut command = Command::new(words[0].replace("{}", &path));
+        command.args(words[1..].iter().map(|word| word.replace("{}", &path)));
+        command
+    };
+    if let Some(dir) = output.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        command.current_dir(dir);
+    }
+
+    let mut child = command
+        .spawn()
+        .map_err(|e| anyhow!("Failed to run the post-processing command: {}", e))?;
+    let status = match timeout {
+        Some(timeout) => {
+            // A timeout too far in the future to represent never expires.
+            let deadline = Instant::now().checked_add(Duration::from_secs_f64(timeout));
+            loop {
+                if let Some(status) = child.try_wait()? {
+                    break status;
+                }
+                if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    child.kill()?;
+                    child.wait()?;
+                    return Err(anyhow!(
+                        "The post-processing command timed out after {} seconds.",
+                        timeout
+                    ));
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+        None => child.wait()?,
+    };
+
+    if !status.success() {
+        return Err(anyhow!("The post-processing command failed ({}).", status));
+    }
+    Ok(())
+}
+
+/// Splits a command into words like a POSIX shell would, without expanding anything. Fails if
+/// the quoting is invalid or the command is empty.
+pub fn split_command(template: &str) -> Result<Vec<String>> {
+    let words = shell_words::split(template)
+        .map_err(|e| anyhow!("Invalid post-processing command: {}", e))?;
+    if words.is_empty() {
+        return Err(anyhow!("The post-processing command is empty."));
+    }
+    Ok(words)
+}
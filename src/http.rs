@@ -0,0 +1,58 @@
+//! Downloading of input files given as HTTP(S) URLs.
+
+use anyhow::{anyhow, Result};
+use std::{io::Read, path::Path};
+
+/// Maximum size of a downloaded file.
+const MAX_DOWNLOAD_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Returns the input path as a URL, if it's an `http` or `https` one.
+pub fn as_url(path: &Path) -> Option<&str> {
+    path.to_str().filter(|s| s.starts_with("http://") || s.starts_with("https://"))
+}
+
+/// Arguments:
+///   - url: The URL of the file to download.
+///
+/// Returns the contents of the file at `url`, following redirects. Fails if the server doesn't
+/// answer with 200 OK.
+pub fn download(url: &str) -> Result<Vec<u8>> {
+    let response = match ureq::get(url).call() {
+        Ok(response) => response,
+        Err(ureq::Error::Status(code, response)) => {
+            return Err(anyhow!(
+                "Failed to download {}: the server answered {} {}.",
+                url,
+                code,
+                response.status_text()
+            ))
+        }
+        Err(ureq::Error::Transport(e)) => {
+            return Err(anyhow!("Failed to download {}: {}", url, e))
+        }
+    };
+
+    if response.status() != 200 {
+        return Err(anyhow!(
+            "Failed to download {}: the server answered {} {}.",
+            url,
+            response.status(),
+            response.status_text()
+        ));
+    }
+
+    let mut data = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_DOWNLOAD_SIZE + 1)
+        .read_to_end(&mut data)?;
+    if data.len() as u64 > MAX_DOWNLOAD_SIZE {
+        return Err(anyhow!(
+            "Failed to download {}: the file is larger than {} bytes.",
+            url,
+            MAX_DOWNLOAD_SIZE
+        ));
+    }
+
+    Ok(data)
+}
@@ -0,0 +1,223 @@
+//! Embedding of ICC profiles in encoded JPEG and WebP images, for
+//! [`ExtractOptions::keep_icc`](crate::ExtractOptions::keep_icc).
+
+use crate::xmp;
+
+/// Marker of the JPEG `APP2` segment.
+const APP2: [u8; 2] = [0xFF, 0xE2];
+/// Identifier starting the JPEG `APP2` segments holding an ICC profile, null terminator included.
+const JPEG_ICC_ID: &[u8] = b"ICC_PROFILE\0";
+/// Largest part of a profile held by a JPEG segment: its 65535 bytes, minus the length field,
+/// the identifier, and the sequence number and count of the segment.
+const JPEG_ICC_PART_LEN: usize = 65535 - 2 - JPEG_ICC_ID.len() - 2;
+/// Flags of the WebP `VP8X` chunk, set when the image has an ICC profile and an alpha channel.
+const VP8X_ICC: u8 = 0x20;
+const VP8X_ALPHA: u8 = 0x10;
+
+/// Returns the decompressed ICC profile of `png`, PNG data, or `None` if it has none or it can't
+/// be read.
+pub(crate) fn png_profile(png: &[u8]) -> Option<Vec<u8>> {
+    let reader = ::png::Decoder::new(png).read_info().ok()?;
+    reader
+        .info()
+        .icc_profile
+        .as_ref()
+        .map(|profile| profile.to_vec())
+}
+
+/// Returns `data`, JPEG data, with `profile` split across `APP2` segments after its start marker
+/// and its JFIF header, if any. `data` is returned as it is if the profile doesn't fit in the
+/// 255 segments allowed.
+pub(crate) fn embed_jpeg(data: Vec<u8>, profile: &[u8]) -> Vec<u8> {
+    let parts: Vec<&[u8]> = profile.chunks(JPEG_ICC_PART_LEN).collect();
+    let Ok(count) = u8::try_from(parts.len()) else {
+        return data;
+    };
+
+    let split = xmp::jpeg_header_len(&data);
+    let mut result = Vec::with_capacity(data.len() + profile.len() + 18 * parts.len());
+    result.extend_from_slice(&data[..split]);
+    for (index, part) in parts.iter().enumerate() {
+        let len = (2 + JPEG_ICC_ID.len() + 2 + part.len()) as u16;
+        result.extend_from_slice(&APP2);
+        result.extend_from_slice(&len.to_be_bytes());
+        result.extend_from_slice(JPEG_ICC_ID);
+        // sequence numbers start at 1
+        result.extend_from_slice(&[index as u8 + 1, count]);
+        result.extend_from_slice(part);
+    }
+    result.extend_from_slice(&data[split..]);
+    result
+}
+
+/// Returns `data`, WebP data, with `profile` in an `ICCP` chunk. Images in the simple format are
+/// moved to the extended format, which starts with a `VP8X` chunk. `data` is returned as it is
+/// if it isn't a lossless or extended WebP image.
+pub(crate) fn embed_webp(data: Vec<u8>, profile: &[u8]) -> Vec<u8> {
+    if data.len() < 20 || !data.starts_with(b"RIFF") || data[8..12] != *b"WEBP" {
+        return data;
+    }
+
+    let mut chunks = Vec::with_capacity(data.len() + 8 + 18 + profile.len() + 1);
+    let rest = match &data[12..16] {
+        b"VP8X" if data.len() >= 30 => {
+            chunks.extend_from_slice(&data[12..30]);
+            chunks[8] |= VP8X_ICC;
+            &data[30..]
+        }
+        b"VP8L" if data.len() >= 25 => {
+            // the 14-bit width and height minus one, and whether the alpha channel is used,
+            // follow the signature byte
+            let bits = u32::from_le_bytes([data[21], data[22], data[23], data[24]]);
+            let (width, height) = (bits & 0x3FFF, (bits >> 14) & 0x3FFF);
+            let alpha = if bits & (1 << 28) != 0 { VP8X_ALPHA } else { 0 };
+
+            chunks.extend_from_slice(b"VP8X");
+            chunks.extend_from_slice(&10u32.to_le_bytes());
+            chunks.extend_from_slice(&[VP8X_ICC | alpha, 0, 0, 0]);
+            chunks.extend_from_slice(&width.to_le_bytes()[..3]);
+            chunks.extend_from_slice(&height.to_le_bytes()[..3]);
+            &data[12..]
+        }
+        _ => return data,
+    };
+
+    // the ICCP chunk must come right after the VP8X chunk, and chunks are padded to an even
+    // length
+    chunks.extend_from_slice(b"ICCP");
+    chunks.extend_from_slice(&(profile.len() as u32).to_le_bytes());
+    chunks.extend_from_slice(profile);
+    if profile.len() % 2 == 1 {
+        chunks.push(0);
+    }
+    chunks.extend_from_slice(rest);
+
+    let mut result = Vec::with_capacity(12 + chunks.len());
+    result.extend_from_slice(b"RIFF");
+    result.extend_from_slice(&(4 + chunks.len() as u32).to_le_bytes());
+    result.extend_from_slice(b"WEBP");
+    result.extend_from_slice(&chunks);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{encode, fixtures, keep_color_profile, png, ExtractOptions, SupportedImages};
+    use image::{load_from_memory, DynamicImage};
+
+    /// Returns the image of the single entry of `icon_dir`, converted with `options` and with
+    /// its color profile kept.
+    fn convert(icon_dir: &ico::IconDir, options: &ExtractOptions) -> Vec<u8> {
+        let image = DynamicImage::ImageRgba8(fixtures::gradient(16, 16));
+        keep_color_profile(icon_dir, 0, encode(&image, options).unwrap(), options)
+    }
+
+    fn options(format: SupportedImages) -> ExtractOptions {
+        ExtractOptions::new()
+            .format(format)
+            .keep_icc(true)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn png_profile_decompresses_iccp_chunk() {
+        let image = fixtures::gradient(16, 16);
+        let data = fixtures::png_with_profile(&image);
+        assert_eq!(png_profile(&data).as_deref(), Some(fixtures::PROFILE));
+        assert_eq!(png_profile(&fixtures::png_data(&image)), None);
+    }
+
+    #[test]
+    fn keep_icc_copies_color_chunks_to_png() {
+        let image = fixtures::gradient(16, 16);
+        let source = fixtures::png_with_profile(&image);
+        let icon_dir = fixtures::icon_dir(&[(16, source.clone())]);
+
+        let png = convert(&icon_dir, &options(SupportedImages::Png));
+        assert_eq!(png::color_chunks(&png), png::color_chunks(&source));
+        assert_eq!(png_profile(&png).as_deref(), Some(fixtures::PROFILE));
+        assert_eq!(load_from_memory(&png).unwrap().to_rgba8(), image);
+
+        // without --keep-icc, the image is untagged
+        let options = ExtractOptions::new().build().unwrap();
+        let png = convert(&icon_dir, &options);
+        assert!(png::color_chunks(&png).is_empty());
+    }
+
+    #[test]
+    fn keep_icc_embeds_profile_in_jpeg() {
+        let source = fixtures::png_with_profile(&fixtures::gradient(16, 16));
+        let icon_dir = fixtures::icon_dir(&[(16, source)]);
+        let jpeg = convert(&icon_dir, &options(SupportedImages::Jpeg));
+
+        let start = xmp::jpeg_header_len(&jpeg);
+        assert_eq!(jpeg[start..start + 2], APP2);
+        let len = u16::from_be_bytes([jpeg[start + 2], jpeg[start + 3]]) as usize;
+        let segment = &jpeg[start + 4..start + 2 + len];
+        assert_eq!(&segment[..JPEG_ICC_ID.len()], JPEG_ICC_ID);
+        assert_eq!(segment[JPEG_ICC_ID.len()..JPEG_ICC_ID.len() + 2], [1, 1]);
+        assert_eq!(&segment[JPEG_ICC_ID.len() + 2..], fixtures::PROFILE);
+        assert!(load_from_memory(&jpeg).is_ok());
+    }
+
+    #[test]
+    fn embed_jpeg_splits_large_profiles() {
+        let profile = vec![7; JPEG_ICC_PART_LEN + 10];
+        let jpeg = embed_jpeg(vec![0xFF, 0xD8, 0xFF, 0xD9], &profile);
+
+        let second = 2 + 2 + 2 + JPEG_ICC_ID.len() + 2 + JPEG_ICC_PART_LEN;
+        assert_eq!(jpeg[2..4], APP2);
+        assert_eq!(jpeg[4 + 2 + JPEG_ICC_ID.len()..][..2], [1, 2]);
+        assert_eq!(jpeg[second..second + 2], APP2);
+        assert_eq!(jpeg[second + 4 + JPEG_ICC_ID.len()..][..2], [2, 2]);
+        assert_eq!(jpeg[jpeg.len() - 2..], [0xFF, 0xD9]);
+        assert_eq!(
+            jpeg.len(),
+            4 + 2 * (4 + JPEG_ICC_ID.len() + 2) + profile.len()
+        );
+    }
+
+    #[test]
+    fn keep_icc_embeds_profile_in_webp() {
+        let image = fixtures::gradient(16, 16);
+        let source = fixtures::png_with_profile(&image);
+        let icon_dir = fixtures::icon_dir(&[(16, source)]);
+        let webp = convert(&icon_dir, &options(SupportedImages::WebP));
+
+        assert_eq!(&webp[12..16], b"VP8X");
+        assert_eq!(webp[20], VP8X_ICC | VP8X_ALPHA);
+        // canvas size minus one, on 24 bits
+        assert_eq!(webp[24..30], [15, 0, 0, 15, 0, 0]);
+        assert_eq!(&webp[30..34], b"ICCP");
+        let len = u32::from_le_bytes([webp[34], webp[35], webp[36], webp[37]]) as usize;
+        assert_eq!(&webp[38..38 + len], fixtures::PROFILE);
+        let riff_len = u32::from_le_bytes([webp[4], webp[5], webp[6], webp[7]]) as usize;
+        assert_eq!(riff_len, webp.len() - 8);
+        assert_eq!(load_from_memory(&webp).unwrap().to_rgba8(), image);
+    }
+
+    #[test]
+    fn keep_icc_without_profile_changes_nothing() {
+        let image = fixtures::gradient(16, 16);
+        let icon_dir = fixtures::icon_dir(&[(16, fixtures::png_data(&image))]);
+        let dynamic = DynamicImage::ImageRgba8(image);
+        for format in [SupportedImages::Jpeg, SupportedImages::WebP] {
+            let options = options(format);
+            assert_eq!(
+                convert(&icon_dir, &options),
+                encode(&dynamic, &options).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn keep_icc_rejects_bmp() {
+        let options = ExtractOptions::new()
+            .format(SupportedImages::Bmp)
+            .keep_icc(true)
+            .build();
+        assert!(options.is_err());
+    }
+}
@@ -0,0 +1,346 @@
+//! Support for ICL icon libraries.
+//!
+//! ICL files are 16-bit NE executables whose resources are icons. Only the parts of the format
+//! needed to find the `RT_GROUP_ICON` and `RT_ICON` resources are parsed: the NE header, the
+//! resource table and the icon group structures. Each icon group is turned back into an ICO file,
+//! so that it can go through the same pipeline as regular ICO files.
+
+use anyhow::{anyhow, Result};
+use std::{fmt, fs, path::Path};
+
+const RT_ICON: u16 = 0x8003;
+const RT_GROUP_ICON: u16 = 0x800E;
+
+/// The ID of a resource, which is either an integer or a name.
+#[derive(Clone)]
+pub enum ResourceId {
+    Integer(u16),
+    Name(String),
+}
+
+impl fmt::Display for ResourceId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ResourceId::Integer(id) => write!(f, "{}", id),
+            ResourceId::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// An icon group in an ICL file.
+pub struct IconGroup {
+    /// The resource ID of the group.
+    pub id: ResourceId,
+    /// The number of icons in the group.
+    pub len: usize,
+    /// The group, rebuilt as an ICO file.
+    ico: Vec<u8>,
+}
+
+impl IconGroup {
+    /// Returns the group as the bytes of an ICO file.
+    pub fn into_ico(self) -> Vec<u8> {
+        self.ico
+    }
+}
+
+struct Resource<'a> {
+    type_id: u16,
+    id: ResourceId,
+    data: &'a [u8],
+}
+
+/// Returns whether the file at `path` is an ICL file, judging from its extension.
+pub fn is_icl(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("icl"))
+}
+
+/// Arguments:
+///   - path: The path to the ICL file.
+///
+/// Returns the icon groups in the ICL file, in resource table order.
+pub fn read_icon_groups(path: &Path) -> Result<Vec<IconGroup>> {
+    let data = fs::read(path)?;
+    let resources = read_resources(&data)?;
+    let icons: Vec<&Resource> = resources.iter().filter(|r| r.type_id == RT_ICON).collect();
+
+    resources
+        .iter()
+        .filter(|r| r.type_id == RT_GROUP_ICON)
+        .map(|group| {
+            let len = read_u16(group.data, 4)? as usize;
+            Ok(IconGroup {
+                id: group.id.clone(),
+                len,
+                ico: build_ico(group.data, len, &icons)?,
+            })
+        })
+        .collect()
+}
+
+/// Reads the icon and icon group resources from the resource table of an NE executable.
+fn read_resources(data: &[u8]) -> Result<Vec<Resource<'_>>> {
+    if !data.starts_with(b"MZ") {
+        return Err(anyhow!("Invalid ICL file: missing MZ header."));
+    }
+
+    let ne_header = read_u32(data, 0x3C)? as usize;
+    if !data
+        .get(ne_header..)
+        .is_some_and(|header| header.starts_with(b"NE"))
+    {
+        return Err(anyhow!("Invalid ICL file: missing NE header."));
+    }
+
+    let table = ne_header + read_u16(data, ne_header + 0x24)? as usize;
+    let shift = read_u16(data, table)?;
+    if shift >= 16 {
+        return Err(anyhow!("Invalid ICL file: bad resource alignment {}.", shift));
+    }
+
+    let mut resources = Vec::new();
+    let mut pos = table + 2;
+    loop {
+        let type_id = read_u16(data, pos)?;
+        if type_id == 0 {
+            break;
+        }
+
+        let count = read_u16(data, pos + 2)?;
+        pos += 8;
+
+        for _ in 0..count {
+            let offset = (read_u16(data, pos)? as usize) << shift;
+            let length = (read_u16(data, pos + 2)? as usize) << shift;
+            let id = read_u16(data, pos + 6)?;
+            pos += 12;
+
+            if type_id != RT_ICON && type_id != RT_GROUP_ICON {
+                continue;
+            }
+
+            let id = if id & 0x8000 != 0 {
+                ResourceId::Integer(id & 0x7FFF)
+            } else {
+                ResourceId::Name(read_name(data, table + id as usize)?)
+            };
+            let bytes = data
+                .get(offset..offset + length)
+                .ok_or_else(|| anyhow!("Invalid ICL file: resource {} is out of bounds.", id))?;
+
+            resources.push(Resource {
+                type_id,
+                id,
+                data: bytes,
+            });
+        }
+    }
+
+    Ok(resources)
+}
+
+/// Arguments:
+///   - group: The data of the `RT_GROUP_ICON` resource.
+///   - len: The number of icons in the group.
+///   - icons: The `RT_ICON` resources of the ICL file.
+///
+/// Returns the bytes of an ICO file containing the icons of the group.
+fn build_ico(group: &[u8], len: usize, icons: &[&Resource]) -> Result<Vec<u8>> {
+    let mut header = Vec::with_capacity(6 + 16 * len);
+    header.extend_from_slice(&[0, 0, 1, 0]);
+    header.extend_from_slice(&(len as u16).to_le_bytes());
+
+    let mut images = Vec::new();
+    for index in 0..len {
+        let entry = 6 + 14 * index;
+        let fields = group
+            .get(entry..entry + 8)
+            .ok_or_else(|| anyhow!("Invalid ICL file: truncated icon group."))?;
+        let size = read_u32(group, entry + 8)? as usize;
+        let icon_id = read_u16(group, entry + 12)?;
+
+        let icon = icons
+            .iter()
+            .find(|icon| matches!(icon.id, ResourceId::Integer(id) if id == icon_id))
+            .ok_or_else(|| anyhow!("Invalid ICL file: icon {} is missing.", icon_id))?;
+        let data = &icon.data[..size.min(icon.data.len())];
+
+        // width, height, color count, reserved, planes and bit count are stored the same way in
+        // both formats, only the last field differs (icon ID vs. data offset)
+        header.extend_from_slice(fields);
+        header.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        header.extend_from_slice(&((6 + 16 * len + images.len()) as u32).to_le_bytes());
+        images.extend_from_slice(data);
+    }
+
+    header.extend_from_slice(&images);
+    Ok(header)
+}
+
+fn read_name(data: &[u8], offset: usize) -> Result<String> {
+    let len = data
+        .get(offset)
+        .map(|&len| len as usize)
+        .ok_or_else(|| anyhow!("Invalid ICL file: resource name is out of bounds."))?;
+    let name = data
+        .get(offset + 1..offset + 1 + len)
+        .ok_or_else(|| anyhow!("Invalid ICL file: resource name is out of bounds."))?;
+    Ok(String::from_utf8_lossy(name).into_owned())
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        .ok_or_else(|| anyhow!("Invalid ICL file: unexpected end of data."))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .ok_or_else(|| anyhow!("Invalid ICL file: unexpected end of data."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ico::{IconDir, IconDirEntry, IconImage, ResourceType};
+    use std::{env, io::Cursor};
+
+    /// Offset of the NE header, and of the resource table from it.
+    const NE_HEADER: usize = 0x40;
+    const TABLE: usize = 0x40;
+
+    /// Returns an ICL file holding `icons` as `RT_ICON` resources 1, 2 and so on, and one
+    /// `RT_GROUP_ICON` resource per group, each listing the icons it holds by index.
+    fn icl(icons: &[Vec<u8>], groups: &[(ResourceId, Vec<usize>)]) -> Vec<u8> {
+        let mut data = vec![0; NE_HEADER];
+        data[..2].copy_from_slice(b"MZ");
+        data[0x3C..0x40].copy_from_slice(&(NE_HEADER as u32).to_le_bytes());
+        data.extend_from_slice(b"NE");
+        data.resize(NE_HEADER + 0x40, 0);
+        data[NE_HEADER + 0x24..NE_HEADER + 0x26].copy_from_slice(&(TABLE as u16).to_le_bytes());
+        let table = NE_HEADER + TABLE;
+
+        let group_data: Vec<Vec<u8>> = groups
+            .iter()
+            .map(|(_, members)| {
+                let mut group = vec![0, 0, 1, 0];
+                group.extend_from_slice(&(members.len() as u16).to_le_bytes());
+                for &index in members {
+                    let icon = &icons[index];
+                    // width, height, color count, reserved, planes and bit count, taken from
+                    // the BMP header of the icon
+                    group.extend_from_slice(&[icon[4], icon[8] / 2, 0, 0, 1, 0]);
+                    group.extend_from_slice(&icon[14..16]);
+                    group.extend_from_slice(&(icon.len() as u32).to_le_bytes());
+                    group.extend_from_slice(&(index as u16 + 1).to_le_bytes());
+                }
+                group
+            })
+            .collect();
+
+        // alignment shift, two type entries of 8 bytes, 12 bytes per resource and the terminator
+        let names_offset = 2 + 2 * 8 + 12 * (icons.len() + groups.len()) + 2;
+        let mut names = Vec::new();
+        let mut resources_offset = table + names_offset;
+        for (id, _) in groups {
+            if let ResourceId::Name(name) = id {
+                resources_offset += 1 + name.len();
+            }
+        }
+
+        let mut table_data = 0u16.to_le_bytes().to_vec();
+        let mut offset = resources_offset;
+        let mut resources = Vec::new();
+        let mut entry = |table_data: &mut Vec<u8>, bytes: &[u8], id: u16| {
+            table_data.extend_from_slice(&(offset as u16).to_le_bytes());
+            table_data.extend_from_slice(&(bytes.len() as u16).to_le_bytes());
+            table_data.extend_from_slice(&[0, 0]);
+            table_data.extend_from_slice(&id.to_le_bytes());
+            table_data.extend_from_slice(&[0; 4]);
+            offset += bytes.len();
+            resources.extend_from_slice(bytes);
+        };
+
+        table_data.extend_from_slice(&RT_ICON.to_le_bytes());
+        table_data.extend_from_slice(&(icons.len() as u16).to_le_bytes());
+        table_data.extend_from_slice(&[0; 4]);
+        for (index, icon) in icons.iter().enumerate() {
+            entry(&mut table_data, icon, 0x8000 | (index as u16 + 1));
+        }
+        table_data.extend_from_slice(&RT_GROUP_ICON.to_le_bytes());
+        table_data.extend_from_slice(&(groups.len() as u16).to_le_bytes());
+        table_data.extend_from_slice(&[0; 4]);
+        for ((id, _), group) in groups.iter().zip(&group_data) {
+            let id = match id {
+                ResourceId::Integer(id) => 0x8000 | id,
+                ResourceId::Name(name) => {
+                    let id = (names_offset + names.len()) as u16;
+                    names.push(name.len() as u8);
+                    names.extend_from_slice(name.as_bytes());
+                    id
+                }
+            };
+            entry(&mut table_data, group, id);
+        }
+        table_data.extend_from_slice(&[0, 0]);
+
+        data.extend_from_slice(&table_data);
+        data.extend_from_slice(&names);
+        data.extend_from_slice(&resources);
+        data
+    }
+
+    fn icon(size: u32) -> (Vec<u8>, Vec<u8>) {
+        let rgba: Vec<u8> = (0..size * size)
+            .flat_map(|i| [i as u8, (i * 3) as u8, size as u8, 255])
+            .collect();
+        let image = IconImage::from_rgba_data(size, size, rgba.clone());
+        (IconDirEntry::encode_as_bmp(&image).unwrap().data().to_vec(), rgba)
+    }
+
+    #[test]
+    fn read_icon_groups_rebuilds_ico_files() {
+        let (small, small_rgba) = icon(16);
+        let (large, large_rgba) = icon(32);
+        let data = icl(
+            &[small, large],
+            &[
+                (ResourceId::Integer(5), vec![0, 1]),
+                (ResourceId::Name("APP".to_string()), vec![1]),
+            ],
+        );
+        let path = env::temp_dir().join(format!("ico2img-icl-{}.icl", std::process::id()));
+        fs::write(&path, data).unwrap();
+        let groups = read_icon_groups(&path);
+        fs::remove_file(&path).unwrap();
+        let groups = groups.unwrap();
+
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].id.to_string(), "5");
+        assert_eq!(groups[0].len, 2);
+        assert_eq!(groups[1].id.to_string(), "APP");
+        assert_eq!(groups[1].len, 1);
+
+        let expected = [vec![small_rgba, large_rgba.clone()], vec![large_rgba]];
+        for (group, expected) in groups.into_iter().zip(expected) {
+            let ico = IconDir::read(Cursor::new(group.into_ico())).unwrap();
+            assert_eq!(ico.resource_type(), ResourceType::Icon);
+            let pixels: Vec<Vec<u8>> = ico
+                .entries()
+                .iter()
+                .map(|entry| entry.decode().unwrap().rgba_data().to_vec())
+                .collect();
+            assert_eq!(pixels, expected);
+        }
+    }
+
+    #[test]
+    fn read_icon_groups_rejects_other_files() {
+        let path = env::temp_dir().join(format!("ico2img-not-icl-{}.icl", std::process::id()));
+        fs::write(&path, b"MZ not an executable").unwrap();
+        let error = read_icon_groups(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(error.is_err());
+    }
+}
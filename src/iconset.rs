@@ -0,0 +1,22 @@
+//! The layout of Apple `.iconset` directories, which `iconutil` turns into ICNS files.
+
+/// The images of an iconset, as their name without extension and their width and height in
+/// pixels. `@2x` images are twice the size of the point size in their name.
+pub const SLOTS: [(&str, u32); 10] = [
+    ("icon_16x16", 16),
+    ("icon_16x16@2x", 32),
+    ("icon_32x32", 32),
+    ("icon_32x32@2x", 64),
+    ("icon_128x128", 128),
+    ("icon_128x128@2x", 256),
+    ("icon_256x256", 256),
+    ("icon_256x256@2x", 512),
+    ("icon_512x512", 512),
+    ("icon_512x512@2x", 1024),
+];
+
+/// Returns whether an image of `size`, as `(width, height)`, fits an image of the iconset
+/// without being resized.
+pub fn has_slot((width, height): (u32, u32)) -> bool {
+    width == height && SLOTS.iter().any(|&(_, size)| size == width)
+}
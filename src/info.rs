@@ -0,0 +1,260 @@
+//! Metadata of ICO files, read from the directory alone, without decoding any image.
+
+use crate::{error::Result, png::PNG_SIGNATURE, Ico2imgError};
+use ico::ResourceType;
+use std::{
+    fmt,
+    fs::File,
+    io::{BufReader, ErrorKind, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Maximum width and height of an entry allowed by the ICO format.
+const MAX_ENTRY_SIZE: u32 = 256;
+
+/// How the image data of an entry is stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[non_exhaustive]
+pub enum EncodingType {
+    Png,
+    Bmp,
+}
+
+impl fmt::Display for EncodingType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EncodingType::Png => write!(f, "PNG"),
+            EncodingType::Bmp => write!(f, "BMP"),
+        }
+    }
+}
+
+/// Metadata of an entry, as recorded in the ICO directory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub struct EntryInfo {
+    /// Width of the image, read from its data, or the declared one if the data can't be read.
+    pub width: u32,
+    /// Height of the image, read from its data, or the declared one if the data can't be read.
+    pub height: u32,
+    /// Width declared in the ICO directory, which may not match the image.
+    pub declared_width: u32,
+    /// Height declared in the ICO directory, which may not match the image.
+    pub declared_height: u32,
+    pub bits_per_pixel: u16,
+    pub encoding_type: EncodingType,
+    /// Offset of the image data from the start of the file.
+    pub raw_data_offset: u32,
+    /// Size of the image data, in bytes.
+    pub raw_data_size: u32,
+    /// The first 8 bytes of the image data (fewer if it's shorter), which identify how it's
+    /// stored: the PNG signature, or the size of the BMP header.
+    pub first_bytes: Vec<u8>,
+    /// Whether the PNG data has an embedded ICC profile (an `iCCP` chunk).
+    pub has_icc_profile: bool,
+    /// Whether the PNG data is marked as sRGB (an `sRGB` chunk).
+    pub has_srgb: bool,
+}
+
+/// Metadata of an ICO file, read without decoding any image.
+///
+/// ```no_run
+/// use ico2img::IcoInfo;
+/// use std::path::Path;
+///
+/// let info = IcoInfo::from_path(Path::new("favicon.ico")).unwrap();
+/// for entry in &info.entries {
+///     println!("{}x{} ({})", entry.width, entry.height, entry.encoding_type);
+///
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub struct IcoInfo {
+    pub entries: Vec<EntryInfo>,
+    pub file_size: u64,
+    #[cfg_attr(feature = "serde", serde(with = "resource_type"))]
+    pub resource_type: ResourceType,
+    /// Whether the data of every entry lies within the file.
+    pub is_valid: bool,
+}
+
+impl IcoInfo {
+    /// Reads the metadata of the ICO file at `path`.
+    pub fn from_path(path: &Path) -> Result<IcoInfo> {
+        let file = File::open(path).map_err(|e| match e.kind() {
+            ErrorKind::NotFound => Ico2imgError::FileNotFound(path.to_path_buf()),
+            _ => Ico2imgError::Io(e),
+        })?;
+        IcoInfo::from_reader(BufReader::new(file))
+    }
+
+    /// Reads the metadata of an ICO file. Only the header, the directory, the first bytes of
+    /// each entry's data and the chunk headers of PNG data are read.
+    pub fn from_reader<R: Read + Seek>(mut reader: R) -> Result<IcoInfo> {
+        let file_size = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+
+        let mut header = [0; 6];
+        reader.read_exact(&mut header)?;
+        if u16::from_le_bytes([header[0], header[1]]) != 0 {
+            return Err(Ico2imgError::IcoParseError(
+                "Invalid ICO header.".to_string(),
+            ));
+        }
+        let resource_type = match u16::from_le_bytes([header[2], header[3]]) {
+            1 => ResourceType::Icon,
+            2 => ResourceType::Cursor,
+            other => {
+                return Err(Ico2imgError::IcoParseError(format!(
+                    "Invalid resource type {}.",
+                    other
+                )))
+            }
+        };
+
+        let count = u16::from_le_bytes([header[4], header[5]]);
+        let mut records = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let mut record = [0; 16];
+            reader.read_exact(&mut record)?;
+            records.push(record);
+        }
+
+        let mut is_valid = true;
+        let mut entries = Vec::with_capacity(records.len());
+        for record in records {
+            let raw_data_size = u32::from_le_bytes([record[8], record[9], record[10], record[11]]);
+            let raw_data_offset =
+                u32::from_le_bytes([record[12], record[13], record[14], record[15]]);
+
+            let in_bounds = raw_data_offset as u64 + raw_data_size as u64 <= file_size;
+            is_valid &= in_bounds;
+
+            let mut data_header = [0; 24];
+            let data_header = if in_bounds {
+                let len = (raw_data_size as usize).min(data_header.len());
+                reader.seek(SeekFrom::Start(raw_data_offset as u64))?;
+                reader.read_exact(&mut data_header[..len])?;
+                &data_header[..len]
+            } else {
+                &data_header[..0]
+            };
+            let encoding_type = if data_header.starts_with(&PNG_SIGNATURE) {
+                EncodingType::Png
+            } else {
+                EncodingType::Bmp
+            };
+
+            // a size of 0 stands for 256 pixels
+            let declared_width = if record[0] == 0 { 256 } else { record[0] as u32 };
+            let declared_height = if record[1] == 0 { 256 } else { record[1] as u32 };
+            let (width, height) =
+                image_size(data_header).unwrap_or((declared_width, declared_height));
+
+            let (mut has_icc_profile, mut has_srgb) = (false, false);
+            if encoding_type == EncodingType::Png {
+                let end = raw_data_offset as u64 + raw_data_size as u64;
+                let mut pos = raw_data_offset as u64 + 8;
+                let mut header = [0; 8];
+                // only the chunks before the image data describe its color space
+                while pos + 8 <= end {
+                    reader.seek(SeekFrom::Start(pos))?;
+                    reader.read_exact(&mut header)?;
+                    match &header[4..8] {
+                        b"IDAT" => break,
+                        b"iCCP" => has_icc_profile = true,
+                        b"sRGB" => has_srgb = true,
+                        _ => {}
+                    }
+                    let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+                    pos += 12 + len as u64;
+                }
+            }
+
+            entries.push(EntryInfo {
+                width,
+                height,
+                declared_width,
+                declared_height,
+                bits_per_pixel: u16::from_le_bytes([record[6], record[7]]),
+                encoding_type,
+                raw_data_offset,
+                raw_data_size,
+                first_bytes: data_header[..data_header.len().min(8)].to_vec(),
+                has_icc_profile,
+                has_srgb,
+            });
+        }
+
+        Ok(IcoInfo {
+            entries,
+            file_size,
+            resource_type,
+            is_valid,
+        })
+    }
+}
+
+impl EntryInfo {
+    /// Returns whether the size declared in the ICO directory differs from the size of the
+    /// image.
+    pub fn has_size_mismatch(&self) -> bool {
+        (self.width, self.height) != (self.declared_width, self.declared_height)
+    }
+
+    /// Returns whether the image is larger than the 256x256 pixels allowed by the ICO format.
+    /// Such entries are written by some favicon generators, and can still be converted.
+    pub fn is_oversize(&self) -> bool {
+        self.width > MAX_ENTRY_SIZE || self.height > MAX_ENTRY_SIZE
+    }
+}
+
+/// Returns the size of the image stored in an entry, read from the first bytes of its data: the
+/// header chunk of PNG data, or the bitmap header of BMP data. Returns `None` if the data is too
+/// short or the size is invalid.
+pub(crate) fn image_size(data: &[u8]) -> Option<(u32, u32)> {
+    let (width, height) = if data.starts_with(&PNG_SIGNATURE) {
+        let field = |i: usize| Some(u32::from_be_bytes(data.get(i..i + 4)?.try_into().ok()?));
+        (field(16)?, field(20)?)
+    } else {
+        let field = |i: usize| Some(i32::from_le_bytes(data.get(i..i + 4)?.try_into().ok()?));
+        // the height of BMP data covers both the color bitmap and the mask
+        (field(4)?.unsigned_abs(), field(8)?.unsigned_abs() / 2)
+    };
+
+    (width > 0 && height > 0).then_some((width, height))
+}
+
+#[cfg(feature = "serde")]
+mod resource_type {
+    use ico::ResourceType;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        value: &ResourceType,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(match value {
+            ResourceType::Icon => "icon",
+            ResourceType::Cursor => "cursor",
+        })
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<ResourceType, D::Error> {
+        match String::deserialize(deserializer)?.as_str() {
+            "icon" => Ok(ResourceType::Icon),
+            "cursor" => Ok(ResourceType::Cursor),
+            other => Err(D::Error::unknown_variant(other, &["icon", "cursor"])),
+        }
+    }
+}
@@ -0,0 +1,187 @@
+//! Inventory of the ICO files of a tree, for `--info --summary`: the sizes each file contains,
+//! whether it has 256x256 art, and totals.
+
+use ico2img::IcoInfo;
+use serde_json::{json, Value};
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+};
+
+/// Columns of the CSV report, in order.
+const CSV_HEADER: &str = "path,entries,sizes,has_256,bytes,error";
+
+/// The row of a file in the inventory.
+pub struct FileReport {
+    path: PathBuf,
+    /// The metadata of the file, or why it couldn't be read.
+    info: Result<IcoInfo, String>,
+}
+
+impl FileReport {
+    /// Returns the sizes of the entries, as `WxH`, in the order of the directory.
+    fn sizes(&self) -> Vec<String> {
+        match self.info {
+            Ok(ref info) => info
+                .entries
+                .iter()
+                .map(|entry| format!("{}x{}", entry.width, entry.height))
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Returns whether the file has an entry of 256x256 pixels (or larger).
+    fn has_256(&self) -> bool {
+        self.info.as_ref().is_ok_and(|info| {
+            info.entries
+                .iter()
+                .any(|entry| entry.width >= 256 && entry.height >= 256)
+        })
+    }
+
+    fn entry_count(&self) -> usize {
+        self.info.as_ref().map_or(0, |info| info.entries.len())
+    }
+
+    fn file_size(&self) -> u64 {
+        self.info.as_ref().map_or(0, |info| info.file_size)
+    }
+}
+
+/// Totals of the inventory.
+struct Totals {
+    files: usize,
+    unreadable: usize,
+    entries: usize,
+    missing_256: usize,
+    bytes: u64,
+}
+
+impl Totals {
+    fn of(reports: &[FileReport]) -> Totals {
+        let readable = reports.iter().filter(|report| report.info.is_ok());
+        Totals {
+            files: reports.len(),
+            unreadable: reports.iter().filter(|report| report.info.is_err()).count(),
+            entries: reports.iter().map(FileReport::entry_count).sum(),
+            missing_256: readable.filter(|report| !report.has_256()).count(),
+            bytes: reports.iter().map(FileReport::file_size).sum(),
+        }
+    }
+}
+
+/// Arguments:
+///   - files: The ICO files to report on.
+///
+/// Returns the report of each file. Files that can't be read are reported with the error.
+pub fn scan(files: Vec<PathBuf>) -> Vec<FileReport> {
+    files
+        .into_iter()
+        .map(|path| {
+            let info = IcoInfo::from_path(&path).map_err(|e| e.to_string());
+            FileReport { path, info }
+        })
+        .collect()
+}
+
+/// Prints the inventory as a table, followed by the totals.
+pub fn print_table(reports: &[FileReport]) {
+    let width = reports
+        .iter()
+        .map(|report| report.path.display().to_string().len())
+        .max()
+        .unwrap_or(0)
+        .max("Path".len());
+
+    println!("{:width$}  {:>7}  {:>4}  {:>10}  Sizes", "Path", "Entries", "256", "Bytes");
+    for report in reports {
+        let path = report.path.display().to_string();
+        match report.info {
+            Ok(_) => println!(
+                "{:width$}  {:>7}  {:>4}  {:>10}  {}",
+                path,
+                report.entry_count(),
+                if report.has_256() { "yes" } else { "no" },
+                report.file_size(),
+                report.sizes().join(" ")
+            ),
+            Err(ref e) => println!("{:width$}  error: {}", path, e),
+        }
+    }
+
+    let totals = Totals::of(reports);
+    println!();
+    println!(
+        "{} files ({} unreadable), {} entries, {} bytes",
+        totals.files, totals.unreadable, totals.entries, totals.bytes
+    );
+    println!("{} files without 256x256 art", totals.missing_256);
+}
+
+/// Writes the inventory as CSV, with a header row and a last row of totals, whose path is
+/// `(total)`. Sizes are separated by spaces.
+pub fn write_csv(reports: &[FileReport], mut writer: impl Write) -> io::Result<()> {
+    writeln!(writer, "{}", CSV_HEADER)?;
+    for report in reports {
+        let (has_256, error) = match report.info {
+            Ok(_) => (report.has_256().to_string(), ""),
+            Err(ref e) => (String::new(), e.as_str()),
+        };
+        writeln!(
+            writer,
+            "{},{},{},{},{},{}",
+            csv_field(&report.path.display().to_string()),
+            report.entry_count(),
+            report.sizes().join(" "),
+            has_256,
+            report.file_size(),
+            csv_field(error)
+        )?;
+    }
+
+    let totals = Totals::of(reports);
+    writeln!(writer, "(total),{},,,{},", totals.entries, totals.bytes)
+}
+
+/// Returns the inventory as JSON, with the report of each file and the totals.
+pub fn to_json(reports: &[FileReport]) -> Value {
+    let files: Vec<Value> = reports
+        .iter()
+        .map(|report| match report.info {
+            Ok(_) => json!({
+                "path": report.path.display().to_string(),
+                "entries": report.entry_count(),
+                "sizes": report.sizes(),
+                "has_256": report.has_256(),
+                "bytes": report.file_size(),
+            }),
+            Err(ref e) => json!({
+                "path": report.path.display().to_string(),
+                "error": e,
+            }),
+        })
+        .collect();
+
+    let totals = Totals::of(reports);
+    json!({
+        "files": files,
+        "totals": {
+            "files": totals.files,
+            "unreadable": totals.unreadable,
+            "entries": totals.entries,
+            "missing_256": totals.missing_256,
+            "bytes": totals.bytes,
+        },
+    })
+}
+
+/// Quotes a CSV field if it contains a comma, a quote or a line break, doubling its quotes, as
+/// RFC 4180 does.
+pub fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
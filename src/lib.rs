@@ -0,0 +1,1458 @@
+//! Conversion of ICO entries to other image formats.
+//!
+//! The conversion of an entry is configured with [`ExtractOptions`], built by chaining its
+//! methods and validated by [`ExtractOptions::build`]. Every option has a default, so only the
+//! ones that differ from it need to be set:
+//!
+//! ```no_run
+//! use ico::IconDir;
+//! use ico2img::{Color, ExtractOptions, FilterType, SupportedImages};
+//! use std::fs::{self, File};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let icon_dir = IconDir::read(File::open("favicon.ico")?)?;
+//! let options = ExtractOptions::new()
+//!     .format(SupportedImages::Jpeg)
+//!     .resize(256, 256)
+//!     .filter(FilterType::Lanczos3)
+//!     .jpeg_quality(90)
+//!     .background(Color::WHITE)
+//!     .build()?;
+//!
+//! let image = ico2img::extract(&icon_dir, 0, &options)?;
+//! fs::write("favicon.jpg", image)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`extract`] decodes, transforms and encodes an entry in one go, or copies it as it is when
+//! it's stored as PNG and converted to PNG without any change (see [`stored_png`]).
+//! [`decode_entry`], [`transform`] and [`encode`] perform each step separately, for when the
+//! intermediate image is needed. [`ExtractionPlan`] converts every entry of an ICO file in two
+//! phases, planning what to write where before writing anything, and
+//! [`validate_extraction_output`] checks the images it wrote in a previous run.
+//! [`concat_images_horizontal`] and [`concat_images_vertical`] join converted images into one.
+//!
+//! [`IcoInfo`] reads the metadata of an ICO file without decoding any image. [`AlphaStats`]
+//! describes the transparency of a decoded one.
+//!
+//! # Errors
+//!
+//! Fallible functions return an [`Ico2imgError`], whose variants tell apart a missing file, an
+//! invalid ICO file, an out-of-bounds index, a decoding or encoding failure and an invalid
+//! option. It implements [`std::error::Error`], so it converts to `anyhow::Error` or
+//! `Box<dyn Error>` with `?`.
+//!
+//! # Stability
+//!
+//! The library follows semantic versioning, and its changes are listed in `CHANGELOG.md`.
+//! [`VERSION`] holds the version of the library in use. Enums and structs that may grow, such as
+//! [`SupportedImages`] or [`IcoInfo`], are marked `#[non_exhaustive]`, so adding a format or a
+//! field isn't a breaking change: match them with a wildcard arm, and don't build the structs
+//! yourself.
+//!
+//! Items are deprecated for at least one minor release before being removed, pointing to their
+//! replacement:
+//!
+//! ```ignore
+//! #[deprecated(since = "0.3.0", note = "use `ExtractOptions::get_format` instead")]
+//! pub fn format(&self) -> SupportedImages {
+//!     self.get_format()
+//! }
+//! ```
+
+use error::Result;
+use ico::{IconDir, IconDirEntry};
+use image::{codecs::jpeg::JpegEncoder, imageops, DynamicImage, ImageFormat, Rgba, RgbaImage};
+use std::{borrow::Cow, fmt, io::Cursor, str::FromStr};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+pub use image::imageops::FilterType;
+pub use adjust::Tint;
+pub use align::{align_to_anchor, find_content_bbox};
+pub use alpha::AlphaStats;
+pub use bmp_format::BmpFormat;
+pub use concat::{concat_images_horizontal, concat_images_vertical};
+pub use duplicates::find_duplicate_entries;
+pub use error::Ico2imgError;
+pub use info::{EncodingType, EntryInfo, IcoInfo};
+pub use palette::{Dither, Palette};
+pub use plan::{ExtractionPlan, ExtractionSummary, PlannedExtraction};
+pub use validate::{
+    psnr, validate_extraction_output, ValidationResult, ValidationStatus, MIN_LOSSY_PSNR,
+};
+
+mod adjust;
+mod align;
+mod alpha;
+mod bmp_format;
+mod concat;
+mod duplicates;
+mod error;
+#[cfg(test)]
+mod fixtures;
+mod icc;
+mod info;
+mod palette;
+mod plan;
+mod png;
+mod validate;
+mod xmp;
+
+/// The version of the library.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Quality used for JPEG images when none is specified, matching the `image` crate's default.
+const DEFAULT_JPEG_QUALITY: u8 = 75;
+
+/// The image formats ICO entries can be converted to. WebP images are always encoded losslessly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[non_exhaustive]
+pub enum SupportedImages {
+    Png,
+    Jpeg,
+    Bmp,
+    WebP,
+}
+
+impl SupportedImages {
+    /// Every supported format.
+    pub const ALL: &'static [SupportedImages] = &[
+        SupportedImages::Png,
+        SupportedImages::Jpeg,
+        SupportedImages::Bmp,
+        SupportedImages::WebP,
+    ];
+
+    /// Returns the file extension used for images in this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            SupportedImages::Png => "png",
+            SupportedImages::Jpeg => "jpg",
+            SupportedImages::Bmp => "bmp",
+            SupportedImages::WebP => "webp",
+        }
+    }
+
+    /// Returns what images in this format can hold, and whether the format is available in this
+    /// build.
+    pub fn capabilities(self) -> FormatCapabilities {
+        // no format depends on a feature yet, they're all encoded by the `image` crate
+        let (lossy, alpha) = match self {
+            SupportedImages::Png => (false, true),
+            SupportedImages::Jpeg => (true, false),
+            SupportedImages::Bmp => (false, true),
+            SupportedImages::WebP => (false, true),
+        };
+        FormatCapabilities {
+            lossy,
+            alpha,
+            feature: None,
+            available: true,
+        }
+    }
+
+    fn image_format(self) -> ImageFormat {
+        match self {
+            SupportedImages::Png => ImageFormat::Png,
+            SupportedImages::Jpeg => ImageFormat::Jpeg,
+            SupportedImages::Bmp => ImageFormat::Bmp,
+            SupportedImages::WebP => ImageFormat::WebP,
+        }
+    }
+}
+
+/// What images in a format can hold, as returned by [`SupportedImages::capabilities`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct FormatCapabilities {
+    /// Whether encoding loses information, so the image differs from the entry.
+    pub lossy: bool,
+    /// Whether transparency is kept. Otherwise, the alpha channel is dropped, so transparent
+    /// images should be composited over a background first.
+    pub alpha: bool,
+    /// The Cargo feature the format requires, if any.
+    pub feature: Option<&'static str>,
+    /// Whether the format is compiled in.
+    pub available: bool,
+}
+
+impl FromStr for SupportedImages {
+    type Err = Ico2imgError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "png" => Ok(SupportedImages::Png),
+            "jpg" | "jpeg" => Ok(SupportedImages::Jpeg),
+            "bmp" => Ok(SupportedImages::Bmp),
+            "webp" => Ok(SupportedImages::WebP),
+            _ => Err(Ico2imgError::InvalidArgument(format!(
+                "The format {} is not supported at the moment. Feel free to contribute to add new formats.",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for SupportedImages {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
+
+/// An RGBA color, parsed from `RRGGBB` or `RRGGBBAA` hexadecimal notation, with an optional
+/// leading `#`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub const WHITE: Color = Color::rgb(255, 255, 255);
+    pub const BLACK: Color = Color::rgb(0, 0, 0);
+
+    /// Returns an opaque color.
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Color {
+        Color { r, g, b, a: 255 }
+    }
+}
+
+impl FromStr for Color {
+    type Err = Ico2imgError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+            return Err(Ico2imgError::InvalidArgument(format!(
+                "Invalid color {}, expected RRGGBB or RRGGBBAA.",
+                s
+            )));
+        }
+
+        let channel = |i: usize| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .map_err(|_| Ico2imgError::InvalidArgument(format!("Invalid color {}.", s)))
+        };
+        Ok(Color {
+            r: channel(0)?,
+            g: channel(2)?,
+            b: channel(4)?,
+            a: if hex.len() == 8 { channel(6)? } else { 255 },
+        })
+    }
+}
+
+impl From<Color> for Rgba<u8> {
+    fn from(color: Color) -> Self {
+        Rgba([color.r, color.g, color.b, color.a])
+    }
+}
+
+/// A rectangle of an image, parsed from `X,Y,WxH`: the position of its top-left corner, then
+/// its size, in pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    /// Returns whether the rectangle lies fully within an image of `width`x`height` pixels.
+    pub fn fits_in(&self, width: u32, height: u32) -> bool {
+        self.x as u64 + self.width as u64 <= width as u64
+            && self.y as u64 + self.height as u64 <= height as u64
+    }
+}
+
+impl FromStr for Rect {
+    type Err = Ico2imgError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || {
+            Ico2imgError::InvalidArgument(format!(
+                "Invalid rectangle {}, expected X,Y,WxH such as 0,0,32x32.",
+                s
+            ))
+        };
+        let mut parts = s.splitn(3, ',');
+        let (Some(x), Some(y), Some(size)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(invalid());
+        };
+        let (width, height) = size.split_once(['x', 'X']).ok_or_else(invalid)?;
+        let number = |part: &str| part.trim().parse::<u32>().map_err(|_| invalid());
+        Ok(Rect {
+            x: number(x)?,
+            y: number(y)?,
+            width: number(width)?,
+            height: number(height)?,
+        })
+    }
+}
+
+impl fmt::Display for Rect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{},{}x{}", self.x, self.y, self.width, self.height)
+    }
+}
+
+/// Where an image is placed on a larger (or smaller) canvas.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[non_exhaustive]
+pub enum Gravity {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    #[default]
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Gravity {
+    /// Returns the offset of an image of `size` placed on a canvas of `canvas_size`, for one
+    /// axis. `start` and `end` tell whether the image sticks to the start or the end of the axis.
+    fn offset(size: u32, canvas_size: u32, start: bool, end: bool) -> i64 {
+        let space = canvas_size as i64 - size as i64;
+        match (start, end) {
+            (true, _) => 0,
+            (_, true) => space,
+            _ => space / 2,
+        }
+    }
+
+    /// Returns the position of the top-left corner of an image of `size` pixels placed on a
+    /// canvas of `canvas_size` pixels, both as `(width, height)`.
+    fn position(self, size: (u32, u32), canvas_size: (u32, u32)) -> (i64, i64) {
+        use Gravity::*;
+
+        let left = matches!(self, TopLeft | Left | BottomLeft);
+        let right = matches!(self, TopRight | Right | BottomRight);
+        let top = matches!(self, TopLeft | Top | TopRight);
+        let bottom = matches!(self, BottomLeft | Bottom | BottomRight);
+        (
+            Gravity::offset(size.0, canvas_size.0, left, right),
+            Gravity::offset(size.1, canvas_size.1, top, bottom),
+        )
+    }
+}
+
+impl FromStr for Gravity {
+    type Err = Ico2imgError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "topleft" => Ok(Gravity::TopLeft),
+            "top" => Ok(Gravity::Top),
+            "topright" => Ok(Gravity::TopRight),
+            "left" => Ok(Gravity::Left),
+            "center" => Ok(Gravity::Center),
+            "right" => Ok(Gravity::Right),
+            "bottomleft" => Ok(Gravity::BottomLeft),
+            "bottom" => Ok(Gravity::Bottom),
+            "bottomright" => Ok(Gravity::BottomRight),
+            _ => Err(Ico2imgError::InvalidArgument(format!(
+                "Invalid gravity {}.",
+                s
+            ))),
+        }
+    }
+}
+
+/// The chroma subsampling of JPEG images: how many pixels share the same color information.
+/// 4:4:4 keeps the color of every pixel, preserving sharp colored edges at the cost of size.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub enum JpegSubsampling {
+    #[cfg_attr(feature = "serde", serde(rename = "444"))]
+    Yuv444,
+    #[cfg_attr(feature = "serde", serde(rename = "422"))]
+    Yuv422,
+    #[cfg_attr(feature = "serde", serde(rename = "420"))]
+    Yuv420,
+}
+
+impl FromStr for JpegSubsampling {
+    type Err = Ico2imgError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.replace(':', "").as_str() {
+            "444" => Ok(JpegSubsampling::Yuv444),
+            "422" => Ok(JpegSubsampling::Yuv422),
+            "420" => Ok(JpegSubsampling::Yuv420),
+            _ => Err(Ico2imgError::InvalidArgument(format!(
+                "Invalid JPEG subsampling {}, expected 444, 422 or 420.",
+                s
+            ))),
+        }
+    }
+}
+
+/// The channels of the converted image, set with [`ExtractOptions::channels`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[non_exhaustive]
+pub enum ChannelLayout {
+    /// Red, green, blue and alpha, with an opaque alpha added to images that have none.
+    Rgba,
+    /// Red, green and blue, with any transparency left flattened over black.
+    Rgb,
+}
+
+/// Options for converting an ICO entry.
+///
+/// Options are set by chaining methods from [`ExtractOptions::new`], then checked with
+/// [`ExtractOptions::build`]:
+///
+/// ```
+/// use ico2img::{ExtractOptions, SupportedImages};
+///
+/// let options = ExtractOptions::new()
+///     .format(SupportedImages::WebP)
+///     .resize(64, 64)
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(options.get_format(), SupportedImages::WebP);
+///
+/// // the JPEG quality only makes sense for JPEG images
+/// assert!(ExtractOptions::new().jpeg_quality(90).build().is_err());
+/// ```
+#[derive(Clone, Debug)]
+pub struct ExtractOptions {
+    format: SupportedImages,
+    crop: Option<Rect>,
+    auto_align: Option<Gravity>,
+    resize: Option<(u32, u32)>,
+    filter: FilterType,
+    jpeg_quality: Option<u8>,
+    jpeg_subsampling: Option<JpegSubsampling>,
+    background: Option<Color>,
+    canvas: Option<(u32, u32)>,
+    gravity: Gravity,
+    allow_crop: bool,
+    pad_to_power_of_two: Option<Gravity>,
+    keep_icc: bool,
+    png_indexed: bool,
+    bmp_depth: Option<u8>,
+    bmp_format: Option<BmpFormat>,
+    palette: Palette,
+    dither: Option<Dither>,
+    reencode: bool,
+    channels: Option<ChannelLayout>,
+    hue_shift: Option<f32>,
+    tint: Option<Tint>,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        ExtractOptions {
+            format: SupportedImages::Png,
+            crop: None,
+            auto_align: None,
+            resize: None,
+            filter: FilterType::Lanczos3,
+            jpeg_quality: None,
+            jpeg_subsampling: None,
+            background: None,
+            canvas: None,
+            gravity: Gravity::Center,
+            allow_crop: false,
+            pad_to_power_of_two: None,
+            keep_icc: false,
+            png_indexed: false,
+            bmp_depth: None,
+            bmp_format: None,
+            palette: Palette::Windows,
+            dither: None,
+            reencode: false,
+            channels: None,
+            hue_shift: None,
+            tint: None,
+        }
+    }
+}
+
+impl ExtractOptions {
+    /// Returns the default options: PNG output, with no resizing and no background.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the format of the resulting image (PNG by default).
+    #[must_use]
+    pub fn format(mut self, format: SupportedImages) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Crops the decoded image to `rect` before anything else, resizing included (no cropping by
+    /// default). Converting an image that the rectangle doesn't fit in fails.
+    #[must_use]
+    pub fn crop(mut self, rect: Rect) -> Self {
+        self.crop = Some(rect);
+        self
+    }
+
+    /// Moves the content of the image, its pixels that aren't fully transparent, to `anchor`
+    /// within the image, after cropping it and before resizing it (the content is left where it
+    /// is by default). Fully transparent images are left as they are.
+    #[must_use]
+    pub fn auto_align(mut self, anchor: Gravity) -> Self {
+        self.auto_align = Some(anchor);
+        self
+    }
+
+    /// Resizes the image to exactly `width`x`height` pixels (no resizing by default).
+    #[must_use]
+    pub fn resize(mut self, width: u32, height: u32) -> Self {
+        self.resize = Some((width, height));
+        self
+    }
+
+    /// Sets the filter used when resizing (Lanczos3 by default).
+    #[must_use]
+    pub fn filter(mut self, filter: FilterType) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Sets the quality of JPEG images, from 1 to 100 (75 by default).
+    #[must_use]
+    pub fn jpeg_quality(mut self, quality: u8) -> Self {
+        self.jpeg_quality = Some(quality);
+        self
+    }
+
+    /// Composites the image over a background color (transparency is kept by default).
+    #[must_use]
+    pub fn background(mut self, color: Color) -> Self {
+        self.background = Some(color);
+        self
+    }
+
+    /// Places the image, without scaling it, on a transparent canvas of exactly `width`x`height`
+    /// pixels (the image is kept as it is by default). The canvas is applied after resizing.
+    #[must_use]
+    pub fn canvas(mut self, width: u32, height: u32) -> Self {
+        self.canvas = Some((width, height));
+        self
+    }
+
+    /// Sets where the image is placed on the canvas (centered by default).
+    #[must_use]
+    pub fn gravity(mut self, gravity: Gravity) -> Self {
+        self.gravity = gravity;
+        self
+    }
+
+    /// Allows images larger than the canvas to be cropped to it, instead of failing (not allowed
+    /// by default).
+    #[must_use]
+    pub fn allow_crop(mut self, allow: bool) -> Self {
+        self.allow_crop = allow;
+        self
+    }
+
+    /// Pads the image with transparent pixels up to the next power-of-two width and height,
+    /// placing it at `anchor` (not padded by default). Padding is applied after the canvas.
+    #[must_use]
+    pub fn pad_to_power_of_two(mut self, anchor: Gravity) -> Self {
+        self.pad_to_power_of_two = Some(anchor);
+        self
+    }
+
+    /// Copies the color profile of PNG-encoded entries to PNG, JPEG and WebP images (not copied
+    /// by default). See [`keep_color_profile`].
+    #[must_use]
+    pub fn keep_icc(mut self, keep: bool) -> Self {
+        self.keep_icc = keep;
+        self
+    }
+
+    /// Encodes PNG images with a palette when they have at most 256 unique colors, which is
+    /// usually much smaller than RGBA. Images with more colors are still encoded as RGBA (not
+    /// indexed by default).
+    #[must_use]
+    pub fn png_indexed(mut self, indexed: bool) -> Self {
+        self.png_indexed = indexed;
+        self
+    }
+
+    /// Encodes BMP images with a palette of 16 colors (`depth` 4) or 256 colors (`depth` 8),
+    /// for platforms that can't display true-color images. BMP images are true-color by default.
+    #[must_use]
+    pub fn bmp_depth(mut self, depth: u8) -> Self {
+        self.bmp_depth = Some(depth);
+        self
+    }
+
+    /// Sets the pixel layout of true-color BMP images. By default, it depends on the image:
+    /// images with an alpha channel are written as RGBA8888, the others as RGB888. It can't be
+    /// combined with [`ExtractOptions::bmp_depth`].
+    #[must_use]
+    pub fn bmp_format(mut self, format: BmpFormat) -> Self {
+        self.bmp_format = Some(format);
+        self
+    }
+
+    /// Sets the palette of indexed BMP images (the Windows palette by default).
+    #[must_use]
+    pub fn palette(mut self, palette: Palette) -> Self {
+        self.palette = palette;
+        self
+    }
+
+    /// Dithers indexed BMP images (not dithered by default).
+    #[must_use]
+    pub fn dither(mut self, dither: Dither) -> Self {
+        self.dither = Some(dither);
+        self
+    }
+
+    /// Decodes and re-encodes PNG-encoded entries even when their data could be copied as it is
+    /// (copied by default). See [`stored_png`].
+    #[must_use]
+    pub fn reencode(mut self, reencode: bool) -> Self {
+        self.reencode = reencode;
+        self
+    }
+
+    /// Converts the image to `layout` before it's encoded, so that every image has the same
+    /// channels. Images are flattened to RGB after being composited over the background, if
+    /// any. By default, the channels depend on the entry and the options.
+    #[must_use]
+    pub fn channels(mut self, layout: ChannelLayout) -> Self {
+        self.channels = Some(layout);
+        self
+    }
+
+    /// Rotates the hue of the image by `degrees`, leaving gray pixels unchanged (no shift by
+    /// default).
+    #[must_use]
+    pub fn hue_shift(mut self, degrees: f32) -> Self {
+        self.hue_shift = Some(degrees);
+        self
+    }
+
+    /// Lays a color over the luminance of the image (no tint by default). The tint is applied
+    /// after the hue shift.
+    #[must_use]
+    pub fn tint(mut self, tint: Tint) -> Self {
+        self.tint = Some(tint);
+        self
+    }
+
+    /// Sets the chroma subsampling of JPEG images. By default, the `image` crate's encoder is
+    /// used, which doesn't subsample. It's ignored for other formats.
+    #[must_use]
+    pub fn jpeg_subsampling(mut self, subsampling: JpegSubsampling) -> Self {
+        self.jpeg_subsampling = Some(subsampling);
+        self
+    }
+
+    /// Checks that the options are consistent with each other, returning them if they are.
+    pub fn build(self) -> Result<Self> {
+        if let Some(quality) = self.jpeg_quality {
+            if self.format != SupportedImages::Jpeg {
+                return Err(Ico2imgError::InvalidArgument(format!(
+                    "The JPEG quality can't be set for {} images.",
+                    self.format
+                )));
+            } else if !(1..=100).contains(&quality) {
+                return Err(Ico2imgError::InvalidArgument(format!(
+                    "Invalid JPEG quality {}, expected a value from 1 to 100.",
+                    quality
+                )));
+            }
+        }
+
+        if let Some((width, height)) = self.resize {
+            if width == 0 || height == 0 {
+                return Err(Ico2imgError::InvalidArgument(format!(
+                    "Invalid size {}x{}.",
+                    width, height
+                )));
+            }
+        }
+
+        if let Some(rect) = self.crop {
+            if rect.width == 0 || rect.height == 0 {
+                return Err(Ico2imgError::InvalidArgument(format!(
+                    "Invalid crop rectangle {}.",
+                    rect
+                )));
+            }
+        }
+
+        if self.keep_icc && self.format == SupportedImages::Bmp {
+            return Err(Ico2imgError::InvalidArgument(format!(
+                "Color profiles can't be kept in {} images, only in PNG, JPEG and WebP images.",
+                self.format
+            )));
+        }
+
+        if self.png_indexed && self.format != SupportedImages::Png {
+            return Err(Ico2imgError::InvalidArgument(format!(
+                "{} images can't be indexed, only PNG images.",
+                self.format
+            )));
+        }
+
+        if let Some(depth) = self.bmp_depth {
+            if self.format != SupportedImages::Bmp {
+                return Err(Ico2imgError::InvalidArgument(format!(
+                    "The bit depth can't be set for {} images, only for BMP images.",
+                    self.format
+                )));
+            } else if depth != 4 && depth != 8 {
+                return Err(Ico2imgError::InvalidArgument(format!(
+                    "Invalid bit depth {}, expected 4 or 8.",
+                    depth
+                )));
+            }
+        }
+
+        if self.bmp_format.is_some() {
+            if self.format != SupportedImages::Bmp {
+                return Err(Ico2imgError::InvalidArgument(format!(
+                    "The pixel format can't be set for {} images, only for BMP images.",
+                    self.format
+                )));
+            } else if self.bmp_depth.is_some() {
+                return Err(Ico2imgError::InvalidArgument(
+                    "The pixel format of indexed BMP images can't be set.".to_string(),
+                ));
+            }
+        }
+
+        if let Some((width, height)) = self.canvas {
+            if width == 0 || height == 0 {
+                return Err(Ico2imgError::InvalidArgument(format!(
+                    "Invalid canvas size {}x{}.",
+                    width, height
+                )));
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// Returns the format of the resulting image.
+    pub fn get_format(&self) -> SupportedImages {
+        self.format
+    }
+
+    /// Returns the size the image is resized to, if any.
+    pub fn get_resize(&self) -> Option<(u32, u32)> {
+        self.resize
+    }
+
+    /// Returns a description of the options, with one `name=value` line per option, that only
+    /// changes when they'd convert an image differently, such as for cache keys. Unlike the
+    /// [`Debug`] output, it doesn't depend on how the options are represented.
+    pub fn fingerprint(&self) -> String {
+        fn gravity(gravity: Gravity) -> &'static str {
+            match gravity {
+                Gravity::TopLeft => "topleft",
+                Gravity::Top => "top",
+                Gravity::TopRight => "topright",
+                Gravity::Left => "left",
+                Gravity::Center => "center",
+                Gravity::Right => "right",
+                Gravity::BottomLeft => "bottomleft",
+                Gravity::Bottom => "bottom",
+                Gravity::BottomRight => "bottomright",
+            }
+        }
+        fn color(color: Color) -> String {
+            format!(
+                "#{:02x}{:02x}{:02x}{:02x}",
+                color.r, color.g, color.b, color.a
+            )
+        }
+        fn optional<T>(value: Option<T>, show: impl FnOnce(T) -> String) -> String {
+            value.map_or_else(|| "none".to_string(), show)
+        }
+
+        let filter = match self.filter {
+            FilterType::Nearest => "nearest",
+            FilterType::Triangle => "triangle",
+            FilterType::CatmullRom => "catmullrom",
+            FilterType::Gaussian => "gaussian",
+            FilterType::Lanczos3 => "lanczos3",
+        };
+        let subsampling = |subsampling| match subsampling {
+            JpegSubsampling::Yuv444 => "444".to_string(),
+            JpegSubsampling::Yuv422 => "422".to_string(),
+            JpegSubsampling::Yuv420 => "420".to_string(),
+        };
+        let bmp_format = |format| match format {
+            BmpFormat::Rgb888 => "rgb888".to_string(),
+            BmpFormat::Rgb565 => "rgb565".to_string(),
+            BmpFormat::Rgba8888 => "rgba8888".to_string(),
+        };
+        let palette = match self.palette {
+            Palette::Windows => "windows",
+            Palette::Adaptive => "adaptive",
+        };
+        let dither = |dither| match dither {
+            Dither::Ordered => "ordered".to_string(),
+            Dither::FloydSteinberg => "floyd-steinberg".to_string(),
+        };
+        let channels = |channels| match channels {
+            ChannelLayout::Rgba => "rgba".to_string(),
+            ChannelLayout::Rgb => "rgb".to_string(),
+        };
+        let size = |(width, height)| format!("{}x{}", width, height);
+
+        [
+            ("format", self.format.to_string()),
+            ("crop", optional(self.crop, |rect| rect.to_string())),
+            (
+                "auto_align",
+                optional(self.auto_align, |anchor| gravity(anchor).to_string()),
+            ),
+            ("resize", optional(self.resize, size)),
+            ("filter", filter.to_string()),
+            (
+                "jpeg_quality",
+                optional(self.jpeg_quality, |quality| quality.to_string()),
+            ),
+            (
+                "jpeg_subsampling",
+                optional(self.jpeg_subsampling, subsampling),
+            ),
+            ("background", optional(self.background, color)),
+            ("canvas", optional(self.canvas, size)),
+            ("gravity", gravity(self.gravity).to_string()),
+            ("allow_crop", self.allow_crop.to_string()),
+            (
+                "pad_to_power_of_two",
+                optional(self.pad_to_power_of_two, |anchor| {
+                    gravity(anchor).to_string()
+                }),
+            ),
+            ("keep_icc", self.keep_icc.to_string()),
+            ("png_indexed", self.png_indexed.to_string()),
+            (
+                "bmp_depth",
+                optional(self.bmp_depth, |depth| depth.to_string()),
+            ),
+            ("bmp_format", optional(self.bmp_format, bmp_format)),
+            ("palette", palette.to_string()),
+            ("dither", optional(self.dither, dither)),
+            ("reencode", self.reencode.to_string()),
+            ("channels", optional(self.channels, channels)),
+            (
+                "hue_shift",
+                optional(self.hue_shift, |degrees| degrees.to_string()),
+            ),
+            (
+                "tint",
+                optional(self.tint, |tint| {
+                    format!("{}:{}", color(tint.color), tint.strength)
+                }),
+            ),
+        ]
+        .iter()
+        .map(|(name, value)| format!("{}={}\n", name, value))
+        .collect()
+    }
+}
+
+/// Arguments:
+///   - icon_dir: The list of icons in the ICO file.
+///   - index: The index of the icon to convert.
+///   - options: The conversion options.
+///
+/// Returns a `Vec<u8>`, containing the bytes to the image at the specified index, converted
+/// according to `options`.
+pub fn extract(icon_dir: &IconDir, index: usize, options: &ExtractOptions) -> Result<Vec<u8>> {
+    if let Some(data) = stored_png(icon_dir, index, options) {
+        return Ok(data.into_owned());
+    }
+    let image = transform(decode_entry(icon_dir, index)?, options)?;
+    Ok(keep_color_profile(icon_dir, index, encode(&image, options)?, options))
+}
+
+/// Arguments:
+///   - icon_dir: The list of icons in the ICO file.
+///   - index: The index of the entry to convert.
+///   - options: The conversion options.
+///
+/// Returns the data of the entry, if it's stored as PNG and `options` would convert it to PNG
+/// without changing it: no cropping, alignment, resizing, adjustment, canvas, padding, background,
+/// channel conversion or palette, and [`ExtractOptions::reencode`] not set. The data can then be
+/// written instead of being decoded and re-encoded, which keeps its compression and its chunks,
+/// except for the color-management ones: like re-encoded images, it's untagged unless
+/// [`ExtractOptions::keep_icc`] is set.
+pub fn stored_png<'a>(
+    icon_dir: &'a IconDir,
+    index: usize,
+    options: &ExtractOptions,
+) -> Option<Cow<'a, [u8]>> {
+    let unchanged = options.format == SupportedImages::Png
+        && !options.reencode
+        && options.crop.is_none()
+        && options.auto_align.is_none()
+        && options.resize.is_none()
+        && options.hue_shift.is_none()
+        && options.tint.is_none()
+        && options.canvas.is_none()
+        && options.pad_to_power_of_two.is_none()
+        && options.background.is_none()
+        && options.channels.is_none()
+        && !options.png_indexed;
+    if !unchanged {
+        return None;
+    }
+
+    let data = icon_dir.entries().get(index)?.data();
+    if !data.starts_with(&png::PNG_SIGNATURE) {
+        return None;
+    }
+    match png::strip_color_chunks(data) {
+        Some(stripped) if !options.keep_icc => Some(Cow::Owned(stripped)),
+        _ => Some(Cow::Borrowed(data)),
+    }
+}
+
+/// Returns the size, as `(width, height)`, of the image stored in `entry`, read from its data
+/// without decoding it. The size declared in the ICO directory, as returned by
+/// [`IconDirEntry::width`] and [`IconDirEntry::height`], can be wrong; it's only returned if the
+/// data can't be read.
+pub fn entry_size(entry: &IconDirEntry) -> (u32, u32) {
+    info::image_size(entry.data()).unwrap_or((entry.width(), entry.height()))
+}
+
+/// Arguments:
+///   - icon_dir: The list of icons in the ICO file.
+///   - index: The index of the icon to decode.
+///
+/// Returns the decoded image at the specified index, as RGBA. PNG entries larger than 256x256,
+/// which the ICO format doesn't allow, are decoded too.
+pub fn decode_entry(icon_dir: &IconDir, index: usize) -> Result<DynamicImage> {
+    if icon_dir.entries().is_empty() {
+        return Err(Ico2imgError::IcoParseError(
+            "No images found in the ICO file.".to_string(),
+        ));
+    } else if index >= icon_dir.entries().len() {
+        return Err(Ico2imgError::IndexOutOfBounds {
+            index,
+            max: icon_dir.entries().len() - 1,
+        });
+    }
+
+    let entry = &icon_dir.entries()[index];
+    let image = match entry.decode() {
+        Ok(image) => image,
+        // the `ico` crate rejects PNG entries larger than 256x256, whose directory fields can't
+        // hold their size, but they're valid PNG images
+        Err(_) if entry.is_png() => {
+            let image = image::load_from_memory_with_format(entry.data(), ImageFormat::Png)
+                .map_err(|e| Ico2imgError::DecodeError {
+                    index,
+                    source: e.to_string(),
+                })?;
+            return Ok(DynamicImage::ImageRgba8(image.to_rgba8()));
+        }
+        Err(e) => {
+            return Err(Ico2imgError::DecodeError {
+                index,
+                source: e.to_string(),
+            })
+        }
+    };
+    let buffer = RgbaImage::from_raw(image.width(), image.height(), image.rgba_data().to_vec())
+        .ok_or_else(|| Ico2imgError::DecodeError {
+            index,
+            source: "the decoded image doesn't match its size".to_string(),
+        })?;
+    Ok(DynamicImage::ImageRgba8(buffer))
+}
+
+/// Arguments:
+///   - image: The decoded image.
+///   - options: The conversion options.
+///
+/// Returns the image cropped, aligned, resized, color-adjusted, placed on its canvas, padded to a
+/// power of two, composited over the background and converted to the channel layout, in this order,
+/// as set in `options`. Fails if the crop rectangle doesn't fit in the image, or if the image is
+/// larger than the canvas and cropping isn't allowed.
+pub fn transform(image: DynamicImage, options: &ExtractOptions) -> Result<DynamicImage> {
+    let mut image = image;
+
+    if let Some(rect) = options.crop {
+        if !rect.fits_in(image.width(), image.height()) {
+            return Err(Ico2imgError::InvalidArgument(format!(
+                "The crop rectangle {} doesn't fit in the {}x{} image.",
+                rect,
+                image.width(),
+                image.height()
+            )));
+        }
+        image = image.crop_imm(rect.x, rect.y, rect.width, rect.height);
+    }
+
+    if let Some(anchor) = options.auto_align {
+        if let Some(bbox) = find_content_bbox(&image.to_rgba8()) {
+            image = align_to_anchor(image, bbox, anchor);
+        }
+    }
+
+    if let Some((width, height)) = options.resize {
+        image = image.resize_exact(width, height, options.filter);
+    }
+
+    if options.hue_shift.is_some() || options.tint.is_some() {
+        let mut rgba = image.to_rgba8();
+        if let Some(degrees) = options.hue_shift {
+            adjust::hue_shift(&mut rgba, degrees);
+        }
+        if let Some(tint) = options.tint {
+            adjust::tint(&mut rgba, tint);
+        }
+        image = DynamicImage::ImageRgba8(rgba);
+    }
+
+    if let Some((width, height)) = options.canvas {
+        if (image.width() > width || image.height() > height) && !options.allow_crop {
+            return Err(Ico2imgError::InvalidArgument(format!(
+                "The {}x{} image doesn't fit on the {}x{} canvas.",
+                image.width(),
+                image.height(),
+                width,
+                height
+            )));
+        }
+
+        image = place(&image, (width, height), options.gravity);
+    }
+
+    if let Some(anchor) = options.pad_to_power_of_two {
+        let size = (
+            image.width().next_power_of_two(),
+            image.height().next_power_of_two(),
+        );
+        image = place(&image, size, anchor);
+    }
+
+    if let Some(color) = options.background {
+        let mut canvas = RgbaImage::from_pixel(image.width(), image.height(), color.into());
+        imageops::overlay(&mut canvas, &image.to_rgba8(), 0, 0);
+        image = DynamicImage::ImageRgba8(canvas);
+    }
+
+    match options.channels {
+        Some(ChannelLayout::Rgba) => image = DynamicImage::ImageRgba8(image.to_rgba8()),
+        Some(ChannelLayout::Rgb) => {
+            let black = Rgba([0, 0, 0, u8::MAX]);
+            let mut canvas = RgbaImage::from_pixel(image.width(), image.height(), black);
+            imageops::overlay(&mut canvas, &image.to_rgba8(), 0, 0);
+            image = DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(canvas).to_rgb8());
+        }
+        None => {}
+    }
+
+    Ok(image)
+}
+
+/// Returns `image` placed on a transparent canvas of `size`, as `(width, height)`, at the
+/// position given by `gravity`. The pixels are copied rather than blended, so semi-transparent
+/// ones keep their exact values.
+fn place(image: &DynamicImage, size: (u32, u32), gravity: Gravity) -> DynamicImage {
+    let (x, y) = gravity.position((image.width(), image.height()), size);
+    let mut canvas = RgbaImage::new(size.0, size.1);
+    imageops::replace(&mut canvas, &image.to_rgba8(), x, y);
+    DynamicImage::ImageRgba8(canvas)
+}
+
+/// Arguments:
+///   - image: The image to encode.
+///   - options: The conversion options.
+///
+/// Returns the bytes of the image, encoded in the format set in `options`. With
+/// [`ExtractOptions::png_indexed`], PNG images of at most 256 colors are encoded with a palette,
+/// with [`ExtractOptions::bmp_depth`], BMP images are reduced to a palette, and with
+/// [`ExtractOptions::bmp_format`], they're written with the given pixel layout.
+pub fn encode(image: &DynamicImage, options: &ExtractOptions) -> Result<Vec<u8>> {
+    if let (SupportedImages::Bmp, Some(depth)) = (options.format, options.bmp_depth) {
+        return Ok(palette::encode_indexed_bmp(
+            &image.to_rgba8(),
+            depth,
+            options.palette,
+            options.dither,
+        ));
+    }
+    let converted;
+    let image = match (options.format, options.bmp_format) {
+        (SupportedImages::Bmp, Some(BmpFormat::Rgb565)) => {
+            return Ok(bmp_format::encode_rgb565_bmp(&image.to_rgba8()))
+        }
+        (SupportedImages::Bmp, Some(BmpFormat::Rgb888)) => {
+            converted = DynamicImage::ImageRgb8(image.to_rgb8());
+            &converted
+        }
+        (SupportedImages::Bmp, Some(BmpFormat::Rgba8888)) => {
+            converted = DynamicImage::ImageRgba8(image.to_rgba8());
+            &converted
+        }
+        _ => image,
+    };
+
+    let mut buffer = Vec::new();
+
+    match options.format {
+        SupportedImages::Jpeg => {
+            let quality = options.jpeg_quality.unwrap_or(DEFAULT_JPEG_QUALITY);
+            match options.jpeg_subsampling {
+                Some(subsampling) => {
+                    encode_subsampled_jpeg(image, quality, subsampling, &mut buffer)?
+                }
+                None => JpegEncoder::new_with_quality(&mut buffer, quality)
+                    .encode_image(&image.to_rgb8())
+                    .map_err(encode_error)?,
+            }
+        }
+        SupportedImages::Png if options.png_indexed => {
+            match png::encode_indexed(&image.to_rgba8())? {
+                Some(indexed) => buffer = indexed,
+                None => image
+                    .write_to(&mut Cursor::new(&mut buffer), ImageFormat::Png)
+                    .map_err(encode_error)?,
+            }
+        }
+        format => image
+            .write_to(&mut Cursor::new(&mut buffer), format.image_format())
+            .map_err(encode_error)?,
+    }
+
+    Ok(buffer)
+}
+
+/// Returns the error of an encoder as an [`Ico2imgError::EncodeError`].
+fn encode_error(e: impl std::error::Error) -> Ico2imgError {
+    Ico2imgError::EncodeError(e.to_string())
+}
+
+/// Encodes `image` as JPEG with the `jpeg-encoder` crate, whose chroma subsampling can be set,
+/// unlike the `image` crate's encoder.
+fn encode_subsampled_jpeg(
+    image: &DynamicImage,
+    quality: u8,
+    subsampling: JpegSubsampling,
+    buffer: &mut Vec<u8>,
+) -> Result<()> {
+    let image = image.to_rgb8();
+    let too_large = |_| {
+        Ico2imgError::EncodeError(format!(
+            "the {}x{} image is too large for JPEG",
+            image.width(),
+            image.height()
+        ))
+    };
+    let (width, height) = (
+        u16::try_from(image.width()).map_err(too_large)?,
+        u16::try_from(image.height()).map_err(too_large)?,
+    );
+
+    let mut encoder = jpeg_encoder::Encoder::new(buffer, quality);
+    encoder.set_sampling_factor(match subsampling {
+        JpegSubsampling::Yuv444 => jpeg_encoder::SamplingFactor::R_4_4_4,
+        JpegSubsampling::Yuv422 => jpeg_encoder::SamplingFactor::R_4_2_2,
+        JpegSubsampling::Yuv420 => jpeg_encoder::SamplingFactor::R_4_2_0,
+    });
+    encoder
+        .encode(image.as_raw(), width, height, jpeg_encoder::ColorType::Rgb)
+        .map_err(encode_error)?;
+    Ok(())
+}
+
+/// Arguments:
+///   - buffer: The bytes of an image, as returned by [`encode`].
+///   - format: The format of the image.
+///   - packet: The XMP packet to embed, an XML document.
+///
+/// Returns `buffer` with `packet` embedded the way PNG images (an `iTXt` chunk) and JPEG images
+/// (an `APP1` segment) store XMP metadata. Fails for other formats, and for JPEG images if the packet
+/// is longer than a segment holds.
+pub fn embed_xmp(buffer: Vec<u8>, format: SupportedImages, packet: &str) -> Result<Vec<u8>> {
+    match format {
+        SupportedImages::Png => Ok(xmp::embed_png(&buffer, packet)),
+        SupportedImages::Jpeg => xmp::embed_jpeg(&buffer, packet),
+        _ => Err(Ico2imgError::InvalidArgument(format!(
+            "XMP metadata can't be embedded in {} images, only in PNG and JPEG ones.",
+            format.extension().to_uppercase()
+        ))),
+    }
+}
+
+/// Arguments:
+///   - icon_dir: The list of icons in the ICO file.
+///   - index: The index of the entry the image was converted from.
+///   - buffer: The bytes of the converted image, as returned by [`encode`].
+///   - options: The conversion options.
+///
+/// Returns `buffer` with the color profile of the entry copied to it, if the entry is
+/// PNG-encoded and [`ExtractOptions::keep_icc`] is set. PNG images get the color-management
+/// chunks of the entry (`iCCP`, `sRGB`, `gAMA` and `cHRM`) as they are, while JPEG images get its
+/// ICC profile in `APP2` segments and WebP images in an `ICCP` chunk. Otherwise, including when
+/// the entry has no ICC profile for JPEG and WebP images, `buffer` is returned as it is.
+pub fn keep_color_profile(
+    icon_dir: &IconDir,
+    index: usize,
+    buffer: Vec<u8>,
+    options: &ExtractOptions,
+) -> Vec<u8> {
+    if !options.keep_icc {
+        return buffer;
+    }
+    let Some(entry) = icon_dir.entries().get(index).filter(|entry| entry.is_png()) else {
+        return buffer;
+    };
+
+    match options.format {
+        SupportedImages::Png => {
+            let chunks = png::color_chunks(entry.data());
+            if chunks.is_empty() {
+                buffer
+            } else {
+                png::insert_chunks(&buffer, &chunks)
+            }
+        }
+        SupportedImages::Jpeg => match icc::png_profile(entry.data()) {
+            Some(profile) => icc::embed_jpeg(buffer, &profile),
+            None => buffer,
+        },
+        SupportedImages::WebP => match icc::png_profile(entry.data()) {
+            Some(profile) => icc::embed_webp(buffer, &profile),
+            None => buffer,
+        },
+        SupportedImages::Bmp => buffer,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::load_from_memory;
+
+    #[test]
+    fn fingerprints_change_with_the_options() {
+        let options = ExtractOptions::new().resize(8, 8);
+        assert_eq!(
+            options.fingerprint(),
+            ExtractOptions::new().resize(8, 8).fingerprint()
+        );
+        assert!(options.fingerprint().contains("resize=8x8\n"));
+        assert!(options.fingerprint().contains("filter=lanczos3\n"));
+
+        for other in [
+            options.clone().resize(8, 9),
+            options.clone().filter(FilterType::Nearest),
+            options.clone().gravity(Gravity::Top),
+            options.clone().hue_shift(90.0),
+            options.clone().format(SupportedImages::Bmp),
+        ] {
+            assert_ne!(other.fingerprint(), options.fingerprint());
+        }
+    }
+
+    #[test]
+    fn stored_png_copies_entry_data() {
+        let image = fixtures::gradient(16, 16);
+        let data = fixtures::png_data(&image);
+        let icon_dir = fixtures::icon_dir(&[(16, data.clone())]);
+        let options = ExtractOptions::new().build().unwrap();
+
+        assert!(matches!(
+            stored_png(&icon_dir, 0, &options),
+            Some(Cow::Borrowed(copied)) if copied == data
+        ));
+        assert_eq!(extract(&icon_dir, 0, &options).unwrap(), data);
+    }
+
+    #[test]
+    fn stored_png_strips_color_profile() {
+        let image = fixtures::gradient(16, 16);
+        let data = fixtures::png_with_profile(&image);
+        let icon_dir = fixtures::icon_dir(&[(16, data.clone())]);
+
+        let options = ExtractOptions::new().build().unwrap();
+        let copied = extract(&icon_dir, 0, &options).unwrap();
+        assert!(png::color_chunks(&copied).is_empty());
+        assert_eq!(
+            copied.len(),
+            data.len() - png::color_chunks(&data).concat().len()
+        );
+        assert_eq!(load_from_memory(&copied).unwrap().to_rgba8(), image);
+
+        let options = ExtractOptions::new().keep_icc(true).build().unwrap();
+        assert_eq!(extract(&icon_dir, 0, &options).unwrap(), data);
+    }
+
+    #[test]
+    fn stored_png_falls_back_to_reencoding() {
+        let image = fixtures::gradient(16, 16);
+        let data = fixtures::png_with_profile(&image);
+        let icon_dir = fixtures::icon_dir(&[(16, data.clone())]);
+
+        for options in [
+            ExtractOptions::new().reencode(true),
+            ExtractOptions::new().resize(8, 8),
+            ExtractOptions::new().background(Color::WHITE),
+            ExtractOptions::new().png_indexed(true),
+            ExtractOptions::new().format(SupportedImages::Bmp),
+        ] {
+            let options = options.build().unwrap();
+            assert!(stored_png(&icon_dir, 0, &options).is_none());
+            let converted = extract(&icon_dir, 0, &options).unwrap();
+            assert_ne!(converted, data);
+            assert!(png::color_chunks(&converted).is_empty());
+        }
+
+        // the re-encoded image has the same pixels as the copied one
+        let options = ExtractOptions::new().reencode(true).build().unwrap();
+        let reencoded = extract(&icon_dir, 0, &options).unwrap();
+        assert_eq!(load_from_memory(&reencoded).unwrap().to_rgba8(), image);
+    }
+
+    #[test]
+    fn stored_png_ignores_bmp_entries() {
+        let image = fixtures::gradient(16, 16);
+        let icon = ico::IconImage::from_rgba_data(16, 16, image.into_raw());
+        let bmp = IconDirEntry::encode_as_bmp(&icon).unwrap();
+        let icon_dir = fixtures::icon_dir(&[(16, bmp.data().to_vec())]);
+        let options = ExtractOptions::new().build().unwrap();
+        assert!(stored_png(&icon_dir, 0, &options).is_none());
+    }
+
+    /// Returns `value` serialized to JSON, after checking that it deserializes back to itself.
+    #[cfg(feature = "serde")]
+    fn round_trip<T>(value: &T) -> String
+    where
+        T: Serialize + serde::de::DeserializeOwned + PartialEq + std::fmt::Debug,
+    {
+        let json = serde_json::to_string(value).unwrap();
+        let parsed: T = serde_json::from_str(&json).unwrap();
+        assert_eq!(&parsed, value, "{}", json);
+        json
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn supported_images_round_trip_through_json() {
+        let names: Vec<String> = SupportedImages::ALL.iter().map(round_trip).collect();
+        assert_eq!(names, [r#""png""#, r#""jpeg""#, r#""bmp""#, r#""webp""#]);
+        assert_eq!(
+            serde_json::from_str::<SupportedImages>(r#""png""#).unwrap(),
+            SupportedImages::Png
+        );
+        assert!(serde_json::from_str::<SupportedImages>(r#""Png""#).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn option_types_round_trip_through_json() {
+        use Gravity::*;
+        for gravity in [
+            TopLeft,
+            Top,
+            TopRight,
+            Left,
+            Center,
+            Right,
+            BottomLeft,
+            Bottom,
+            BottomRight,
+        ] {
+            round_trip(&gravity);
+        }
+        assert_eq!(round_trip(&Gravity::TopLeft), r#""topleft""#);
+
+        assert_eq!(round_trip(&JpegSubsampling::Yuv444), r#""444""#);
+        assert_eq!(round_trip(&JpegSubsampling::Yuv422), r#""422""#);
+        assert_eq!(round_trip(&JpegSubsampling::Yuv420), r#""420""#);
+        assert_eq!(round_trip(&ChannelLayout::Rgba), r#""rgba""#);
+        assert_eq!(round_trip(&ChannelLayout::Rgb), r#""rgb""#);
+        round_trip(&Rect {
+            x: 1,
+            y: 2,
+            width: 3,
+            height: 4,
+        });
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn ico_info_round_trips_through_json() {
+        let image = fixtures::gradient(16, 16);
+        let icon_dir = fixtures::icon_dir(&[(16, fixtures::png_data(&image))]);
+        let mut data = Vec::new();
+        icon_dir.write(&mut data).unwrap();
+
+        let info = IcoInfo::from_reader(std::io::Cursor::new(data)).unwrap();
+        let json = round_trip(&info);
+        assert!(json.contains(r#""encoding_type":"png""#), "{}", json);
+    }
+
+    #[test]
+    fn pad_to_power_of_two_pads_48_to_64() {
+        let image = fixtures::gradient(48, 48);
+        let options = ExtractOptions::new()
+            .pad_to_power_of_two(Gravity::TopLeft)
+            .build()
+            .unwrap();
+        let padded = transform(DynamicImage::ImageRgba8(image.clone()), &options)
+            .unwrap()
+            .to_rgba8();
+
+        assert_eq!(padded.dimensions(), (64, 64));
+        for (x, y, pixel) in padded.enumerate_pixels() {
+            if x < 48 && y < 48 {
+                assert_eq!(pixel, image.get_pixel(x, y), "({}, {})", x, y);
+            } else {
+                assert_eq!(pixel.0, [0; 4], "({}, {})", x, y);
+            }
+        }
+
+        let options = ExtractOptions::new()
+            .pad_to_power_of_two(Gravity::Center)
+            .build()
+            .unwrap();
+        let centered = transform(DynamicImage::ImageRgba8(image.clone()), &options)
+            .unwrap()
+            .to_rgba8();
+        assert_eq!(centered.get_pixel(8, 8), image.get_pixel(0, 0));
+        assert_eq!(centered.get_pixel(7, 8).0, [0; 4]);
+        assert_eq!(centered.get_pixel(56, 56).0, [0; 4]);
+    }
+
+    #[test]
+    fn pad_to_power_of_two_keeps_powers_of_two() {
+        let image = fixtures::gradient(32, 16);
+        let options = ExtractOptions::new()
+            .pad_to_power_of_two(Gravity::TopLeft)
+            .build()
+            .unwrap();
+        let padded = transform(DynamicImage::ImageRgba8(image.clone()), &options).unwrap();
+        assert_eq!(padded.to_rgba8(), image);
+    }
+}
@@ -0,0 +1,113 @@
+//! Detection of the type of the input file from its first bytes, to explain why a file that
+//! isn't an ICO file can't be read.
+
+use ico2img::Ico2imgError;
+use std::path::Path;
+
+/// File types that can be told apart by their first bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileType {
+    Ico,
+    Cur,
+    Png,
+    Jpeg,
+    /// A Windows executable or library (including NE files such as ICL icon libraries).
+    Pe,
+    Unknown,
+}
+
+/// Returns the type of a file judging from its first 16 bytes. Shorter files should be padded
+/// with zeros.
+pub fn detect_file_type(header: &[u8; 16]) -> FileType {
+    match header {
+        [0x00, 0x00, 0x01, 0x00, ..] => FileType::Ico,
+        [0x00, 0x00, 0x02, 0x00, ..] => FileType::Cur,
+        [0x89, b'P', b'N', b'G', ..] => FileType::Png,
+        [0xFF, 0xD8, 0xFF, ..] => FileType::Jpeg,
+        [b'M', b'Z', ..] => FileType::Pe,
+        _ => FileType::Unknown,
+    }
+}
+
+/// Arguments:
+///   - path: The path to the input file, for error messages.
+///   - data: The contents of the input file.
+///
+/// Checks that `data` starts like an ICO or CUR file, failing with an explanation of what the
+/// file is otherwise.
+pub fn check_icon_file(path: &Path, data: &[u8]) -> Result<(), Ico2imgError> {
+    let mut header = [0; 16];
+    let len = data.len().min(header.len());
+    header[..len].copy_from_slice(&data[..len]);
+
+    let path = path.display();
+    let message = match detect_file_type(&header) {
+        FileType::Ico | FileType::Cur => return Ok(()),
+        FileType::Png => format!(
+            "{} is a PNG image, not an ICO file. Use --append to add it to an ICO file.",
+            path
+        ),
+        FileType::Jpeg => format!(
+            "{} is a JPEG image, not an ICO file. Use --append to add it to an ICO file.",
+            path
+        ),
+        FileType::Pe => format!(
+            "{} is a Windows executable, not an ICO file. Its icons need to be extracted first, \
+             unless it's an ICL icon library, which can be read with the `icl` feature.",
+            path
+        ),
+        FileType::Unknown => format!(
+            "{} isn't an ICO or CUR file: it doesn't start with their signature.",
+            path
+        ),
+    };
+    Err(Ico2imgError::IcoParseError(message))
+}
+
+/// The signature at the start of PNG data.
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// Returns whether `data`, the data of an ICO entry, claims to be PNG data by starting with the
+/// first 4 bytes of the PNG signature. The rest of the signature is checked by
+/// [`check_png_header`], as its line endings are what file transfers tend to mangle.
+pub fn claims_png(data: &[u8]) -> bool {
+    data.starts_with(&PNG_SIGNATURE[..4])
+}
+
+/// Checks that `data` starts with the full PNG signature followed by a valid `IHDR` chunk,
+/// returning why it doesn't otherwise.
+pub fn check_png_header(data: &[u8]) -> Result<(), &'static str> {
+    if !data.starts_with(&PNG_SIGNATURE) {
+        return Err("its PNG signature is damaged");
+    }
+    let Some(chunk) = data.get(PNG_SIGNATURE.len()..PNG_SIGNATURE.len() + 25) else {
+        return Err("it ends before the IHDR chunk");
+    };
+    if chunk[4..8] != *b"IHDR" {
+        return Err("it doesn't start with an IHDR chunk");
+    }
+    if chunk[0..4] != 13u32.to_be_bytes() {
+        return Err("its IHDR chunk has the wrong length");
+    }
+
+    let width = u32::from_be_bytes([chunk[8], chunk[9], chunk[10], chunk[11]]);
+    let height = u32::from_be_bytes([chunk[12], chunk[13], chunk[14], chunk[15]]);
+    if width == 0 || height == 0 || width > i32::MAX as u32 || height > i32::MAX as u32 {
+        return Err("its IHDR chunk has an invalid size");
+    }
+    let (bit_depth, color_type) = (chunk[16], chunk[17]);
+    let valid_depths: &[u8] = match color_type {
+        0 => &[1, 2, 4, 8, 16],
+        3 => &[1, 2, 4, 8],
+        2 | 4 | 6 => &[8, 16],
+        _ => return Err("its IHDR chunk has an invalid color type"),
+    };
+    if !valid_depths.contains(&bit_depth) {
+        return Err("its IHDR chunk has an invalid bit depth for its color type");
+    }
+    // compression and filter methods, then interlace method
+    if chunk[18] != 0 || chunk[19] != 0 || chunk[20] > 1 {
+        return Err("its IHDR chunk has an invalid compression, filter or interlace method");
+    }
+    Ok(())
+}
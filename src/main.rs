@@ -1,21 +1,187 @@
 use anyhow::{anyhow, Result};
-use clap::Parser;
-use ico::IconDir;
-use image::{load_from_memory, ImageFormat};
+use clap::{parser::ValueSource, ArgAction, ArgMatches, CommandFactory, FromArgMatches, Parser};
+use ico::{IconDir, IconDirEntry};
+use ico2img::{
+    AlphaStats, BmpFormat, ChannelLayout, Color, Dither, EncodingType, EntryInfo, ExtractOptions,
+    FilterType, Gravity, IcoInfo, JpegSubsampling, Palette, Rect, SupportedImages, Tint,
+    ValidationStatus,
+};
+use image::{load_from_memory, DynamicImage, ImageFormat, Rgba, RgbaImage};
 use std::{
-    fs::File,
-    io::{BufReader, BufWriter, Cursor, Read, Write},
-    path::PathBuf,
+    collections::{HashMap, HashSet},
+    env,
+    ffi::OsString,
+    fmt,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Cursor, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
 };
-use toml::Value;
+use bmp::BmpAlpha;
+use budget::MemoryBudget;
+use cache::Cache;
+use filter::{EntryFields, EntryFilter};
+use mask::{Mask, MaskFormat};
+use style::ColorChoice;
+use summary::RunSummary;
+use theme::Variant;
+use toml::{Table, Value};
+
+mod ani;
+mod batch;
+mod bmp;
+mod budget;
+mod cache;
+mod diff;
+mod edit;
+mod filter;
+mod hook;
+#[cfg(feature = "http")]
+mod http;
+#[cfg(feature = "icl")]
+mod icl;
+mod iconset;
+mod inventory;
+mod magic;
+mod mask;
+mod metadata;
+mod owner;
+mod paths;
+mod preview;
+mod progress;
+mod recover;
+mod retry;
+#[cfg(all(unix, feature = "serve"))]
+mod serve;
+mod sprite;
+mod style;
+mod summary;
+mod sync;
+mod theme;
+mod timing;
+#[cfg(feature = "check-update")]
+mod update;
+mod warning;
+mod xdg;
+
+/// Exit code used when `--time-limit` is exceeded.
+const TIME_LIMIT_EXIT_CODE: i32 = 7;
+
+/// Interval at which threads waiting for `--memory-limit` check whether they can go on.
+const MEMORY_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Size of the largest frame read by `--stdin-framed`, far above that of any real ICO file.
+const MAX_FRAME_SIZE: u32 = 64 * 1024 * 1024;
+
+/// Number of colors above which `--analyze` stops counting.
+const MAX_COLOR_COUNT: usize = 65536;
+
+/// Columns of the CSV report of `--list`, in order. `colors` and `alpha` are empty without
+/// `--analyze`.
+const ENTRY_CSV_HEADER: &str = "path,index,width,height,bits_per_pixel,encoding,colors,alpha";
+
+/// Columns of the CSV report of `--list` on an ANI file, in order.
+const FRAME_CSV_HEADER: &str = "frame,entries,jiffies";
+
+/// Columns of the CSV report of `--list` on an ICL file, in order.
+#[cfg(feature = "icl")]
+const GROUP_CSV_HEADER: &str = "group,entries";
 
 #[derive(Parser)]
+#[command(group(clap::ArgGroup::new("inspect").args(["list", "info"]).multiple(true)))]
+#[command(group(clap::ArgGroup::new("report").args(["list", "summary"]).multiple(true)))]
+#[command(group(clap::ArgGroup::new("concat").args(["concat_horizontal", "concat_vertical"])))]
 struct Args {
-    #[arg(help = "The path to the ICO image.")]
-    file: PathBuf,
+    #[cfg_attr(
+        all(unix, feature = "serve"),
+        arg(required_unless_present_any = [
+            "stdin_framed",
+            "dump_config",
+            "export_config",
+            "list_formats",
+            "pack_dir",
+            "serve"
+        ])
+    )]
+    #[cfg_attr(
+        not(all(unix, feature = "serve")),
+        arg(required_unless_present_any = [
+            "stdin_framed",
+            "dump_config",
+            "export_config",
+            "list_formats",
+            "pack_dir"
+        ])
+    )]
+    #[arg(help = "The path to the ICO image, or its http(s) URL with the `http` feature.")]
+    file: Option<PathBuf>,
+
+    #[arg(
+        requires = "summary",
+        help = "More ICO files or directories to report on with --info --summary."
+    )]
+    more_files: Vec<PathBuf>,
 
-    #[arg(short, help = "The output PNG image.")]
-    output: PathBuf,
+    #[cfg_attr(
+        all(unix, feature = "serve"),
+        arg(required_unless_present_any = [
+            "list",
+            "info",
+            "stats",
+            "decode_only",
+            "validate_png_signatures",
+            "validate_output",
+            "detect_duplicate_entries",
+            "preview_only",
+            "dump_config",
+            "export_config",
+            "list_formats",
+            "output_ico",
+            "sync",
+            "append",
+            "remove_size",
+            "spritesheet",
+            "dump_raw",
+            "xdg_install",
+            "iconset",
+            "serve"
+        ])
+    )]
+    #[cfg_attr(
+        not(all(unix, feature = "serve")),
+        arg(required_unless_present_any = [
+            "list",
+            "info",
+            "stats",
+            "decode_only",
+            "validate_png_signatures",
+            "validate_output",
+            "detect_duplicate_entries",
+            "preview_only",
+            "dump_config",
+            "export_config",
+            "list_formats",
+            "output_ico",
+            "sync",
+            "append",
+            "remove_size",
+            "spritesheet",
+            "dump_raw",
+            "xdg_install",
+            "iconset"
+        ])
+    )]
+    #[arg(
+        short,
+        help = "The output image, or the output directory with a directory input or --stdin-framed."
+    )]
+    output: Option<PathBuf>,
 
     #[arg(
         short,
@@ -31,116 +197,4187 @@ struct Args {
         help = "The format of the resulting converted image.",
         default_value = "png"
     )]
-    format: String,
+    format: SupportedImages,
+
+    #[arg(
+        long,
+        value_name = "INDEX:FORMAT,...",
+        value_parser = parse_format_map,
+        help = "The format of the converted image for each entry index, such as 0:webp,1:png. Other entries use --format."
+    )]
+    format_map: Option<HashMap<usize, SupportedImages>>,
+
+    #[arg(
+        long,
+        value_name = "X,Y,WxH",
+        help = "Crop the decoded image to this rectangle, before resizing it. Repeat it to write an image per rectangle, named as given by --crop-template."
+    )]
+    crop: Vec<Rect>,
+
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        default_value = "{stem}_{crop}",
+        help = "The names of the images written for several --crop rectangles, without extension, with {stem} and {crop}, the rectangle as {X}_{Y}_{W}x{H}, replaced."
+    )]
+    crop_template: String,
+
+    #[arg(
+        long,
+        value_name = "ANCHOR",
+        help = "Move the content of the image, its pixels that aren't fully transparent, to this anchor within the image, before resizing it. Takes the same values as --gravity."
+    )]
+    auto_align: Option<Gravity>,
+
+    #[arg(long, value_name = "WxH", value_parser = parse_size, help = "Resize the image.")]
+    resize: Option<(u32, u32)>,
+
+    #[arg(
+        long,
+        value_parser = parse_filter,
+        help = "The filter used for resizing: nearest, triangle, catmullrom, gaussian or lanczos3.",
+        default_value = "lanczos3"
+    )]
+    filter: FilterType,
+
+    #[arg(long, value_name = "QUALITY", help = "The quality of JPEG images, from 1 to 100.")]
+    jpeg_quality: Option<u8>,
+
+    #[arg(
+        long,
+        value_name = "444|422|420",
+        help = "The chroma subsampling of JPEG images. 4:4:4 keeps sharp colored edges at the cost of size."
+    )]
+    jpeg_subsampling: Option<JpegSubsampling>,
+
+    #[arg(
+        long,
+        value_name = "4|8",
+        help = "Encode BMP images with a palette of 16 (4) or 256 (8) colors."
+    )]
+    depth: Option<u8>,
+
+    #[arg(
+        long,
+        value_name = "rgb888|rgb565|rgba8888",
+        conflicts_with = "depth",
+        help = "The pixel layout of BMP images. By default, images with transparency are written as RGBA8888 and the others as RGB888."
+    )]
+    bmp_format: Option<BmpFormat>,
+
+    #[arg(
+        long,
+        value_name = "windows|adaptive",
+        requires = "depth",
+        help = "The palette of BMP images with --depth: the fixed Windows palette (the default) or one fitting the image."
+    )]
+    palette: Option<Palette>,
+
+    #[arg(
+        long,
+        value_name = "ordered|floyd-steinberg",
+        requires = "depth",
+        help = "Dither BMP images reduced to a palette with --depth."
+    )]
+    dither: Option<Dither>,
+
+    #[arg(
+        long,
+        value_name = "RRGGBB[AA]",
+        help = "Composite the image over a background color."
+    )]
+    background: Option<Color>,
+
+    #[arg(
+        long,
+        value_name = "light|dark|both",
+        conflicts_with = "background",
+        help = "Composite the image over the light or dark theme background, or over both, writing {stem}_light and {stem}_dark next to the output path."
+    )]
+    variant: Option<Variant>,
+
+    #[arg(
+        long,
+        value_name = "RRGGBB[AA]",
+        requires = "variant",
+        help = "The background of the light variant.",
+        default_value = "FFFFFF"
+    )]
+    light_background: Color,
+
+    #[arg(
+        long,
+        value_name = "RRGGBB[AA]",
+        requires = "variant",
+        help = "The background of the dark variant.",
+        default_value = "1E1E1E"
+    )]
+    dark_background: Color,
+
+    #[arg(
+        long,
+        value_name = "RRGGBB[:STRENGTH]",
+        help = "Lay a color over the luminance of the image, with a strength from 0 to 1."
+    )]
+    tint: Option<Tint>,
+
+    #[arg(
+        long,
+        value_name = "DEGREES",
+        allow_negative_numbers = true,
+        help = "Rotate the hue of the image, leaving gray pixels unchanged."
+    )]
+    hue_shift: Option<f32>,
+
+    #[arg(
+        long,
+        value_name = "WxH",
+        value_parser = parse_size,
+        help = "Place the image, without scaling it, on a canvas of exactly this size."
+    )]
+    canvas: Option<(u32, u32)>,
+
+    #[arg(
+        long,
+        requires = "canvas",
+        help = "Where to place the image on the canvas: center, top, bottom, left, right, topleft, topright, bottomleft or bottomright.",
+        default_value = "center"
+    )]
+    gravity: Gravity,
+
+    #[arg(
+        long,
+        requires = "canvas",
+        help = "Crop images larger than the canvas instead of failing."
+    )]
+    allow_crop: bool,
+
+    #[arg(
+        long,
+        conflicts_with = "canvas",
+        help = "Pad the image with transparent pixels up to power-of-two dimensions."
+    )]
+    pot: bool,
+
+    #[arg(
+        long,
+        requires = "pot",
+        help = "Where to place the image when padding it to power-of-two dimensions.",
+        default_value = "topleft"
+    )]
+    pot_anchor: Gravity,
+
+    #[arg(
+        long,
+        help = "Copy the color profile of PNG-encoded entries to the PNG, JPEG or WebP image."
+    )]
+    keep_icc: bool,
+
+    #[arg(
+        long,
+        help = "Embed XMP metadata in PNG and JPEG images: the source file, entry index, creation time, tool and image size."
+    )]
+    write_xmp: bool,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "write_xmp",
+        help = "The template of the XMP metadata, whose {source}, {index}, {created}, {tool}, {version}, {width} and {height} placeholders are replaced."
+    )]
+    xmp_template: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Encode PNG images with a palette when they have at most 256 colors."
+    )]
+    png_indexed: bool,
+
+    #[arg(
+        long,
+        help = "Decode and re-encode PNG-encoded entries converted to PNG without any change, instead of copying their data."
+    )]
+    reencode: bool,
+
+    #[arg(
+        long,
+        help = "Convert every image to RGBA before encoding it, adding an opaque alpha where missing."
+    )]
+    force_rgba: bool,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["force_rgba", "export_mask"],
+        help = "Flatten every image to RGB before encoding it, over --background or black."
+    )]
+    force_rgb: bool,
+
+    #[arg(
+        long = "export-1bit-mask",
+        conflicts_with = "background",
+        help = "Also write a 1-bit transparency mask, named {stem}_{index}_mask.{ext}, next to the image."
+    )]
+    export_mask: bool,
+
+    #[arg(
+        long,
+        requires = "export_mask",
+        help = "The alpha value from which a pixel counts as opaque in the mask.",
+        default_value = "128"
+    )]
+    mask_threshold: u8,
+
+    #[arg(
+        long,
+        requires = "export_mask",
+        help = "The format of the mask: png or pbm.",
+        default_value = "png"
+    )]
+    mask_format: MaskFormat,
+
+    #[arg(
+        long,
+        requires = "export_mask",
+        help = "Dither the alpha channel into the mask, to render soft shadows."
+    )]
+    mask_dither: bool,
+
+    #[arg(
+        long,
+        requires = "export_mask",
+        help = "Set opaque pixels in the mask rather than transparent ones."
+    )]
+    mask_invert: bool,
+
+    #[arg(
+        long,
+        help = "Draw a preview of the selected entry in the terminal, before converting it."
+    )]
+    preview: bool,
+
+    #[arg(
+        long,
+        conflicts_with = "preview",
+        help = "Draw a preview of the selected entry in the terminal, without converting it."
+    )]
+    preview_only: bool,
 
+    #[arg(
+        long,
+        help = "Time each stage of the conversion (reading, parsing, decoding, encoding and writing) and print the durations at the end."
+    )]
+    timings: bool,
 
     #[arg(short, long, help = "Enable verbose output.")]
     verbose: bool,
 
     #[arg(short, help = "The configuration path")]
     config: Option<PathBuf>,
-}
 
-fn main() -> Result<()> {
-    let args = Args::parse();
-    let path = &args.file;
-    let reader = BufReader::new(File::open(path)?);
-    let icon_dir = IconDir::read(reader)?;
+    #[arg(
+        long,
+        requires = "config",
+        help = "The profile of the configuration file to use, from its [profile.<NAME>] table."
+    )]
+    profile: Option<String>,
 
-    let index = args.image_index;
-    let verbose = args.verbose;
-    let mut format = args.format.as_str();
+    #[arg(
+        long,
+        requires = "config",
+        help = "Print the options read from the configuration file, with the profile merged in."
+    )]
+    dump_config: bool,
 
-    if verbose {
-        println!(
-            "Number of entries in ICO file: {}",
-            icon_dir.entries().len()
-        );
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with = "dump_config",
+        help = "Write every option that can be set in a configuration file, as resolved from the command line, the configuration file and the defaults, to this path as TOML, or to standard output with -."
+    )]
+    export_config: Option<PathBuf>,
 
-        let entry = &icon_dir.entries()[args.image_index];
-        println!(
-            "Image details: {}x{} - {} bits per pixel",
-            entry.width(),
-            entry.height(),
-            entry.bits_per_pixel()
-        );
-    }
+    #[arg(
+        long,
+        help = "Print the supported output formats, whether they're lossy and keep transparency, and the feature they require."
+    )]
+    list_formats: bool,
+
+    #[arg(
+        long,
+        help = "Use the entry sizes declared in the ICO directory, even if they differ from the images."
+    )]
+    trust_directory: bool,
+
+    #[arg(
+        long,
+        help = "List the entries (or ICL icon groups) in the input file. On a terminal, entries are colored by bit depth (1-bit gray, 4-bit yellow, 8-bit cyan, 24-bit green, 32-bit bright white), and the largest one is bold."
+    )]
+    list: bool,
 
-    let mut writer = BufWriter::new(File::create(&args.output)?);
+    #[arg(
+        long,
+        value_name = "WHEN",
+        help = "When to color the output: auto (on a terminal, unless NO_COLOR is set), always or never.",
+        default_value = "auto"
+    )]
+    color: ColorChoice,
 
-    if let Some(ref conf) = args.config {
-        let mut reader = BufReader::new(File::open(conf)?);
-        let mut contents = String::new();
-        reader.read_to_string(&mut contents)?;
-        let config: Value = toml::from_str(contents.as_str())?;
+    #[arg(
+        long,
+        conflicts_with = "color",
+        help = "Print without colors, even on a terminal. Same as --color never."
+    )]
+    no_color: bool,
 
-        format = config["ico2img"]["format"]
-            .as_str()
-            .ok_or_else(|| anyhow!("Output format type isn't specified."))?;
+    #[arg(
+        long,
+        value_name = "EXPR",
+        help = "Only list, decode, pack or put in the sprite sheet the entries matching this expression, such as 'size >= 48 and bpp == 32 and stored == png'."
+    )]
+    entry_filter: Option<EntryFilter>,
 
-        let buffer = handle_ico(&icon_dir, index)?;
+    #[arg(
+        long,
+        requires = "inspect",
+        help = "With --list or --info, decode each entry to count its colors and compute statistics of its alpha channel."
+    )]
+    analyze: bool,
 
-        match format.to_lowercase().as_str() {
-            "png" => {
-                writer.write_all(&buffer)?;
-            }
-            "jpg" | "jpeg" => {
-                let image = load_from_memory(buffer.as_slice())?.to_rgb8();
-                let mut img_buffer = Vec::new();
-                let mut cursor = Cursor::new(&mut img_buffer);
-                image.write_to(&mut cursor, ImageFormat::Jpeg)?;
-                writer.write_all(&img_buffer)?;
-            }
-            "bmp" => {
-                let image = load_from_memory(buffer.as_slice())?;
-                let mut img_buffer = Vec::new();
-                let mut cursor = Cursor::new(&mut img_buffer);
-                image.write_to(&mut cursor, ImageFormat::Bmp)?;
-                writer.write_all(&img_buffer)?;
-            }
-            "webp" => {
-                let image = load_from_memory(buffer.as_slice())?;
-                let mut img_buffer = Vec::new();
-                let mut cursor = Cursor::new(&mut img_buffer);
-                image.write_to(&mut cursor, ImageFormat::WebP)?;
-                writer.write_all(&img_buffer)?;
-            }
-            _ => {
-                eprintln!("The specified format is not supported at the moment.");
-                eprintln!("Feel free to contribute to add new formats");
-                panic!();
-            }
-        }
-    } else {
-        let buffer = handle_ico(&icon_dir, index)?;
-        writer.write_all(&buffer)?;
-    }
+    #[arg(
+        long,
+        help = "Only select the entries with at least one pixel that isn't fully opaque."
+    )]
+    require_alpha: bool,
 
-    Ok(())
-}
+    #[arg(
+        long,
+        conflicts_with = "require_alpha",
+        help = "Only select the entries whose pixels are all fully opaque."
+    )]
+    require_opaque: bool,
 
-/// Arguments:
-///   - icon_dir: The list of icons in the ICO file.
-///   - index: The index of the icon to get.
-/// Returns a `Vec<u8>`, containing the bytes to a PNG image, converted from the ICO image at the
-/// specified index.
-fn handle_ico(icon_dir: &IconDir, index: usize) -> Result<Vec<u8>> {
-    if icon_dir.entries().is_empty() {
-        return Err(anyhow!("No images found in the ICO file."));
-    } else if index >= icon_dir.entries().len() {
-        return Err(anyhow!("Invalid image index: {}.", index));
-    }
+    #[arg(
+        long,
+        help = "Encode every entry to every supported format and compare the sizes of the results."
+    )]
+    stats: bool,
+
+    #[arg(
+        long,
+        help = "Decode every entry, printing how long each took, without encoding or writing anything."
+    )]
+    decode_only: bool,
+
+    #[arg(
+        long,
+        help = "Check that the entries stored as PNG start with the PNG signature and a valid IHDR chunk, reporting those that don't."
+    )]
+    validate_png_signatures: bool,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Check the images extracted to DIR in a previous run, named {stem}_{index} and in the --format format, against the entries of the ICO file."
+    )]
+    validate_output: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Report the entries of the same size whose pixels are identical or look alike."
+    )]
+    detect_duplicate_entries: bool,
+
+    #[arg(
+        long,
+        help = "Print the metadata of the input file and its entries, without decoding them unless --analyze is given."
+    )]
+    info: bool,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Write the data of each entry, as stored in the ICO file, to this directory without decoding it."
+    )]
+    dump_raw: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "WIDTH",
+        requires = "dump_raw",
+        help = "Zero-pad the entry indices in the names written by --dump-raw to this many digits, instead of the digits of the last index."
+    )]
+    pad_index: Option<usize>,
+
+    #[arg(
+        long,
+        requires = "dump_raw",
+        conflicts_with = "pad_index",
+        help = "Name the files written by --dump-raw after the size and bit depth of their entry, as {stem}_{width}x{height}_{bpp}bpp, instead of its index."
+    )]
+    auto_rename: bool,
+
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        requires = "auto_rename",
+        default_value = "{stem}_{width}x{height}_{bpp}bpp",
+        help = "The names given by --auto-rename, without extension, with {stem}, {width}, {height} and {bpp} replaced."
+    )]
+    auto_rename_template: String,
+
+    #[arg(
+        long,
+        requires = "info",
+        help = "With --info, report on the sizes of every input file and on their totals."
+    )]
+    summary: bool,
+
+    #[arg(
+        long,
+        requires = "report",
+        help = "Print the --list or --summary report as CSV, with a header row."
+    )]
+    csv: bool,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        requires = "report",
+        help = "Write the --list or --summary report as CSV to this file instead of standard output."
+    )]
+    csv_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        requires = "summary",
+        conflicts_with_all = ["csv", "csv_file"],
+        help = "Print the --summary report as JSON."
+    )]
+    json: bool,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Write an image of the differences with the image of the same name in DIR, named {stem}_{index}_diff.png."
+    )]
+    diff_vs: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Re-read the written image and check that it decodes with the expected size."
+    )]
+    verify_output: bool,
+
+    #[arg(
+        long,
+        requires = "verify_output",
+        help = "Delete the output file if it fails verification."
+    )]
+    verify_delete_on_fail: bool,
+
+    #[arg(
+        long,
+        help = "Recover the complete entries of a truncated ICO file, skipping the others."
+    )]
+    recover: bool,
+
+    #[arg(
+        long,
+        help = "Compare the written image against the decoded entry, deleting it on mismatch."
+    )]
+    verify_after_write: bool,
+
+    #[arg(
+        long,
+        value_name = "DB",
+        help = "Minimum PSNR accepted by --verify-after-write for lossy formats.",
+        default_value = "30"
+    )]
+    verify_psnr: f64,
+
+    #[arg(
+        long,
+        conflicts_with = "file",
+        help = "Read length-prefixed ICO files from standard input, writing to the output directory."
+    )]
+    stdin_framed: bool,
+
+    #[arg(
+        long,
+        value_name = "COMMAND",
+        help = "Run this command on each written image, with {} replaced by its path and the output directory as working directory."
+    )]
+    post_process: Option<String>,
+
+    #[arg(
+        long,
+        requires = "post_process",
+        help = "Run the --post-process command through the shell, instead of splitting it into words."
+    )]
+    post_process_shell: bool,
+
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        value_parser = parse_seconds,
+        requires = "post_process",
+        help = "Kill the --post-process command, failing the conversion, after this many seconds."
+    )]
+    post_process_timeout: Option<f64>,
+
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        value_parser = parse_seconds,
+        help = "Stop converting, with exit code 7, once this many seconds have passed."
+    )]
+    time_limit: Option<f64>,
+
+    #[arg(
+        long,
+        help = "Fail if the output directory doesn't exist, instead of creating it."
+    )]
+    no_create_dir: bool,
+
+    #[arg(
+        long,
+        value_name = "UID:GID",
+        value_parser = owner::parse_owner,
+        help = "Give the written images and created directories this owner (Unix only)."
+    )]
+    chown: Option<(u32, u32)>,
+
+    #[arg(
+        long,
+        value_name = "MODE",
+        value_parser = owner::parse_mode,
+        help = "Give the written images these octal permissions, such as 644 (Unix only)."
+    )]
+    file_mode: Option<u32>,
+
+    #[arg(
+        long,
+        value_name = "MODE",
+        value_parser = owner::parse_mode,
+        help = "Give the created output directories these octal permissions, such as 755 (Unix only)."
+    )]
+    dir_mode: Option<u32>,
+
+    #[arg(
+        long,
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "The number of threads converting files in parallel when the input is a directory.",
+        default_value = "1"
+    )]
+    threads: u32,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Keep the converted images in this directory, to reuse them when converting the same entries with the same options again."
+    )]
+    cache_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "BYTES",
+        requires = "cache_dir",
+        help = "Evict the least recently used images of the cache when it grows over this size."
+    )]
+    cache_max_size: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "BYTES",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Approximate limit of the memory used by the decoded images of a directory input waiting to be written."
+    )]
+    memory_limit: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u16).range(1..),
+        help = "Read at most this many entries of each ICO file, ignoring the others.",
+        default_value = "256"
+    )]
+    max_entries_per_file: u16,
+
+    #[arg(long, help = "Fail the run if any warning is emitted.")]
+    abort_on_warning: bool,
+
+    #[arg(
+        long,
+        help = "Fail before converting anything if the output format, or the format of any entry in the format map, is lossy. BMP is lossy with --depth or --bmp-format rgb565."
+    )]
+    lossless_only: bool,
+
+    #[arg(
+        long,
+        value_name = "COLORS",
+        help = "Warn before encoding an image to a lossy format if it has at most this many colors, or transparency with sharp edges, which a lossless format keeps better. With --abort-on-warning, the image isn't written."
+    )]
+    max_colors_warn: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Print progress events (started, entry_decoded, entry_written, entry_failed and finished) to stderr, as one JSON object per line."
+    )]
+    progress_json: bool,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        num_args = 0..=1,
+        require_equals = true,
+        help = "On failure, write the error as a JSON object, with a code, the message, the input, the entry index and a hint, to stderr instead of the usual message, or to PATH with --errors-json=PATH."
+    )]
+    errors_json: Option<Option<PathBuf>>,
+
+    #[arg(long, help = "Skip inputs that fail to convert instead of stopping.")]
+    skip_errors: bool,
+
+    #[arg(
+        long,
+        value_name = "N",
+        default_value = "0",
+        help = "Retry reading input files and writing images up to N times when it fails with an I/O error, waiting longer before each retry."
+    )]
+    retries: u32,
+
+    #[arg(
+        short,
+        long,
+        help = "Search subdirectories too when the input is a directory."
+    )]
+    recursive: bool,
+
+    #[arg(
+        long,
+        value_name = "N",
+        requires = "recursive",
+        help = "Maximum depth of subdirectories to search (0 searches only the input directory)."
+    )]
+    max_depth: Option<usize>,
+
+    #[arg(
+        long,
+        requires = "recursive",
+        help = "Follow symbolic links when searching for ICO files."
+    )]
+    follow_symlinks: bool,
+
+    #[arg(
+        long,
+        requires = "recursive",
+        help = "Write the images of files found in subdirectories to the same subdirectories of the output directory."
+    )]
+    mirror_structure: bool,
+
+    #[arg(
+        long,
+        help = "Write the images of a directory, and the data written by --dump-raw, to subdirectories named by the bit depth of their entry, such as 32bpp."
+    )]
+    group_by_bpp: bool,
+
+    #[arg(
+        long,
+        help = "Fail if inputs of a directory would be converted to images with the same name, instead of renaming them."
+    )]
+    strict_names: bool,
+
+    #[arg(
+        long,
+        value_name = "IMAGE",
+        num_args = 1..,
+        help = "Add the images to the ICO file, which is rewritten in place unless -o is given."
+    )]
+    append: Vec<PathBuf>,
+
+    #[arg(
+        long,
+        requires = "append",
+        help = "Replace existing entries with the same size as an appended image."
+    )]
+    replace: bool,
+
+    #[arg(
+        long,
+        value_name = "WxH",
+        value_parser = parse_size,
+        help = "Remove the entries of the given size from the ICO file, which is rewritten in place unless -o is given."
+    )]
+    remove_size: Vec<(u32, u32)>,
+
+    #[arg(
+        long,
+        value_name = "MAPPING",
+        help = "Regenerate the images listed in a TOML mapping of entry sizes to output paths, exiting with 1 if any changed."
+    )]
+    sync: Option<PathBuf>,
+
+    #[arg(
+        long,
+        requires = "sync",
+        help = "With --sync, delete the outputs whose mapping was removed since the last sync."
+    )]
+    prune: bool,
+
+    #[arg(
+        long,
+        requires = "sync",
+        help = "With --sync, only report the outputs that are out of date, without writing them."
+    )]
+    check_only: bool,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with = "spritesheet",
+        help = "Convert every entry and pack the results into a new ICO file at this path."
+    )]
+    output_ico: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with = "file",
+        help = "Pack the images of a directory whose names match --pack-pattern into the ICO file given with -o."
+    )]
+    pack_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        requires = "pack_dir",
+        help = "The pattern of the names of the images to pack, where * stands for any text and {width}, {height} and {index} for numbers.",
+        default_value = "*_{width}x{height}.png"
+    )]
+    pack_pattern: String,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["resize", "canvas", "pot"],
+        help = "Pack every entry of the input files into a sprite sheet written to this path."
+    )]
+    spritesheet: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        requires = "spritesheet",
+        help = "Write a map of the offset of each entry in the sprite sheet to this path, as a CSS style sheet if it ends with .css, or as JSON otherwise."
+    )]
+    sprite_map: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Write a JSON summary of the conversion (inputs, outputs, failures, bytes written and duration) to this path."
+    )]
+    summary_json: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Pretty-print JSON output (sprite maps and summaries), which is compact by default."
+    )]
+    json_pretty: bool,
+
+    #[arg(
+        long,
+        value_name = "WxH",
+        value_parser = parse_size,
+        requires = "spritesheet",
+        help = "The size each entry is resized to in the sprite sheet.",
+        default_value = "32x32"
+    )]
+    cell: (u32, u32),
+
+    #[arg(
+        long,
+        value_parser = clap::value_parser!(u32).range(1..),
+        requires = "spritesheet",
+        help = "The maximum number of entries in a row of the sprite sheet.",
+        default_value = "8"
+    )]
+    columns: u32,
+
+    #[arg(
+        long,
+        requires = "spritesheet",
+        conflicts_with = "columns",
+        help = "Pack the sprite sheet as a single row (horizontal) or column (vertical) instead of a grid."
+    )]
+    strip: Option<sprite::Strip>,
+
+    #[arg(
+        long,
+        value_name = "PIXELS",
+        requires = "spritesheet",
+        help = "The transparent space between two entries of the sprite sheet.",
+        default_value = "0"
+    )]
+    spacing: u32,
+
+    #[arg(
+        long,
+        num_args = 2,
+        value_names = ["THEME", "NAME"],
+        conflicts_with_all = ["resize", "canvas", "pot", "spritesheet"],
+        help = "Install the entries of the input file as the icon NAME of the XDG icon theme THEME, in $XDG_DATA_HOME/icons, at each standard size."
+    )]
+    xdg_install: Option<Vec<String>>,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with_all = ["resize", "canvas", "pot", "spritesheet", "xdg_install"],
+        help = "Write the entries of the input file as the PNG images of an Apple iconset directory (icon_16x16.png, icon_16x16@2x.png, ...), for iconutil."
+    )]
+    iconset: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "SIZES",
+        value_delimiter = ',',
+        value_parser = clap::value_parser!(u32).range(1..),
+        conflicts_with_all = ["resize", "spritesheet", "xdg_install", "iconset"],
+        help = "Resize the largest selected entry to each of these comma-separated sizes, such as 16,32,180, writing one square image per size next to the output path."
+    )]
+    emit_sizes: Vec<u32>,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["spritesheet", "output_ico", "xdg_install", "iconset", "emit_sizes"],
+        help = "Join the selected entries side by side into a single image at the output path, each resized to the height of the tallest one."
+    )]
+    concat_horizontal: bool,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["spritesheet", "output_ico", "xdg_install", "iconset", "emit_sizes"],
+        help = "Join the selected entries from top to bottom into a single image at the output path, each resized to the width of the widest one."
+    )]
+    concat_vertical: bool,
+
+    #[arg(
+        long,
+        value_name = "PIXELS",
+        requires = "concat",
+        help = "The space between two images joined with --concat-horizontal or --concat-vertical.",
+        default_value = "0"
+    )]
+    concat_gap: u32,
+
+    #[arg(
+        long,
+        value_name = "RRGGBB[AA]",
+        requires = "concat",
+        help = "The color of the space between joined images, transparent by default."
+    )]
+    concat_background: Option<Color>,
+
+    #[arg(
+        long,
+        help = "With --output-ico, --pack-dir or --append, write images larger than 256x256, which the ICO format doesn't allow, instead of failing."
+    )]
+    allow_oversize: bool,
+
+    #[arg(
+        long,
+        value_name = "MODE",
+        help = "How to read the fourth byte of 32-bit BMP images added to ICO files: force (always as alpha), auto (as alpha if the image would otherwise be opaque) or ignore.",
+        default_value = "auto"
+    )]
+    bmp_alpha: BmpAlpha,
+
+    #[arg(long, help = "Allow edits that leave the ICO file without entries.")]
+    force: bool,
+
+    #[cfg(all(unix, feature = "serve"))]
+    #[arg(
+        long,
+        value_name = "SOCKET",
+        conflicts_with = "file",
+        help = "Serve conversion requests on a Unix domain socket."
+    )]
+    serve: Option<PathBuf>,
+
+    #[cfg(feature = "optimize")]
+    #[arg(long, help = "Losslessly optimize PNG images with oxipng.")]
+    optimize: bool,
+
+    #[cfg(feature = "check-update")]
+    #[arg(
+        long,
+        exclusive = true,
+        help = "Check whether a newer release of ico2img is available on GitHub."
+    )]
+    check_update: bool,
+
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Index of the frame to convert from an ANI file, starting at 0."
+    )]
+    ani_frame: Option<usize>,
+
+    #[cfg(feature = "icl")]
+    #[arg(short, long, help = "ID of the icon group to convert from an ICL file.")]
+    group: Option<String>,
+
+    // the parsed arguments, with the configuration file merged in, for --export-config
+    #[arg(skip)]
+    matches: ArgMatches,
+}
+
+fn main() -> Result<()> {
+    let args = parse_args()?;
+    style::set(if args.no_color {
+        ColorChoice::Never
+    } else {
+        args.color
+    });
+    let abort_on_warning = args.abort_on_warning;
+    let (errors_json, input) = (args.errors_json.clone(), args.file.clone());
+    if args.progress_json {
+        progress::enable();
+    }
+    progress::emit(
+        "started",
+        serde_json::json!({ "input": args.file, "output": args.output }),
+    );
+    let result = run(args);
+    progress::emit(
+        "finished",
+        serde_json::json!({
+            "success": result.is_ok(),
+            "error": result.as_ref().err().map(ToString::to_string),
+            "warnings": warning::count(),
+        }),
+    );
+    let exit_code = match result {
+        Err(ref e) if e.is::<TimeLimitExceeded>() => TIME_LIMIT_EXIT_CODE,
+        _ => 1,
+    };
+    if let (Err(e), Some(path)) = (&result, errors_json) {
+        let json = error_json(e, input.as_deref()).to_string();
+        match path {
+            Some(path) => fs::write(path, json + "\n")?,
+            // the JSON object replaces the message, so that standard error can be parsed
+            None => {
+                eprintln!("{}", json);
+                process::exit(exit_code);
+            }
+        }
+    }
+    if let Err(ref e) = result {
+        if exit_code != 1 {
+            eprintln!("Error: {:#}", e);
+            process::exit(exit_code);
+        }
+    }
+    result?;
+
+    let warnings = warning::count();
+    if abort_on_warning && warnings > 0 {
+        return Err(anyhow!(
+            "{} warning(s) were emitted, failing because of --abort-on-warning.",
+            warnings
+        ));
+    }
+    Ok(())
+}
+
+/// Arguments:
+///   - error: The error that failed the run.
+///   - input: The input path, if any.
+///
+/// Returns the JSON object of `--errors-json` describing `error`: its `code`, from
+/// [`Ico2imgError::code`] for errors of the library, its `message`, the `input`, the `index` of
+/// the entry it's about, if any, and a `hint` at how to fix it.
+fn error_json(error: &anyhow::Error, input: Option<&Path>) -> serde_json::Value {
+    let library_error = error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<ico2img::Ico2imgError>());
+    let code = match library_error {
+        Some(e) => e.code(),
+        None if error.is::<TimeLimitExceeded>() => "time_limit_exceeded",
+        None if error.chain().any(|cause| cause.is::<io::Error>()) => "io_error",
+        None => "error",
+    };
+    let hint = match code {
+        "file_not_found" => Some("Check the path of the input file."),
+        "io_error" => Some("Check that the files can be read and written, with space left."),
+        "ico_parse_error" => Some("The file may be damaged or not an ICO file; try --recover."),
+        "index_out_of_bounds" => Some("List the entries with --list, and pick an index from them."),
+        "decode_error" => Some("The entry may be damaged; see --validate-png-signatures."),
+        "encode_error" => Some("Try another output format with -f."),
+        "write_error" => Some("Check that the output directory exists and can be written to."),
+        "invalid_argument" => Some("Check the options against --help."),
+        "time_limit_exceeded" => Some("Raise --time-limit, or convert fewer files at once."),
+        _ => None,
+    };
+
+    serde_json::json!({
+        "code": code,
+        "message": format!("{:#}", error),
+        "input": input,
+        "index": library_error.and_then(ico2img::Ico2imgError::entry_index),
+        "hint": hint,
+    })
+}
+
+fn run(args: Args) -> Result<()> {
+    if args.timings {
+        timing::enable();
+    }
+
+    if args.list_formats {
+        print_formats();
+        return Ok(());
+    }
+
+    #[cfg(feature = "check-update")]
+    if args.check_update {
+        return update::check_update();
+    }
+
+    if args.dump_config {
+        let config = read_config(&args)?.unwrap_or_default();
+        print!("{}", toml::to_string(&config)?);
+        return Ok(());
+    }
+
+    if let Some(ref dir) = args.pack_dir {
+        let output = args
+            .output
+            .as_ref()
+            .ok_or_else(|| anyhow!("No output path specified."))?;
+        return edit::pack_images_from_dir(
+            dir,
+            &args.pack_pattern,
+            output,
+            args.allow_oversize,
+            args.bmp_alpha,
+        );
+    }
+
+    if args.list {
+        return list_entries(&args);
+    }
+
+    if let Some(ref mapping) = args.sync {
+        let icon_dir = read_icon_dir(&args, input_path(&args)?)?;
+        let changed = sync::sync(
+            &icon_dir,
+            mapping,
+            args.filter,
+            args.trust_directory,
+            args.prune,
+            args.check_only,
+        )?;
+        if changed {
+            process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if args.info {
+        return print_info(&args);
+    }
+
+    if let Some(ref dir) = args.dump_raw {
+        return dump_raw(&args, dir);
+    }
+
+    if args.stats {
+        return print_stats(&args);
+    }
+
+    if args.decode_only {
+        return check_decode(&args);
+    }
+
+    if args.validate_png_signatures {
+        return validate_png_signatures(&args);
+    }
+
+    if let Some(ref dir) = args.validate_output {
+        return validate_output(&args, dir);
+    }
+
+    if args.detect_duplicate_entries {
+        return detect_duplicate_entries(&args);
+    }
+
+    #[cfg(all(unix, feature = "serve"))]
+    if let Some(ref socket) = args.serve {
+        return serve::serve(socket, args.verbose);
+    }
+
+    if !args.append.is_empty() || !args.remove_size.is_empty() {
+        let mut icon_dir = read_icon_dir(&args, input_path(&args)?)?;
+        if !args.remove_size.is_empty() {
+            icon_dir = edit::remove_sizes(&icon_dir, &args.remove_size, args.trust_directory);
+        }
+        if !args.append.is_empty() {
+            icon_dir = edit::append_images(
+                &icon_dir,
+                &args.append,
+                args.replace,
+                args.trust_directory,
+                args.bmp_alpha,
+                args.allow_oversize,
+            )?;
+        }
+
+        if icon_dir.entries().is_empty() && !args.force {
+            return Err(anyhow!(
+                "The edit would leave the ICO file without entries, use --force to allow it."
+            ));
+        }
+        return write_icon_dir(&args, &icon_dir);
+    }
+
+    let options = ExtractOptions::from(&args)
+        .format(read_format(&args))
+        .build()?;
+    if args.lossless_only {
+        check_lossless(&args, options.get_format())?;
+    }
+
+    let single_output = args.output_ico.is_none()
+        && args.spritesheet.is_none()
+        && args.xdg_install.is_none()
+        && args.iconset.is_none()
+        && args.emit_sizes.is_empty()
+        && !args.concat_horizontal
+        && !args.concat_vertical;
+    let both_variants = args.variant == Some(Variant::Both);
+    if args.crop.len() > 1 && (!single_output || both_variants) {
+        return Err(anyhow!(
+            "Several --crop rectangles can only be used to convert an entry to an image per rectangle."
+        ));
+    }
+    if both_variants && !single_output {
+        return Err(anyhow!(
+            "--variant both can only be used to convert an entry to an image per theme."
+        ));
+    }
+
+    if let Some(ref path) = args.export_config {
+        let config = toml::to_string(&resolved_config(&args))?;
+        if path.as_os_str() == "-" {
+            print!("{}", config);
+        } else {
+            fs::write(path, config)?;
+        }
+        return Ok(());
+    }
+
+    #[cfg(feature = "optimize")]
+    if args.optimize && options.get_format() != SupportedImages::Png {
+        warning::warn(format_args!("--optimize only applies to PNG images."));
+    }
+    if args.write_xmp
+        && !matches!(
+            options.get_format(),
+            SupportedImages::Png | SupportedImages::Jpeg
+        )
+    {
+        return Err(anyhow!("--write-xmp only applies to PNG and JPEG images."));
+    }
+
+    #[cfg(not(unix))]
+    if args.chown.is_some() || args.file_mode.is_some() || args.dir_mode.is_some() {
+        warning::warn(format_args!(
+            "--chown, --file-mode and --dir-mode only apply on Unix, and are ignored."
+        ));
+    }
+
+    if args.jpeg_subsampling.is_some() && options.get_format() != SupportedImages::Jpeg {
+        warning::warn(format_args!(
+            "--jpeg-subsampling only applies to JPEG images."
+        ));
+    }
+
+    // check the post-processing command before converting anything
+    if let Some(ref command) = args.post_process {
+        if !args.post_process_shell {
+            hook::split_command(command)?;
+        }
+    }
+
+    if let Some(ref ico_path) = args.output_ico {
+        let icon_dir = read_icon_dir(&args, input_path(&args)?)?;
+        let images = selected_entries(&args, &icon_dir)
+            .into_iter()
+            .map(|index| {
+                let stage = timing::start("decode", &format!("entry {}", index));
+                let image = ico2img::decode_entry(&icon_dir, index)?;
+                timing::finish(stage);
+                Ok(ico2img::transform(image, &options)?)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if images.is_empty() {
+            return Err(anyhow!("No entries are selected."));
+        }
+        if args.verbose {
+            println!("Packing {} entries into {}", images.len(), ico_path.display());
+        }
+        let stage = timing::start("write", &ico_path.display().to_string());
+        edit::pack_into_ico(images, ico_path, args.allow_oversize)?;
+        timing::finish(stage);
+        if let Some(timer) = timing::timer() {
+            timer.print();
+        }
+        return Ok(());
+    }
+
+    if let Some(ref sheet_path) = args.spritesheet {
+        return make_spritesheet(&args, &options, sheet_path);
+    }
+
+    if let Some(ref theme_and_name) = args.xdg_install {
+        return install_xdg_icons(&args, &options, &theme_and_name[0], &theme_and_name[1]);
+    }
+
+    if let Some(ref dir) = args.iconset {
+        return write_iconset(&args, &options, dir);
+    }
+
+    if args.preview || args.preview_only {
+        print_preview(&args)?;
+        if args.preview_only {
+            return Ok(());
+        }
+    }
+
+    let output = args
+        .output
+        .as_ref()
+        .ok_or_else(|| anyhow!("No output path specified."))?;
+
+    if !args.emit_sizes.is_empty() {
+        return emit_sizes(&args, &options, output);
+    }
+
+    if args.concat_horizontal || args.concat_vertical {
+        return concat_entries(&args, &options, output);
+    }
+
+    if args.crop.len() > 1 {
+        return crop_entries(&args, &options, output);
+    }
+
+    if both_variants {
+        return emit_variants(&args, &options, output);
+    }
+
+    // the summary is written even if the conversion fails, to record what was done until then
+    let cache = match args.cache_dir {
+        Some(ref dir) => Some(Cache::open(dir, args.cache_max_size)?),
+        None => None,
+    };
+    let mut summary = RunSummary::new();
+    let result = convert(&args, &options, output, &mut summary, cache.as_ref());
+    if let Some(ref summary_path) = args.summary_json {
+        summary.write(summary_path, args.json_pretty)?;
+    }
+    if let Some(ref cache) = cache {
+        cache.save()?;
+    }
+    if let Some(timer) = timing::timer() {
+        timer.print();
+    }
+    result
+}
+
+/// Arguments:
+///   - args: The command-line arguments.
+///   - options: The conversion options.
+///   - output: The path to the resulting image, or the output directory.
+///   - summary: The summary to record the outputs and failures in.
+///   - cache: The cache of converted images, if any.
+///
+/// Converts the input file, the ICO files of the input directory or the ICO files read from
+/// standard input.
+fn convert(
+    args: &Args,
+    options: &ExtractOptions,
+    output: &Path,
+    summary: &mut RunSummary,
+    cache: Option<&Cache>,
+) -> Result<()> {
+    if args.stdin_framed {
+        return convert_stdin_frames(args, options, output, summary);
+    }
+
+    let input = input_path(args)?;
+    if input.is_dir() {
+        return convert_dir(args, options, input, output, summary, cache);
+    }
+
+    let result = render_file(args, options, cache, input, |icon_dir| {
+        if args.verbose {
+            print_entry_details(args, icon_dir);
+        }
+        render_entry(args, icon_dir, options)
+    })
+    .and_then(|(image, buffer)| {
+        let buffer = with_xmp(args, options, input, &image, buffer)?;
+        write_entry(args, &image, &buffer, output)?;
+        Ok(buffer.len())
+    });
+    match &result {
+        Ok(bytes) => summary.add_output(*bytes),
+        Err(e) => summary.add_failure(input.display().to_string(), e),
+    }
+    result.map(|_| ())
+}
+
+/// Prints the number of entries of the ICO file, and the size and bit depth of the selected one.
+fn print_entry_details(args: &Args, icon_dir: &IconDir) {
+    println!(
+        "Number of entries in ICO file: {}",
+        icon_dir.entries().len()
+    );
+
+    // an index out of bounds fails the conversion right after
+    let Some(entry) = icon_dir.entries().get(args.image_index) else {
+        return;
+    };
+    let (width, height) = entry_size(entry, args.trust_directory);
+    println!(
+        "Image details: {}x{} - {} bits per pixel",
+        width,
+        height,
+        entry.bits_per_pixel()
+    );
+}
+
+impl From<&Args> for ExtractOptions {
+    fn from(args: &Args) -> Self {
+        let mut options = transform_options(args)
+            .format(args.format)
+            .keep_icc(args.keep_icc)
+            .png_indexed(args.png_indexed)
+            .reencode(args.reencode);
+
+        if let Some(quality) = args.jpeg_quality {
+            options = options.jpeg_quality(quality);
+        }
+        if let Some(depth) = args.depth {
+            options = options.bmp_depth(depth);
+        }
+        if let Some(format) = args.bmp_format {
+            options = options.bmp_format(format);
+        }
+        if let Some(palette) = args.palette {
+            options = options.palette(palette);
+        }
+        if let Some(dither) = args.dither {
+            options = options.dither(dither);
+        }
+        if let Some(subsampling) = args.jpeg_subsampling {
+            options = options.jpeg_subsampling(subsampling);
+        }
+
+        options
+    }
+}
+
+/// Returns the options of the pixel transformations given on the command line, without any of
+/// the format or its encoder settings.
+fn transform_options(args: &Args) -> ExtractOptions {
+    let mut options = ExtractOptions::new().filter(args.filter);
+
+    // several rectangles are applied one at a time, by crop_entries
+    if let [rect] = args.crop[..] {
+        options = options.crop(rect);
+    }
+    if let Some(anchor) = args.auto_align {
+        options = options.auto_align(anchor);
+    }
+    if let Some((width, height)) = args.resize {
+        options = options.resize(width, height);
+    }
+    if let Some(color) = args.background {
+        options = options.background(color);
+    }
+    // both variants are composited one at a time, by emit_variants
+    match args.variant {
+        Some(Variant::Light) => options = options.background(args.light_background),
+        Some(Variant::Dark) => options = options.background(args.dark_background),
+        Some(Variant::Both) | None => {}
+    }
+    if args.force_rgba {
+        options = options.channels(ChannelLayout::Rgba);
+    } else if args.force_rgb {
+        options = options.channels(ChannelLayout::Rgb);
+    }
+    if let Some((width, height)) = args.canvas {
+        options = options
+            .canvas(width, height)
+            .gravity(args.gravity)
+            .allow_crop(args.allow_crop);
+    }
+    if args.pot {
+        options = options.pad_to_power_of_two(args.pot_anchor);
+    }
+    if let Some(degrees) = args.hue_shift {
+        options = options.hue_shift(degrees);
+    }
+    if let Some(tint) = args.tint {
+        options = options.tint(tint);
+    }
+
+    options
+}
+
+/// Returns the output format: the format mapped to the selected entry by `--format-map`, if any,
+/// or the one of `-f`.
+fn read_format(args: &Args) -> SupportedImages {
+    args.format_map
+        .as_ref()
+        .and_then(|map| map.get(&args.image_index))
+        .copied()
+        .unwrap_or(args.format)
+}
+
+/// Fails if `format`, the output format, or any format of `--format-map` is lossy, naming it,
+/// for `--lossless-only`. BMP is lossy too when images are reduced to a palette by `--depth` or
+/// to 16 bits by `--bmp-format rgb565`.
+fn check_lossless(args: &Args, format: SupportedImages) -> Result<()> {
+    let mut formats = vec![format];
+    if let Some(ref map) = args.format_map {
+        formats.extend(map.values());
+    }
+
+    let reduction = match (args.depth, args.bmp_format) {
+        (Some(_), _) => Some("--depth"),
+        (_, Some(BmpFormat::Rgb565)) => Some("--bmp-format rgb565"),
+        _ => None,
+    };
+    if let Some(reduction) = reduction {
+        if formats.contains(&SupportedImages::Bmp) {
+            return Err(anyhow!(
+                "BMP images are lossy with {}, and --lossless-only was given.",
+                reduction
+            ));
+        }
+    }
+
+    let lossy = formats
+        .into_iter()
+        .find(|format| format.capabilities().lossy);
+    match lossy {
+        Some(format) => Err(anyhow!(
+            "The {} format is lossy, and --lossless-only was given.",
+            format
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Returns the command-line arguments, with the options set in the configuration file (with its
+/// profile merged in) filled in wherever they aren't given on the command line.
+fn parse_args() -> Result<Args> {
+    let argv: Vec<OsString> = env::args_os().collect();
+    let matches = Args::command().get_matches_from(&argv);
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let Some(config) = read_config(&args)? else {
+        return Ok(Args { matches, ..args });
+    };
+
+    // everything after `--` is an input, so the options of the configuration file go before it
+    let end = argv
+        .iter()
+        .position(|arg| arg == "--")
+        .unwrap_or(argv.len());
+    let mut merged = argv[..end].to_vec();
+    merged.extend(config_args(&matches, &config)?);
+    merged.extend_from_slice(&argv[end..]);
+
+    let matches = Args::command().get_matches_from(&merged);
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    Ok(Args { matches, ..args })
+}
+
+/// Returns the options that a configuration file can set: those that aren't inputs, nor select or
+/// print the configuration.
+fn configurable_args(command: &clap::Command) -> impl Iterator<Item = &clap::Arg> {
+    command.get_arguments().filter(|arg| {
+        !arg.is_positional()
+            && ![
+                "config",
+                "profile",
+                "dump_config",
+                "export_config",
+                "help",
+                "version",
+            ]
+            .contains(&arg.get_id().as_str())
+    })
+}
+
+/// Arguments:
+///   - matches: The arguments given on the command line.
+///   - config: The options set in the configuration file.
+///
+/// Returns the command-line arguments setting the options of `config`, except for those given on
+/// the command line, which take precedence, and those set to their default value. The options
+/// are named like the fields of `--export-config`, or like their long flag. Fails on names that
+/// aren't options.
+fn config_args(matches: &ArgMatches, config: &Table) -> Result<Vec<OsString>> {
+    let mut command = Args::command();
+    command.build();
+
+    let mut args = Vec::new();
+    for (key, value) in config {
+        let name = key.replace('-', "_");
+        let arg = configurable_args(&command)
+            .find(|arg| {
+                arg.get_id() == name.as_str()
+                    || arg.get_long().map(|long| long.replace('-', "_")) == Some(name.clone())
+            })
+            .ok_or_else(|| anyhow!("Unknown option {} in the configuration file.", key))?;
+        let id = arg.get_id().as_str();
+        if matches.value_source(id) == Some(ValueSource::CommandLine) {
+            continue;
+        }
+
+        let flag = match arg.get_long() {
+            Some(long) => format!("--{}", long),
+            None => format!("-{}", arg.get_short().unwrap_or_default()),
+        };
+        let takes_values = arg.get_action().takes_values();
+        let optional_value = arg
+            .get_num_args()
+            .is_some_and(|range| range.min_values() == 0);
+        if let Value::Boolean(set) = value {
+            if !takes_values || optional_value {
+                if *set {
+                    args.push(flag.into());
+                }
+                continue;
+            }
+        } else if !takes_values {
+            return Err(anyhow!("{} must be true or false.", key));
+        }
+
+        let values = match value {
+            Value::Array(values) => values
+                .iter()
+                .map(|value| config_value(key, value))
+                .collect::<Result<Vec<_>>>()?,
+            value => vec![config_value(key, value)?],
+        };
+        if let [value] = values.as_slice() {
+            if arg.get_default_values() == [value.as_str()] {
+                continue;
+            }
+        }
+
+        let value_count = arg.get_num_args().map_or(1, |range| range.min_values());
+        if let Some(delimiter) = arg.get_value_delimiter() {
+            args.push(format!("{}={}", flag, values.join(&delimiter.to_string())).into());
+        } else if value_count > 1 {
+            // options taking several values at once, like --xdg-install
+            args.push(flag.into());
+            args.extend(values.into_iter().map(OsString::from));
+        } else {
+            args.extend(
+                values
+                    .iter()
+                    .map(|value| format!("{}={}", flag, value).into()),
+            );
+        }
+    }
+    Ok(args)
+}
+
+/// Returns the `value` of the `key` option of the configuration file as it's given on the command
+/// line. Tables, like `format_map`, are given as comma-separated `KEY:VALUE` pairs.
+fn config_value(key: &str, value: &Value) -> Result<String> {
+    match value {
+        Value::String(value) => Ok(value.clone()),
+        Value::Integer(value) => Ok(value.to_string()),
+        Value::Float(value) => Ok(value.to_string()),
+        Value::Boolean(value) => Ok(value.to_string()),
+        Value::Table(table) => Ok(table
+            .iter()
+            .map(|(name, value)| Ok(format!("{}:{}", name, config_value(key, value)?)))
+            .collect::<Result<Vec<_>>>()?
+            .join(",")),
+        _ => Err(anyhow!(
+            "Invalid value for {} in the configuration file.",
+            key
+        )),
+    }
+}
+
+/// Returns a configuration file setting every option that can be set in one as it's resolved
+/// from the command line, the configuration file (with its profile merged in) and the defaults,
+/// so that loading it with `-c` converts images the same way.
+fn resolved_config(args: &Args) -> Table {
+    let mut command = Args::command();
+    command.build();
+
+    let mut options = Table::new();
+    for arg in configurable_args(&command) {
+        let id = arg.get_id().as_str();
+        let Some(raw) = args.matches.get_raw(id) else {
+            continue;
+        };
+        let values: Vec<String> = raw
+            .map(|value| value.to_string_lossy().into_owned())
+            .collect();
+
+        let value = if !arg.get_action().takes_values() {
+            Value::Boolean(values.iter().any(|value| value == "true"))
+        } else if values.is_empty() {
+            // options whose value is optional, like --errors-json, given without one
+            Value::Boolean(true)
+        } else if id == "format_map" {
+            let mut indices: Vec<_> = args.format_map.iter().flatten().collect();
+            indices.sort_by_key(|&(index, _)| index);
+            Value::Table(
+                indices
+                    .into_iter()
+                    .map(|(index, format)| (index.to_string(), format.to_string().into()))
+                    .collect(),
+            )
+        } else if matches!(arg.get_action(), ArgAction::Append)
+            || arg
+                .get_num_args()
+                .is_some_and(|range| range.max_values() > 1)
+        {
+            Value::Array(values.into_iter().map(config_number).collect())
+        } else {
+            config_number(values.into_iter().next().unwrap_or_default())
+        };
+        options.insert(id.to_string(), value);
+    }
+
+    let mut config = Table::new();
+    config.insert("ico2img".to_string(), options.into());
+    config
+}
+
+/// Returns `value` as a TOML integer if it's one, written the same way, or as a string otherwise.
+fn config_number(value: String) -> Value {
+    match value.parse::<i64>() {
+        Ok(number) if number.to_string() == value => Value::Integer(number),
+        _ => Value::String(value),
+    }
+}
+
+/// Returns the options set in the configuration file, if one is provided: the `[ico2img]` table,
+/// with the `[profile.<NAME>]` table selected by `--profile` merged over it.
+fn read_config(args: &Args) -> Result<Option<Table>> {
+    let Some(ref conf) = args.config else {
+        return Ok(None);
+    };
+
+    let mut reader = BufReader::new(File::open(conf)?);
+    let mut contents = String::new();
+    reader.read_to_string(&mut contents)?;
+    let mut config: Table = toml::from_str(contents.as_str())?;
+
+    let mut options = match config.remove("ico2img") {
+        Some(Value::Table(table)) => table,
+        Some(_) => return Err(anyhow!("The ico2img section of the configuration isn't a table.")),
+        None => Table::new(),
+    };
+
+    if let Some(ref name) = args.profile {
+        let mut profiles = match config.remove("profile") {
+            Some(Value::Table(table)) => table,
+            _ => Table::new(),
+        };
+
+        match profiles.remove(name) {
+            Some(Value::Table(profile)) => options.extend(profile),
+            Some(_) => return Err(anyhow!("The profile {} isn't a table.", name)),
+            None if profiles.is_empty() => {
+                return Err(anyhow!(
+                    "No profile named {}, the configuration has no profiles.",
+                    name
+                ))
+            }
+            None => {
+                let names: Vec<&str> = profiles.keys().map(String::as_str).collect();
+                return Err(anyhow!(
+                    "No profile named {}, the defined profiles are: {}.",
+                    name,
+                    names.join(", ")
+                ));
+            }
+        }
+    }
+
+    Ok(Some(options))
+}
+
+/// Arguments:
+///   - args: The command-line arguments.
+///   - icon_dir: The list of icons in the ICO file.
+///   - options: The conversion options.
+///   - output: The path to the resulting image.
+///
+/// Converts the selected entry of the ICO file, writes it to `output` and verifies it if asked
+/// to. Returns the size of the written image, in bytes.
+fn convert_entry(
+    args: &Args,
+    icon_dir: &IconDir,
+    options: &ExtractOptions,
+    output: &Path,
+) -> Result<usize> {
+    let (image, buffer) = render_entry(args, icon_dir, options)?;
+    write_entry(args, &image, &buffer, output)?;
+    Ok(buffer.len())
+}
+
+/// Arguments:
+///   - args: The command-line arguments.
+///   - options: The conversion options.
+///   - cache: The cache of converted images, if any.
+///   - file: The path to the ICO file.
+///   - render: Converts the selected entry of the ICO file once it's read.
+///
+/// Returns the selected entry of the ICO file, converted, along with its encoded bytes. The
+/// encoded bytes are taken from the cache if they're there, and stored in it otherwise. The
+/// entry is still decoded and transformed on a cache hit, so that it's checked and reported the
+/// same way, and so that masks, diffs and verification see the converted pixels rather than the
+/// encoded ones.
+fn render_file(
+    args: &Args,
+    options: &ExtractOptions,
+    cache: Option<&Cache>,
+    file: &Path,
+    render: impl FnOnce(&IconDir) -> Result<(DynamicImage, Vec<u8>)>,
+) -> Result<(DynamicImage, Vec<u8>)> {
+    let Some(cache) = cache else {
+        return render(&read_icon_dir(args, file)?);
+    };
+
+    let key = Cache::key(file, args.image_index, &cache_fingerprint(args, options))?;
+    let ext = options.get_format().extension();
+    if let Some(buffer) = cache.get(&key, ext) {
+        let icon_dir = read_icon_dir(args, file)?;
+        let image = decode_selected(args, &icon_dir)?;
+        let image = if ico2img::stored_png(&icon_dir, args.image_index, options).is_some() {
+            image
+        } else {
+            ico2img::transform(image, options)?
+        };
+        if args.verbose {
+            println!("Using the cached image of {}", file.display());
+        }
+        return Ok((image, buffer));
+    }
+
+    let (image, buffer) = render(&read_icon_dir(args, file)?)?;
+    cache.put(&key, ext, &buffer)?;
+    Ok((image, buffer))
+}
+
+/// Returns a description of everything that changes the converted images, for their cache key:
+/// the conversion options, and the options changing which entry is read and how.
+fn cache_fingerprint(args: &Args, options: &ExtractOptions) -> String {
+    #[cfg(feature = "optimize")]
+    let optimize = args.optimize;
+    #[cfg(not(feature = "optimize"))]
+    let optimize = false;
+    #[cfg(feature = "icl")]
+    let group = args.group.as_deref().unwrap_or_default();
+    #[cfg(not(feature = "icl"))]
+    let group = "";
+
+    format!(
+        "{}trust_directory={}\noptimize={}\nrecover={}\nmax_entries_per_file={}\nani_frame={}\n\
+         group={}\n",
+        options.fingerprint(),
+        args.trust_directory,
+        optimize,
+        args.recover,
+        args.max_entries_per_file,
+        args.ani_frame
+            .map_or("none".to_string(), |frame| frame.to_string()),
+        group
+    )
+}
+
+/// Arguments:
+///   - args: The command-line arguments.
+///   - icon_dir: The list of icons in the ICO file.
+///   - options: The conversion options.
+///
+/// Returns the selected entry of the ICO file, converted, along with its encoded bytes.
+fn render_entry(
+    args: &Args,
+    icon_dir: &IconDir,
+    options: &ExtractOptions,
+) -> Result<(DynamicImage, Vec<u8>)> {
+    let label = format!("entry {}", args.image_index);
+    let image = decode_selected(args, icon_dir)?;
+
+    if let Some(data) = ico2img::stored_png(icon_dir, args.image_index, options) {
+        if args.verbose {
+            println!(
+                "Copying the PNG data of entry {} as it is",
+                args.image_index
+            );
+        }
+        #[cfg(feature = "optimize")]
+        let data = if args.optimize {
+            optimize_png(data.into_owned(), args.verbose)?
+        } else {
+            data.into_owned()
+        };
+        #[cfg(not(feature = "optimize"))]
+        let data = data.into_owned();
+        return Ok((image, data));
+    }
+
+    let stage = timing::start("transform", &label);
+    let image = ico2img::transform(image, options)?;
+    timing::finish(stage);
+    check_lossy_quality(args, &image, options)?;
+
+    let stage = timing::start("encode", &label);
+    let buffer = ico2img::encode(&image, options)?;
+    if args.png_indexed && args.verbose {
+        report_indexed_png(&image, options, &buffer)?;
+    }
+    let buffer = ico2img::keep_color_profile(icon_dir, args.image_index, buffer, options);
+
+    #[cfg(feature = "optimize")]
+    let buffer = if args.optimize && options.get_format() == SupportedImages::Png {
+        optimize_png(buffer, args.verbose)?
+    } else {
+        buffer
+    };
+    timing::finish(stage);
+
+    Ok((image, buffer))
+}
+
+/// Returns the selected entry of the ICO file, decoded, after reporting it with `--progress-json`.
+/// Fails if it doesn't have the transparency required by `--require-alpha` or
+/// `--require-opaque`.
+fn decode_selected(args: &Args, icon_dir: &IconDir) -> Result<DynamicImage> {
+    let stage = timing::start("decode", &format!("entry {}", args.image_index));
+    let image = ico2img::decode_entry(icon_dir, args.image_index)?;
+    timing::finish(stage);
+    progress::emit(
+        "entry_decoded",
+        serde_json::json!({
+            "index": args.image_index,
+            "width": image.width(),
+            "height": image.height(),
+        }),
+    );
+    if !matches_alpha_requirement(args, &image) {
+        let (found, flag) = if args.require_alpha {
+            ("opaque", "--require-alpha")
+        } else {
+            ("transparent", "--require-opaque")
+        };
+        return Err(anyhow!(
+            "Entry {} is {}, which {} excludes.",
+            args.image_index,
+            found,
+            flag
+        ));
+    }
+    Ok(image)
+}
+
+/// Arguments:
+///   - args: The command-line arguments.
+///   - options: The conversion options.
+///   - source: The ICO file the image was converted from.
+///   - image: The converted image.
+///   - buffer: The encoded bytes of the image.
+///
+/// Returns `buffer` with XMP metadata describing the image embedded in it if `--write-xmp` is
+/// given, or as it is otherwise.
+fn with_xmp(
+    args: &Args,
+    options: &ExtractOptions,
+    source: &Path,
+    image: &DynamicImage,
+    buffer: Vec<u8>,
+) -> Result<Vec<u8>> {
+    if !args.write_xmp {
+        return Ok(buffer);
+    }
+
+    let template = match args.xmp_template {
+        Some(ref path) => fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?,
+        None => metadata::DEFAULT_TEMPLATE.to_string(),
+    };
+    let packet = metadata::packet(
+        &template,
+        &metadata::source_uri(source),
+        args.image_index,
+        (image.width(), image.height()),
+    );
+    Ok(ico2img::embed_xmp(buffer, options.get_format(), &packet)?)
+}
+
+/// Arguments:
+///   - args: The command-line arguments.
+///   - image: The converted image.
+///   - buffer: The encoded bytes of the image.
+///   - output: The path to the resulting image.
+///
+/// Writes the converted image to `output`, along with its mask and diff if asked to, verifies it
+/// if asked to and runs the post-processing command on it.
+fn write_entry(args: &Args, image: &DynamicImage, buffer: &[u8], output: &Path) -> Result<()> {
+    let stage = timing::start("write", &output.display().to_string());
+    let what = format!("writing {}", output.display());
+    retry::retry(args.retries, &what, || {
+        let mut writer = BufWriter::new(File::create(output)?);
+        writer.write_all(buffer)?;
+        writer.flush()
+    })?;
+    timing::finish(stage);
+    owner::apply(output, args.chown, args.file_mode);
+    progress::emit(
+        "entry_written",
+        serde_json::json!({
+            "index": args.image_index,
+            "path": output,
+            "bytes": buffer.len(),
+        }),
+    );
+
+    if args.export_mask {
+        let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+        let mask_path = paths::fit_output_path(&output.with_file_name(format!(
+            "{}_{}_mask.{}",
+            stem,
+            args.image_index,
+            args.mask_format.extension()
+        )));
+        Mask::from_alpha(
+            &image.to_rgba8(),
+            args.mask_threshold,
+            args.mask_dither,
+            args.mask_invert,
+        )
+        .write(&mask_path, args.mask_format)?;
+        owner::apply(&mask_path, args.chown, args.file_mode);
+
+        if args.verbose {
+            println!("Wrote mask to {}", mask_path.display());
+        }
+    }
+
+    if let Some(ref previous_dir) = args.diff_vs {
+        let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+        let diff_path = paths::fit_output_path(
+            &output.with_file_name(format!("{}_{}_diff.png", stem, args.image_index)),
+        );
+        let previous_path = previous_dir.join(output.file_name().unwrap_or_default());
+        diff::write_diff_image(image, &previous_path, &diff_path)?;
+        owner::apply(&diff_path, args.chown, args.file_mode);
+
+        if args.verbose {
+            println!("Wrote diff to {}", diff_path.display());
+        }
+    }
+
+    if args.verify_output {
+        if let Err(e) = verify_output_file(output, image.width(), image.height()) {
+            if args.verify_delete_on_fail {
+                fs::remove_file(output)?;
+            }
+            return Err(e);
+        }
+    }
+
+    if args.verify_after_write {
+        let start = Instant::now();
+        let stage = timing::start("verify", &output.display().to_string());
+        let result = verify_round_trip(args, output, image);
+        timing::finish(stage);
+        if let Err(e) = result {
+            fs::remove_file(output)?;
+            return Err(e);
+        }
+
+        if args.verbose {
+            println!("Verification took {:.2?}", start.elapsed());
+        }
+    }
+
+    if let Some(ref command) = args.post_process {
+        hook::post_process(
+            command,
+            args.post_process_shell,
+            args.post_process_timeout,
+            output,
+        )?;
+
+        if args.verbose {
+            println!("Post-processed {}", output.display());
+        }
+    }
+
+    Ok(())
+}
+
+/// Arguments:
+///   - buffer: The bytes of a PNG image.
+///   - verbose: Whether to report the size reduction.
+///
+/// Returns the PNG image losslessly optimized with oxipng.
+#[cfg(feature = "optimize")]
+fn optimize_png(buffer: Vec<u8>, verbose: bool) -> Result<Vec<u8>> {
+    let optimized = oxipng::optimize_from_memory(&buffer, &oxipng::Options::default())?;
+
+    if verbose {
+        println!(
+            "Optimized PNG from {} to {} bytes ({:.1}% smaller)",
+            buffer.len(),
+            optimized.len(),
+            100.0 * (1.0 - optimized.len() as f64 / buffer.len() as f64)
+        );
+    }
+
+    Ok(optimized)
+}
+
+/// Prints how much smaller the PNG image `buffer` is for being indexed than it would be as
+/// RGBA, or that `image` had too many colors to be indexed.
+fn report_indexed_png(image: &DynamicImage, options: &ExtractOptions, buffer: &[u8]) -> Result<()> {
+    // the color type follows the signature, the length and type of the IHDR chunk, and the
+    // width, height and bit depth of the image; 3 stands for indexed
+    if buffer.get(25) != Some(&3) {
+        println!("The image has more than 256 colors, encoded it as RGBA");
+        return Ok(());
+    }
+
+    let truecolor = ico2img::encode(image, &options.clone().png_indexed(false))?;
+    println!(
+        "Indexed PNG is {} bytes instead of {} ({:.1}% smaller)",
+        buffer.len(),
+        truecolor.len(),
+        100.0 * (1.0 - buffer.len() as f64 / truecolor.len() as f64)
+    );
+    Ok(())
+}
+
+/// Arguments:
+///   - args: The command-line arguments.
+///   - options: The conversion options.
+///   - output_dir: The directory to write the images to.
+///   - summary: The summary to record the outputs and failures in.
+///
+/// Reads ICO files from standard input, each prefixed by its length as a 4-byte little-endian
+/// integer, and converts each of them to `stdin_{n}` in `output_dir`, until the end of input.
+/// Frames larger than [`MAX_FRAME_SIZE`] are skipped without being read into memory, so that
+/// `--skip-errors` can go on with the next one.
+fn convert_stdin_frames(
+    args: &Args,
+    options: &ExtractOptions,
+    output_dir: &Path,
+    summary: &mut RunSummary,
+) -> Result<()> {
+    prepare_output_dir(args, output_dir)?;
+
+    let start = Instant::now();
+    let mut stdin = io::stdin().lock();
+    let mut frame = 0;
+    loop {
+        check_time_limit(args, start, &format!("{} frames", frame))?;
+
+        let Some(len) = read_frame_len(&mut stdin)? else {
+            break;
+        };
+        let name = format!("stdin_{}", frame);
+        let output = output_dir.join(format!("{}.{}", name, options.get_format().extension()));
+        let result = read_frame(&mut stdin, len)
+            .and_then(|data| check_ico_data(args, Path::new(&name), data))
+            .and_then(|data| parse_icon_dir(args, Path::new(&name), data))
+            .and_then(|icon_dir| convert_entry(args, &icon_dir, options, &output));
+
+        match result {
+            Ok(bytes) => {
+                summary.add_output(bytes);
+                summary.add_frame(frame, Ok(&output));
+                if args.verbose {
+                    println!("Converted frame {} to {}", frame, output.display());
+                }
+            }
+            Err(e) => {
+                summary.add_failure(format!("frame {}", frame), &e);
+                summary.add_frame(frame, Err(&e));
+                if !args.skip_errors {
+                    return Err(anyhow!("Failed to convert frame {}: {}", frame, e));
+                }
+                eprintln!("Skipping frame {}: {}", frame, e);
+                if e.is::<TruncatedFrame>() {
+                    break;
+                }
+            }
+        }
+
+        frame += 1;
+    }
+
+    Ok(())
+}
+
+/// The error of a frame of `--stdin-framed` cut short by the end of input, after which there's
+/// nothing left to read.
+#[derive(Debug)]
+struct TruncatedFrame(u32, usize);
+
+impl fmt::Display for TruncatedFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "The input ended after {} of the {} bytes of the frame.",
+            self.1, self.0
+        )
+    }
+}
+
+impl std::error::Error for TruncatedFrame {}
+
+/// Returns the length prefixing the next frame of `reader`, or `None` at the end of input. Fails
+/// if the input ends within the length.
+fn read_frame_len(reader: &mut impl Read) -> Result<Option<u32>> {
+    let mut len = [0; 4];
+    let mut read = 0;
+    while read < len.len() {
+        match reader.read(&mut len[read..]) {
+            Ok(0) if read == 0 => return Ok(None),
+            Ok(0) => {
+                return Err(anyhow!(
+                    "The input ended after {} of the 4 bytes of a frame length.",
+                    read
+                ))
+            }
+            Ok(n) => read += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(Some(u32::from_le_bytes(len)))
+}
+
+/// Returns the next frame of `reader`, of `len` bytes. Frames larger than [`MAX_FRAME_SIZE`]
+/// are skipped instead, failing. Fails with [`TruncatedFrame`] if the input ends within the
+/// frame.
+fn read_frame(reader: &mut impl Read, len: u32) -> Result<Vec<u8>> {
+    if len > MAX_FRAME_SIZE {
+        let skipped = io::copy(&mut reader.take(len as u64), &mut io::sink())?;
+        if skipped < len as u64 {
+            return Err(TruncatedFrame(len, skipped as usize).into());
+        }
+        return Err(anyhow!(
+            "The frame is {} bytes long, more than the {} bytes allowed.",
+            len,
+            MAX_FRAME_SIZE
+        ));
+    }
+
+    let mut data = Vec::new();
+    reader.take(len as u64).read_to_end(&mut data)?;
+    if data.len() < len as usize {
+        return Err(TruncatedFrame(len, data.len()).into());
+    }
+    Ok(data)
+}
+
+/// Arguments:
+///   - args: The command-line arguments.
+///   - options: The conversion options.
+///   - input_dir: The directory to search for ICO files.
+///   - output_dir: The directory to write the images to.
+///   - summary: The summary to record the outputs and failures in.
+///   - cache: The cache of converted images, if any.
+///
+/// Converts every ICO file found in `input_dir` to `{stem}.{format}` in `output_dir`.
+fn convert_dir(
+    args: &Args,
+    options: &ExtractOptions,
+    input_dir: &Path,
+    output_dir: &Path,
+    summary: &mut RunSummary,
+    cache: Option<&Cache>,
+) -> Result<()> {
+    let files = batch::find_icon_files(
+        input_dir,
+        args.recursive,
+        args.max_depth,
+        args.follow_symlinks,
+    );
+    if args.verbose {
+        println!("Found {} ICO files in {}", files.len(), input_dir.display());
+    }
+
+    // names are checked before anything is written
+    let collisions = batch::colliding_names(&files, input_dir, args.mirror_structure);
+    if args.strict_names && !collisions.is_empty() {
+        let groups: Vec<String> = collisions
+            .iter()
+            .map(|group| {
+                let names: Vec<String> = group
+                    .iter()
+                    .map(|&index| files[index].display().to_string())
+                    .collect();
+                format!("  {}", names.join(", "))
+            })
+            .collect();
+        return Err(anyhow!(
+            "These inputs would be converted to images with the same name:\n{}",
+            groups.join("\n")
+        ));
+    }
+    let stems = batch::output_stems(&files, input_dir, args.mirror_structure);
+
+    prepare_output_dir(args, output_dir)?;
+
+    let outputs: Vec<PathBuf> = files
+        .iter()
+        .zip(&stems)
+        .map(|(file, stem)| {
+            let name = format!("{}.{}", stem, options.get_format().extension());
+            if file.file_stem().unwrap_or_default().to_string_lossy() != *stem {
+                warning::warn(format_args!(
+                    "{} has the same name as another input, converting it to {} instead.",
+                    file.display(),
+                    name
+                ));
+            }
+            let relative_dir = file
+                .strip_prefix(input_dir)
+                .ok()
+                .and_then(Path::parent)
+                .filter(|_| args.mirror_structure);
+            // the bit depth comes first, so each of its directories mirrors the input directory
+            let bits_per_pixel = if args.group_by_bpp {
+                selected_bpp(args, file)
+            } else {
+                None
+            };
+            let output_dir = match bits_per_pixel {
+                Some(bits_per_pixel) => output_dir.join(bpp_dir_name(bits_per_pixel)),
+                None => output_dir.to_path_buf(),
+            };
+            let output = match relative_dir {
+                Some(dir) => output_dir.join(dir).join(name),
+                None => output_dir.join(name),
+            };
+            paths::fit_output_path(&output)
+        })
+        .collect();
+
+    // workers read and convert the files, while only this thread writes them, so the outputs are
+    // written in the same order whatever the number of threads
+    let start = Instant::now();
+    let budget = args
+        .memory_limit
+        .map(|limit| MemoryBudget::new(limit.try_into().unwrap_or(usize::MAX)));
+    let next_write = AtomicUsize::new(0);
+    let (job_sender, job_receiver) = mpsc::channel();
+    let job_receiver = Mutex::new(job_receiver);
+    let (result_sender, result_receiver) = mpsc::channel();
+    for index in 0..files.len() {
+        job_sender.send(index)?;
+    }
+    drop(job_sender);
+
+    // the index of the next file to write, which is also the number of files written
+    let mut next = 0;
+    thread::scope(|scope| {
+        for _ in 0..args.threads {
+            let (job_receiver, result_sender) = (&job_receiver, result_sender.clone());
+            let (files, budget, next_write) = (&files, &budget, &next_write);
+            scope.spawn(move || {
+                loop {
+                    // the lock is released as soon as a job is received
+                    let job = job_receiver.lock().unwrap().recv();
+                    let Ok(index) = job else {
+                        break;
+                    };
+                    if time_limit_exceeded(args, start) {
+                        break;
+                    }
+
+                    let mut bytes = 0;
+                    let result = render_file(args, options, cache, &files[index], |icon_dir| {
+                        if let Some(budget) = budget {
+                            bytes = decoded_size(args, icon_dir);
+                            reserve_memory(budget, bytes, index, next_write);
+                        }
+                        render_entry(args, icon_dir, options)
+                    });
+                    if result_sender.send((index, bytes, result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(result_sender);
+
+        let mut pending = HashMap::new();
+        'results: for (index, bytes, result) in result_receiver {
+            pending.insert(index, (bytes, result));
+            while let Some((bytes, result)) = pending.remove(&next) {
+                if time_limit_exceeded(args, start) {
+                    // the workers stop once they find that results aren't received anymore
+                    next_write.store(files.len(), Ordering::SeqCst);
+                    break 'results;
+                }
+                let (file, output) = (&files[next], &outputs[next]);
+                next += 1;
+                next_write.store(next, Ordering::SeqCst);
+
+                let result = result.and_then(|(image, buffer)| {
+                    let buffer = with_xmp(args, options, file, &image, buffer)?;
+                    if let Some(dir) = output
+                        .parent()
+                        .filter(|_| args.mirror_structure || args.group_by_bpp)
+                    {
+                        owner::create_dir_all(dir, args.chown, args.dir_mode)?;
+                    }
+                    write_entry(args, &image, &buffer, output)?;
+                    Ok(buffer.len())
+                });
+                if let Some(ref budget) = budget {
+                    budget.release(bytes);
+                }
+                match result {
+                    Ok(bytes) => {
+                        summary.add_output(bytes);
+                        if args.verbose {
+                            println!("Converted {} to {}", file.display(), output.display());
+                        }
+                    }
+                    Err(e) => {
+                        summary.add_failure(file.display().to_string(), &e);
+                        if !args.skip_errors {
+                            // let the workers waiting for memory go on, to find that results
+                            // aren't received anymore
+                            next_write.store(files.len(), Ordering::SeqCst);
+                            return Err(anyhow!("Failed to convert {}: {}", file.display(), e));
+                        }
+                        eprintln!("Skipping {}: {}", file.display(), e);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    })?;
+
+    // the workers stop taking files once the time limit is exceeded, so the files left are
+    // those it stopped
+    if next < files.len() {
+        let skipped: Vec<String> = files[next..]
+            .iter()
+            .map(|file| format!("  {}", file.display()))
+            .collect();
+        return Err(TimeLimitExceeded(format!(
+            "The time limit of {} seconds was exceeded after converting {} of {} files. These \
+             files weren't converted:\n{}",
+            args.time_limit.unwrap_or_default(),
+            next,
+            files.len(),
+            skipped.join("\n")
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// Arguments:
+///   - args: The command-line arguments.
+///   - options: The conversion options.
+///   - sheet_path: The path to the resulting sprite sheet.
+///
+/// Packs every entry of the input file, or of the ICO files in the input directory, into a sprite
+/// sheet. Entries are ordered by file path, then by index, so the sheet and its map are the same
+/// across runs.
+fn make_spritesheet(args: &Args, options: &ExtractOptions, sheet_path: &Path) -> Result<()> {
+    let input = input_path(args)?;
+    let files = if input.is_dir() {
+        batch::find_icon_files(input, args.recursive, args.max_depth, args.follow_symlinks)
+    } else {
+        vec![input.to_path_buf()]
+    };
+
+    let (width, height) = args.cell;
+    let cell_options = options.clone().resize(width, height).build()?;
+
+    let mut sprites = Vec::new();
+    for file in &files {
+        let source = file.strip_prefix(input).unwrap_or(file);
+        let source = if source.as_os_str().is_empty() {
+            file.display().to_string()
+        } else {
+            source.display().to_string()
+        };
+
+        let result = read_icon_dir(args, file).and_then(|icon_dir| {
+            selected_entries(args, &icon_dir)
+                .into_iter()
+                .map(|index| {
+                    let image = ico2img::decode_entry(&icon_dir, index)?;
+                    Ok(sprite::Sprite {
+                        source: source.clone(),
+                        index,
+                        image: ico2img::transform(image, &cell_options)?,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()
+        });
+
+        match result {
+            Ok(file_sprites) => sprites.extend(file_sprites),
+            Err(e) if args.skip_errors => eprintln!("Skipping {}: {}", file.display(), e),
+            Err(e) => return Err(anyhow!("Failed to read {}: {}", file.display(), e)),
+        }
+    }
+
+    if sprites.is_empty() {
+        return Err(anyhow!("No entries found to put in the sprite sheet."));
+    }
+
+    let columns = match args.strip {
+        Some(sprite::Strip::Horizontal) => sprites.len() as u32,
+        Some(sprite::Strip::Vertical) => 1,
+        None => args.columns,
+    };
+    let (sheet, positions) = sprite::pack(&sprites, args.cell, columns, args.spacing);
+    let buffer = ico2img::encode(&DynamicImage::ImageRgba8(sheet), options)?;
+    fs::write(sheet_path, buffer)?;
+
+    if let Some(ref map_path) = args.sprite_map {
+        let is_css = map_path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("css"));
+        let map = if is_css {
+            let sheet_url = sheet_path.file_name().unwrap_or_default().to_string_lossy();
+            sprite::sprite_css(&sprites, &positions, args.cell, &sheet_url).into_bytes()
+        } else if args.json_pretty {
+            serde_json::to_vec_pretty(&sprite::sprite_map(&sprites, &positions, args.cell))?
+        } else {
+            serde_json::to_vec(&sprite::sprite_map(&sprites, &positions, args.cell))?
+        };
+        fs::write(map_path, map)?;
+    }
+
+    if args.verbose {
+        println!(
+            "Packed {} entries from {} files into {}",
+            sprites.len(),
+            files.len(),
+            sheet_path.display()
+        );
+    }
+
+    Ok(())
+}
+
+/// Arguments:
+///   - args: The command-line arguments.
+///   - options: The conversion options.
+///   - output: The path to the resulting image.
+///
+/// Converts the selected entries of the input file and joins them into a single image, side by
+/// side with `--concat-horizontal` or from top to bottom with `--concat-vertical`, in the order
+/// of their indices.
+fn concat_entries(args: &Args, options: &ExtractOptions, output: &Path) -> Result<()> {
+    let icon_dir = read_icon_dir(args, input_path(args)?)?;
+    let images = selected_entries(args, &icon_dir)
+        .into_iter()
+        .map(|index| Ok(ico2img::transform(ico2img::decode_entry(&icon_dir, index)?, options)?))
+        .collect::<Result<Vec<_>>>()?;
+    if images.is_empty() {
+        return Err(anyhow!("No entries are selected."));
+    }
+
+    let background = args
+        .concat_background
+        .map_or(Rgba([0, 0, 0, 0]), Rgba::from);
+    let image = if args.concat_horizontal {
+        ico2img::concat_images_horizontal(&images, args.concat_gap, background)
+    } else {
+        ico2img::concat_images_vertical(&images, args.concat_gap, background)
+    };
+    let buffer = ico2img::encode(&image, options)?;
+    fs::write(output, &buffer)?;
+    owner::apply(output, args.chown, args.file_mode);
+
+    if args.verbose {
+        println!(
+            "Joined {} entries into a {}x{} image at {}",
+            images.len(),
+            image.width(),
+            image.height(),
+            output.display()
+        );
+    }
+    Ok(())
+}
+
+/// Arguments:
+///   - args: The command-line arguments.
+///   - options: The conversion options.
+///   - output: The path the names of the images are derived from.
+///
+/// Crops the selected entry of the input file to each rectangle of `--crop`, converting each
+/// part as set in `options` and writing it in the directory of `output`, named after
+/// `--crop-template` with `{stem}` replaced by the file stem of `output` and `{crop}` by
+/// `{x}_{y}_{width}x{height}`. The entry is decoded once.
+fn crop_entries(args: &Args, options: &ExtractOptions, output: &Path) -> Result<()> {
+    let input = input_path(args)?;
+    if input.is_dir() {
+        return Err(anyhow!(
+            "Several --crop rectangles need an ICO file, not a directory."
+        ));
+    }
+
+    let icon_dir = read_icon_dir(args, input)?;
+    let image = ico2img::decode_entry(&icon_dir, args.image_index)?;
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    for &rect in &args.crop {
+        let crop_options = options.clone().crop(rect).build()?;
+        let cropped = ico2img::transform(image.clone(), &crop_options)?;
+        let buffer = ico2img::encode(&cropped, &crop_options)?;
+        let crop = format!("{}_{}_{}x{}", rect.x, rect.y, rect.width, rect.height);
+        let name = args
+            .crop_template
+            .replace("{stem}", &stem)
+            .replace("{crop}", &crop);
+        let path = paths::fit_output_path(&output.with_file_name(format!(
+            "{}.{}",
+            name,
+            options.get_format().extension()
+        )));
+        write_entry(args, &cropped, &buffer, &path)?;
+
+        if args.verbose {
+            println!("Wrote the {} rectangle to {}", rect, path.display());
+        }
+    }
+
+    Ok(())
+}
+
+/// Arguments:
+///   - args: The command-line arguments.
+///   - options: The conversion options.
+///   - output: The path the names of the images are derived from.
+///
+/// Composites the selected entry of the input file over the light and dark theme backgrounds,
+/// writing the images as `{stem}_light.{extension}` and `{stem}_dark.{extension}` in the
+/// directory of `output`. The entry is decoded once.
+fn emit_variants(args: &Args, options: &ExtractOptions, output: &Path) -> Result<()> {
+    let input = input_path(args)?;
+    if input.is_dir() {
+        return Err(anyhow!(
+            "--variant both needs an ICO file, not a directory."
+        ));
+    }
+
+    let icon_dir = read_icon_dir(args, input)?;
+    let image = ico2img::decode_entry(&icon_dir, args.image_index)?;
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    for (theme, background) in [
+        ("light", args.light_background),
+        ("dark", args.dark_background),
+    ] {
+        let theme_options = options.clone().background(background).build()?;
+        let composited = ico2img::transform(image.clone(), &theme_options)?;
+        let buffer = ico2img::encode(&composited, &theme_options)?;
+        let path = paths::fit_output_path(&output.with_file_name(format!(
+            "{}_{}.{}",
+            stem,
+            theme,
+            options.get_format().extension()
+        )));
+        write_entry(args, &composited, &buffer, &path)?;
+
+        if args.verbose {
+            println!("Wrote the {} variant to {}", theme, path.display());
+        }
+    }
+
+    Ok(())
+}
+
+/// Arguments:
+///   - args: The command-line arguments.
+///   - options: The conversion options.
+///   - output: The path the names of the images are derived from.
+///
+/// Resizes the largest selected entry of the input file to each size of `--emit-sizes`, writing
+/// the images as `{stem}_{size}x{size}.{extension}` in the directory of `output`. The entry is
+/// decoded once.
+fn emit_sizes(args: &Args, options: &ExtractOptions, output: &Path) -> Result<()> {
+    let input = input_path(args)?;
+    if input.is_dir() {
+        return Err(anyhow!("--emit-sizes needs an ICO file, not a directory."));
+    }
+
+    let icon_dir = read_icon_dir(args, input)?;
+    let index = selected_entries(args, &icon_dir)
+        .into_iter()
+        .max_by_key(|&index| {
+            let (width, height) = entry_size(&icon_dir.entries()[index], args.trust_directory);
+            width as u64 * height as u64
+        })
+        .ok_or_else(|| anyhow!("No entry of the input file is selected."))?;
+    let image = ico2img::decode_entry(&icon_dir, index)?;
+    if args.verbose {
+        println!(
+            "Resizing entry {} ({}x{})",
+            index,
+            image.width(),
+            image.height()
+        );
+    }
+
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    for &size in &args.emit_sizes {
+        let size_options = options.clone().resize(size, size).build()?;
+        let resized = ico2img::transform(image.clone(), &size_options)?;
+        let buffer = ico2img::encode(&resized, &size_options)?;
+        let path = paths::fit_output_path(&output.with_file_name(format!(
+            "{}_{}x{}.{}",
+            stem,
+            size,
+            size,
+            options.get_format().extension()
+        )));
+        write_entry(args, &resized, &buffer, &path)?;
+
+        if args.verbose {
+            println!("Wrote {}x{} image to {}", size, size, path.display());
+        }
+    }
+
+    Ok(())
+}
+
+/// Arguments:
+///   - args: The command-line arguments.
+///   - options: The conversion options, whose format is replaced by PNG.
+///   - theme: The name of the icon theme.
+///   - name: The name of the icon.
+///
+/// Installs the selected entries of the input file into the icon theme, at each standard size of
+/// the XDG icon theme specification: each size gets the entry of that size, or else the smallest
+/// larger entry resized to it. Then updates the icon cache of the theme.
+fn install_xdg_icons(args: &Args, options: &ExtractOptions, theme: &str, name: &str) -> Result<()> {
+    for value in [theme, name] {
+        if value.is_empty() || value.contains(['/', '\\']) || value == "." || value == ".." {
+            return Err(anyhow!("Invalid theme or icon name {:?}.", value));
+        }
+    }
+    let input = input_path(args)?;
+    if input.is_dir() {
+        return Err(anyhow!("--xdg-install needs an ICO file, not a directory."));
+    }
+
+    let icon_dir = read_icon_dir(args, input)?;
+    let entries: Vec<(usize, (u32, u32))> = selected_entries(args, &icon_dir)
+        .into_iter()
+        .map(|index| {
+            let size = entry_size(&icon_dir.entries()[index], args.trust_directory);
+            (index, size)
+        })
+        .collect();
+    let options = options.clone().format(SupportedImages::Png).build()?;
+    let theme_dir = xdg::icons_dir()?.join(theme);
+
+    let mut installed = 0;
+    for size in xdg::SIZES {
+        let Some((index, resize)) = source_entry(&entries, size) else {
+            continue;
+        };
+        let size_options = if resize {
+            options.clone().resize(size, size)
+        } else {
+            options.clone()
+        };
+        let buffer = ico2img::extract(&icon_dir, index, &size_options)?;
+
+        let path = xdg::icon_path(&theme_dir, size, name);
+        if let Some(dir) = path.parent() {
+            owner::create_dir_all(dir, args.chown, args.dir_mode)?;
+        }
+        fs::write(&path, buffer)?;
+        owner::apply(&path, args.chown, args.file_mode);
+        installed += 1;
+
+        if args.verbose {
+            println!("Installed entry {} as {}", index, path.display());
+        }
+    }
+
+    if installed == 0 {
+        return Err(anyhow!(
+            "No entry is large enough for the smallest icon size, {}x{}.",
+            xdg::SIZES[0],
+            xdg::SIZES[0]
+        ));
+    }
+    xdg::update_icon_cache(&theme_dir, args.verbose);
+    Ok(())
+}
+
+/// Arguments:
+///   - args: The command-line arguments.
+///   - options: The conversion options, whose format is replaced by PNG.
+///   - dir: The iconset directory, such as `App.iconset`.
+///
+/// Writes the selected entries of the input file as the images of an Apple iconset: each image
+/// is the entry of its size, or else the smallest larger entry resized to it. Warns about the
+/// entries that don't fit any image of the iconset as they are, and about the images that no
+/// entry is large enough for, which are left out.
+fn write_iconset(args: &Args, options: &ExtractOptions, dir: &Path) -> Result<()> {
+    let input = input_path(args)?;
+    if input.is_dir() {
+        return Err(anyhow!("--iconset needs an ICO file, not a directory."));
+    }
+
+    let icon_dir = read_icon_dir(args, input)?;
+    let entries: Vec<(usize, (u32, u32))> = selected_entries(args, &icon_dir)
+        .into_iter()
+        .map(|index| {
+            let size = entry_size(&icon_dir.entries()[index], args.trust_directory);
+            (index, size)
+        })
+        .collect();
+    for &(index, (width, height)) in &entries {
+        if !iconset::has_slot((width, height)) {
+            warning::warn(format_args!(
+                "entry {} is {}x{}, which isn't a size of the iconset, so it's only used resized.",
+                index, width, height
+            ));
+        }
+    }
+    let options = options.clone().format(SupportedImages::Png).build()?;
+    prepare_output_dir(args, dir)?;
+
+    let mut written = 0;
+    for (name, size) in iconset::SLOTS {
+        let Some((index, resize)) = source_entry(&entries, size) else {
+            warning::warn(format_args!(
+                "no entry is at least {}x{}, leaving out {}.png.",
+                size, size, name
+            ));
+            continue;
+        };
+        let size_options = if resize {
+            options.clone().resize(size, size)
+        } else {
+            options.clone()
+        };
+        let buffer = ico2img::extract(&icon_dir, index, &size_options)?;
+
+        let path = dir.join(format!("{}.png", name));
+        fs::write(&path, buffer)?;
+        owner::apply(&path, args.chown, args.file_mode);
+        written += 1;
+
+        if args.verbose {
+            println!("Wrote entry {} as {}", index, path.display());
+        }
+    }
+
+    if written == 0 {
+        return Err(anyhow!("No entry is large enough for the iconset."));
+    }
+    Ok(())
+}
+
+/// Arguments:
+///   - entries: The index and size, as `(width, height)`, of each entry to choose from.
+///   - size: The width and height of the image to write.
+///
+/// Returns the index of the entry to write at `size`, and whether it has to be resized: the
+/// entry of exactly that size, or else the smallest one larger than it. Entries aren't scaled
+/// up, so `None` is returned if every entry is smaller.
+fn source_entry(entries: &[(usize, (u32, u32))], size: u32) -> Option<(usize, bool)> {
+    if let Some(&(index, _)) = entries.iter().find(|(_, dims)| *dims == (size, size)) {
+        return Some((index, false));
+    }
+    entries
+        .iter()
+        .filter(|(_, (width, height))| *width >= size && *height >= size)
+        .min_by_key(|(_, (width, height))| *width as u64 * *height as u64)
+        .map(|&(index, _)| (index, true))
+}
+
+/// Returns the indices of the entries of the ICO file selected by `--entry-filter`.
+fn selected_entries(args: &Args, icon_dir: &IconDir) -> Vec<usize> {
+    icon_dir
+        .entries()
+        .iter()
+        .enumerate()
+        .filter(|(index, entry)| {
+            let size = entry_size(entry, args.trust_directory);
+            is_selected(args, *index, size, entry.bits_per_pixel(), entry.is_png())
+        })
+        .map(|(index, _)| index)
+        .filter(|&index| {
+            // entries that can't be decoded are kept, to fail when they're converted
+            !checks_alpha(args)
+                || ico2img::decode_entry(icon_dir, index)
+                    .map_or(true, |image| matches_alpha_requirement(args, &image))
+        })
+        .collect()
+}
+
+/// Returns whether the entry at `index`, of the given size and bits per pixel and stored as PNG
+/// data or not, is selected by `--entry-filter`. Every entry is selected without a filter.
+fn is_selected(
+    args: &Args,
+    index: usize,
+    (width, height): (u32, u32),
+    bpp: u16,
+    png: bool,
+) -> bool {
+    match args.entry_filter {
+        Some(ref filter) => filter.matches(&EntryFields {
+            index,
+            width,
+            height,
+            bpp,
+            png,
+        }),
+        None => true,
+    }
+}
+
+/// Returns the approximate size, in bytes, of the selected entry of the ICO file once decoded.
+fn decoded_size(args: &Args, icon_dir: &IconDir) -> usize {
+    icon_dir
+        .entries()
+        .get(args.image_index)
+        .map_or(0, |entry| {
+            let (width, height) = entry_size(entry, args.trust_directory);
+            width as usize * height as usize * 4
+        })
+}
+
+/// Allocates `bytes` bytes of the memory budget for the file at `index`, waiting for the images
+/// before it to be written if they don't fit. The file that the writing thread waits for is
+/// always allocated, even over the budget, so that the conversion can't deadlock.
+fn reserve_memory(budget: &MemoryBudget, bytes: usize, index: usize, next_write: &AtomicUsize) {
+    while !budget.try_allocate(bytes) {
+        if index <= next_write.load(Ordering::SeqCst) {
+            budget.force_allocate(bytes);
+            return;
+        }
+        budget.wait(MEMORY_POLL_INTERVAL);
+    }
+}
+
+/// Returns whether more time than allowed by `--time-limit` has passed since `start`.
+fn time_limit_exceeded(args: &Args, start: Instant) -> bool {
+    args.time_limit.is_some_and(|limit| start.elapsed() > Duration::from_secs_f64(limit))
+}
+
+/// Fails with [`TimeLimitExceeded`] if more time than allowed by `--time-limit` has passed since
+/// `start`. `done` describes what was converted so far. The images already written are kept.
+fn check_time_limit(args: &Args, start: Instant, done: &str) -> Result<()> {
+    if time_limit_exceeded(args, start) {
+        return Err(TimeLimitExceeded(format!(
+            "The time limit of {} seconds was exceeded after converting {}.",
+            args.time_limit.unwrap_or_default(),
+            done
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// The error of a run stopped by `--time-limit`, which `main` exits with `TIME_LIMIT_EXIT_CODE`
+/// for, once the summary, the cache and the reports are written.
+#[derive(Debug)]
+struct TimeLimitExceeded(String);
+
+impl fmt::Display for TimeLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for TimeLimitExceeded {}
+
+/// Creates the output directory if it doesn't exist, or fails if it doesn't and
+/// `--no-create-dir` is given.
+fn prepare_output_dir(args: &Args, output_dir: &Path) -> Result<()> {
+    if !args.no_create_dir {
+        owner::create_dir_all(output_dir, args.chown, args.dir_mode)?;
+    } else if !output_dir.is_dir() {
+        return Err(anyhow!(
+            "The output directory {} doesn't exist, and --no-create-dir was given.",
+            output_dir.display()
+        ));
+    }
+    Ok(())
+}
+
+/// Reads the icon directory of the input file. With the `icl` feature enabled, ICL icon
+/// libraries are supported too, in which case the selected (or first) icon group is read.
+fn read_icon_dir(args: &Args, path: &Path) -> Result<IconDir> {
+    let stage = timing::start("read", &path.display().to_string());
+    let data = read_ico_data(args, path)?;
+    timing::finish(stage);
+
+    parse_icon_dir(args, path, data)
+}
+
+/// Arguments:
+///   - args: The command-line arguments.
+///   - path: The path the ICO file was read from, for messages.
+///   - data: The contents of the ICO file, once checked by [`check_ico_data`].
+///
+/// Returns the list of icons in the ICO file, with at most `--max-entries-per-file` of them,
+/// warning about entries whose size doesn't match the directory unless `--trust-directory` is
+/// given.
+fn parse_icon_dir(args: &Args, path: &Path, data: Vec<u8>) -> Result<IconDir> {
+    let data = limit_entries(args, path, data);
+
+    let stage = timing::start("parse", &path.display().to_string());
+    let icon_dir = IconDir::read(Cursor::new(data))
+        .map_err(|e| ico2img::Ico2imgError::IcoParseError(e.to_string()))?;
+    timing::finish(stage);
+
+    if !args.trust_directory {
+        for (index, entry) in icon_dir.entries().iter().enumerate() {
+            let (width, height) = ico2img::entry_size(entry);
+            if (width, height) != (entry.width(), entry.height()) {
+                warn_size_mismatch(index, (entry.width(), entry.height()), (width, height));
+            }
+        }
+    }
+
+    Ok(icon_dir)
+}
+
+/// Returns the data of the ICO file with its directory cut down to `--max-entries-per-file`
+/// entries, warning about it, if it has more. The data itself is left as it is, since entries
+/// are located by their offset from the start of the file.
+fn limit_entries(args: &Args, path: &Path, mut data: Vec<u8>) -> Vec<u8> {
+    let Some(count) = data
+        .get(4..6)
+        .map(|count| u16::from_le_bytes([count[0], count[1]]))
+    else {
+        return data;
+    };
+    let max = args.max_entries_per_file;
+    if count <= max {
+        return data;
+    }
+
+    warning::warn(format_args!(
+        "{} claims {} entries, only the first {} are read.",
+        path.display(),
+        count,
+        max
+    ));
+    data[4..6].copy_from_slice(&max.to_le_bytes());
+    data
+}
+
+/// Writes the data of each selected entry, as stored in the ICO file, to `dir`: PNG data as
+/// `{stem}_{index}.png`, and BMP data as `{stem}_{index}.dib`, since it lacks the file header of
+/// BMP images. Nothing is decoded, so PNG entries are extracted losslessly. The index is
+/// zero-padded to the digits of the last one, or to `--pad-index`, so that the names sort in
+/// order. With `--auto-rename`, the files are named after the size and bit depth of their entry
+/// instead.
+fn dump_raw(args: &Args, dir: &Path) -> Result<()> {
+    let path = input_path(args)?;
+    let data = read_ico_data(args, path)?;
+    let info = IcoInfo::from_reader(Cursor::new(&data))?;
+    prepare_output_dir(args, dir)?;
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let width = args
+        .pad_index
+        .unwrap_or_else(|| info.entries.len().saturating_sub(1).to_string().len());
+    let selected: Vec<(usize, &EntryInfo)> = info
+        .entries
+        .iter()
+        .enumerate()
+        .filter(|(index, entry)| {
+            let size = (entry.width, entry.height);
+            let png = entry.encoding_type == EncodingType::Png;
+            is_selected(args, *index, size, entry.bits_per_pixel, png)
+        })
+        .collect();
+    let names = if args.auto_rename {
+        let entries: Vec<&EntryInfo> = selected.iter().map(|&(_, entry)| entry).collect();
+        auto_rename(&args.auto_rename_template, &stem, &entries)
+    } else {
+        selected
+            .iter()
+            .map(|(index, entry)| format!("{}_{:0width$}.{}", stem, index, raw_extension(entry)))
+            .collect()
+    };
+
+    for (&(index, entry), name) in selected.iter().zip(names) {
+        let start = entry.raw_data_offset as usize;
+        let Some(bytes) = data.get(start..start + entry.raw_data_size as usize) else {
+            warning::warn(format_args!(
+                "data of entry {} lies outside the file, skipping.",
+                index
+            ));
+            continue;
+        };
+
+        let entry_dir = if args.group_by_bpp {
+            let entry_dir = dir.join(bpp_dir_name(entry.bits_per_pixel));
+            owner::create_dir_all(&entry_dir, args.chown, args.dir_mode)?;
+            entry_dir
+        } else {
+            dir.to_path_buf()
+        };
+        let output = paths::fit_output_path(&entry_dir.join(name));
+        fs::write(&output, bytes)?;
+        owner::apply(&output, args.chown, args.file_mode);
+
+        if args.verbose {
+            println!(
+                "Wrote {} bytes of entry {} to {}",
+                bytes.len(),
+                index,
+                output.display()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the extension of the files written by `--dump-raw` for `entry`: `png` for PNG data and
+/// `dib` for BMP data.
+fn raw_extension(entry: &EntryInfo) -> &'static str {
+    match entry.encoding_type {
+        EncodingType::Png => "png",
+        _ => "dib",
+    }
+}
+
+/// Arguments:
+///   - template: The name of the files, without extension, as given by `--auto-rename-template`.
+///   - stem: The file stem of the input file.
+///   - entries: The entries written by `--dump-raw`, in order.
+///
+/// Returns the name of the file of each entry, with `{stem}`, `{width}`, `{height}` and `{bpp}`
+/// replaced in `template`. When several entries would get the same name, each of them is told
+/// apart with a letter, `_a`, `_b` and so on, in the order of the entries.
+fn auto_rename(template: &str, stem: &str, entries: &[&EntryInfo]) -> Vec<String> {
+    let names: Vec<String> = entries
+        .iter()
+        .map(|entry| {
+            template
+                .replace("{stem}", stem)
+                .replace("{width}", &entry.width.to_string())
+                .replace("{height}", &entry.height.to_string())
+                .replace("{bpp}", &entry.bits_per_pixel.to_string())
+                + "."
+                + raw_extension(entry)
+        })
+        .collect();
+
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for name in &names {
+        *counts.entry(name).or_default() += 1;
+    }
+    let mut used: HashMap<&str, usize> = HashMap::new();
+    names
+        .iter()
+        .map(|name| {
+            if counts[name.as_str()] == 1 {
+                return name.clone();
+            }
+            let number = used.entry(name).or_default();
+            *number += 1;
+            let (base, extension) = name.rsplit_once('.').unwrap_or((name.as_str(), ""));
+            format!("{}_{}.{}", base, letters(*number), extension)
+        })
+        .collect()
+}
+
+/// Returns the letters numbering the `number`-th of several entries of the same name, from 1:
+/// `a` to `z`, then `aa`, `ab` and so on.
+fn letters(mut number: usize) -> String {
+    let mut letters = Vec::new();
+    while number > 0 {
+        number -= 1;
+        letters.push(b'a' + (number % 26) as u8);
+        number /= 26;
+    }
+    letters.reverse();
+    String::from_utf8(letters).unwrap_or_default()
+}
+
+/// Returns the name of the subdirectory of `--group-by-bpp` for entries of `bits_per_pixel`.
+fn bpp_dir_name(bits_per_pixel: u16) -> String {
+    format!("{}bpp", bits_per_pixel)
+}
+
+/// Returns the bit depth of the entry of `file` selected with `-i`, as declared in its ICO
+/// directory, or `None` if the file can't be read, in which case its conversion fails anyway.
+fn selected_bpp(args: &Args, file: &Path) -> Option<u16> {
+    let data = read_ico_data(args, file).ok()?;
+    let info = IcoInfo::from_reader(Cursor::new(&data)).ok()?;
+    let entry = info.entries.get(args.image_index)?;
+    Some(entry.bits_per_pixel)
+}
+
+/// Prints a table of the supported output formats and their capabilities.
+fn print_formats() {
+    println!(
+        "{:8}{:11}{:13}{:7}Feature",
+        "Format", "Extension", "Compression", "Alpha"
+    );
+    for &format in SupportedImages::ALL {
+        let capabilities = format.capabilities();
+        let feature = match capabilities.feature {
+            None => "built in".to_string(),
+            Some(feature) if capabilities.available => format!("{} (enabled)", feature),
+            Some(feature) => format!("{} (not compiled in)", feature),
+        };
+        let compression = if capabilities.lossy {
+            "lossy"
+        } else {
+            "lossless"
+        };
+        println!(
+            "{:8}{:11}{:13}{:7}{}",
+            format!("{:?}", format).to_lowercase(),
+            format.extension(),
+            compression,
+            if capabilities.alpha { "yes" } else { "no" },
+            feature
+        );
+    }
+}
+
+/// Returns the size of an entry, as `(width, height)`: the size of its image, or the size
+/// declared in the ICO directory with `--trust-directory`.
+fn entry_size(entry: &IconDirEntry, trust_directory: bool) -> (u32, u32) {
+    if trust_directory {
+        (entry.width(), entry.height())
+    } else {
+        ico2img::entry_size(entry)
+    }
+}
+
+/// Warns that the size declared for an entry in the ICO directory differs from its image.
+fn warn_size_mismatch(index: usize, declared: (u32, u32), actual: (u32, u32)) {
+    warning::warn(format_args!(
+        "entry {} is declared as {}x{}, but its image is {}x{}.",
+        index, declared.0, declared.1, actual.0, actual.1
+    ));
+}
+
+/// Returns the bytes of the ICO file to read: the input file itself, the selected frame of an ANI
+/// file, the selected icon group of an ICL file, or the entries recovered from a damaged file with
+/// `--recover`.
+fn read_ico_data(args: &Args, path: &Path) -> Result<Vec<u8>> {
+    if ani::is_ani(path) {
+        let mut frames = ani::read_frames(path)?;
+        let index = args.ani_frame.unwrap_or(0);
+        if index >= frames.len() {
+            return Err(anyhow!(
+                "Frame {} is out of bounds, the ANI file has {} frames.",
+                index,
+                frames.len()
+            ));
+        }
+        return Ok(frames.swap_remove(index).into_ico());
+    }
+
+    #[cfg(feature = "icl")]
+    if icl::is_icl(path) {
+        let mut groups = icl::read_icon_groups(path)?;
+        let position = match args.group {
+            Some(ref id) => groups
+                .iter()
+                .position(|group| group.id.to_string() == *id)
+                .ok_or_else(|| anyhow!("No icon group with ID {} found.", id))?,
+            None if groups.is_empty() => {
+                return Err(anyhow!("No icon groups found in the ICL file."))
+            }
+            None => 0,
+        };
+        return Ok(groups.swap_remove(position).into_ico());
+    }
+
+    let what = format!("reading {}", path.display());
+    #[cfg(feature = "http")]
+    let data = match http::as_url(path) {
+        Some(url) => http::download(url)?,
+        None => {
+            retry::retry(args.retries, &what, || fs::read(path)).map_err(|e| read_error(path, e))?
+        }
+    };
+    #[cfg(not(feature = "http"))]
+    let data =
+        retry::retry(args.retries, &what, || fs::read(path)).map_err(|e| read_error(path, e))?;
+
+    check_ico_data(args, path, data)
+}
+
+/// Returns the error of reading `path`, telling a missing file apart.
+fn read_error(path: &Path, e: io::Error) -> ico2img::Ico2imgError {
+    if e.kind() == ErrorKind::NotFound {
+        ico2img::Ico2imgError::FileNotFound(path.to_path_buf())
+    } else {
+        ico2img::Ico2imgError::Io(e)
+    }
+}
+
+/// Returns `data`, read from `path`, once checked to be an ICO or CUR file, or as recovered with
+/// `--recover`.
+fn check_ico_data(args: &Args, path: &Path, data: Vec<u8>) -> Result<Vec<u8>> {
+    magic::check_icon_file(path, &data)?;
+    if args.recover {
+        return recover::recover_ico(&data);
+    }
+
+    Ok(data)
+}
+
+/// Writes the edited icon directory to the output path, or back to the input file if no output
+/// path is given.
+fn write_icon_dir(args: &Args, icon_dir: &IconDir) -> Result<()> {
+    let path = match args.output {
+        Some(ref output) => output.as_path(),
+        None => input_path(args)?,
+    };
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    icon_dir.write(&mut writer)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Parses a duration, in seconds, which must fit in a [`Duration`].
+fn parse_seconds(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(seconds) if Duration::try_from_secs_f64(seconds).is_ok() => Ok(seconds),
+        _ => Err(format!("Invalid duration {}, expected a number of seconds.", s)),
+    }
+}
+
+/// Parses a size given as `WxH`, such as `32x32`.
+fn parse_size(s: &str) -> Result<(u32, u32), String> {
+    let (width, height) = s
+        .split_once('x')
+        .ok_or_else(|| format!("invalid size {}, expected WxH", s))?;
+    let width = width.parse().map_err(|_| format!("invalid width {}", width))?;
+    let height = height
+        .parse()
+        .map_err(|_| format!("invalid height {}", height))?;
+    Ok((width, height))
+}
+
+/// Parses the formats of entries given as `INDEX:FORMAT,...`, such as `0:webp,1:png`.
+fn parse_format_map(s: &str) -> Result<HashMap<usize, SupportedImages>, String> {
+    s.split(',')
+        .map(|pair| -> Result<(usize, SupportedImages), String> {
+            let (index, format) = pair
+                .split_once(':')
+                .ok_or_else(|| format!("invalid mapping {}, expected INDEX:FORMAT", pair))?;
+            let index = index
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid entry index {}", index))?;
+            let format = format
+                .trim()
+                .parse::<SupportedImages>()
+                .map_err(|e| e.to_string())?;
+            Ok((index, format))
+        })
+        .collect()
+}
+
+/// Parses the name of a resizing filter.
+fn parse_filter(s: &str) -> Result<FilterType, String> {
+    match s.to_lowercase().as_str() {
+        "nearest" => Ok(FilterType::Nearest),
+        "triangle" => Ok(FilterType::Triangle),
+        "catmullrom" => Ok(FilterType::CatmullRom),
+        "gaussian" => Ok(FilterType::Gaussian),
+        "lanczos3" => Ok(FilterType::Lanczos3),
+        _ => Err(format!("unknown filter {}", s)),
+    }
+}
+
+/// Returns the path to the input file.
+fn input_path(args: &Args) -> Result<&Path> {
+    args.file
+        .as_deref()
+        .ok_or_else(|| anyhow!("No input file specified."))
+}
+
+/// Prints the entries in the input file. For ANI files and ICL files, the frames and icon groups
+/// are printed instead, unless a frame or group has been selected.
+fn list_entries(args: &Args) -> Result<()> {
+    let path = input_path(args)?;
+
+    if ani::is_ani(path) && args.ani_frame.is_none() {
+        let frames = ani::read_frames(path)?;
+        if let Some(mut writer) = csv_writer(args)? {
+            writeln!(writer, "{}", FRAME_CSV_HEADER)?;
+            for (index, frame) in frames.iter().enumerate() {
+                writeln!(writer, "{},{},{}", index, frame.len, frame.rate)?;
+            }
+            writer.flush()?;
+            return Ok(());
+        }
+        for (index, frame) in frames.iter().enumerate() {
+            println!(
+                "Frame {}: {} entries, shown for {} jiffies ({} ms)",
+                index,
+                frame.len,
+                frame.rate,
+                frame.rate as u64 * 1000 / 60
+            );
+        }
+        return Ok(());
+    }
+
+    #[cfg(feature = "icl")]
+    if icl::is_icl(path) && args.group.is_none() {
+        let groups = icl::read_icon_groups(path)?;
+        if let Some(mut writer) = csv_writer(args)? {
+            writeln!(writer, "{}", GROUP_CSV_HEADER)?;
+            for group in groups {
+                writeln!(
+                    writer,
+                    "{},{}",
+                    inventory::csv_field(&group.id.to_string()),
+                    group.len
+                )?;
+            }
+            writer.flush()?;
+            return Ok(());
+        }
+        for group in groups {
+            println!("Group {}: {} entries", group.id, group.len);
+        }
+        return Ok(());
+    }
+
+    let data = read_ico_data(args, path)?;
+    let info = IcoInfo::from_reader(Cursor::new(&data))?;
+    // the directory listing alone doesn't decode any entry
+    let icon_dir = if args.analyze || checks_alpha(args) {
+        Some(IconDir::read(Cursor::new(&data))?)
+    } else {
+        None
+    };
+
+    let listed_size = |entry: &EntryInfo| {
+        if args.trust_directory {
+            (entry.declared_width, entry.declared_height)
+        } else {
+            (entry.width, entry.height)
+        }
+    };
+    let largest = info
+        .entries
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, entry)| {
+            let (width, height) = listed_size(entry);
+            width as u64 * height as u64
+        })
+        .map(|(index, _)| index);
+    let color = style::stdout();
+    let mut csv = csv_writer(args)?;
+    if let Some(ref mut writer) = csv {
+        writeln!(writer, "{}", ENTRY_CSV_HEADER)?;
+    }
+
+    for (index, entry) in info.entries.iter().enumerate() {
+        let (width, height) = listed_size(entry);
+        if !args.trust_directory && entry.has_size_mismatch() {
+            warn_size_mismatch(
+                index,
+                (entry.declared_width, entry.declared_height),
+                (entry.width, entry.height),
+            );
+        }
+        let png = entry.encoding_type == EncodingType::Png;
+        if !is_selected(args, index, (width, height), entry.bits_per_pixel, png) {
+            continue;
+        }
+        if let Some(ref icon_dir) = icon_dir {
+            let matches = ico2img::decode_entry(icon_dir, index)
+                .map_or(true, |image| matches_alpha_requirement(args, &image));
+            if !matches {
+                continue;
+            }
+        }
+
+        let analysis = match (&icon_dir, args.analyze) {
+            (Some(icon_dir), true) => match analyze_entry(icon_dir, index) {
+                Ok((color_count, alpha)) => {
+                    let colors = if color_count > MAX_COLOR_COUNT {
+                        format!("{}+", MAX_COLOR_COUNT)
+                    } else {
+                        color_count.to_string()
+                    };
+                    Some((colors, describe_alpha(&alpha)))
+                }
+                Err(e) => {
+                    warning::warn(format_args!("can't analyze entry {}: {}", index, e));
+                    None
+                }
+            },
+            _ => None,
+        };
+
+        if let Some(ref mut writer) = csv {
+            let (colors, alpha) = analysis.unwrap_or_default();
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{}",
+                inventory::csv_field(&path.display().to_string()),
+                index,
+                width,
+                height,
+                entry.bits_per_pixel,
+                entry.encoding_type,
+                colors,
+                inventory::csv_field(&alpha)
+            )?;
+            continue;
+        }
+
+        let mut line =
+            format_entry_row(index, entry, (width, height), color, largest == Some(index));
+        if let Some((colors, alpha)) = analysis {
+            line += &format!(" - {} colors, {}", colors, alpha);
+        }
+        println!("{}", line);
+    }
+
+    if let Some(mut writer) = csv {
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+/// Returns where to write the CSV report of `--csv` or `--csv-file`, or `None` if neither is
+/// given: the file of `--csv-file`, or else standard output.
+fn csv_writer(args: &Args) -> Result<Option<Box<dyn Write>>> {
+    Ok(match args.csv_file {
+        Some(ref path) => Some(Box::new(BufWriter::new(File::create(path)?))),
+        None if args.csv => Some(Box::new(io::stdout().lock())),
+        None => None,
+    })
+}
+
+/// Arguments:
+///   - index: The index of the entry in the ICO file.
+///   - entry: The entry to describe.
+///   - size: The size of the entry, as listed.
+///   - color: Whether to color the row by the bit depth of the entry.
+///   - largest: Whether the entry is the largest of the file, to be printed in bold.
+///
+/// Returns the row of the entry in `--list`, with its ANSI escapes if `color` is set.
+fn format_entry_row(
+    index: usize,
+    entry: &EntryInfo,
+    (width, height): (u32, u32),
+    color: bool,
+    largest: bool,
+) -> String {
+    let row = format!(
+        "Entry {}: {}x{} - {} bits per pixel ({})",
+        index, width, height, entry.bits_per_pixel, entry.encoding_type
+    );
+    if !color {
+        return row;
+    }
+
+    let mut codes = Vec::new();
+    match entry.bits_per_pixel {
+        1 => codes.push("90"),
+        4 => codes.push("33"),
+        8 => codes.push("36"),
+        24 => codes.push("32"),
+        32 => codes.push("97"),
+        _ => {}
+    }
+    if largest {
+        codes.push("1");
+    }
+    if codes.is_empty() {
+        row
+    } else {
+        format!("\x1b[{}m{}\x1b[0m", codes.join(";"), row)
+    }
+}
+
+/// Arguments:
+///   - icon_dir: The list of icons in the ICO file.
+///   - index: The index of the entry to analyze.
+///
+/// Returns the number of unique colors in the entry, counting at most one more than
+/// `MAX_COLOR_COUNT`, and whether any of its pixels isn't fully opaque.
+fn analyze_entry(icon_dir: &IconDir, index: usize) -> Result<(usize, AlphaStats)> {
+    let image = ico2img::decode_entry(icon_dir, index)?.to_rgba8();
+    Ok((
+        count_colors(&image, MAX_COLOR_COUNT),
+        AlphaStats::from_image(&image),
+    ))
+}
+
+/// Returns the number of unique colors in `image`, counting at most one more than `limit`.
+fn count_colors(image: &RgbaImage, limit: usize) -> usize {
+    let mut colors = HashSet::new();
+    for pixel in image.pixels() {
+        if colors.len() > limit {
+            break;
+        }
+        colors.insert(pixel.0);
+    }
+    colors.len()
+}
+
+/// Warns, with `--max-colors-warn`, if the converted entry is about to be encoded to a lossy
+/// format although it looks like a flat icon: it has at most as many colors as allowed, or
+/// transparency with sharp edges, where lossy compression shows most. With
+/// `--abort-on-warning`, fails instead, so the image isn't written.
+fn check_lossy_quality(args: &Args, image: &DynamicImage, options: &ExtractOptions) -> Result<()> {
+    let Some(max_colors) = args.max_colors_warn else {
+        return Ok(());
+    };
+    let format = options.get_format();
+    if !format.capabilities().lossy {
+        return Ok(());
+    }
+
+    let rgba = image.to_rgba8();
+    let mut reasons = Vec::new();
+    let colors = count_colors(&rgba, max_colors);
+    if colors <= max_colors {
+        reasons.push(format!("has only {} colors", colors));
+    }
+    let sharp_alpha = rgba.pixels().any(|pixel| pixel[3] == 0)
+        && rgba
+            .pixels()
+            .all(|pixel| pixel[3] == 0 || pixel[3] == u8::MAX);
+    if sharp_alpha {
+        reasons.push("has sharp-edged transparency".to_string());
+    }
+    if reasons.is_empty() {
+        return Ok(());
+    }
+
+    let details = format!(
+        "{} {}, which {} images render poorly; a lossless format such as PNG would be better.",
+        args.image_index,
+        reasons.join(" and "),
+        format
+    );
+    if args.abort_on_warning {
+        return Err(anyhow!("Entry {}", details));
+    }
+    warning::warn(format_args!("entry {}", details));
+    Ok(())
+}
+
+/// Returns a description of the transparency of an entry, from the statistics of its alpha
+/// channel.
+fn describe_alpha(stats: &AlphaStats) -> String {
+    if stats.has_transparency {
+        format!(
+            "with alpha (min {}, mean {:.1}, {:.1}% fully transparent)",
+            stats.min_alpha, stats.mean_alpha, stats.transparent_percent
+        )
+    } else {
+        "opaque".to_string()
+    }
+}
+
+/// Returns whether `--require-alpha` or `--require-opaque` is given, so entries have to be
+/// decoded to be selected.
+fn checks_alpha(args: &Args) -> bool {
+    args.require_alpha || args.require_opaque
+}
+
+/// Returns whether `image` has the transparency required by `--require-alpha` or
+/// `--require-opaque`. Any image matches if neither is given.
+fn matches_alpha_requirement(args: &Args, image: &DynamicImage) -> bool {
+    if !checks_alpha(args) {
+        return true;
+    }
+    let stats = AlphaStats::from_image(&image.to_rgba8());
+    stats.has_transparency == args.require_alpha
+}
+
+/// Prints the metadata of the input file and of each of its entries.
+fn print_info(args: &Args) -> Result<()> {
+    if args.summary {
+        return print_summary(args);
+    }
+
+    let path = input_path(args)?;
+    let data = read_ico_data(args, path)?;
+    let info = IcoInfo::from_reader(Cursor::new(&data))?;
+    let icon_dir = if args.analyze {
+        Some(IconDir::read(Cursor::new(&data))?)
+    } else {
+        None
+    };
+
+    println!("File size: {} bytes", info.file_size);
+    println!(
+        "Resource type: {}",
+        match info.resource_type {
+            ico::ResourceType::Icon => "icon",
+            ico::ResourceType::Cursor => "cursor",
+        }
+    );
+    println!("Valid: {}", if info.is_valid { "yes" } else { "no" });
+    for (index, entry) in info.entries.iter().enumerate() {
+        let profile = match (entry.has_icc_profile, entry.has_srgb) {
+            (true, true) => ", ICC profile, sRGB",
+            (true, false) => ", ICC profile",
+            (false, true) => ", sRGB",
+            (false, false) => "",
+        };
+        // the directory stores sizes in a byte, so 256 is stored as 0
+        let mut notes = format!(
+            " (stored as {} by {}{})",
+            display_dimension(entry.declared_width as u8),
+            display_dimension(entry.declared_height as u8),
+            if entry.has_size_mismatch() {
+                ", which doesn't match the image"
+            } else {
+                ""
+            }
+        );
+        if entry.is_oversize() {
+            notes += " (larger than the ICO format allows)";
+        }
+        let first_bytes: String = entry
+            .first_bytes
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        println!(
+            "Entry {}: {}x{}{} - {} bits per pixel, {}{}, {} bytes at offset {} (0x{:x}), starting with {}",
+            index,
+            entry.width,
+            entry.height,
+            notes,
+            entry.bits_per_pixel,
+            entry.encoding_type,
+            profile,
+            entry.raw_data_size,
+            entry.raw_data_offset,
+            entry.raw_data_offset,
+            first_bytes
+        );
+        if let Some(ref icon_dir) = icon_dir {
+            match analyze_entry(icon_dir, index) {
+                Ok((_, alpha)) => println!("  Alpha: {}", describe_alpha(&alpha)),
+                Err(e) => warning::warn(format_args!("can't analyze entry {}: {}", index, e)),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns `raw_byte`, a width or height as stored in the ICO directory, for display, with the
+/// 256 pixels that 0 stands for: `0 (256)`.
+fn display_dimension(raw_byte: u8) -> String {
+    match raw_byte {
+        0 => "0 (256)".to_string(),
+        size => size.to_string(),
+    }
+}
+
+/// Prints the inventory of the input files, and of the ICO files in the input directories, as a
+/// table, CSV or JSON.
+fn print_summary(args: &Args) -> Result<()> {
+    let mut files = Vec::new();
+    for path in args.file.iter().chain(&args.more_files) {
+        if path.is_dir() {
+            files.extend(batch::find_icon_files(
+                path,
+                args.recursive,
+                args.max_depth,
+                args.follow_symlinks,
+            ));
+        } else {
+            files.push(path.clone());
+        }
+    }
+
+    let reports = inventory::scan(files);
+    if let Some(mut writer) = csv_writer(args)? {
+        inventory::write_csv(&reports, &mut writer)?;
+        writer.flush()?;
+    } else if args.json {
+        let json = inventory::to_json(&reports);
+        if args.json_pretty {
+            println!("{}", serde_json::to_string_pretty(&json)?);
+        } else {
+            println!("{}", serde_json::to_string(&json)?);
+        }
+    } else {
+        inventory::print_table(&reports);
+    }
+
+    Ok(())
+}
+
+/// Draws the selected entry of the input file in the terminal, scaled down to the width of the
+/// terminal (read from `COLUMNS`, 80 by default). Fails if colors are disabled, which they are
+/// by default when standard output isn't a terminal or `NO_COLOR` is set.
+fn print_preview(args: &Args) -> Result<()> {
+    if !style::stdout() {
+        return Err(anyhow!(
+            "The preview needs a terminal with colors, write the image with -o to view it instead."
+        ));
+    }
+
+    let input = input_path(args)?;
+    if input.is_dir() {
+        return Err(anyhow!("The preview needs an ICO file, not a directory."));
+    }
+
+    let icon_dir = read_icon_dir(args, input)?;
+    let image = ico2img::decode_entry(&icon_dir, args.image_index)?.to_rgba8();
+    let width = env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .unwrap_or(80);
+    let true_color = env::var("COLORTERM").is_ok_and(|v| v == "truecolor" || v == "24bit");
+    print!("{}", preview::render(&image, width, true_color));
+    Ok(())
+}
+
+/// Decodes every entry of the input file selected by `--entry-filter`, printing the time each
+/// took, or why it failed. Fails if any entry can't be decoded.
+fn check_decode(args: &Args) -> Result<()> {
+    let start = Instant::now();
+    let icon_dir = read_icon_dir(args, input_path(args)?)?;
+    if args.verbose {
+        println!("Read the ICO directory in {:.2?}", start.elapsed());
+    }
+
+    let indices = selected_entries(args, &icon_dir);
+    let mut failures = 0;
+    for &index in &indices {
+        let start = Instant::now();
+        match ico2img::decode_entry(&icon_dir, index) {
+            Ok(image) => println!(
+                "Entry {}: decoded {}x{} in {:.2?}",
+                index,
+                image.width(),
+                image.height(),
+                start.elapsed()
+            ),
+            Err(ico2img::Ico2imgError::DecodeError { source, .. }) => {
+                println!("Entry {}: failed to decode: {}", index, source);
+                failures += 1;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    if failures > 0 {
+        return Err(anyhow!(
+            "{} of {} entries failed to decode.",
+            failures,
+            indices.len()
+        ));
+    }
+    Ok(())
+}
+
+/// Checks the raw data of the selected entries of the input file that start like PNG data,
+/// without decoding them, since the decoder may read some damaged data anyway. Fails listing
+/// the entries whose PNG signature or IHDR chunk is invalid.
+fn validate_png_signatures(args: &Args) -> Result<()> {
+    let data = read_ico_data(args, input_path(args)?)?;
+    let info = IcoInfo::from_reader(Cursor::new(&data))?;
+
+    let (mut checked, mut invalid) = (0, Vec::new());
+    for (index, entry) in info.entries.iter().enumerate() {
+        let size = (entry.width, entry.height);
+        let png = entry.encoding_type == EncodingType::Png;
+        if !is_selected(args, index, size, entry.bits_per_pixel, png) {
+            continue;
+        }
+        let start = entry.raw_data_offset as usize;
+        let Some(bytes) = data.get(start..start + entry.raw_data_size as usize) else {
+            warning::warn(format_args!(
+                "data of entry {} lies outside the file, skipping.",
+                index
+            ));
+            continue;
+        };
+        if !magic::claims_png(bytes) {
+            continue;
+        }
+
+        checked += 1;
+        match magic::check_png_header(bytes) {
+            Ok(()) if args.verbose => println!("Entry {}: valid PNG data", index),
+            Ok(()) => {}
+            Err(problem) => {
+                println!("Entry {}: stored as PNG, but {}", index, problem);
+                invalid.push(index.to_string());
+            }
+        }
+    }
+
+    if !invalid.is_empty() {
+        return Err(anyhow!(
+            "{} of {} PNG entries are invalid: {}.",
+            invalid.len(),
+            checked,
+            invalid.join(", ")
+        ));
+    }
+    println!("{} PNG entries are valid", checked);
+    Ok(())
+}
+
+/// Checks the images extracted from the input file to `dir` in a previous run, printing those that
+/// are missing, can't be decoded or don't match their entry. Images in a lossy format whose PSNR is
+/// low only get a warning. Fails if any image doesn't pass.
+fn validate_output(args: &Args, dir: &Path) -> Result<()> {
+    let input = input_path(args)?;
+    let results = ico2img::validate_extraction_output(input, dir, read_format(args))?;
+
+    let mut failed = Vec::new();
+    for result in &results {
+        let path = result.path.display();
+        match result.status {
+            ValidationStatus::Valid if args.verbose => {
+                println!("Entry {}: {} matches", result.index, path)
+            }
+            ValidationStatus::Valid => {}
+            ValidationStatus::LowPsnr(psnr) => warning::warn(format_args!(
+                "{} differs from entry {}: PSNR is {:.2} dB, below {:.0} dB.",
+                path,
+                result.index,
+                psnr,
+                ico2img::MIN_LOSSY_PSNR
+            )),
+            ValidationStatus::Missing => println!("Entry {}: {} is missing", result.index, path),
+            ValidationStatus::Invalid(ref reason) => {
+                println!("Entry {}: {} can't be read: {}", result.index, path, reason)
+            }
+            ValidationStatus::SizeMismatch { expected, actual } => println!(
+                "Entry {}: {} is {}x{}, expected {}x{}",
+                result.index, path, actual.0, actual.1, expected.0, expected.1
+            ),
+            _ => println!(
+                "Entry {}: {} doesn't match the entry pixel-for-pixel",
+                result.index, path
+            ),
+        }
+        if result.is_failure() {
+            failed.push(result.index.to_string());
+        }
+    }
+
+    if !failed.is_empty() {
+        return Err(anyhow!(
+            "{} of {} extracted images don't match the ICO file: entries {}.",
+            failed.len(),
+            results.len(),
+            failed.join(", ")
+        ));
+    }
+    println!("{} extracted images match the ICO file", results.len());
+    Ok(())
+}
+
+/// Decodes every entry of the input file and prints the pairs of entries of the same size that are
+/// duplicates: those whose pixels are identical, and those whose average hashes are close.
+fn detect_duplicate_entries(args: &Args) -> Result<()> {
+    let icon_dir = read_icon_dir(args, input_path(args)?)?;
+    let images = (0..icon_dir.entries().len())
+        .map(|index| Ok(ico2img::decode_entry(&icon_dir, index)?))
+        .collect::<Result<Vec<_>>>()?;
+
+    let duplicates = ico2img::find_duplicate_entries(&images);
+    for &(a, b, distance) in &duplicates {
+        let bits_per_pixel = |index: usize| icon_dir.entries()[index].bits_per_pixel();
+        let how = if distance == 0.0 {
+            "are identical".to_string()
+        } else {
+            format!(
+                "look alike ({:.0}% of their hashes differ)",
+                distance * 100.0
+            )
+        };
+        println!(
+            "Entries {} and {} ({}x{}, {} and {} bpp) {}",
+            a,
+            b,
+            images[a].width(),
+            images[a].height(),
+            bits_per_pixel(a),
+            bits_per_pixel(b),
+            how
+        );
+    }
+    if duplicates.is_empty() {
+        println!("No duplicate entries found");
+    }
+    Ok(())
+}
+
+/// Encodes every entry of the input file to every supported format, then prints, for each
+/// format, the total and average size of the images and their best and worst compression ratios
+/// against the raw RGBA data.
+fn print_stats(args: &Args) -> Result<()> {
+    let icon_dir = read_icon_dir(args, input_path(args)?)?;
+    let options = transform_options(args).build()?;
+
+    let mut images = Vec::with_capacity(icon_dir.entries().len());
+    for index in 0..icon_dir.entries().len() {
+        images.push(ico2img::transform(ico2img::decode_entry(&icon_dir, index)?, &options)?);
+    }
+    if images.is_empty() {
+        return Err(anyhow!("No images found in the ICO file."));
+    }
+
+    println!(
+        "{:<8} {:>12} {:>12} {:>8} {:>8}",
+        "Format", "Total", "Average", "Best", "Worst"
+    );
+    for &format in SupportedImages::ALL {
+        // the encoder settings only apply to the formats they belong to
+        let mut format_options = ExtractOptions::new()
+            .format(format)
+            .keep_icc(args.keep_icc && format != SupportedImages::Bmp)
+            .png_indexed(args.png_indexed && format == SupportedImages::Png);
+        match format {
+            SupportedImages::Jpeg => {
+                if let Some(quality) = args.jpeg_quality {
+                    format_options = format_options.jpeg_quality(quality);
+                }
+                if let Some(subsampling) = args.jpeg_subsampling {
+                    format_options = format_options.jpeg_subsampling(subsampling);
+                }
+            }
+            SupportedImages::Bmp => {
+                if let Some(depth) = args.depth {
+                    format_options = format_options.bmp_depth(depth);
+                }
+                if let Some(bmp_format) = args.bmp_format {
+                    format_options = format_options.bmp_format(bmp_format);
+                }
+                if let Some(palette) = args.palette {
+                    format_options = format_options.palette(palette);
+                }
+                if let Some(dither) = args.dither {
+                    format_options = format_options.dither(dither);
+                }
+            }
+            _ => {}
+        }
+        let format_options = format_options.build()?;
+
+        let mut total = 0;
+        let (mut best, mut worst) = (f64::INFINITY, 0.0_f64);
+        for image in &images {
+            let size = ico2img::encode(image, &format_options)?.len();
+            let raw_size = image.width() as f64 * image.height() as f64 * 4.0;
+            let ratio = size as f64 / raw_size;
+            total += size;
+            best = best.min(ratio);
+            worst = worst.max(ratio);
+        }
+
+        println!(
+            "{:<8} {:>10} B {:>10} B {:>7.1}% {:>7.1}%",
+            format,
+            total,
+            total / images.len(),
+            best * 100.0,
+            worst * 100.0
+        );
+    }
+
+    Ok(())
+}
+
+/// Arguments:
+///   - path: The path to the written image.
+///   - expected_width: The width the image should have.
+///   - expected_height: The height the image should have.
+///
+/// Reads the image back from disk and checks that it isn't empty, that it decodes, and that its
+/// dimensions match the expected ones.
+fn verify_output_file(path: &Path, expected_width: u32, expected_height: u32) -> Result<()> {
+    let data = fs::read(path)?;
+    if data.is_empty() {
+        return Err(anyhow!("Output file {} is empty.", path.display()));
+    }
+
+    let image = load_from_memory(&data)
+        .map_err(|e| anyhow!("Output file {} can't be decoded: {}", path.display(), e))?;
+    if image.width() == 0 || image.height() == 0 {
+        return Err(anyhow!("Output file {} has no pixels.", path.display()));
+    } else if image.width() != expected_width || image.height() != expected_height {
+        return Err(anyhow!(
+            "Output file {} is {}x{}, expected {}x{}.",
+            path.display(),
+            image.width(),
+            image.height(),
+            expected_width,
+            expected_height
+        ));
+    }
+
+    Ok(())
+}
+
+/// Arguments:
+///   - args: The command-line arguments.
+///   - path: The path to the written image.
+///   - original: The image before encoding.
+///
+/// Decodes the written image and compares it to the original one. Images in lossless formats
+/// must match pixel-for-pixel, while JPEG images must reach the PSNR set by `--verify-psnr`. BMP
+/// images reduced by `--depth` or written with `--bmp-format`, which may drop bits of color or
+/// the alpha channel, are checked by [`check_reduced_bmp`].
+fn verify_round_trip(args: &Args, path: &Path, original: &DynamicImage) -> Result<()> {
+    let data = fs::read(path)?;
+    let format = image::guess_format(&data)?;
+    let written = load_from_memory(&data)?;
+    let min_psnr = args.verify_psnr;
+
+    if written.width() != original.width() || written.height() != original.height() {
+        return Err(anyhow!(
+            "Output file {} is {}x{}, expected {}x{}.",
+            path.display(),
+            written.width(),
+            written.height(),
+            original.width(),
+            original.height()
+        ));
+    }
+
+    if format == ImageFormat::Jpeg {
+        // JPEG has no alpha channel, so only the color channels are compared
+        let psnr = ico2img::psnr(original.to_rgb8().as_raw(), written.to_rgb8().as_raw());
+        if psnr < min_psnr {
+            return Err(anyhow!(
+                "Output file {} differs from the ICO entry: PSNR is {:.2} dB, expected at least {:.2} dB.",
+                path.display(),
+                psnr,
+                min_psnr
+            ));
+        }
+    } else if format == ImageFormat::Bmp && (args.depth.is_some() || args.bmp_format.is_some())
+    {
+        check_reduced_bmp(args, path, &original.to_rgba8(), &written.to_rgba8())?;
+    } else if written.to_rgba8() != original.to_rgba8() {
+        return Err(anyhow!(
+            "Output file {} doesn't match the ICO entry pixel-for-pixel.",
+            path.display()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Arguments:
+///   - args: The command-line arguments.
+///   - path: The path to the written image.
+///   - original: The image before encoding.
+///   - written: The written image, a BMP image reduced by `--depth` or `--bmp-format`.
+///
+/// Checks that `written` is `original` reduced as expected, from the pixels of both rather than
+/// by encoding `original` again. Pixels that BMP can't keep transparent, whose alpha is below
+/// 128, must be black with `--depth` and RGB565. With `--depth`, the image must have at most
+/// the colors of the palette, and each pixel must be of one of its colors closest to the
+/// original pixel, unless the image is dithered. RGB565 pixels must be within rounding of the
+/// original ones, and RGB888 pixels must keep their color.
+fn check_reduced_bmp(
+    args: &Args,
+    path: &Path,
+    original: &RgbaImage,
+    written: &RgbaImage,
+) -> Result<()> {
+    let colors: HashSet<[u8; 3]> = written
+        .pixels()
+        .map(|pixel| [pixel[0], pixel[1], pixel[2]])
+        .collect();
+    if let Some(depth) = args.depth {
+        if colors.len() > 1 << depth {
+            return Err(anyhow!(
+                "Output file {} has {} colors, more than a {}-bit palette holds.",
+                path.display(),
+                colors.len(),
+                depth
+            ));
+        }
+    }
+
+    let distance = |a: [u8; 3], b: [u8; 3]| -> i32 {
+        (0..3).map(|c| (a[c] as i32 - b[c] as i32).pow(2)).sum()
+    };
+    for (x, y, pixel) in original.enumerate_pixels() {
+        let [r, g, b, a] = pixel.0;
+        let [wr, wg, wb, _] = written.get_pixel(x, y).0;
+        let (wanted, got) = ([r, g, b], [wr, wg, wb]);
+        let transparent = a < 128;
+
+        let matches = match (args.depth, args.bmp_format) {
+            (Some(_), _) if transparent => got == [0, 0, 0],
+            (Some(_), _) if args.dither.is_some() => true,
+            (Some(_), _) => colors
+                .iter()
+                .all(|&color| distance(wanted, got) <= distance(wanted, color)),
+            (None, Some(BmpFormat::Rgb565)) if transparent => got == [0, 0, 0],
+            // 5 bits of red and blue and 6 bits of green, rounded both ways
+            (None, Some(BmpFormat::Rgb565)) => {
+                let tolerance = [5, 3, 5];
+                (0..3).all(|c| wanted[c].abs_diff(got[c]) <= tolerance[c])
+            }
+            (None, Some(BmpFormat::Rgb888)) => got == wanted,
+            _ => written.get_pixel(x, y) == pixel,
+        };
+        if !matches {
+            return Err(anyhow!(
+                "Output file {} doesn't match the ICO entry at ({}, {}) once reduced.",
+                path.display(),
+                x,
+                y
+            ));
+        }
+    }
+
+    Ok(())
+}
 
-    if index == 0 {
-        let image = icon_dir.entries()[0].decode()?;
-        let mut png_buffer = Vec::new();
-        image.write_png(&mut png_buffer)?;
-        Ok(png_buffer)
-    } else {
-        let entry = &icon_dir.entries()[index];
-        let image = entry.decode()?;
-        let mut buffer = Vec::new();
-        image.write_png(&mut buffer)?;
-        Ok(buffer)
-    }
-}
@@ -0,0 +1,133 @@
+//! Export of 1-bit transparency masks, for toolkits that need the color data and the mask of an
+//! icon as separate bitmaps.
+//!
+//! A pixel of the mask is either set (black) or clear (white). By default, transparent pixels are
+//! set, like in the AND mask of ICO files.
+
+use anyhow::{anyhow, Result};
+use image::RgbaImage;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    str::FromStr,
+};
+
+/// The formats masks can be written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MaskFormat {
+    Png,
+    Pbm,
+}
+
+impl MaskFormat {
+    /// Returns the file extension used for masks in this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            MaskFormat::Png => "png",
+            MaskFormat::Pbm => "pbm",
+        }
+    }
+}
+
+impl FromStr for MaskFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "png" => Ok(MaskFormat::Png),
+            "pbm" => Ok(MaskFormat::Pbm),
+            _ => Err(anyhow!("Invalid mask format {}, expected png or pbm.", s)),
+        }
+    }
+}
+
+/// A 1-bit mask, with one `bool` per pixel telling whether it's set.
+pub struct Mask {
+    width: u32,
+    height: u32,
+    bits: Vec<bool>,
+}
+
+impl Mask {
+    /// Arguments:
+    ///   - image: The image to derive the mask from.
+    ///   - threshold: The alpha value from which a pixel counts as opaque.
+    ///   - dither: Whether to diffuse the error of each pixel to its neighbors (Floyd-Steinberg),
+    ///     to render soft shadows.
+    ///   - invert: Whether to set opaque pixels rather than transparent ones.
+    ///
+    /// Returns the mask of `image`.
+    pub fn from_alpha(image: &RgbaImage, threshold: u8, dither: bool, invert: bool) -> Mask {
+        let (width, height) = image.dimensions();
+        let (w, h) = (width as usize, height as usize);
+        let mut alpha: Vec<f32> = image.pixels().map(|p| p[3] as f32).collect();
+        let mut bits = vec![false; w * h];
+
+        for y in 0..h {
+            for x in 0..w {
+                let i = y * w + x;
+                let opaque = alpha[i] >= threshold as f32;
+                bits[i] = opaque == invert;
+
+                if dither {
+                    let error = alpha[i] - if opaque { 255.0 } else { 0.0 };
+                    let mut spread = |dx: isize, dy: usize, weight: f32| {
+                        let nx = x as isize + dx;
+                        if nx >= 0 && (nx as usize) < w && y + dy < h {
+                            alpha[(y + dy) * w + nx as usize] += error * weight;
+                        }
+                    };
+                    spread(1, 0, 7.0 / 16.0);
+                    spread(-1, 1, 3.0 / 16.0);
+                    spread(0, 1, 5.0 / 16.0);
+                    spread(1, 1, 1.0 / 16.0);
+                }
+            }
+        }
+
+        Mask {
+            width,
+            height,
+            bits,
+        }
+    }
+
+    /// Returns the rows of the mask packed 8 pixels per byte, most significant bit first, with a
+    /// bit of 1 for set pixels if `set_is_one`, and 0 otherwise.
+    fn packed_rows(&self, set_is_one: bool) -> Vec<u8> {
+        let row_len = (self.width as usize).div_ceil(8);
+        let mut packed = vec![0; row_len * self.height as usize];
+        for (y, row) in self.bits.chunks(self.width as usize).enumerate() {
+            for (x, &set) in row.iter().enumerate() {
+                if set == set_is_one {
+                    packed[y * row_len + x / 8] |= 0x80 >> (x % 8);
+                }
+            }
+        }
+        packed
+    }
+
+    /// Writes the mask to `path` in `format`.
+    pub fn write(&self, path: &Path, format: MaskFormat) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        match format {
+            MaskFormat::Png => {
+                let mut encoder = png::Encoder::new(&mut writer, self.width, self.height);
+                encoder.set_color(png::ColorType::Grayscale);
+                encoder.set_depth(png::BitDepth::One);
+                // a bit of 0 is black in 1-bit grayscale
+                encoder
+                    .write_header()?
+                    .write_image_data(&self.packed_rows(false))?;
+            }
+            MaskFormat::Pbm => {
+                // a bit of 1 is black in PBM
+                write!(writer, "P4\n{} {}\n", self.width, self.height)?;
+                writer.write_all(&self.packed_rows(true))?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
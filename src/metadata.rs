@@ -0,0 +1,126 @@
+//! XMP packets for `--write-xmp`, describing where a converted image comes from.
+//!
+//! A packet is made from a template, the default one or the file of `--xmp-template`, whose
+//! `{placeholder}`s are replaced by XML-escaped values: `{source}`, `{index}`, `{created}`,
+//! `{tool}`, `{version}`, `{width}` and `{height}`.
+
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The template used without `--xmp-template`. Its header holds a byte order mark, as XMP
+/// packets do.
+pub const DEFAULT_TEMPLATE: &str = concat!(
+    "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n",
+    r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:dc="http://purl.org/dc/elements/1.1/"
+    xmlns:xmp="http://ns.adobe.com/xap/1.0/"
+    xmlns:tiff="http://ns.adobe.com/tiff/1.0/"
+    xmlns:ico2img="https://github.com/walker84837/ico2img-rs/ns/1.0/">
+   <dc:source>{source}</dc:source>
+   <ico2img:EntryIndex>{index}</ico2img:EntryIndex>
+   <xmp:CreateDate>{created}</xmp:CreateDate>
+   <xmp:CreatorTool>{tool} {version}</xmp:CreatorTool>
+   <tiff:ImageWidth>{width}</tiff:ImageWidth>
+   <tiff:ImageLength>{height}</tiff:ImageLength>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end="w"?>"#
+);
+
+/// Arguments:
+///   - template: The template of the packet.
+///   - source: The URI of the ICO file, as returned by [`source_uri`].
+///   - index: The index of the entry the image was converted from.
+///   - size: The width and height of the image, in pixels.
+///
+/// Returns the packet describing the image, with the current time as its creation time.
+pub fn packet(template: &str, source: &str, index: usize, (width, height): (u32, u32)) -> String {
+    let values = [
+        ("{source}", source.to_string()),
+        ("{index}", index.to_string()),
+        ("{created}", now_utc()),
+        ("{tool}", env!("CARGO_PKG_NAME").to_string()),
+        ("{version}", env!("CARGO_PKG_VERSION").to_string()),
+        ("{width}", width.to_string()),
+        ("{height}", height.to_string()),
+    ];
+    values
+        .iter()
+        .fold(template.to_string(), |packet, (placeholder, value)| {
+            packet.replace(placeholder, &escape_xml(value))
+        })
+}
+
+/// Returns the URI of the input file: URLs as they are, and paths as absolute `file://` URIs,
+/// with the bytes outside of unreserved characters and slashes percent-encoded.
+pub fn source_uri(path: &Path) -> String {
+    let text = path.to_string_lossy();
+    if text.starts_with("http://") || text.starts_with("https://") {
+        return text.into_owned();
+    }
+
+    let absolute = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let absolute = absolute.to_string_lossy().replace('\\', "/");
+    // canonical paths on Windows start with `//?/`, then the drive
+    let absolute = absolute.strip_prefix("//?/").unwrap_or(&absolute);
+    let mut uri = String::from("file://");
+    if !absolute.starts_with('/') {
+        uri.push('/');
+    }
+    for byte in absolute.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/:".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri += &format!("%{:02X}", byte);
+        }
+    }
+    uri
+}
+
+/// Returns `text` with the characters that are special in XML escaped.
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Returns the current time in UTC, as `YYYY-MM-DDThh:mm:ssZ`.
+fn now_utc() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let (days, time) = (seconds / 86400, seconds % 86400);
+
+    // converts days since 1970-01-01 to a date of the proleptic Gregorian calendar, counting
+    // from 0000-03-01 so that leap days end the years
+    let days = days as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
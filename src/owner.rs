@@ -0,0 +1,85 @@
+//! Owner and permissions of the written files and created directories, for `--chown`,
+//! `--file-mode` and `--dir-mode`. They only apply on Unix; elsewhere, the options are ignored
+//! with a warning.
+
+use anyhow::{anyhow, Result};
+use std::{fs, path::Path};
+
+/// Returns the user and group IDs parsed from `uid:gid`.
+pub fn parse_owner(s: &str) -> Result<(u32, u32)> {
+    let (uid, gid) = s
+        .split_once(':')
+        .ok_or_else(|| anyhow!("Invalid owner {}, expected UID:GID.", s))?;
+    let parse = |id: &str| {
+        id.parse::<u32>()
+            .map_err(|_| anyhow!("Invalid owner {}, expected numeric IDs.", s))
+    };
+    Ok((parse(uid)?, parse(gid)?))
+}
+
+/// Returns the permission bits parsed from an octal mode, such as `644` or `0755`.
+pub fn parse_mode(s: &str) -> Result<u32> {
+    match u32::from_str_radix(s, 8) {
+        Ok(mode) if mode <= 0o7777 => Ok(mode),
+        _ => Err(anyhow!(
+            "Invalid mode {}, expected an octal number up to 7777.",
+            s
+        )),
+    }
+}
+
+/// Arguments:
+///   - path: The file or directory written.
+///   - owner: The user and group IDs to give it, if any.
+///   - mode: The permission bits to give it, if any.
+///
+/// Sets the owner and permissions of `path`, warning about each change that fails, such as
+/// changing the owner without running as root.
+pub fn apply(path: &Path, owner: Option<(u32, u32)>, mode: Option<u32>) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        if let Some(mode) = mode {
+            if let Err(e) = fs::set_permissions(path, fs::Permissions::from_mode(mode)) {
+                crate::warning::warn(format_args!(
+                    "can't set the mode of {} to {:o}: {}",
+                    path.display(),
+                    mode,
+                    e
+                ));
+            }
+        }
+        if let Some((uid, gid)) = owner {
+            if let Err(e) = std::os::unix::fs::chown(path, Some(uid), Some(gid)) {
+                crate::warning::warn(format_args!(
+                    "can't change the owner of {} to {}:{}: {}",
+                    path.display(),
+                    uid,
+                    gid,
+                    e
+                ));
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    let _ = (path, owner, mode);
+}
+
+/// Creates `dir` and its missing parents, like [`fs::create_dir_all`], giving the directories
+/// created the owner and permissions passed to [`apply`]. Existing directories are left as they
+/// are.
+pub fn create_dir_all(dir: &Path, owner: Option<(u32, u32)>, mode: Option<u32>) -> Result<()> {
+    let missing: Vec<&Path> = dir
+        .ancestors()
+        .take_while(|dir| !dir.as_os_str().is_empty() && !dir.exists())
+        .collect();
+    fs::create_dir_all(dir)?;
+    // deepest first, so that a restrictive mode on a parent doesn't prevent changing the
+    // directories below
+    for dir in missing {
+        apply(dir, owner, mode);
+    }
+    Ok(())
+}
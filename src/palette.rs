@@ -0,0 +1,366 @@
+//! Indexed BMP images of 16 and 256 colors, for platforms that can't display true-color ones.
+//! The `image` crate only writes true-color BMP images, so they're written here.
+
+use crate::{error::Result, Ico2imgError};
+use image::RgbaImage;
+use std::str::FromStr;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Alpha below which a pixel is written as the transparent index, 0.
+const ALPHA_THRESHOLD: u8 = 128;
+/// Size of the file header of BMP images.
+const FILE_HEADER_LEN: u32 = 14;
+/// Size of the `BITMAPINFOHEADER` of BMP images.
+const INFO_HEADER_LEN: u32 = 40;
+
+/// The 16 colors of the Windows (and VGA) palette, in their usual order.
+const WINDOWS_16: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00],
+    [0x80, 0x00, 0x00],
+    [0x00, 0x80, 0x00],
+    [0x80, 0x80, 0x00],
+    [0x00, 0x00, 0x80],
+    [0x80, 0x00, 0x80],
+    [0x00, 0x80, 0x80],
+    [0xC0, 0xC0, 0xC0],
+    [0x80, 0x80, 0x80],
+    [0xFF, 0x00, 0x00],
+    [0x00, 0xFF, 0x00],
+    [0xFF, 0xFF, 0x00],
+    [0x00, 0x00, 0xFF],
+    [0xFF, 0x00, 0xFF],
+    [0x00, 0xFF, 0xFF],
+    [0xFF, 0xFF, 0xFF],
+];
+
+/// The first 10 of the 20 static colors of the Windows system palette.
+const WINDOWS_STATIC_LOW: [[u8; 3]; 10] = [
+    [0x00, 0x00, 0x00],
+    [0x80, 0x00, 0x00],
+    [0x00, 0x80, 0x00],
+    [0x80, 0x80, 0x00],
+    [0x00, 0x00, 0x80],
+    [0x80, 0x00, 0x80],
+    [0x00, 0x80, 0x80],
+    [0xC0, 0xC0, 0xC0],
+    [0xC0, 0xDC, 0xC0],
+    [0xA6, 0xCA, 0xF0],
+];
+
+/// The last 10 of the 20 static colors of the Windows system palette.
+const WINDOWS_STATIC_HIGH: [[u8; 3]; 10] = [
+    [0xFF, 0xFB, 0xF0],
+    [0xA0, 0xA0, 0xA4],
+    [0x80, 0x80, 0x80],
+    [0xFF, 0x00, 0x00],
+    [0x00, 0xFF, 0x00],
+    [0xFF, 0xFF, 0x00],
+    [0x00, 0x00, 0xFF],
+    [0xFF, 0x00, 0xFF],
+    [0x00, 0xFF, 0xFF],
+    [0xFF, 0xFF, 0xFF],
+];
+
+/// The 4x4 Bayer matrix of ordered dithering, with thresholds from 0 to 15.
+const BAYER_4X4: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// The colors indexed BMP images are reduced to, set with [`crate::ExtractOptions::palette`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[non_exhaustive]
+pub enum Palette {
+    /// The fixed palette of Windows: its 16 colors for 4-bit images, and for 8-bit images its 20
+    /// static colors, the 6x6x6 color cube of its halftone palette and a ramp of grays.
+    Windows,
+    /// The colors that best fit the image, chosen by median cut.
+    Adaptive,
+}
+
+impl FromStr for Palette {
+    type Err = Ico2imgError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "windows" => Ok(Palette::Windows),
+            "adaptive" => Ok(Palette::Adaptive),
+            _ => Err(Ico2imgError::InvalidArgument(format!(
+                "Invalid palette {}, expected windows or adaptive.",
+                s
+            ))),
+        }
+    }
+}
+
+/// How the colors lost by reducing an image to a palette are made up for, set with
+/// [`crate::ExtractOptions::dither`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+#[non_exhaustive]
+pub enum Dither {
+    /// With a regular pattern (a 4x4 Bayer matrix), which stays the same as the image changes.
+    Ordered,
+    /// By diffusing the error of each pixel to its neighbors, which renders gradients best.
+    FloydSteinberg,
+}
+
+impl FromStr for Dither {
+    type Err = Ico2imgError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "ordered" => Ok(Dither::Ordered),
+            "floyd-steinberg" | "fs" => Ok(Dither::FloydSteinberg),
+            _ => Err(Ico2imgError::InvalidArgument(format!(
+                "Invalid dithering {}, expected ordered or floyd-steinberg.",
+                s
+            ))),
+        }
+    }
+}
+
+/// Arguments:
+///   - image: The image to encode.
+///   - depth: The number of bits per pixel, 4 or 8.
+///   - palette: The colors to reduce the image to.
+///   - dither: The dithering, if any.
+///
+/// Returns the image encoded as an indexed BMP image. BMP images have no transparency, so
+/// pixels whose alpha is below 128 are written as index 0, which is black in the Windows
+/// palette and reserved for them in adaptive palettes; the others are made opaque.
+pub(crate) fn encode_indexed_bmp(
+    image: &RgbaImage,
+    depth: u8,
+    palette: Palette,
+    dither: Option<Dither>,
+) -> Vec<u8> {
+    let size = 1usize << depth;
+    let colors = match (palette, depth) {
+        (Palette::Windows, 4) => WINDOWS_16.to_vec(),
+        (Palette::Windows, _) => windows_256(),
+        (Palette::Adaptive, _) => adaptive(image, size),
+    };
+    let indices = quantize(image, &colors, dither);
+    write_bmp(image.width(), image.height(), depth, &colors, &indices)
+}
+
+/// Returns the 256 colors of the Windows palette.
+fn windows_256() -> Vec<[u8; 3]> {
+    let mut colors = WINDOWS_STATIC_LOW.to_vec();
+    let levels = [0x00, 0x33, 0x66, 0x99, 0xCC, 0xFF];
+    for r in levels {
+        for g in levels {
+            for b in levels {
+                colors.push([r, g, b]);
+            }
+        }
+    }
+    // the 20 remaining colors are grays between those of the cube
+    for i in 1..=20u32 {
+        let gray = (i * 255 / 21) as u8;
+        colors.push([gray, gray, gray]);
+    }
+    colors.extend(WINDOWS_STATIC_HIGH);
+    colors
+}
+
+/// Returns at most `size` colors fitting the opaque pixels of `image`, chosen by median cut.
+/// If the image has transparent pixels, the first color is black, reserved for them.
+fn adaptive(image: &RgbaImage, size: usize) -> Vec<[u8; 3]> {
+    let mut pixels: Vec<[u8; 3]> = Vec::new();
+    let mut has_transparency = false;
+    for pixel in image.pixels() {
+        let [r, g, b, a] = pixel.0;
+        if a < ALPHA_THRESHOLD {
+            has_transparency = true;
+        } else {
+            pixels.push([r, g, b]);
+        }
+    }
+
+    let mut colors = if has_transparency {
+        vec![[0, 0, 0]]
+    } else {
+        Vec::new()
+    };
+    let size = size - colors.len();
+
+    let mut unique = pixels.clone();
+    unique.sort_unstable();
+    unique.dedup();
+    if unique.len() <= size {
+        colors.extend(unique);
+        return colors;
+    }
+
+    let mut boxes = vec![pixels];
+    while boxes.len() < size {
+        // split the box with the widest range of a channel, at its median along that channel
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .map(|(i, pixels)| {
+                let (channel, range) = widest_channel(pixels);
+                (i, channel, range)
+            })
+            .max_by_key(|&(_, _, range)| range);
+        let Some((i, channel, range)) = widest else {
+            break;
+        };
+        if range == 0 {
+            break;
+        }
+        let mut pixels = boxes.swap_remove(i);
+        pixels.sort_unstable_by_key(|pixel| pixel[channel]);
+        let upper = pixels.split_off(pixels.len() / 2);
+        boxes.push(pixels);
+        boxes.push(upper);
+    }
+
+    colors.extend(
+        boxes
+            .iter()
+            .filter(|pixels| !pixels.is_empty())
+            .map(|pixels| {
+                let mut sums = [0u64; 3];
+                for pixel in pixels {
+                    for (sum, &channel) in sums.iter_mut().zip(pixel) {
+                        *sum += channel as u64;
+                    }
+                }
+                sums.map(|sum| (sum / pixels.len() as u64) as u8)
+            }),
+    );
+    colors
+}
+
+/// Returns the channel with the widest range of values among `pixels`, and that range.
+fn widest_channel(pixels: &[[u8; 3]]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let values = pixels.iter().map(|pixel| pixel[channel]);
+            let range = values.clone().max().unwrap_or(0) - values.min().unwrap_or(0);
+            (channel, range)
+        })
+        .max_by_key(|&(_, range)| range)
+        .unwrap_or((0, 0))
+}
+
+/// Returns the index in `colors` of each pixel of `image`, row by row from the top, dithered if
+/// asked to. Pixels whose alpha is below the threshold get index 0.
+fn quantize(image: &RgbaImage, colors: &[[u8; 3]], dither: Option<Dither>) -> Vec<u8> {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    // the spacing of the palette colors along each channel, roughly
+    let spread = 255.0 / (colors.len() as f32).cbrt();
+    let mut errors = vec![[0f32; 3]; width * height];
+    let mut indices = vec![0; width * height];
+
+    for y in 0..height {
+        for x in 0..width {
+            let [r, g, b, a] = image.get_pixel(x as u32, y as u32).0;
+            if a < ALPHA_THRESHOLD {
+                continue;
+            }
+
+            let offset = match dither {
+                Some(Dither::Ordered) => {
+                    (BAYER_4X4[y % 4][x % 4] as f32 + 0.5) / 16.0 * spread - spread / 2.0
+                }
+                _ => 0.0,
+            };
+            let error = errors[y * width + x];
+            let wanted = [r, g, b];
+            let wanted =
+                [0, 1, 2].map(|c| (wanted[c] as f32 + error[c] + offset).clamp(0.0, 255.0));
+            let index = nearest(colors, wanted);
+            indices[y * width + x] = index as u8;
+
+            if dither == Some(Dither::FloydSteinberg) {
+                let chosen = colors[index];
+                let error = [0, 1, 2].map(|c| wanted[c] - chosen[c] as f32);
+                let mut diffuse = |dx: isize, dy: usize, weight: f32| {
+                    let nx = x as isize + dx;
+                    if nx >= 0 && (nx as usize) < width && y + dy < height {
+                        let neighbor = &mut errors[(y + dy) * width + nx as usize];
+                        for (channel, error) in neighbor.iter_mut().zip(error) {
+                            *channel += error * weight;
+                        }
+                    }
+                };
+                diffuse(1, 0, 7.0 / 16.0);
+                diffuse(-1, 1, 3.0 / 16.0);
+                diffuse(0, 1, 5.0 / 16.0);
+                diffuse(1, 1, 1.0 / 16.0);
+            }
+        }
+    }
+
+    indices
+}
+
+/// Returns the index of the color of `colors` closest to `wanted`.
+fn nearest(colors: &[[u8; 3]], wanted: [f32; 3]) -> usize {
+    colors
+        .iter()
+        .enumerate()
+        .map(|(i, color)| {
+            let distance: f32 = (0..3).map(|c| (color[c] as f32 - wanted[c]).powi(2)).sum();
+            (i, distance)
+        })
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map_or(0, |(i, _)| i)
+}
+
+/// Returns a BMP file of `width` by `height` pixels of `depth` bits, with the palette `colors`
+/// and the palette `indices` of the pixels, row by row from the top.
+fn write_bmp(width: u32, height: u32, depth: u8, colors: &[[u8; 3]], indices: &[u8]) -> Vec<u8> {
+    // rows are padded to a multiple of 4 bytes
+    let row_len = (width * depth as u32).div_ceil(32) * 4;
+    let palette_len = (1u32 << depth) * 4;
+    let pixels_offset = FILE_HEADER_LEN + INFO_HEADER_LEN + palette_len;
+    let file_len = pixels_offset + row_len * height;
+
+    let mut bmp = Vec::with_capacity(file_len as usize);
+    bmp.extend_from_slice(b"BM");
+    bmp.extend_from_slice(&file_len.to_le_bytes());
+    bmp.extend_from_slice(&[0; 4]);
+    bmp.extend_from_slice(&pixels_offset.to_le_bytes());
+
+    bmp.extend_from_slice(&INFO_HEADER_LEN.to_le_bytes());
+    bmp.extend_from_slice(&(width as i32).to_le_bytes());
+    bmp.extend_from_slice(&(height as i32).to_le_bytes());
+    bmp.extend_from_slice(&1u16.to_le_bytes());
+    bmp.extend_from_slice(&(depth as u16).to_le_bytes());
+    // BI_RGB, the image size (which may be 0 for BI_RGB), the resolution (72 DPI) and the
+    // number of colors used and important (0 for all)
+    bmp.extend_from_slice(&0u32.to_le_bytes());
+    bmp.extend_from_slice(&(row_len * height).to_le_bytes());
+    bmp.extend_from_slice(&2835u32.to_le_bytes());
+    bmp.extend_from_slice(&2835u32.to_le_bytes());
+    bmp.extend_from_slice(&0u32.to_le_bytes());
+    bmp.extend_from_slice(&0u32.to_le_bytes());
+
+    for i in 0..1usize << depth {
+        let [r, g, b] = colors.get(i).copied().unwrap_or([0, 0, 0]);
+        bmp.extend_from_slice(&[b, g, r, 0]);
+    }
+
+    // rows are stored bottom-up
+    for y in (0..height as usize).rev() {
+        let row = &indices[y * width as usize..(y + 1) * width as usize];
+        let start = bmp.len();
+        if depth == 4 {
+            for pair in row.chunks(2) {
+                bmp.push((pair[0] << 4) | pair.get(1).copied().unwrap_or(0));
+            }
+        } else {
+            bmp.extend_from_slice(row);
+        }
+        bmp.resize(start + row_len as usize, 0);
+    }
+
+    bmp
+}
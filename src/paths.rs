@@ -0,0 +1,162 @@
+//! Adjustment of the computed output paths to the limits of file systems, so that long input
+//! names don't fail with an opaque error when the output is created.
+
+use std::path::{Path, PathBuf};
+
+/// Maximum length of a file name, in bytes, on most file systems (ext4, NTFS, APFS).
+const MAX_NAME_LEN: usize = 255;
+/// Maximum length of a path on Windows without the `\\?\` prefix, excluding the terminating NUL.
+#[cfg(windows)]
+const MAX_PATH_LEN: usize = 259;
+/// Number of hexadecimal digits of the hash added to truncated names.
+const HASH_LEN: usize = 8;
+
+/// Returns `path` with its file name shortened if it's longer than file systems allow, warning
+/// about it. On Windows, paths longer than `MAX_PATH` are also given the `\\?\` prefix, which
+/// lifts the limit.
+pub fn fit_output_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let path = match shorten_name(&name, MAX_NAME_LEN) {
+        Some(short_name) => {
+            let short_path = path.with_file_name(short_name);
+            crate::warning::warn(format_args!(
+                "the file name of {} is too long, writing to {} instead.",
+                path.display(),
+                short_path.display()
+            ));
+            short_path
+        }
+        None => path.to_path_buf(),
+    };
+
+    #[cfg(windows)]
+    if let Some(extended) = extended_length_path(&path, MAX_PATH_LEN) {
+        return extended;
+    }
+
+    path
+}
+
+/// Returns `path` made absolute with the `\\?\` prefix if it's longer than `max_len`, and
+/// doesn't have the prefix yet.
+#[cfg(any(windows, test))]
+fn extended_length_path(path: &Path, max_len: usize) -> Option<PathBuf> {
+    if path.as_os_str().len() <= max_len || path.to_string_lossy().starts_with(r"\\?\") {
+        return None;
+    }
+
+    // `absolute` also turns slashes into backslashes and resolves `..`, which the prefix requires
+    let absolute = std::path::absolute(path).ok()?;
+    let mut extended = std::ffi::OsString::from(r"\\?\");
+    extended.push(absolute);
+    Some(PathBuf::from(extended))
+}
+
+/// Returns `name` truncated to at most `max_len` bytes if it's longer, keeping its extension.
+/// The end of the stem is replaced with a hash of the whole name, so that names sharing a long
+/// prefix stay distinct. The same name is always shortened the same way.
+fn shorten_name(name: &str, max_len: usize) -> Option<String> {
+    if name.len() <= max_len {
+        return None;
+    }
+
+    // an extension too long to be kept is truncated along with the stem
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension))
+            if !stem.is_empty() && extension.len() + HASH_LEN + 2 < max_len =>
+        {
+            (stem, format!(".{}", extension))
+        }
+        _ => (name, String::new()),
+    };
+    let hash = format!("{:016x}", fnv1a(name.as_bytes()));
+
+    let mut keep = max_len.saturating_sub(extension.len() + HASH_LEN + 1);
+    while !stem.is_char_boundary(keep) {
+        keep -= 1;
+    }
+    Some(format!("{}-{}{}", &stem[..keep], &hash[..HASH_LEN], extension))
+}
+
+/// Returns the 64-bit FNV-1a hash of `data`, which unlike the hashers of the standard library is
+/// stable across Rust versions.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_names_are_kept() {
+        assert_eq!(shorten_name("app_0.png", MAX_NAME_LEN), None);
+        let name = format!("{}.png", "a".repeat(MAX_NAME_LEN - 4));
+        assert_eq!(shorten_name(&name, MAX_NAME_LEN), None);
+    }
+
+    #[test]
+    fn long_names_fit_the_name_limit() {
+        let name = format!("{}.png", "a".repeat(300));
+        let short = shorten_name(&name, MAX_NAME_LEN).unwrap();
+        assert_eq!(short.len(), MAX_NAME_LEN);
+        assert!(short.starts_with("aaaa"));
+        assert!(short.ends_with(".png"));
+        // the same name is always shortened the same way
+        assert_eq!(shorten_name(&name, MAX_NAME_LEN).unwrap(), short);
+
+        let other = format!("{}b.png", "a".repeat(299));
+        let other_short = shorten_name(&other, MAX_NAME_LEN).unwrap();
+        assert_eq!(other_short.len(), MAX_NAME_LEN);
+        assert_ne!(other_short, short);
+    }
+
+    #[test]
+    fn long_names_are_cut_on_char_boundaries() {
+        let name = format!("{}.png", "é".repeat(200));
+        let short = shorten_name(&name, MAX_NAME_LEN).unwrap();
+        assert!(short.len() <= MAX_NAME_LEN);
+        assert!(short.ends_with(".png"));
+    }
+
+    #[test]
+    fn long_extensions_are_truncated_with_the_stem() {
+        let name = format!("app.{}", "x".repeat(300));
+        let short = shorten_name(&name, MAX_NAME_LEN).unwrap();
+        assert_eq!(short.len(), MAX_NAME_LEN);
+        assert!(short.starts_with("app.xxx"));
+    }
+
+    #[test]
+    fn fit_output_path_shortens_only_the_file_name() {
+        let dir = Path::new("output").join("d".repeat(300));
+        let path = dir.join(format!("{}.png", "a".repeat(300)));
+        let fitted = fit_output_path(&path);
+        assert_eq!(fitted.file_name().unwrap().len(), MAX_NAME_LEN);
+        #[cfg(not(windows))]
+        assert_eq!(fitted.parent(), Some(dir.as_path()));
+
+        let path = dir.join("app_0.png");
+        #[cfg(not(windows))]
+        assert_eq!(fit_output_path(&path), path);
+    }
+
+    #[test]
+    fn long_paths_fit_the_path_limit() {
+        let short = Path::new("icons").join("app_0.png");
+        assert_eq!(extended_length_path(&short, 259), None);
+
+        let long = Path::new("icons")
+            .join("d".repeat(200))
+            .join("e".repeat(100))
+            .join("app_0.png");
+        let extended = extended_length_path(&long, 259).unwrap();
+        let extended = extended.to_string_lossy();
+        assert!(extended.starts_with(r"\\?\"));
+        assert!(Path::new(&*extended).ends_with(&long));
+        // a path that has the prefix is kept
+        assert_eq!(extended_length_path(Path::new(&*extended), 259), None);
+    }
+}
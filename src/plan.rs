@@ -0,0 +1,200 @@
+//! Conversion of an ICO file in two phases: planning, which reads the file and decides what to
+//! write where, and execution, which writes the images.
+//!
+//! Planning writes nothing, so a plan can be inspected as a dry run before it's executed. Its
+//! tasks are independent of each other, and can be executed in any order or in parallel with
+//! [`ExtractionPlan::execute_task`].
+
+use crate::{error::Result, extract, EntryInfo, ExtractOptions, Ico2imgError, IcoInfo};
+use ico::IconDir;
+use std::{
+    fs,
+    io::{Cursor, ErrorKind},
+    path::{Path, PathBuf},
+    slice,
+};
+
+/// The conversion of one entry of an ICO file.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct PlannedExtraction {
+    /// The index of the entry in the ICO file.
+    pub index: usize,
+    pub entry_info: EntryInfo,
+    pub output_path: PathBuf,
+    pub options: ExtractOptions,
+}
+
+/// The conversions of every entry of an ICO file, to be executed with
+/// [`ExtractionPlan::execute`].
+pub struct ExtractionPlan {
+    icon_dir: IconDir,
+    tasks: Vec<PlannedExtraction>,
+}
+
+/// The images written by [`ExtractionPlan::execute`].
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct ExtractionSummary {
+    pub written: Vec<PathBuf>,
+    pub bytes_written: u64,
+}
+
+impl ExtractionPlan {
+    /// Arguments:
+    ///   - ico_path: The path to the ICO file.
+    ///   - options: The conversion options, used for every entry.
+    ///
+    /// Returns the plan converting every entry of the ICO file, each to an image next to it named
+    /// `{stem}_{index}.{extension}`. The index is zero-padded to the number of digits of the last
+    /// one, such as `app_03.png` in a file of 12 entries, so that the names sort in order.
+    pub fn build(ico_path: &Path, options: &ExtractOptions) -> Result<ExtractionPlan> {
+        let output_dir = ico_path.parent().unwrap_or(Path::new(""));
+        ExtractionPlan::build_in(ico_path, output_dir, options)
+    }
+
+    /// Returns the plan converting every entry of the ICO file at `ico_path`, like
+    /// [`ExtractionPlan::build`], but with the images written to `output_dir`.
+    pub fn build_in(
+        ico_path: &Path,
+        output_dir: &Path,
+        options: &ExtractOptions,
+    ) -> Result<ExtractionPlan> {
+        let data = fs::read(ico_path).map_err(|e| match e.kind() {
+            ErrorKind::NotFound => Ico2imgError::FileNotFound(ico_path.to_path_buf()),
+            _ => Ico2imgError::Io(e),
+        })?;
+        let info = IcoInfo::from_reader(Cursor::new(&data))?;
+        let icon_dir = IconDir::read(Cursor::new(&data))
+            .map_err(|e| Ico2imgError::IcoParseError(e.to_string()))?;
+        if icon_dir.entries().is_empty() {
+            return Err(Ico2imgError::IcoParseError(
+                "No images found in the ICO file.".to_string(),
+            ));
+        }
+
+        let stem = ico_path
+            .file_stem()
+            .ok_or_else(|| {
+                Ico2imgError::InvalidArgument(format!(
+                    "The ICO path {} has no file name.",
+                    ico_path.display()
+                ))
+            })?
+            .to_string_lossy();
+        let width = (info.entries.len() - 1).to_string().len();
+        let tasks = info
+            .entries
+            .into_iter()
+            .enumerate()
+            .map(|(index, entry_info)| PlannedExtraction {
+                index,
+                entry_info,
+                output_path: output_dir.join(format!(
+                    "{}_{:0width$}.{}",
+                    stem,
+                    index,
+                    options.get_format().extension()
+                )),
+                options: options.clone(),
+            })
+            .collect();
+
+        Ok(ExtractionPlan { icon_dir, tasks })
+    }
+
+    /// Returns the entries of the ICO file the plan converts.
+    pub(crate) fn icon_dir(&self) -> &IconDir {
+        &self.icon_dir
+    }
+
+    /// Returns an iterator over the planned conversions, in the order of the entries.
+    pub fn iter(&self) -> slice::Iter<'_, PlannedExtraction> {
+        self.tasks.iter()
+    }
+
+    /// Converts the entry of `task`, which must come from this plan, and writes the image.
+    /// Returns the number of bytes written.
+    pub fn execute_task(&self, task: &PlannedExtraction) -> Result<u64> {
+        let image = extract(&self.icon_dir, task.index, &task.options)?;
+        fs::write(&task.output_path, &image).map_err(|e| Ico2imgError::WriteError {
+            path: task.output_path.clone(),
+            source: e.to_string(),
+        })?;
+        Ok(image.len() as u64)
+    }
+
+    /// Executes every planned conversion in order, stopping at the first failure.
+    pub fn execute(&self) -> Result<ExtractionSummary> {
+        let mut summary = ExtractionSummary::default();
+        for task in &self.tasks {
+            summary.bytes_written += self.execute_task(task)?;
+            summary.written.push(task.output_path.clone());
+        }
+        Ok(summary)
+    }
+}
+
+impl<'a> IntoIterator for &'a ExtractionPlan {
+    type Item = &'a PlannedExtraction;
+    type IntoIter = slice::Iter<'a, PlannedExtraction>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use std::env;
+
+    /// Returns the file names of the plan of an ICO file of `count` entries named `app.ico`.
+    fn output_names(count: u32) -> Vec<String> {
+        let entries: Vec<(u32, Vec<u8>)> = (1..=count)
+            .map(|size| (size, fixtures::png_data(&fixtures::gradient(size, size))))
+            .collect();
+        let mut data = Vec::new();
+        fixtures::icon_dir(&entries).write(&mut data).unwrap();
+
+        let dir = env::temp_dir().join(format!("ico2img-plan-{}-{}", count, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.ico");
+        fs::write(&path, data).unwrap();
+        let plan = ExtractionPlan::build(&path, &ExtractOptions::new());
+        fs::remove_dir_all(&dir).unwrap();
+
+        plan.unwrap()
+            .iter()
+            .map(|task| {
+                assert_eq!(task.output_path.parent(), Some(dir.as_path()));
+                task.output_path
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn indices_of_up_to_ten_entries_are_not_padded() {
+        assert_eq!(output_names(1), ["app_0.png"]);
+        let names = output_names(10);
+        assert_eq!(names.first().unwrap(), "app_0.png");
+        assert_eq!(names.last().unwrap(), "app_9.png");
+    }
+
+    #[test]
+    fn indices_of_more_entries_are_padded_to_the_last_one() {
+        let names = output_names(11);
+        assert_eq!(names.first().unwrap(), "app_00.png");
+        assert_eq!(names[9], "app_09.png");
+        assert_eq!(names.last().unwrap(), "app_10.png");
+        // the names sort in the order of the entries
+        let mut sorted = names.clone();
+        sorted.sort();
+        assert_eq!(sorted, names);
+    }
+}
@@ -0,0 +1,208 @@
+//! Handling of the color-management chunks of PNG data, and encoding of indexed PNG images.
+
+use crate::{encode_error, error::Result};
+use ::png::{BitDepth, ColorType, Encoder};
+use image::RgbaImage;
+use std::collections::{hash_map::Entry, HashMap};
+
+/// The signature at the start of PNG data.
+pub(crate) const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// Maximum number of colors in the palette of an indexed PNG image.
+const MAX_PALETTE_LEN: usize = 256;
+
+/// Types of the chunks describing the color space of an image, copied by
+/// [`ExtractOptions::keep_icc`](crate::ExtractOptions::keep_icc).
+const COLOR_CHUNKS: [&[u8; 4]; 4] = [b"iCCP", b"sRGB", b"gAMA", b"cHRM"];
+
+/// Returns the raw chunks (length, type, data and CRC) found in `png` before the image data, in
+/// order. Stops at the first truncated chunk.
+fn chunks(png: &[u8]) -> Vec<&[u8]> {
+    let mut chunks = Vec::new();
+    if !png.starts_with(&PNG_SIGNATURE) {
+        return chunks;
+    }
+
+    let mut pos = PNG_SIGNATURE.len();
+    while let Some(header) = png.get(pos..pos + 8) {
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        if &header[4..8] == b"IDAT" {
+            break;
+        }
+
+        let end = pos + 12 + len;
+        let Some(chunk) = png.get(pos..end) else {
+            break;
+        };
+        chunks.push(chunk);
+        pos = end;
+    }
+
+    chunks
+}
+
+/// Returns the color-management chunks of `png`, as raw chunks.
+pub(crate) fn color_chunks(png: &[u8]) -> Vec<&[u8]> {
+    chunks(png)
+        .into_iter()
+        .filter(|chunk| COLOR_CHUNKS.iter().any(|ty| &chunk[4..8] == *ty))
+        .collect()
+}
+
+/// Returns `png` without its color-management chunks, or `None` if it has none.
+pub(crate) fn strip_color_chunks(png: &[u8]) -> Option<Vec<u8>> {
+    let chunks = chunks(png);
+    let is_color = |chunk: &&[u8]| COLOR_CHUNKS.iter().any(|ty| &chunk[4..8] == *ty);
+    if !chunks.iter().any(is_color) {
+        return None;
+    }
+
+    // the chunks before the image data are contiguous, after the signature
+    let end = PNG_SIGNATURE.len() + chunks.iter().map(|chunk| chunk.len()).sum::<usize>();
+    let mut result = Vec::with_capacity(png.len());
+    result.extend_from_slice(&PNG_SIGNATURE);
+    for chunk in chunks.iter().filter(|chunk| !is_color(chunk)) {
+        result.extend_from_slice(chunk);
+    }
+    result.extend_from_slice(&png[end..]);
+    Some(result)
+}
+
+/// Returns the raw chunk of type `ty` holding `data`, with its length and CRC.
+pub(crate) fn chunk(ty: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = Vec::with_capacity(12 + data.len());
+    chunk.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    chunk.extend_from_slice(ty);
+    chunk.extend_from_slice(data);
+    let crc = crc32(&chunk[4..]);
+    chunk.extend_from_slice(&crc.to_be_bytes());
+    chunk
+}
+
+/// Returns the CRC-32 of `bytes`, as computed for PNG chunks.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = u32::MAX;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Returns `png` with `extra` inserted after its header chunk. The chunks are copied as they are,
+/// CRC included.
+pub(crate) fn insert_chunks(png: &[u8], extra: &[&[u8]]) -> Vec<u8> {
+    let Some(&header) = chunks(png).first() else {
+        return png.to_vec();
+    };
+
+    let split = PNG_SIGNATURE.len() + header.len();
+    let mut result = Vec::with_capacity(png.len() + extra.iter().map(|c| c.len()).sum::<usize>());
+    result.extend_from_slice(&png[..split]);
+    for chunk in extra {
+        result.extend_from_slice(chunk);
+    }
+    result.extend_from_slice(&png[split..]);
+    result
+}
+
+/// Returns `image` encoded as an indexed PNG image, with the smallest bit depth its palette fits
+/// in and a `tRNS` chunk for the alpha of translucent colors, or `None` if it has more than 256
+/// unique colors.
+pub(crate) fn encode_indexed(image: &RgbaImage) -> Result<Option<Vec<u8>>> {
+    let mut palette: Vec<[u8; 4]> = Vec::new();
+    let mut indices: HashMap<[u8; 4], u8> = HashMap::new();
+    for pixel in image.pixels() {
+        if let Entry::Vacant(entry) = indices.entry(pixel.0) {
+            if palette.len() == MAX_PALETTE_LEN {
+                return Ok(None);
+            }
+            entry.insert(0);
+            palette.push(pixel.0);
+        }
+    }
+
+    // translucent colors first, so that the `tRNS` chunk can stop after the last of them
+    palette.sort_by_key(|color| color[3] == u8::MAX);
+    for (index, color) in palette.iter().enumerate() {
+        indices.insert(*color, index as u8);
+    }
+
+    let (depth, bits) = match palette.len() {
+        0..=2 => (BitDepth::One, 1),
+        3..=4 => (BitDepth::Two, 2),
+        5..=16 => (BitDepth::Four, 4),
+        _ => (BitDepth::Eight, 8),
+    };
+    let row_len = (image.width() as usize * bits).div_ceil(8);
+    let mut data = vec![0; row_len * image.height() as usize];
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let bit = x as usize * bits;
+        data[y as usize * row_len + bit / 8] |= indices[&pixel.0] << (8 - bits - bit % 8);
+    }
+
+    let mut buffer = Vec::new();
+    let mut encoder = Encoder::new(&mut buffer, image.width(), image.height());
+    encoder.set_color(ColorType::Indexed);
+    encoder.set_depth(depth);
+    encoder.set_palette(
+        palette
+            .iter()
+            .flat_map(|color| [color[0], color[1], color[2]])
+            .collect::<Vec<_>>(),
+    );
+    let alphas: Vec<u8> = palette
+        .iter()
+        .take_while(|color| color[3] < u8::MAX)
+        .map(|color| color[3])
+        .collect();
+    if !alphas.is_empty() {
+        encoder.set_trns(alphas);
+    }
+
+    let mut writer = encoder.write_header().map_err(encode_error)?;
+    writer.write_image_data(&data).map_err(encode_error)?;
+    writer.finish().map_err(encode_error)?;
+    Ok(Some(buffer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{load_from_memory, Rgba};
+
+    #[test]
+    fn encode_indexed_two_colors() {
+        let image = RgbaImage::from_fn(9, 3, |x, y| {
+            if (x + y) % 2 == 0 {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 255, 128])
+            }
+        });
+        let png = encode_indexed(&image).unwrap().unwrap();
+
+        let decoder = ::png::Decoder::new(png.as_slice());
+        let reader = decoder.read_info().unwrap();
+        let info = reader.info();
+        assert_eq!(info.color_type, ColorType::Indexed);
+        assert_eq!(info.bit_depth, BitDepth::One);
+        assert_eq!(info.palette.as_deref().map(<[u8]>::len), Some(6));
+        // the translucent color comes first
+        assert_eq!(info.trns.as_deref(), Some(&[128][..]));
+
+        assert_eq!(load_from_memory(&png).unwrap().to_rgba8(), image);
+    }
+
+    #[test]
+    fn encode_indexed_too_many_colors() {
+        let image = RgbaImage::from_fn(16, 17, |x, y| Rgba([x as u8, y as u8, 0, 255]));
+        assert!(encode_indexed(&image).unwrap().is_none());
+    }
+}
@@ -0,0 +1,61 @@
+//! Rough previews of images in the terminal, drawn with half-block characters: each character
+//! cell shows two pixels, the top one as the foreground color of `▀` and the bottom one as the
+//! background color.
+
+use image::{imageops, Rgba, RgbaImage};
+
+/// Color transparent pixels are composited over, close to the background of most dark terminals.
+const BACKGROUND: [u8; 3] = [24, 24, 24];
+
+/// Arguments:
+///   - image: The image to preview.
+///   - max_width: The width of the terminal, in characters.
+///   - true_color: Whether the terminal supports 24-bit colors, rather than only 256.
+///
+/// Returns the escape sequences and characters drawing `image`, scaled down to fit in
+/// `max_width` characters, one line per two rows of pixels.
+pub fn render(image: &RgbaImage, max_width: u32, true_color: bool) -> String {
+    let image = if image.width() > max_width {
+        let height = (image.height() as u64 * max_width as u64 / image.width() as u64) as u32;
+        imageops::resize(image, max_width, height.max(1), imageops::FilterType::Triangle)
+    } else {
+        image.clone()
+    };
+
+    let mut out = String::new();
+    for y in (0..image.height()).step_by(2) {
+        for x in 0..image.width() {
+            let top = composite(image.get_pixel(x, y));
+            // an odd last row is drawn over the background
+            let bottom = if y + 1 < image.height() {
+                composite(image.get_pixel(x, y + 1))
+            } else {
+                BACKGROUND
+            };
+            out += &if true_color {
+                format!(
+                    "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m▀",
+                    top[0], top[1], top[2], bottom[0], bottom[1], bottom[2]
+                )
+            } else {
+                format!("\x1b[38;5;{}m\x1b[48;5;{}m▀", ansi_256(top), ansi_256(bottom))
+            };
+        }
+        out.push_str("\x1b[0m\n");
+    }
+    out
+}
+
+/// Returns the color of `pixel` composited over `BACKGROUND`.
+fn composite(pixel: &Rgba<u8>) -> [u8; 3] {
+    let alpha = pixel[3] as u32;
+    [0, 1, 2].map(|c| {
+        ((pixel[c] as u32 * alpha + BACKGROUND[c] as u32 * (255 - alpha) + 127) / 255) as u8
+    })
+}
+
+/// Returns the closest color of the 6x6x6 cube of the 256-color palette.
+fn ansi_256(color: [u8; 3]) -> u8 {
+    let [r, g, b] = color.map(|c| ((c as u32 * 5 + 127) / 255) as u8);
+    16 + 36 * r + 6 * g + b
+}
@@ -0,0 +1,33 @@
+//! Progress events for `--progress-json`, printed to standard error as one JSON object per line
+//! for frontends to follow a run without parsing the human-readable output.
+//!
+//! Like the timer of `--timings`, the event stream is global, and emitting an event does nothing
+//! until it's enabled.
+
+use serde_json::{json, Value};
+use std::{
+    io::{self, Write},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Starts printing the events emitted from now on.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Prints the event `name`, such as `entry_written`, with the fields of `fields`, which must be
+/// a JSON object, as `{"event": name, ...fields}`.
+pub fn emit(name: &str, fields: Value) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let mut event = json!({ "event": name });
+    if let (Some(event), Value::Object(fields)) = (event.as_object_mut(), fields) {
+        event.extend(fields);
+    }
+    // a single write per line, so that events of different threads don't interleave
+    let _ = io::stderr().write_all(format!("{}\n", event).as_bytes());
+}
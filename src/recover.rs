@@ -0,0 +1,74 @@
+//! Best-effort reading of truncated ICO files.
+//!
+//! `IconDir::read` fails as soon as any entry's data is missing, which loses the entries that
+//! were stored before the point of truncation. Here the directory is parsed by hand instead, and
+//! an ICO file is rebuilt from the entries whose data is complete.
+
+use anyhow::{anyhow, Result};
+
+/// Size of the ICONDIR header.
+const HEADER_SIZE: usize = 6;
+/// Size of an ICONDIRENTRY record.
+const ENTRY_SIZE: usize = 16;
+
+/// Arguments:
+///   - data: The contents of a (possibly truncated) ICO or CUR file.
+///
+/// Returns the bytes of an ICO file made of the complete entries. A warning is printed for every
+/// entry that had to be skipped.
+pub fn recover_ico(data: &[u8]) -> Result<Vec<u8>> {
+    if data.len() < HEADER_SIZE {
+        return Err(anyhow!("The ICO file is too short to contain a header."));
+    }
+
+    let reserved = u16::from_le_bytes([data[0], data[1]]);
+    let resource_type = u16::from_le_bytes([data[2], data[3]]);
+    if reserved != 0 || !(1..=2).contains(&resource_type) {
+        return Err(anyhow!("The file isn't an ICO or CUR file."));
+    }
+
+    let count = u16::from_le_bytes([data[4], data[5]]) as usize;
+    let mut complete = Vec::new();
+    for index in 0..count {
+        let start = HEADER_SIZE + index * ENTRY_SIZE;
+        let Some(record) = data.get(start..start + ENTRY_SIZE) else {
+            crate::warning::warn(format_args!(
+                "the directory is truncated after {} entries.",
+                index
+            ));
+            break;
+        };
+
+        let size = u32::from_le_bytes([record[8], record[9], record[10], record[11]]) as usize;
+        let offset = u32::from_le_bytes([record[12], record[13], record[14], record[15]]) as usize;
+        match data.get(offset..offset + size) {
+            Some(image) => complete.push((record, image)),
+            None => crate::warning::warn(format_args!(
+                "data of entry {} is truncated, skipping.",
+                index
+            )),
+        }
+    }
+
+    println!("Recovered {} of {} entries.", complete.len(), count);
+    if complete.is_empty() {
+        return Err(anyhow!("No complete entries found in the ICO file."));
+    }
+
+    let mut ico = Vec::new();
+    ico.extend_from_slice(&data[0..4]);
+    ico.extend_from_slice(&(complete.len() as u16).to_le_bytes());
+
+    let mut offset = HEADER_SIZE + complete.len() * ENTRY_SIZE;
+    for (record, image) in &complete {
+        ico.extend_from_slice(&record[0..8]);
+        ico.extend_from_slice(&(image.len() as u32).to_le_bytes());
+        ico.extend_from_slice(&(offset as u32).to_le_bytes());
+        offset += image.len();
+    }
+    for (_, image) in &complete {
+        ico.extend_from_slice(image);
+    }
+
+    Ok(ico)
+}
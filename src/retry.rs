@@ -0,0 +1,142 @@
+//! Retrying of file operations that fail for transient reasons, such as an unreliable network
+//! file system, for `--retries`. Decoding and encoding errors are never retried, as they would
+//! fail the same way again.
+
+use std::{
+    io::{self, ErrorKind},
+    thread,
+    time::Duration,
+};
+
+/// Delay before the first retry, doubled before each of the next ones.
+const INITIAL_DELAY: Duration = Duration::from_millis(100);
+/// Longest delay between two attempts.
+const MAX_DELAY: Duration = Duration::from_secs(10);
+
+/// Arguments:
+///   - retries: The number of times to run `operation` again after it fails.
+///   - what: What `operation` does, for the messages, such as `reading app.ico`.
+///   - operation: The file operation.
+///
+/// Returns the result of `operation`, which is run again after a delay while it fails with an
+/// error that may be transient, at most `retries` more times. Each retry is printed to standard
+/// error, with the error.
+pub fn retry<T>(
+    retries: u32,
+    what: &str,
+    operation: impl FnMut() -> io::Result<T>,
+) -> io::Result<T> {
+    retry_after(retries, what, INITIAL_DELAY, operation)
+}
+
+/// Same as [`retry`], waiting `delay` before the first retry.
+fn retry_after<T>(
+    retries: u32,
+    what: &str,
+    mut delay: Duration,
+    mut operation: impl FnMut() -> io::Result<T>,
+) -> io::Result<T> {
+    for attempt in 1..=retries {
+        match operation() {
+            Err(e) if is_transient(&e) => {
+                eprintln!(
+                    "Failed {}: {}, retrying in {:?} ({} of {})",
+                    what, e, delay, attempt, retries
+                );
+                thread::sleep(delay);
+                delay = (delay * 2).min(MAX_DELAY);
+            }
+            result => return result,
+        }
+    }
+    operation()
+}
+
+/// Returns whether `error` may not happen again, unlike a missing file or a denied permission.
+fn is_transient(error: &io::Error) -> bool {
+    !matches!(
+        error.kind(),
+        ErrorKind::NotFound
+            | ErrorKind::PermissionDenied
+            | ErrorKind::AlreadyExists
+            | ErrorKind::InvalidInput
+            | ErrorKind::InvalidData
+            | ErrorKind::Unsupported
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A file that fails to be read with `kind` the first `failures` times.
+    struct FlakyFile {
+        failures: u32,
+        kind: ErrorKind,
+        reads: u32,
+    }
+
+    impl FlakyFile {
+        fn new(failures: u32, kind: ErrorKind) -> Self {
+            FlakyFile {
+                failures,
+                kind,
+                reads: 0,
+            }
+        }
+
+        fn read(&mut self) -> io::Result<Vec<u8>> {
+            self.reads += 1;
+            if self.reads <= self.failures {
+                Err(io::Error::new(self.kind, "flaky"))
+            } else {
+                Ok(b"data".to_vec())
+            }
+        }
+    }
+
+    #[test]
+    fn transient_errors_are_retried() {
+        let mut file = FlakyFile::new(2, ErrorKind::Interrupted);
+        let data = retry_after(3, "reading", Duration::ZERO, || file.read()).unwrap();
+        assert_eq!(data, b"data");
+        assert_eq!(file.reads, 3);
+
+        let mut file = FlakyFile::new(3, ErrorKind::WouldBlock);
+        assert!(retry_after(3, "reading", Duration::ZERO, || file.read()).is_ok());
+        assert_eq!(file.reads, 4);
+    }
+
+    #[test]
+    fn retries_are_limited() {
+        let mut file = FlakyFile::new(5, ErrorKind::Other);
+        let error = retry_after(2, "reading", Duration::ZERO, || file.read()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Other);
+        assert_eq!(file.reads, 3);
+
+        let mut file = FlakyFile::new(1, ErrorKind::Other);
+        assert!(retry_after(0, "reading", Duration::ZERO, || file.read()).is_err());
+        assert_eq!(file.reads, 1);
+    }
+
+    #[test]
+    fn lasting_errors_are_not_retried() {
+        for kind in [
+            ErrorKind::NotFound,
+            ErrorKind::PermissionDenied,
+            ErrorKind::InvalidData,
+        ] {
+            let mut file = FlakyFile::new(1, kind);
+            let error = retry_after(3, "reading", Duration::ZERO, || file.read()).unwrap_err();
+            assert_eq!(error.kind(), kind);
+            assert_eq!(file.reads, 1);
+        }
+    }
+
+    #[test]
+    fn successes_are_not_retried() {
+        let mut file = FlakyFile::new(0, ErrorKind::Other);
+        assert!(retry(3, "reading", || file.read()).is_ok());
+        assert_eq!(file.reads, 1);
+    }
+}
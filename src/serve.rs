@@ -0,0 +1,230 @@
+//! Server mode, converting ICO files sent over a Unix domain socket.
+//!
+//! Messages are made of a JSON header followed by a payload, each prefixed by its length as a
+//! 4-byte little-endian integer. A request header holds the conversion options, all optional:
+//!
+//! ``` json
+//! {"index": 0, "format": "png", "resize": [64, 64], "jpeg_quality": 90, "background": "ffffff"}
+//! ```
+//!
+//! and its payload is the ICO file. The response header is either
+//! `{"ok": true, "format": "png", "width": 64, "height": 64}`, with the converted image as the
+//! payload, or `{"ok": false, "error": "..."}` with an empty payload. Several requests can be sent
+//! over the same connection; they're handled one at a time, while each connection is handled on
+//! its own thread.
+
+use anyhow::{anyhow, Result};
+use ico::IconDir;
+use ico2img::{Color, ExtractOptions};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use signal_hook::consts::{SIGINT, SIGTERM};
+use std::{
+    fs,
+    io::{BufRead, BufReader, BufWriter, Cursor, ErrorKind, Read, Write},
+    os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// Maximum size of a request header.
+const MAX_HEADER_SIZE: usize = 64 * 1024;
+/// Maximum size of an ICO file sent in a request.
+const MAX_DATA_SIZE: usize = 64 * 1024 * 1024;
+/// How often the listener checks for new connections, and idle connections for new requests,
+/// and both for termination signals.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// How long to wait for a client, within a request or for the next one, before giving up on its
+/// connection.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct Request {
+    index: usize,
+    format: Option<String>,
+    resize: Option<[u32; 2]>,
+    jpeg_quality: Option<u8>,
+    background: Option<String>,
+}
+
+/// Arguments:
+///   - socket: The path to the socket to listen on.
+///   - verbose: Whether to print every request.
+///
+/// Listens for conversion requests until SIGTERM or SIGINT is received. The requests being
+/// handled when the signal arrives are finished before returning, and idle connections are
+/// closed.
+pub fn serve(socket: &Path, verbose: bool) -> Result<()> {
+    // a socket left behind by a previous run would make binding fail
+    if fs::symlink_metadata(socket).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        fs::remove_file(socket)?;
+    }
+
+    let listener = UnixListener::bind(socket)?;
+    listener.set_nonblocking(true)?;
+
+    let terminate = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(SIGTERM, Arc::clone(&terminate))?;
+    signal_hook::flag::register(SIGINT, Arc::clone(&terminate))?;
+
+    if verbose {
+        println!("Listening on {}", socket.display());
+    }
+
+    let mut connections: Vec<JoinHandle<()>> = Vec::new();
+    while !terminate.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let terminate = Arc::clone(&terminate);
+                connections.retain(|connection| !connection.is_finished());
+                connections.push(thread::spawn(move || {
+                    if let Err(e) = handle_connection(&stream, &terminate, verbose) {
+                        eprintln!("Closing connection: {}", e);
+                    }
+                }));
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+            Err(e) => eprintln!("Failed to accept a connection: {}", e),
+        }
+    }
+
+    for connection in connections {
+        // a panic was already reported by the thread
+        let _ = connection.join();
+    }
+    fs::remove_file(socket)?;
+    Ok(())
+}
+
+/// Handles the requests sent over a connection, until the client closes it or the server is
+/// asked to terminate.
+fn handle_connection(stream: &UnixStream, terminate: &AtomicBool, verbose: bool) -> Result<()> {
+    stream.set_nonblocking(false)?;
+
+    let mut reader = BufReader::new(stream);
+    let mut writer = BufWriter::new(stream);
+    while wait_for_request(&mut reader, terminate)? {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let Some(header) = read_frame(&mut reader, MAX_HEADER_SIZE)? else {
+            break;
+        };
+        let data = read_frame(&mut reader, MAX_DATA_SIZE)?
+            .ok_or_else(|| anyhow!("Request is missing its ICO data."))?;
+
+        let (response, image) = match convert(&header, &data) {
+            Ok(converted) => converted,
+            Err(e) => (json!({ "ok": false, "error": e.to_string() }), Vec::new()),
+        };
+        if verbose {
+            println!("Handled request: {}", response);
+        }
+
+        write_frame(&mut writer, &serde_json::to_vec(&response)?)?;
+        write_frame(&mut writer, &image)?;
+        writer.flush()?;
+    }
+
+    Ok(())
+}
+
+/// Waits for the client to send a request, checking for termination signals every
+/// [`POLL_INTERVAL`]. Returns `false` if the client closed the connection or the server is asked
+/// to terminate first, and fails if the client stays idle for [`READ_TIMEOUT`].
+fn wait_for_request(reader: &mut BufReader<&UnixStream>, terminate: &AtomicBool) -> Result<bool> {
+    reader.get_ref().set_read_timeout(Some(POLL_INTERVAL))?;
+    let start = Instant::now();
+    loop {
+        // data left over from the previous request is returned without reading, and nothing is
+        // consumed, so the request is then read from the buffer
+        match reader.fill_buf() {
+            Ok(buffer) => return Ok(!buffer.is_empty()),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted
+                ) => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        if terminate.load(Ordering::Relaxed) {
+            return Ok(false);
+        } else if start.elapsed() >= READ_TIMEOUT {
+            return Err(anyhow!(
+                "No request received in {} seconds.",
+                READ_TIMEOUT.as_secs()
+            ));
+        }
+    }
+}
+
+/// Converts the ICO file in `data` according to the JSON request `header`, returning the
+/// response header and the converted image.
+fn convert(header: &[u8], data: &[u8]) -> Result<(Value, Vec<u8>)> {
+    let request: Request = serde_json::from_slice(header)?;
+
+    let mut options = ExtractOptions::new();
+    if let Some(ref format) = request.format {
+        options = options.format(format.parse()?);
+    }
+    if let Some([width, height]) = request.resize {
+        options = options.resize(width, height);
+    }
+    if let Some(quality) = request.jpeg_quality {
+        options = options.jpeg_quality(quality);
+    }
+    if let Some(ref color) = request.background {
+        options = options.background(color.parse::<Color>()?);
+    }
+    let options = options.build()?;
+
+    let icon_dir = IconDir::read(Cursor::new(data))?;
+    let image = ico2img::transform(ico2img::decode_entry(&icon_dir, request.index)?, &options)?;
+    let bytes = ico2img::encode(&image, &options)?;
+
+    let response = json!({
+        "ok": true,
+        "format": options.get_format().extension(),
+        "width": image.width(),
+        "height": image.height(),
+    });
+    Ok((response, bytes))
+}
+
+/// Reads a length-prefixed frame, returning `None` if the connection was closed before it.
+fn read_frame(reader: &mut impl Read, max_size: usize) -> Result<Option<Vec<u8>>> {
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+
+    let len = u32::from_le_bytes(len) as usize;
+    if len > max_size {
+        return Err(anyhow!(
+            "Frame of {} bytes exceeds the limit of {} bytes.",
+            len,
+            max_size
+        ));
+    }
+
+    let mut frame = vec![0; len];
+    reader.read_exact(&mut frame)?;
+    Ok(Some(frame))
+}
+
+/// Writes a length-prefixed frame.
+fn write_frame(writer: &mut impl Write, frame: &[u8]) -> Result<()> {
+    writer.write_all(&(frame.len() as u32).to_le_bytes())?;
+    writer.write_all(frame)?;
+    Ok(())
+}
@@ -0,0 +1,162 @@
+//! Packing of ICO entries into a sprite sheet, as a grid or as a single row or column.
+
+use anyhow::{anyhow, Result};
+use image::{imageops, DynamicImage, RgbaImage};
+use serde_json::{json, Map, Value};
+use std::{path::Path, str::FromStr};
+
+/// A sheet of a single row or column of sprites, for `--strip`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strip {
+    Horizontal,
+    Vertical,
+}
+
+impl FromStr for Strip {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "horizontal" => Ok(Strip::Horizontal),
+            "vertical" => Ok(Strip::Vertical),
+            _ => Err(anyhow!(
+                "Invalid strip {}, expected horizontal or vertical.",
+                s
+            )),
+        }
+    }
+}
+
+/// An image to place on a sprite sheet, along with where it comes from.
+pub struct Sprite {
+    /// The ICO file the image was read from, as shown in the sprite map.
+    pub source: String,
+    /// The index of the entry in the ICO file.
+    pub index: usize,
+    /// The image, already resized to the size of a cell.
+    pub image: DynamicImage,
+}
+
+/// Arguments:
+///   - sprites: The images to pack, in order.
+///   - cell: The size, as `(width, height)`, of a cell of the sheet.
+///   - columns: The maximum number of cells in a row.
+///   - spacing: The transparent space between two cells, in pixels.
+///
+/// Returns the sprite sheet, with the sprites packed left-to-right then top-to-bottom, and the
+/// position of the top-left corner of each sprite.
+pub fn pack(
+    sprites: &[Sprite],
+    cell: (u32, u32),
+    columns: u32,
+    spacing: u32,
+) -> (RgbaImage, Vec<(u32, u32)>) {
+    let count = sprites.len() as u32;
+    let columns = columns.min(count).max(1);
+    let rows = count.div_ceil(columns);
+    let (step_x, step_y) = (cell.0 + spacing, cell.1 + spacing);
+
+    let mut sheet = RgbaImage::new(columns * step_x - spacing, rows * step_y - spacing);
+    let mut positions = Vec::with_capacity(sprites.len());
+    for (i, sprite) in sprites.iter().enumerate() {
+        let i = i as u32;
+        let (x, y) = ((i % columns) * step_x, (i / columns) * step_y);
+        imageops::overlay(&mut sheet, &sprite.image.to_rgba8(), x as i64, y as i64);
+        positions.push((x, y));
+    }
+
+    (sheet, positions)
+}
+
+/// Arguments:
+///   - sprites: The packed images.
+///   - positions: The position of each image on the sheet, as returned by [`pack`].
+///   - cell: The size, as `(width, height)`, of a cell of the sheet.
+///
+/// Returns the JSON map of the sheet, giving the offset of each entry keyed by its source file
+/// and index.
+pub fn sprite_map(sprites: &[Sprite], positions: &[(u32, u32)], cell: (u32, u32)) -> Value {
+    let mut files = Map::new();
+    for (sprite, &(x, y)) in sprites.iter().zip(positions) {
+        let entry = json!({ "index": sprite.index, "x": x, "y": y });
+        match files.get_mut(&sprite.source) {
+            Some(Value::Array(entries)) => entries.push(entry),
+            _ => {
+                files.insert(sprite.source.clone(), json!([entry]));
+            }
+        }
+    }
+
+    json!({
+        "cell": { "width": cell.0, "height": cell.1 },
+        "sprites": files,
+    })
+}
+
+/// Arguments:
+///   - sprites: The packed images.
+///   - positions: The position of each image on the sheet, as returned by [`pack`].
+///   - cell: The size, as `(width, height)`, of a cell of the sheet.
+///   - sheet_url: The URL of the sheet, relative to the style sheet.
+///
+/// Returns a CSS style sheet with an `.icon` rule showing the sheet, and an `.icon-{index}` rule
+/// per entry giving its `background-position`. When the sheet holds entries of several files,
+/// the rules are named `.icon-{file}-{index}` instead, where `{file}` is the source file without
+/// its extension and with other characters than letters, digits, `-` and `_` replaced by `-`.
+pub fn sprite_css(
+    sprites: &[Sprite],
+    positions: &[(u32, u32)],
+    cell: (u32, u32),
+    sheet_url: &str,
+) -> String {
+    let several_files = sprites
+        .iter()
+        .any(|sprite| sprite.source != sprites[0].source);
+
+    let mut css = format!(
+        ".icon {{\n  background-image: url(\"{}\");\n  background-repeat: no-repeat;\n  \
+         display: inline-block;\n  width: {}px;\n  height: {}px;\n}}\n",
+        sheet_url.replace('"', "\\\""),
+        cell.0,
+        cell.1
+    );
+    for (sprite, &(x, y)) in sprites.iter().zip(positions) {
+        let name = if several_files {
+            format!("{}-{}", class_name(&sprite.source), sprite.index)
+        } else {
+            sprite.index.to_string()
+        };
+        css += &format!(
+            "\n.icon-{} {{\n  background-position: {} {};\n}}\n",
+            name,
+            css_offset(x),
+            css_offset(y)
+        );
+    }
+    css
+}
+
+/// Returns the `background-position` of a sprite at `offset` on the sheet, for one axis.
+fn css_offset(offset: u32) -> String {
+    if offset == 0 {
+        "0".to_string()
+    } else {
+        format!("-{}px", offset)
+    }
+}
+
+/// Returns `source`, a path, without its extension and with the characters that can't appear
+/// in a CSS class name replaced by `-`.
+fn class_name(source: &str) -> String {
+    let stem = Path::new(source).with_extension("");
+    stem.to_string_lossy()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,65 @@
+//! Whether human-readable output is colored, for `--color`.
+//!
+//! The choice is global, like the event stream of `--progress-json`, and every piece of output
+//! that uses escape sequences asks this module rather than checking the terminal itself. Until
+//! it's set, colors are chosen automatically.
+
+use anyhow::{anyhow, Result};
+use std::{
+    env,
+    io::{self, IsTerminal},
+    str::FromStr,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+/// When to color the output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// When the output is a terminal and `NO_COLOR` isn't set.
+    Auto,
+    /// Always, even when the output is redirected.
+    Always,
+    /// Never.
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(anyhow!(
+                "Invalid color choice {}, expected auto, always or never.",
+                s
+            )),
+        }
+    }
+}
+
+static CHOICE: AtomicU8 = AtomicU8::new(ColorChoice::Auto as u8);
+
+/// Sets when the output printed from now on is colored.
+pub fn set(choice: ColorChoice) {
+    CHOICE.store(choice as u8, Ordering::Relaxed);
+}
+
+/// Returns whether the output printed to standard output is colored.
+pub fn stdout() -> bool {
+    enabled(io::stdout().is_terminal())
+}
+
+/// Returns whether the output printed to standard error is colored.
+pub fn stderr() -> bool {
+    enabled(io::stderr().is_terminal())
+}
+
+fn enabled(is_terminal: bool) -> bool {
+    match CHOICE.load(Ordering::Relaxed) {
+        choice if choice == ColorChoice::Always as u8 => true,
+        choice if choice == ColorChoice::Never as u8 => false,
+        _ => is_terminal && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()),
+    }
+}
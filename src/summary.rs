@@ -0,0 +1,91 @@
+//! Summary of a conversion run, written as JSON for CI systems to archive.
+
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::{env, fs, path::Path, time::Instant};
+
+/// The totals of a conversion run, collected as inputs are converted.
+pub struct RunSummary {
+    start: Instant,
+    outputs: usize,
+    bytes_written: u64,
+    /// The inputs that failed to convert, along with the error.
+    failures: Vec<(String, String)>,
+    /// The frames read by `--stdin-framed`, with their output or their error.
+    frames: Vec<Value>,
+}
+
+impl RunSummary {
+    /// Returns an empty summary, with the duration of the run counted from now.
+    pub fn new() -> RunSummary {
+        RunSummary {
+            start: Instant::now(),
+            outputs: 0,
+            bytes_written: 0,
+            failures: Vec::new(),
+            frames: Vec::new(),
+        }
+    }
+
+    /// Records an input converted to an image of `bytes` bytes.
+    pub fn add_output(&mut self, bytes: usize) {
+        self.outputs += 1;
+        self.bytes_written += bytes as u64;
+    }
+
+    /// Records an input that failed to convert, emitting an `entry_failed` progress event.
+    pub fn add_failure(&mut self, input: String, error: &anyhow::Error) {
+        crate::progress::emit(
+            "entry_failed",
+            json!({ "input": input, "error": error.to_string() }),
+        );
+        self.failures.push((input, error.to_string()));
+    }
+
+    /// Records the frame `index` of `--stdin-framed`, converted to `output` or failed with an
+    /// error.
+    pub fn add_frame(&mut self, index: usize, output: Result<&Path, &anyhow::Error>) {
+        self.frames.push(match output {
+            Ok(path) => json!({ "frame": index, "output": path }),
+            Err(e) => json!({ "frame": index, "error": e.to_string() }),
+        });
+    }
+
+    /// Returns the summary as JSON, along with the version of ico2img, its arguments, the frames
+    /// read by `--stdin-framed`, if any, and, with `--timings`, the duration of each stage.
+    pub fn to_json(&self) -> Value {
+        let failures: Vec<Value> = self
+            .failures
+            .iter()
+            .map(|(input, error)| json!({ "input": input, "error": error }))
+            .collect();
+
+        let mut json = json!({
+            "version": ico2img::VERSION,
+            "args": env::args().collect::<Vec<_>>(),
+            "inputs": self.outputs + self.failures.len(),
+            "outputs": self.outputs,
+            "failures": failures,
+            "bytes_written": self.bytes_written,
+            "duration_secs": self.start.elapsed().as_secs_f64(),
+        });
+        if !self.frames.is_empty() {
+            json["frames"] = Value::Array(self.frames.clone());
+        }
+        if let Some(timer) = crate::timing::timer() {
+            json["profile"] = timer.to_json();
+        }
+        json
+    }
+
+    /// Writes the summary to `path`, pretty-printed if `pretty`.
+    pub fn write(&self, path: &Path, pretty: bool) -> Result<()> {
+        let json = if pretty {
+            serde_json::to_vec_pretty(&self.to_json())?
+        } else {
+            serde_json::to_vec(&self.to_json())?
+        };
+        fs::write(path, json)?;
+        Ok(())
+    }
+}
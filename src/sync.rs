@@ -0,0 +1,155 @@
+//! Regeneration of the images derived from an ICO file, such as `favicon-32x32.png` next to
+//! `favicon.ico`, from a mapping of entry sizes to output paths:
+//!
+//! ``` toml
+//! [outputs]
+//! "16x16" = "favicon-16x16.png"
+//! "32x32" = "favicon-32x32.png"
+//! "180x180" = "apple-touch-icon.png"
+//! ```
+//!
+//! Output paths are relative to the mapping file. Sizes without a matching entry are resized from
+//! the largest one. The outputs written by the last sync are recorded next to the mapping, in a
+//! `.synced` file, so that outputs whose mapping was removed can be pruned.
+
+use anyhow::{anyhow, Result};
+use ico::IconDir;
+use ico2img::{ExtractOptions, FilterType, SupportedImages};
+use image::DynamicImage;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use toml::{Table, Value};
+
+/// Arguments:
+///   - path: The path to the mapping file.
+///
+/// Returns the sizes and output paths listed in the `[outputs]` table of the mapping, sorted by
+/// output path. Output paths are resolved against the directory of the mapping.
+pub fn read_mapping(path: &Path) -> Result<Vec<((u32, u32), PathBuf)>> {
+    let mut mapping: Table = toml::from_str(&fs::read_to_string(path)?)?;
+    let outputs = match mapping.remove("outputs") {
+        Some(Value::Table(outputs)) => outputs,
+        _ => return Err(anyhow!("The mapping has no [outputs] table.")),
+    };
+
+    let base = path.parent().unwrap_or(Path::new(""));
+    let mut entries = Vec::with_capacity(outputs.len());
+    for (size, output) in outputs {
+        let size = crate::parse_size(&size).map_err(|e| anyhow!(e))?;
+        let output = output
+            .as_str()
+            .ok_or_else(|| anyhow!("The output for {}x{} isn't a path.", size.0, size.1))?;
+        entries.push((size, base.join(output)));
+    }
+
+    entries.sort_by(|a, b| a.1.cmp(&b.1));
+    Ok(entries)
+}
+
+/// Arguments:
+///   - icon_dir: The ICO file to derive the images from.
+///   - mapping_path: The path to the mapping file.
+///   - filter: The filter used for sizes without a matching entry.
+///   - trust_directory: Whether to use the entry sizes declared in the ICO directory rather than
+///     the sizes of their images.
+///   - prune: Whether to delete the outputs whose mapping was removed since the last sync.
+///   - check_only: Whether to only report the outputs that are out of date, without writing.
+///
+/// Brings the outputs of the mapping up to date, rewriting only those whose pixels changed.
+/// Returns whether any output was (or, with `check_only`, would be) changed.
+pub fn sync(
+    icon_dir: &IconDir,
+    mapping_path: &Path,
+    filter: FilterType,
+    trust_directory: bool,
+    prune: bool,
+    check_only: bool,
+) -> Result<bool> {
+    let mapping = read_mapping(mapping_path)?;
+    let state_path = mapping_path.with_extension("synced");
+    let mut changed = false;
+
+    for ((width, height), output) in &mapping {
+        let image = derive_image(icon_dir, (*width, *height), filter, trust_directory)?;
+        if is_up_to_date(output, &image) {
+            continue;
+        }
+
+        changed = true;
+        if check_only {
+            println!("Out of date: {}", output.display());
+            continue;
+        }
+
+        let format: SupportedImages = output
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .ok_or_else(|| anyhow!("Unknown image format for {}.", output.display()))?
+            .parse()?;
+        let options = ExtractOptions::new().format(format).build()?;
+        fs::write(output, ico2img::encode(&image, &options)?)?;
+        println!("Updated: {}", output.display());
+    }
+
+    if prune {
+        let previous = fs::read_to_string(&state_path).unwrap_or_default();
+        for line in previous.lines().filter(|line| !line.is_empty()) {
+            let path = PathBuf::from(line);
+            if !path.exists() || mapping.iter().any(|(_, output)| *output == path) {
+                continue;
+            }
+
+            changed = true;
+            if check_only {
+                println!("Not mapped anymore: {}", path.display());
+            } else {
+                fs::remove_file(&path)?;
+                println!("Removed: {}", path.display());
+            }
+        }
+    }
+
+    if !check_only {
+        let state: String = mapping
+            .iter()
+            .map(|(_, output)| format!("{}\n", output.display()))
+            .collect();
+        fs::write(&state_path, state)?;
+    }
+
+    Ok(changed)
+}
+
+/// Returns the entry of `icon_dir` of exactly `size` pixels, or the largest entry resized to that
+/// size if there's none.
+fn derive_image(
+    icon_dir: &IconDir,
+    size: (u32, u32),
+    filter: FilterType,
+    trust_directory: bool,
+) -> Result<DynamicImage> {
+    let sizes: Vec<(u32, u32)> = icon_dir
+        .entries()
+        .iter()
+        .map(|e| crate::entry_size(e, trust_directory))
+        .collect();
+    if let Some(index) = sizes.iter().position(|&s| s == size) {
+        return Ok(ico2img::decode_entry(icon_dir, index)?);
+    }
+
+    let largest = (0..sizes.len())
+        .max_by_key(|&i| sizes[i].0 * sizes[i].1)
+        .ok_or_else(|| anyhow!("No images found in the ICO file."))?;
+    let image = ico2img::decode_entry(icon_dir, largest)?;
+    Ok(image.resize_exact(size.0, size.1, filter))
+}
+
+/// Returns whether the image at `path` exists and has the same pixels as `image`.
+fn is_up_to_date(path: &Path, image: &DynamicImage) -> bool {
+    fs::read(path)
+        .ok()
+        .and_then(|data| image::load_from_memory(&data).ok())
+        .is_some_and(|existing| existing.to_rgba8() == image.to_rgba8())
+}
@@ -0,0 +1,31 @@
+//! Light and dark theme variants of the converted images, for `--variant`.
+
+use anyhow::{anyhow, Result};
+use std::str::FromStr;
+
+/// The themes the converted image is composited for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Variant {
+    /// Over the light theme background.
+    Light,
+    /// Over the dark theme background.
+    Dark,
+    /// Over each background, writing an image per theme.
+    Both,
+}
+
+impl FromStr for Variant {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "light" => Ok(Variant::Light),
+            "dark" => Ok(Variant::Dark),
+            "both" => Ok(Variant::Both),
+            _ => Err(anyhow!(
+                "Invalid variant {}, expected light, dark or both.",
+                s
+            )),
+        }
+    }
+}
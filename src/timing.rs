@@ -0,0 +1,126 @@
+//! Timing of the stages of a conversion (reading, parsing, decoding, encoding, writing and
+//! verifying), for `--timings`.
+//!
+//! The timer is global, so that the stages can be timed wherever they happen, on any thread,
+//! without passing it around. Timing a stage does nothing until the timer is enabled.
+
+use serde_json::{json, Value};
+use std::{
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+static TIMER: OnceLock<StageTimer> = OnceLock::new();
+
+/// A stage being timed, returned by [`StageTimer::start`].
+pub struct StageHandle {
+    name: &'static str,
+    label: String,
+    start: Instant,
+}
+
+/// The durations of the stages timed so far, in the order they finished.
+#[derive(Default)]
+pub struct StageTimer {
+    stages: Mutex<Vec<(&'static str, String, Duration)>>,
+}
+
+impl StageTimer {
+    /// Starts timing the stage `name` of what `label` describes, such as a file or an entry.
+    pub fn start(&self, name: &'static str, label: &str) -> StageHandle {
+        StageHandle {
+            name,
+            label: label.to_string(),
+            start: Instant::now(),
+        }
+    }
+
+    /// Records the duration of the stage, and returns it.
+    pub fn finish(&self, handle: StageHandle) -> Duration {
+        let elapsed = handle.start.elapsed();
+        self.stages
+            .lock()
+            .unwrap()
+            .push((handle.name, handle.label, elapsed));
+        elapsed
+    }
+
+    /// Returns the total duration of each stage, in the order the stages first finished.
+    fn totals(&self) -> Vec<(&'static str, Duration)> {
+        let mut totals: Vec<(&'static str, Duration)> = Vec::new();
+        for &(name, _, elapsed) in self.stages.lock().unwrap().iter() {
+            match totals.iter_mut().find(|(total_name, _)| *total_name == name) {
+                Some((_, total)) => *total += elapsed,
+                None => totals.push((name, elapsed)),
+            }
+        }
+        totals
+    }
+
+    /// Prints the duration of every stage timed, then the total of each stage.
+    pub fn print(&self) {
+        let stages = self.stages.lock().unwrap();
+        let width = stages
+            .iter()
+            .map(|(_, label, _)| label.len())
+            .max()
+            .unwrap_or(0);
+        for (name, label, elapsed) in stages.iter() {
+            println!("{:9}  {:width$}  {:>10.3} ms", name, label, millis(*elapsed));
+        }
+        drop(stages);
+
+        println!();
+        for (name, total) in self.totals() {
+            println!("Total {:9}  {:>10.3} ms", name, millis(total));
+        }
+    }
+
+    /// Returns the durations of the stages, and their totals, in milliseconds.
+    pub fn to_json(&self) -> Value {
+        let stages: Vec<Value> = self
+            .stages
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, label, elapsed)| {
+                json!({ "stage": name, "label": label, "ms": millis(*elapsed) })
+            })
+            .collect();
+        let totals: serde_json::Map<String, Value> = self
+            .totals()
+            .into_iter()
+            .map(|(name, total)| (name.to_string(), json!(millis(total))))
+            .collect();
+
+        json!({ "stages": stages, "totals": totals })
+    }
+}
+
+/// Enables the global timer.
+pub fn enable() {
+    let _ = TIMER.set(StageTimer::default());
+}
+
+/// Returns the global timer, if it's enabled.
+pub fn timer() -> Option<&'static StageTimer> {
+    TIMER.get()
+}
+
+/// Starts timing the stage `name` of what `label` describes with the global timer, if it's
+/// enabled.
+pub fn start(name: &'static str, label: &str) -> Option<StageHandle> {
+    timer().map(|timer| timer.start(name, label))
+}
+
+/// Records the duration of a stage started with [`start`].
+pub fn finish(handle: Option<StageHandle>) {
+    if let (Some(timer), Some(handle)) = (timer(), handle) {
+        timer.finish(handle);
+    }
+}
+
+/// Returns a duration in milliseconds.
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
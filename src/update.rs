@@ -0,0 +1,64 @@
+//! Checking for newer releases of ico2img on GitHub.
+
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use std::time::Duration;
+
+/// URL of the latest release in the GitHub API, which needs no authentication.
+const LATEST_RELEASE_URL: &str =
+    "https://api.github.com/repos/walker84837/ico2img-rs/releases/latest";
+/// Time after which the request is given up on.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Prints whether a newer release than this version is available. A failed request, such as
+/// without network access, is only a warning, so that scripts checking for updates don't fail.
+pub fn check_update() -> Result<()> {
+    let current = env!("CARGO_PKG_VERSION");
+    let latest = match latest_version() {
+        Ok(latest) => latest,
+        Err(e) => {
+            crate::warning::warn(format_args!("can't check for updates: {}", e));
+            return Ok(());
+        }
+    };
+
+    match (parse_version(&latest), parse_version(current)) {
+        (Some(latest_parts), Some(current_parts)) if latest_parts > current_parts => {
+            println!(
+                "ico2img {} is available, this is version {}.",
+                latest, current
+            )
+        }
+        (Some(_), Some(_)) => println!("ico2img {} is up to date.", current),
+        _ => println!(
+            "The latest release of ico2img is {}, this is version {}.",
+            latest, current
+        ),
+    }
+    Ok(())
+}
+
+/// Returns the version of the latest release, from its tag, without a leading `v`.
+fn latest_version() -> Result<String> {
+    let agent = ureq::AgentBuilder::new().timeout(TIMEOUT).build();
+    let response = agent
+        .get(LATEST_RELEASE_URL)
+        .set("Accept", "application/vnd.github+json")
+        .set("User-Agent", concat!("ico2img/", env!("CARGO_PKG_VERSION")))
+        .call()?;
+    let release: Value = serde_json::from_str(&response.into_string()?)?;
+    let tag = release["tag_name"]
+        .as_str()
+        .ok_or_else(|| anyhow!("the release has no tag"))?;
+    Ok(tag.trim_start_matches('v').to_string())
+}
+
+/// Returns the numbers of a `MAJOR.MINOR.PATCH` version, ignoring any pre-release suffix.
+fn parse_version(version: &str) -> Option<Vec<u64>> {
+    version
+        .split(['-', '+'])
+        .next()?
+        .split('.')
+        .map(|part| part.parse().ok())
+        .collect()
+}
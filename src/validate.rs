@@ -0,0 +1,137 @@
+//! Checks of images extracted in a previous run against the ICO file they were extracted from,
+//! so that CI can confirm that neither has changed since.
+
+use crate::{decode_entry, error::Result, ExtractOptions, ExtractionPlan, SupportedImages};
+use image::{load_from_memory, DynamicImage};
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// PSNR, in dB, below which an image in a lossy format is reported as
+/// [`ValidationStatus::LowPsnr`].
+pub const MIN_LOSSY_PSNR: f64 = 30.0;
+
+/// The outcome of checking one extracted image, as returned by [`validate_extraction_output`].
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum ValidationStatus {
+    /// The image matches the entry: pixel-for-pixel in a lossless format, or with a PSNR of at
+    /// least [`MIN_LOSSY_PSNR`] in a lossy one.
+    Valid,
+    /// The image is in a lossy format, and its PSNR against the entry, in dB, is below
+    /// [`MIN_LOSSY_PSNR`]. It's worth a warning rather than a failure.
+    LowPsnr(f64),
+    /// The image doesn't exist.
+    Missing,
+    /// The image can't be read or decoded, for the given reason.
+    Invalid(String),
+    /// The image doesn't have the size of the entry.
+    SizeMismatch {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    /// The image is in a lossless format, but its pixels differ from those of the entry.
+    PixelMismatch,
+}
+
+/// The check of the image extracted from one entry.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct ValidationResult {
+    /// The index of the entry in the ICO file.
+    pub index: usize,
+    /// The path the image is expected at.
+    pub path: PathBuf,
+    pub status: ValidationStatus,
+}
+
+impl ValidationResult {
+    /// Returns whether the image failed the check. Images with a low PSNR don't.
+    pub fn is_failure(&self) -> bool {
+        !matches!(
+            self.status,
+            ValidationStatus::Valid | ValidationStatus::LowPsnr(_)
+        )
+    }
+}
+
+/// Arguments:
+///   - ico: The path to the ICO file.
+///   - output_dir: The directory the images were extracted to.
+///   - format: The format the images were extracted to.
+///
+/// Returns the check of the image of each entry of the ICO file, expected in `output_dir` under
+/// the name given by [`ExtractionPlan::build_in`]. Each image must exist, decode, and have the
+/// size of the decoded entry; its pixels must match those of the entry exactly in a lossless
+/// format, or closely enough in a lossy one. Fails only if the ICO file can't be read.
+pub fn validate_extraction_output(
+    ico: &Path,
+    output_dir: &Path,
+    format: SupportedImages,
+) -> Result<Vec<ValidationResult>> {
+    let options = ExtractOptions::new().format(format).build()?;
+    let plan = ExtractionPlan::build_in(ico, output_dir, &options)?;
+
+    plan.iter()
+        .map(|task| {
+            let entry = decode_entry(plan.icon_dir(), task.index)?;
+            Ok(ValidationResult {
+                index: task.index,
+                path: task.output_path.clone(),
+                status: check_image(&task.output_path, &entry, format),
+            })
+        })
+        .collect()
+}
+
+/// Returns how the image at `path`, in `format`, compares to `entry`, the decoded entry it was
+/// extracted from.
+fn check_image(path: &Path, entry: &DynamicImage, format: SupportedImages) -> ValidationStatus {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == ErrorKind::NotFound => return ValidationStatus::Missing,
+        Err(e) => return ValidationStatus::Invalid(e.to_string()),
+    };
+    let image = match load_from_memory(&data) {
+        Ok(image) => image,
+        Err(e) => return ValidationStatus::Invalid(e.to_string()),
+    };
+
+    let (expected, actual) = (
+        (entry.width(), entry.height()),
+        (image.width(), image.height()),
+    );
+    if expected != actual {
+        return ValidationStatus::SizeMismatch { expected, actual };
+    }
+
+    if format.capabilities().lossy {
+        // lossy formats may have no alpha channel, so only the color channels are compared
+        let psnr = psnr(entry.to_rgb8().as_raw(), image.to_rgb8().as_raw());
+        if psnr < MIN_LOSSY_PSNR {
+            return ValidationStatus::LowPsnr(psnr);
+        }
+    } else if image.to_rgba8() != entry.to_rgba8() {
+        return ValidationStatus::PixelMismatch;
+    }
+    ValidationStatus::Valid
+}
+
+/// Returns the peak signal-to-noise ratio, in dB, between two buffers of 8-bit samples, which is
+/// infinite when they're identical.
+pub fn psnr(a: &[u8], b: &[u8]) -> f64 {
+    let mse = a
+        .iter()
+        .zip(b)
+        .map(|(&x, &y)| (x as f64 - y as f64).powi(2))
+        .sum::<f64>()
+        / a.len() as f64;
+
+    if mse == 0.0 {
+        f64::INFINITY
+    } else {
+        10.0 * (255.0 * 255.0 / mse).log10()
+    }
+}
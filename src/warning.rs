@@ -0,0 +1,25 @@
+//! Warnings emitted during a run, counted so that `--abort-on-warning` can fail the run.
+
+use crate::style;
+use std::{
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Prints `message` as a warning, with the label in yellow when colors are enabled, and counts
+/// it.
+pub fn warn(message: fmt::Arguments) {
+    COUNT.fetch_add(1, Ordering::Relaxed);
+    if style::stderr() {
+        eprintln!("\x1b[33mWarning:\x1b[0m {}", message);
+    } else {
+        eprintln!("Warning: {}", message);
+    }
+}
+
+/// Returns the number of warnings emitted so far.
+pub fn count() -> usize {
+    COUNT.load(Ordering::Relaxed)
+}
@@ -0,0 +1,63 @@
+//! Installation of icons into an XDG icon theme, as
+//! `$XDG_DATA_HOME/icons/<theme>/<size>x<size>/apps/<name>.png`.
+
+use anyhow::{anyhow, Result};
+use std::{
+    env,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// The sizes of the icons of a theme, as listed by the XDG icon theme specification.
+pub const SIZES: [u32; 9] = [16, 22, 24, 32, 48, 64, 128, 256, 512];
+
+/// Returns the directory holding the icon themes of the user: `$XDG_DATA_HOME/icons`, or
+/// `$HOME/.local/share/icons` if `XDG_DATA_HOME` isn't set to an absolute path.
+pub fn icons_dir() -> Result<PathBuf> {
+    let data_home = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute());
+    let data_home = match data_home {
+        Some(dir) => dir,
+        None => env::var_os("HOME")
+            .map(|home| Path::new(&home).join(".local").join("share"))
+            .ok_or_else(|| anyhow!("Neither XDG_DATA_HOME nor HOME is set."))?,
+    };
+    Ok(data_home.join("icons"))
+}
+
+/// Returns the path of the icon `name` of `size` in the theme directory `theme_dir`.
+pub fn icon_path(theme_dir: &Path, size: u32, name: &str) -> PathBuf {
+    theme_dir
+        .join(format!("{}x{}", size, size))
+        .join("apps")
+        .join(format!("{}.png", name))
+}
+
+/// Runs `gtk-update-icon-cache` on `theme_dir`, so that the installed icons are picked up. It's
+/// skipped if the command isn't installed, and a failure is only a warning.
+pub fn update_icon_cache(theme_dir: &Path, verbose: bool) {
+    let result = Command::new("gtk-update-icon-cache")
+        .args(["--force", "--ignore-theme-index"])
+        .arg(theme_dir)
+        .status();
+    match result {
+        Ok(status) if status.success() => {
+            if verbose {
+                println!("Updated the icon cache of {}", theme_dir.display());
+            }
+        }
+        Ok(status) => crate::warning::warn(format_args!(
+            "gtk-update-icon-cache failed on {} ({})",
+            theme_dir.display(),
+            status
+        )),
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            if verbose {
+                println!("gtk-update-icon-cache isn't installed, not updating the icon cache");
+            }
+        }
+        Err(e) => crate::warning::warn(format_args!("can't run gtk-update-icon-cache: {}", e)),
+    }
+}
@@ -0,0 +1,116 @@
+//! Embedding of XMP packets in encoded PNG and JPEG images.
+
+use crate::{error::Result, png, Ico2imgError};
+
+/// Keyword of the PNG `iTXt` chunk holding an XMP packet.
+const PNG_KEYWORD: &[u8] = b"XML:com.adobe.xmp";
+/// Namespace starting the JPEG `APP1` segment holding an XMP packet, null terminator included.
+const JPEG_NAMESPACE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+/// Marker of the JPEG `APP0` segment, which holds the JFIF header.
+const APP0: [u8; 2] = [0xFF, 0xE0];
+/// Marker of the JPEG `APP1` segment.
+const APP1: [u8; 2] = [0xFF, 0xE1];
+
+/// Returns `data`, PNG data, with `packet` in an uncompressed `iTXt` chunk after its header.
+pub(crate) fn embed_png(data: &[u8], packet: &str) -> Vec<u8> {
+    // keyword, then no compression, no language tag and no translated keyword
+    let mut text = Vec::with_capacity(PNG_KEYWORD.len() + 5 + packet.len());
+    text.extend_from_slice(PNG_KEYWORD);
+    text.extend_from_slice(&[0, 0, 0, 0, 0]);
+    text.extend_from_slice(packet.as_bytes());
+    png::insert_chunks(data, &[&png::chunk(b"iTXt", &text)])
+}
+
+/// Returns `data`, JPEG data, with `packet` in an `APP1` segment after its start marker and its
+/// JFIF header, if any. Fails if the packet doesn't fit in a segment, which is at most 64 KiB.
+pub(crate) fn embed_jpeg(data: &[u8], packet: &str) -> Result<Vec<u8>> {
+    let len = 2 + JPEG_NAMESPACE.len() + packet.len();
+    let len = u16::try_from(len).map_err(|_| {
+        Ico2imgError::InvalidArgument(format!(
+            "The XMP packet is {} bytes long, more than a JPEG segment holds.",
+            packet.len()
+        ))
+    })?;
+
+    let split = jpeg_header_len(data);
+
+    let mut result = Vec::with_capacity(data.len() + 2 + len as usize);
+    result.extend_from_slice(&data[..split]);
+    result.extend_from_slice(&APP1);
+    result.extend_from_slice(&len.to_be_bytes());
+    result.extend_from_slice(JPEG_NAMESPACE);
+    result.extend_from_slice(packet.as_bytes());
+    result.extend_from_slice(&data[split..]);
+    Ok(result)
+}
+
+/// Returns the length of the start marker and JFIF header of `data`, JPEG data, after which
+/// metadata segments are inserted.
+pub(crate) fn jpeg_header_len(data: &[u8]) -> usize {
+    let mut len = 2;
+    if data.get(len..len + 2) == Some(&APP0) {
+        if let Some(app0_len) = data.get(len + 2..len + 4) {
+            len += 2 + u16::from_be_bytes([app0_len[0], app0_len[1]]) as usize;
+        }
+    }
+    len.min(data.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{embed_xmp, SupportedImages};
+    use image::{codecs::jpeg::JpegEncoder, load_from_memory, ImageFormat, RgbImage, RgbaImage};
+    use std::io::Cursor;
+
+    const PACKET: &str = "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"><rdf:RDF/></x:xmpmeta>";
+
+    #[test]
+    fn embed_png_adds_itxt_chunk() {
+        let image = RgbaImage::from_pixel(4, 3, image::Rgba([10, 20, 30, 255]));
+        let mut data = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+            .unwrap();
+
+        let embedded = embed_xmp(data, SupportedImages::Png, PACKET).unwrap();
+        let reader = ::png::Decoder::new(embedded.as_slice())
+            .read_info()
+            .unwrap();
+        let text = &reader.info().utf8_text;
+        assert_eq!(text.len(), 1);
+        assert_eq!(text[0].keyword, "XML:com.adobe.xmp");
+        assert_eq!(text[0].get_text().unwrap(), PACKET);
+        assert_eq!(load_from_memory(&embedded).unwrap().to_rgba8(), image);
+    }
+
+    #[test]
+    fn embed_jpeg_adds_app1_segment_after_jfif_header() {
+        let image = RgbImage::from_pixel(8, 8, image::Rgb([200, 100, 50]));
+        let mut data = Vec::new();
+        JpegEncoder::new(&mut data).encode_image(&image).unwrap();
+        assert_eq!(data[2..4], APP0);
+        let app1 = 4 + u16::from_be_bytes([data[4], data[5]]) as usize;
+
+        let embedded = embed_xmp(data.clone(), SupportedImages::Jpeg, PACKET).unwrap();
+        assert_eq!(embedded[..app1], data[..app1]);
+        assert_eq!(embedded[app1..app1 + 2], APP1);
+        let len = u16::from_be_bytes([embedded[app1 + 2], embedded[app1 + 3]]) as usize;
+        let segment = &embedded[app1 + 4..app1 + 2 + len];
+        assert_eq!(&segment[..JPEG_NAMESPACE.len()], JPEG_NAMESPACE);
+        assert_eq!(&segment[JPEG_NAMESPACE.len()..], PACKET.as_bytes());
+        assert_eq!(embedded[app1 + 2 + len..], data[app1..]);
+        assert!(load_from_memory(&embedded).is_ok());
+    }
+
+    #[test]
+    fn embed_jpeg_rejects_oversize_packets() {
+        let packet = "x".repeat(u16::MAX as usize);
+        assert!(embed_jpeg(&[0xFF, 0xD8], &packet).is_err());
+    }
+
+    #[test]
+    fn embed_xmp_rejects_other_formats() {
+        assert!(embed_xmp(Vec::new(), SupportedImages::Bmp, PACKET).is_err());
+    }
+}
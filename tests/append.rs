@@ -0,0 +1,49 @@
+mod common;
+
+use common::{gradient, ico2img, run, temp_dir, write_ico};
+use ico::IconDir;
+use std::fs::{self, File};
+
+#[test]
+fn append_adds_oversize_images_with_allow_oversize() {
+    let dir = temp_dir("append-oversize");
+    let ico = write_ico(&dir.join("app.ico"), &[16]);
+    let image = dir.join("large.png");
+    gradient(512, 512).save(&image).unwrap();
+
+    let output = run(ico2img().arg(&ico).arg("--append").arg(&image), 1);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--allow-oversize"));
+
+    run(
+        ico2img()
+            .arg(&ico)
+            .arg("--append")
+            .arg(&image)
+            .arg("--allow-oversize"),
+        0,
+    );
+
+    let icon_dir = IconDir::read(File::open(&ico).unwrap()).unwrap();
+    assert_eq!(icon_dir.entries().len(), 2);
+    let entry = &icon_dir.entries()[1];
+    assert!(entry.is_png());
+    let decoded = entry.decode().unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (512, 512));
+    assert_eq!(decoded.rgba_data(), gradient(512, 512).as_raw().as_slice());
+
+    // a byte of 0 stands for 256 pixels and more in the directory
+    let data = fs::read(&ico).unwrap();
+    assert_eq!(&data[6 + 16..6 + 16 + 2], &[0, 0]);
+
+    run(
+        ico2img()
+            .arg(&ico)
+            .arg("-i")
+            .arg("1")
+            .arg("-o")
+            .arg(dir.join("large-out.png")),
+        0,
+    );
+    let converted = image::open(dir.join("large-out.png")).unwrap().to_rgba8();
+    assert_eq!(converted, gradient(512, 512));
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use common::{ico2img, run, temp_dir, write_ico};
+use std::{collections::HashSet, fs};
+
+#[test]
+fn auto_rename_gives_entries_of_the_same_size_unique_names() {
+    let dir = temp_dir("auto-rename");
+    let input = write_ico(&dir.join("app.ico"), &[16, 32, 16, 16]);
+    let output = dir.join("raw");
+
+    run(
+        ico2img()
+            .arg(&input)
+            .arg("--dump-raw")
+            .arg(&output)
+            .arg("--auto-rename"),
+        0,
+    );
+
+    let names: HashSet<String> = fs::read_dir(&output)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    let expected: HashSet<String> = [
+        "app_16x16_32bpp_a.dib",
+        "app_32x32_32bpp.dib",
+        "app_16x16_32bpp_b.dib",
+        "app_16x16_32bpp_c.dib",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect();
+    assert_eq!(names, expected);
+}
+
+#[test]
+fn auto_rename_keeps_names_unique_with_a_constant_template() {
+    let dir = temp_dir("auto-rename-template");
+    let input = write_ico(&dir.join("app.ico"), &[16, 32, 48]);
+    let output = dir.join("raw");
+
+    run(
+        ico2img()
+            .arg(&input)
+            .arg("--dump-raw")
+            .arg(&output)
+            .arg("--auto-rename")
+            .arg("--auto-rename-template")
+            .arg("icon"),
+        0,
+    );
+
+    let mut names: Vec<String> = fs::read_dir(&output)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["icon_a.dib", "icon_b.dib", "icon_c.dib"]);
+}
@@ -0,0 +1,122 @@
+mod common;
+
+use common::{ico2img, run, temp_dir, write_ico};
+use ico::IconDir;
+use std::{fs, fs::File, path::Path};
+
+/// The pixels of the fixtures, from the top-left one, row by row, as RGBA.
+const PIXELS: [[u8; 4]; 4] = [
+    [255, 0, 0, 255],
+    [0, 255, 0, 128],
+    [0, 0, 255, 64],
+    [10, 20, 30, 0],
+];
+
+/// Returns a 2x2 32-bit BMP file of `pixels` with a DIB header of `header_len` bytes: 40 for
+/// `BITMAPINFOHEADER` (V3), 108 for `BITMAPV4HEADER` and 124 for `BITMAPV5HEADER`. With
+/// `bitfields`, the pixels are laid out by BGRA masks, which follow a V3 header, and are part of
+/// the larger ones.
+fn bmp(header_len: u32, bitfields: bool, pixels: &[[u8; 4]; 4]) -> Vec<u8> {
+    let masks_len = if bitfields && header_len == 40 { 12 } else { 0 };
+    let pixels_offset = 14 + header_len + masks_len;
+    let mut data = Vec::new();
+    data.extend_from_slice(b"BM");
+    data.extend_from_slice(&(pixels_offset + 16).to_le_bytes());
+    data.extend_from_slice(&[0; 4]);
+    data.extend_from_slice(&pixels_offset.to_le_bytes());
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&header_len.to_le_bytes());
+    header.extend_from_slice(&2i32.to_le_bytes());
+    header.extend_from_slice(&2i32.to_le_bytes());
+    header.extend_from_slice(&1u16.to_le_bytes());
+    header.extend_from_slice(&32u16.to_le_bytes());
+    header.extend_from_slice(&(if bitfields { 3u32 } else { 0 }).to_le_bytes());
+    header.extend_from_slice(&16u32.to_le_bytes());
+    header.extend_from_slice(&[0; 16]);
+    if bitfields {
+        for mask in [0x00FF_0000u32, 0x0000_FF00, 0x0000_00FF] {
+            header.extend_from_slice(&mask.to_le_bytes());
+        }
+        if header_len > 40 {
+            header.extend_from_slice(&0xFF00_0000u32.to_le_bytes());
+        }
+    }
+    if header_len > 40 {
+        header.resize(header_len as usize, 0);
+        // LCS_sRGB
+        header[56..60].copy_from_slice(b"BGRs");
+    }
+    data.extend_from_slice(&header);
+
+    // rows are stored bottom-up
+    for row in pixels.chunks(2).rev() {
+        for [r, g, b, a] in row {
+            data.extend_from_slice(&[*b, *g, *r, *a]);
+        }
+    }
+    data
+}
+
+/// Appends the BMP file `data` to a new ICO file with `--bmp-alpha mode`, and returns the pixels
+/// of the added entry.
+fn append(dir: &Path, name: &str, data: &[u8], mode: &str) -> Vec<[u8; 4]> {
+    let ico = write_ico(&dir.join(format!("{}.ico", name)), &[16]);
+    let image = dir.join(format!("{}.bmp", name));
+    fs::write(&image, data).unwrap();
+    run(
+        ico2img()
+            .arg(&ico)
+            .arg("--append")
+            .arg(&image)
+            .args(["--bmp-alpha", mode]),
+        0,
+    );
+
+    let icon_dir = IconDir::read(File::open(&ico).unwrap()).unwrap();
+    let entry = icon_dir.entries()[1].decode().unwrap();
+    assert_eq!((entry.width(), entry.height()), (2, 2));
+    entry
+        .rgba_data()
+        .chunks_exact(4)
+        .map(|pixel| pixel.try_into().unwrap())
+        .collect()
+}
+
+#[test]
+fn bmp_alpha_is_kept_with_every_header_version() {
+    let dir = temp_dir("bmp-alpha");
+    let fixtures = [
+        ("v3", bmp(40, false, &PIXELS)),
+        ("v3-bitfields", bmp(40, true, &PIXELS)),
+        ("v4", bmp(108, true, &PIXELS)),
+        ("v5", bmp(124, true, &PIXELS)),
+    ];
+    for (name, data) in &fixtures {
+        for mode in ["auto", "force"] {
+            let pixels = append(&dir, &format!("{}-{}", name, mode), data, mode);
+            assert_eq!(pixels, PIXELS, "{} with --bmp-alpha {}", name, mode);
+        }
+
+        let pixels = append(&dir, &format!("{}-ignore", name), data, "ignore");
+        assert!(
+            pixels.iter().all(|pixel| pixel[3] == 255),
+            "{} with --bmp-alpha ignore: {:?}",
+            name,
+            pixels
+        );
+    }
+}
+
+#[test]
+fn bmp_alpha_of_zero_is_only_read_with_force() {
+    let dir = temp_dir("bmp-alpha-zero");
+    let opaque = PIXELS.map(|[r, g, b, _]| [r, g, b, 0]);
+    let data = bmp(40, false, &opaque);
+
+    let pixels = append(&dir, "auto", &data, "auto");
+    assert!(pixels.iter().all(|pixel| pixel[3] == 255), "{:?}", pixels);
+
+    let pixels = append(&dir, "force", &data, "force");
+    assert!(pixels.iter().all(|pixel| pixel[3] == 0), "{:?}", pixels);
+}
@@ -0,0 +1,54 @@
+mod common;
+
+use common::{ico2img, run, temp_dir, write_ico};
+use std::fs;
+
+#[test]
+fn bmp_format_passes_verify_after_write() {
+    let dir = temp_dir("bmp-format-verify");
+    let ico = write_ico(&dir.join("icon.ico"), &[32]);
+
+    for (format, bits) in [("rgb565", 16), ("rgb888", 24), ("rgba8888", 32)] {
+        let output = dir.join(format!("{}.bmp", format));
+        run(
+            ico2img().arg(&ico).arg("-o").arg(&output).args([
+                "-f",
+                "bmp",
+                "--bmp-format",
+                format,
+                "--verify-after-write",
+            ]),
+            0,
+        );
+        let bmp = fs::read(&output).unwrap();
+        assert_eq!(u16::from_le_bytes([bmp[28], bmp[29]]), bits);
+    }
+}
+
+#[test]
+fn rgb565_keeps_5_6_5_bits() {
+    let dir = temp_dir("bmp-format-rgb565");
+    let ico = write_ico(&dir.join("icon.ico"), &[16]);
+    let output = dir.join("icon.bmp");
+    run(
+        ico2img()
+            .arg(&ico)
+            .arg("-o")
+            .arg(&output)
+            .args(["-f", "bmp", "--bmp-format", "rgb565"]),
+        0,
+    );
+
+    let image = image::open(&output).unwrap().to_rgb8();
+    // every opaque channel is one of the 32 or 64 levels of its field, scaled to 8 bits
+    let levels = |bits: u32| -> Vec<u8> {
+        let max = (1 << bits) - 1;
+        (0..=max).map(|v| (v * 255 / max) as u8).collect()
+    };
+    let (red, green) = (levels(5), levels(6));
+    for pixel in image.pixels() {
+        let near =
+            |levels: &[u8], value: u8| levels.iter().any(|&level| level.abs_diff(value) <= 1);
+        assert!(near(&red, pixel[0]) && near(&green, pixel[1]) && near(&red, pixel[2]));
+    }
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use common::{gradient, ico2img, ico_bytes, run, temp_dir, write_ico};
+use image::{Rgba, RgbaImage};
+use std::{fs, path::Path, process::Command};
+
+fn convert(ico: &Path, cache: &Path, output: &Path) -> Command {
+    let mut command = ico2img();
+    command
+        .arg(ico)
+        .arg("-o")
+        .arg(output)
+        .arg("--cache-dir")
+        .arg(cache)
+        .arg("-v");
+    command
+}
+
+#[test]
+fn cache_hits_check_and_report_the_entry() {
+    let dir = temp_dir("cache-checks");
+    // the gradient has translucent pixels
+    let ico = write_ico(&dir.join("icon.ico"), &[16]);
+    let (cache, output) = (dir.join("cache"), dir.join("icon.png"));
+
+    let first = run(&mut convert(&ico, &cache, &output), 0);
+    assert!(!String::from_utf8_lossy(&first.stdout).contains("Using the cached image"));
+    let second = run(convert(&ico, &cache, &output).arg("--progress-json"), 0);
+    assert!(String::from_utf8_lossy(&second.stdout).contains("Using the cached image"));
+    assert!(String::from_utf8_lossy(&second.stderr).contains("\"event\":\"entry_decoded\""));
+
+    fs::remove_file(&output).unwrap();
+    let rejected = run(convert(&ico, &cache, &output).arg("--require-opaque"), 1);
+    assert!(String::from_utf8_lossy(&rejected.stderr).contains("--require-opaque"));
+    assert!(!output.exists());
+}
+
+#[test]
+fn cache_keys_depend_on_entry_options() {
+    let dir = temp_dir("cache-keys");
+    let images: Vec<RgbaImage> = vec![
+        gradient(16, 16),
+        RgbaImage::from_pixel(32, 32, Rgba([0, 0, 255, 255])),
+    ];
+    let ico = dir.join("icon.ico");
+    fs::write(&ico, ico_bytes(&images)).unwrap();
+    let (cache, output) = (dir.join("cache"), dir.join("icon.png"));
+
+    run(convert(&ico, &cache, &output).args(["-i", "1"]), 0);
+    // the second entry is cut from the directory, so it can't come from the cache
+    run(
+        convert(&ico, &cache, &output).args(["-i", "1", "--max-entries-per-file", "1"]),
+        1,
+    );
+    let recovered = run(
+        convert(&ico, &cache, &output).args(["-i", "1", "--recover"]),
+        0,
+    );
+    assert!(!String::from_utf8_lossy(&recovered.stdout).contains("Using the cached image"));
+}
+
+#[test]
+fn cache_hits_use_the_converted_pixels() {
+    let dir = temp_dir("cache-pixels");
+    let ico = write_ico(&dir.join("icon.ico"), &[16]);
+    let (cache, previous) = (dir.join("cache"), dir.join("previous"));
+    fs::create_dir(&previous).unwrap();
+    run(
+        ico2img()
+            .arg(&ico)
+            .arg("-o")
+            .arg(previous.join("icon.jpg"))
+            .args(["-f", "jpeg"]),
+        0,
+    );
+
+    // JPEG is lossy, so the converted pixels differ from the encoded ones
+    let output = dir.join("icon.jpg");
+    let diff = dir.join("icon_0_diff.png");
+    let mut diffs = Vec::new();
+    for _ in 0..2 {
+        run(
+            convert(&ico, &cache, &output)
+                .args(["-f", "jpeg", "--diff-vs"])
+                .arg(&previous),
+            0,
+        );
+        diffs.push(fs::read(&diff).unwrap());
+        fs::remove_file(&diff).unwrap();
+    }
+    assert_eq!(diffs[0], diffs[1]);
+}
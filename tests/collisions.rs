@@ -0,0 +1,43 @@
+mod common;
+
+use common::{gradient, ico2img, run, temp_dir, write_ico};
+use std::fs;
+
+#[test]
+fn three_way_collisions_are_renamed_or_rejected() {
+    let dir = temp_dir("collisions");
+    let input = dir.join("input");
+    for (parent, size) in [("a", 16), ("b", 32), ("c", 48)] {
+        fs::create_dir_all(input.join(parent)).unwrap();
+        write_ico(&input.join(parent).join("app.ico"), &[size]);
+    }
+
+    let output = run(
+        ico2img()
+            .arg(&input)
+            .arg("-o")
+            .arg(dir.join("strict"))
+            .args(["--recursive", "--strict-names"]),
+        1,
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    for parent in ["a", "b", "c"] {
+        let path = input.join(parent).join("app.ico");
+        assert!(stderr.contains(&path.display().to_string()), "{}", stderr);
+    }
+    assert!(!dir.join("strict").exists());
+
+    run(
+        ico2img()
+            .arg(&input)
+            .arg("-o")
+            .arg(dir.join("output"))
+            .arg("--recursive"),
+        0,
+    );
+    for (parent, size) in [("a", 16), ("b", 32), ("c", 48)] {
+        let path = dir.join("output").join(format!("{}_app.png", parent));
+        assert_eq!(image::open(path).unwrap().to_rgba8(), gradient(size, size));
+    }
+    assert_eq!(fs::read_dir(dir.join("output")).unwrap().count(), 3);
+}
@@ -0,0 +1,28 @@
+mod common;
+
+use common::{ico2img, run, temp_dir, write_ico};
+
+const ESC: u8 = 0x1B;
+
+#[test]
+fn color_never_has_no_escape_sequences() {
+    let dir = temp_dir("color-never");
+    let ico = write_ico(&dir.join("icon.ico"), &[16, 32]);
+
+    let output = run(ico2img().arg(&ico).args(["--list", "--color", "never"]), 0);
+    assert!(!output.stdout.is_empty());
+    assert!(!output.stdout.contains(&ESC));
+}
+
+#[test]
+fn color_always_colors_piped_output() {
+    let dir = temp_dir("color-always");
+    let ico = write_ico(&dir.join("icon.ico"), &[16, 32]);
+
+    // stdout is a pipe here, which disables colors unless they're forced
+    let output = run(ico2img().arg(&ico).args(["--list", "--color", "always"]), 0);
+    assert!(output.stdout.contains(&ESC));
+
+    let output = run(ico2img().arg(&ico).arg("--list"), 0);
+    assert!(!output.stdout.contains(&ESC));
+}
@@ -0,0 +1,83 @@
+//! Helpers shared by the integration tests: temporary directories, ICO fixtures written on the
+//! fly, and the `ico2img` binary.
+
+#![allow(dead_code)]
+
+use ico::{IconDir, IconDirEntry, IconImage, ResourceType};
+use image::{Rgba, RgbaImage};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+/// Returns an empty directory named after `name` in the temporary directory, removing whatever
+/// an earlier run left there.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("ico2img-test-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Returns a `width`x`height` image with a gradient over all four channels, so that resizing,
+/// cropping or reordering it shows.
+pub fn gradient(width: u32, height: u32) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| {
+        Rgba([
+            (x * 255 / width.max(2).saturating_sub(1)) as u8,
+            (y * 255 / height.max(2).saturating_sub(1)) as u8,
+            ((x + y) % 256) as u8,
+            if (x + y) % 3 == 0 {
+                255
+            } else {
+                128 + (x % 128) as u8
+            },
+        ])
+    })
+}
+
+/// Returns the bytes of an ICO file holding `images`, in order, with entries of 256 pixels and
+/// more stored as PNG and the others as BMP.
+pub fn ico_bytes(images: &[RgbaImage]) -> Vec<u8> {
+    let mut icon_dir = IconDir::new(ResourceType::Icon);
+    for image in images {
+        let icon = IconImage::from_rgba_data(image.width(), image.height(), image.to_vec());
+        let entry = if image.width() >= 256 {
+            IconDirEntry::encode_as_png(&icon).unwrap()
+        } else {
+            IconDirEntry::encode_as_bmp(&icon).unwrap()
+        };
+        icon_dir.add_entry(entry);
+    }
+    let mut data = Vec::new();
+    icon_dir.write(&mut data).unwrap();
+    data
+}
+
+/// Writes an ICO file holding a gradient of each of `sizes` at `path`, and returns `path`.
+pub fn write_ico(path: &Path, sizes: &[u32]) -> PathBuf {
+    let images: Vec<RgbaImage> = sizes.iter().map(|&size| gradient(size, size)).collect();
+    fs::write(path, ico_bytes(&images)).unwrap();
+    path.to_path_buf()
+}
+
+/// Returns a command running the `ico2img` binary, with `NO_COLOR` unset.
+pub fn ico2img() -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_ico2img"));
+    command.env_remove("NO_COLOR");
+    command
+}
+
+/// Runs `command`, and returns its output after checking that it exited with `code`.
+pub fn run(command: &mut Command, code: i32) -> Output {
+    let output = command.output().unwrap();
+    assert_eq!(
+        output.status.code(),
+        Some(code),
+        "stdout: {}\nstderr: {}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
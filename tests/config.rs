@@ -0,0 +1,161 @@
+mod common;
+
+use common::{ico2img, run, temp_dir, write_ico};
+use std::{fs, path::Path};
+
+const CONFIG: &str = r#"
+[ico2img]
+format = "png"
+
+[profile.web]
+format = "webp"
+resize = "8x8"
+keep-icc = true
+
+[profile.broken]
+sharpen = 2
+"#;
+
+/// Returns the dimensions of the image at `path`.
+fn dimensions(path: &Path) -> (u32, u32) {
+    image::image_dimensions(path).unwrap()
+}
+
+#[test]
+fn profiles_set_any_option() {
+    let dir = temp_dir("config-profiles");
+    let ico = write_ico(&dir.join("icon.ico"), &[16]);
+    let config = dir.join("ico2img.toml");
+    fs::write(&config, CONFIG).unwrap();
+
+    let output = dir.join("icon.webp");
+    run(
+        ico2img()
+            .arg(&ico)
+            .arg("-o")
+            .arg(&output)
+            .arg("-c")
+            .arg(&config)
+            .args(["--profile", "web"]),
+        0,
+    );
+    assert_eq!(
+        image::guess_format(&fs::read(&output).unwrap()).unwrap(),
+        image::ImageFormat::WebP
+    );
+    assert_eq!(dimensions(&output), (8, 8));
+}
+
+#[test]
+fn command_line_options_override_the_config() {
+    let dir = temp_dir("config-precedence");
+    let ico = write_ico(&dir.join("icon.ico"), &[16]);
+    let config = dir.join("ico2img.toml");
+    fs::write(&config, CONFIG).unwrap();
+
+    let output = dir.join("icon.jpg");
+    run(
+        ico2img()
+            .arg(&ico)
+            .arg("-o")
+            .arg(&output)
+            .arg("-c")
+            .arg(&config)
+            .args(["--profile", "web", "-f", "jpeg", "--resize", "4x4"]),
+        0,
+    );
+    assert_eq!(
+        image::guess_format(&fs::read(&output).unwrap()).unwrap(),
+        image::ImageFormat::Jpeg
+    );
+    assert_eq!(dimensions(&output), (4, 4));
+}
+
+#[test]
+fn unknown_options_are_rejected() {
+    let dir = temp_dir("config-unknown");
+    let ico = write_ico(&dir.join("icon.ico"), &[16]);
+    let config = dir.join("ico2img.toml");
+    fs::write(&config, CONFIG).unwrap();
+
+    let output = run(
+        ico2img()
+            .arg(&ico)
+            .arg("-o")
+            .arg(dir.join("icon.png"))
+            .arg("-c")
+            .arg(&config)
+            .args(["--profile", "broken"]),
+        1,
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unknown option sharpen"));
+    assert!(!dir.join("icon.png").exists());
+
+    let output = run(
+        ico2img()
+            .arg(&ico)
+            .arg("-o")
+            .arg(dir.join("icon.png"))
+            .arg("-c")
+            .arg(&config)
+            .args(["--profile", "print"]),
+        1,
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains("broken, web"));
+}
+
+#[test]
+fn exported_config_reproduces_the_options() {
+    let dir = temp_dir("config-export");
+    let ico = write_ico(&dir.join("icon.ico"), &[16]);
+    let config = dir.join("ico2img.toml");
+    fs::write(&config, CONFIG).unwrap();
+
+    let exported = dir.join("exported.toml");
+    run(
+        ico2img()
+            .arg("-c")
+            .arg(&config)
+            .args(["-f", "jpeg", "--resize", "8x8", "--format-map", "1:bmp"])
+            .args(["--hue-shift", "90", "--errors-json"])
+            .arg("--export-config")
+            .arg(&exported),
+        0,
+    );
+    let contents = fs::read_to_string(&exported).unwrap();
+    let table: toml::Table = toml::from_str(&contents).unwrap();
+    let options = table["ico2img"].as_table().unwrap();
+    assert_eq!(options["format"].as_str(), Some("jpeg"));
+    assert_eq!(options["resize"].as_str(), Some("8x8"));
+    assert_eq!(options["hue_shift"].as_integer(), Some(90));
+    assert_eq!(options["errors_json"].as_bool(), Some(true));
+    assert_eq!(options["format_map"]["1"].as_str(), Some("bmp"));
+    assert_eq!(options["image_index"].as_integer(), Some(0));
+    assert_eq!(options["keep_icc"].as_bool(), Some(false));
+
+    // reloading the exported configuration resolves the same options
+    let reloaded = run(
+        ico2img()
+            .arg("-c")
+            .arg(&exported)
+            .args(["--export-config", "-"]),
+        0,
+    );
+    assert_eq!(String::from_utf8_lossy(&reloaded.stdout), contents);
+
+    let output = dir.join("icon.jpg");
+    run(
+        ico2img()
+            .arg(&ico)
+            .arg("-o")
+            .arg(&output)
+            .arg("-c")
+            .arg(&exported),
+        0,
+    );
+    assert_eq!(
+        image::guess_format(&fs::read(&output).unwrap()).unwrap(),
+        image::ImageFormat::Jpeg
+    );
+    assert_eq!(dimensions(&output), (8, 8));
+}
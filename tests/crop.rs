@@ -0,0 +1,85 @@
+mod common;
+
+use common::{gradient, ico2img, run, temp_dir, write_ico};
+use image::{imageops, imageops::FilterType, RgbaImage};
+
+/// Returns `source` cropped to `x,y,size x size`, resized to 8x8 and placed at the top left of
+/// a transparent 12x12 canvas, as `--crop`, `--resize 8x8 --filter nearest` and
+/// `--canvas 12x12 --gravity topleft` do, in this order.
+fn expected(source: &RgbaImage, x: u32, y: u32, size: u32) -> RgbaImage {
+    let cropped = imageops::crop_imm(source, x, y, size, size).to_image();
+    let resized = imageops::resize(&cropped, 8, 8, FilterType::Nearest);
+    let mut canvas = RgbaImage::new(12, 12);
+    imageops::replace(&mut canvas, &resized, 0, 0);
+    canvas
+}
+
+#[test]
+fn crop_applies_before_resize_and_canvas() {
+    let dir = temp_dir("crop");
+    let input = write_ico(&dir.join("strip.ico"), &[16]);
+
+    run(
+        ico2img()
+            .arg(&input)
+            .arg("-o")
+            .arg(dir.join("frame.png"))
+            .args(["--crop", "4,2,4x4", "--crop", "8,8,8x8"])
+            .args(["--resize", "8x8", "--filter", "nearest"])
+            .args(["--canvas", "12x12", "--gravity", "topleft"]),
+        0,
+    );
+
+    let source = gradient(16, 16);
+    let first = image::open(dir.join("frame_4_2_4x4.png"))
+        .unwrap()
+        .to_rgba8();
+    assert_eq!(first, expected(&source, 4, 2, 4));
+    let second = image::open(dir.join("frame_8_8_8x8.png"))
+        .unwrap()
+        .to_rgba8();
+    assert_eq!(second, expected(&source, 8, 8, 8));
+}
+
+#[test]
+fn crop_template_names_the_images() {
+    let dir = temp_dir("crop-template");
+    let input = write_ico(&dir.join("strip.ico"), &[16]);
+
+    run(
+        ico2img()
+            .arg(&input)
+            .arg("-o")
+            .arg(dir.join("frame.png"))
+            .args(["--crop", "0,0,8x8", "--crop", "8,0,8x8"])
+            .args(["--crop-template", "{stem}-part-{crop}"]),
+        0,
+    );
+
+    let source = gradient(16, 16);
+    let left = image::open(dir.join("frame-part-0_0_8x8.png"))
+        .unwrap()
+        .to_rgba8();
+    assert_eq!(left, imageops::crop_imm(&source, 0, 0, 8, 8).to_image());
+    let right = image::open(dir.join("frame-part-8_0_8x8.png"))
+        .unwrap()
+        .to_rgba8();
+    assert_eq!(right, imageops::crop_imm(&source, 8, 0, 8, 8).to_image());
+}
+
+#[test]
+fn crop_outside_the_image_fails_with_its_size() {
+    let dir = temp_dir("crop-outside");
+    let input = write_ico(&dir.join("strip.ico"), &[16]);
+
+    let output = run(
+        ico2img()
+            .arg(&input)
+            .arg("-o")
+            .arg(dir.join("frame.png"))
+            .args(["--crop", "8,8,16x16"]),
+        1,
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains("16x16"));
+    assert!(!dir.join("frame.png").exists());
+}
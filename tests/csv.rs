@@ -0,0 +1,102 @@
+mod common;
+
+use common::{ico2img, run, temp_dir, write_ico};
+use std::fs;
+
+/// File names with every character that has to be quoted in CSV.
+const NAMES: [&str; 4] = [
+    "plain.ico",
+    "with, comma.ico",
+    "with \"quotes\".ico",
+    "with\nnew line.ico",
+];
+
+/// Parses `csv` as RFC 4180 specifies, and returns its records.
+fn parse_csv(csv: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let (mut record, mut field) = (Vec::new(), String::new());
+    let mut chars = csv.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => record.push(std::mem::take(&mut field)),
+            '\n' if !quoted => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            c => field.push(c),
+        }
+    }
+    assert!(!quoted, "unterminated quoted field in {:?}", csv);
+    assert!(
+        field.is_empty() && record.is_empty(),
+        "no final line break in {:?}",
+        csv
+    );
+    records
+}
+
+#[test]
+fn listed_entries_keep_odd_file_names() {
+    let dir = temp_dir("csv-list");
+    for name in NAMES {
+        let ico = write_ico(&dir.join(name), &[16, 32]);
+        let output = run(ico2img().arg(&ico).args(["--list", "--csv"]), 0);
+
+        let records = parse_csv(&String::from_utf8(output.stdout).unwrap());
+        assert_eq!(records.len(), 3, "{}: {:?}", name, records);
+        assert_eq!(records[0][0], "path");
+        for (index, record) in records[1..].iter().enumerate() {
+            assert_eq!(record.len(), records[0].len(), "{:?}", record);
+            assert_eq!(record[0], ico.display().to_string());
+            assert_eq!(record[1], index.to_string());
+        }
+    }
+}
+
+#[test]
+fn inventories_keep_odd_file_names() {
+    let dir = temp_dir("csv-inventory");
+    for name in NAMES {
+        write_ico(&dir.join(name), &[16]);
+    }
+    // an unreadable file, whose error is reported in the last column
+    fs::write(dir.join("broken, \"file\".ico"), b"not an icon").unwrap();
+    let csv = dir.join("out").join("inventory.csv");
+    fs::create_dir(dir.join("out")).unwrap();
+
+    run(
+        ico2img()
+            .arg(&dir)
+            .args(["--info", "--summary", "--csv-file"])
+            .arg(&csv),
+        0,
+    );
+
+    let records = parse_csv(&fs::read_to_string(&csv).unwrap());
+    assert_eq!(
+        records[0].join(","),
+        "path,entries,sizes,has_256,bytes,error"
+    );
+    assert_eq!(records.len(), NAMES.len() + 3, "{:?}", records);
+    for record in &records {
+        assert_eq!(record.len(), 6, "{:?}", record);
+    }
+    for name in NAMES {
+        let path = dir.join(name).display().to_string();
+        let record = records.iter().find(|record| record[0] == path);
+        let record = record.unwrap_or_else(|| panic!("{:?} not in {:?}", path, records));
+        assert_eq!(record[1], "1");
+        assert_eq!(record[5], "");
+    }
+    let broken = dir.join("broken, \"file\".ico").display().to_string();
+    let record = records.iter().find(|record| record[0] == broken).unwrap();
+    assert!(!record[5].is_empty(), "{:?}", record);
+    assert_eq!(records.last().unwrap()[0], "(total)");
+}
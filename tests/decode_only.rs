@@ -0,0 +1,49 @@
+mod common;
+
+use common::{gradient, ico2img, run, temp_dir, write_ico};
+use image::{DynamicImage, ImageFormat};
+use std::{fs, io::Cursor};
+
+#[test]
+fn png_entries_the_ico_crate_rejects_decode() {
+    let dir = temp_dir("decode-only-png");
+
+    // an oversize PNG of 16 bits per channel, which the `ico` crate can't decode
+    let image = DynamicImage::ImageRgba8(gradient(512, 512)).into_rgba16();
+    let mut png = Vec::new();
+    DynamicImage::ImageRgba16(image)
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .unwrap();
+
+    let mut data = vec![0, 0, 1, 0, 1, 0];
+    data.extend_from_slice(&[0, 0, 0, 0, 1, 0, 32, 0]);
+    data.extend_from_slice(&(png.len() as u32).to_le_bytes());
+    data.extend_from_slice(&22u32.to_le_bytes());
+    data.extend_from_slice(&png);
+    let ico = dir.join("app.ico");
+    fs::write(&ico, data).unwrap();
+
+    let output = run(ico2img().arg(&ico).arg("--decode-only"), 0);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Entry 0: decoded 512x512"), "{}", stdout);
+}
+
+#[test]
+fn damaged_entries_are_reported() {
+    let dir = temp_dir("decode-only-damaged");
+    let mut data = fs::read(write_ico(&dir.join("app.ico"), &[16])).unwrap();
+    // the BMP header of the only entry starts at offset 22
+    data[22..26].copy_from_slice(&[0xff; 4]);
+    let ico = dir.join("damaged.ico");
+    fs::write(&ico, data).unwrap();
+
+    let output = run(ico2img().arg(&ico).arg("--decode-only"), 1);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.starts_with("Entry 0: failed to decode: "),
+        "{}",
+        stdout
+    );
+    assert!(!stdout.contains("entry 0"), "{}", stdout);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("1 of 1 entries failed to decode."));
+}
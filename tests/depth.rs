@@ -0,0 +1,98 @@
+mod common;
+
+use common::{gradient, ico2img, ico_bytes, run, temp_dir, write_ico};
+use std::{env, fs, path::Path};
+
+/// Compares `actual` to the golden file `name`, or overwrites the golden file with it when
+/// `UPDATE_GOLDEN` is set.
+fn assert_golden(name: &str, actual: &[u8]) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(name);
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, actual).unwrap();
+    }
+    assert!(
+        fs::read(&path).unwrap() == actual,
+        "{} doesn't match its golden file",
+        name
+    );
+}
+
+#[test]
+fn depth_matches_golden_files() {
+    let dir = temp_dir("depth-golden");
+    let ico = write_ico(&dir.join("icon.ico"), &[16, 32]);
+
+    for depth in ["4", "8"] {
+        let output = dir.join(format!("depth{}.bmp", depth));
+        run(
+            ico2img()
+                .arg(&ico)
+                .arg("-o")
+                .arg(&output)
+                .args(["-f", "bmp", "-i", "1", "--depth", depth]),
+            0,
+        );
+        let bmp = fs::read(&output).unwrap();
+        // bits per pixel, in the info header
+        assert_eq!(bmp[28], depth.parse::<u8>().unwrap());
+        assert_golden(&format!("depth{}.bmp", depth), &bmp);
+    }
+}
+
+#[test]
+fn depth_passes_verify_after_write() {
+    let dir = temp_dir("depth-verify");
+    let ico = write_ico(&dir.join("icon.ico"), &[32]);
+
+    for depth in ["4", "8"] {
+        let output = dir.join(format!("depth{}.bmp", depth));
+        run(
+            ico2img().arg(&ico).arg("-o").arg(&output).args([
+                "-f",
+                "bmp",
+                "--depth",
+                depth,
+                "--verify-after-write",
+            ]),
+            0,
+        );
+        assert!(output.exists());
+    }
+}
+
+#[test]
+fn reduced_images_pass_verify_after_write() {
+    let dir = temp_dir("depth-verify-reduced");
+    // every third pixel is fully transparent, which BMP writes as black
+    let mut image = gradient(32, 32);
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        if (x + y) % 3 == 1 {
+            pixel[3] = 0;
+        }
+    }
+    let ico = dir.join("icon.ico");
+    fs::write(&ico, ico_bytes(&[image])).unwrap();
+
+    for extra in [
+        &["--depth", "4"][..],
+        &["--depth", "8", "--palette", "adaptive"],
+        &["--depth", "4", "--dither", "floyd-steinberg"],
+        &["--depth", "8", "--dither", "ordered"],
+        &["--bmp-format", "rgb565"],
+        &["--bmp-format", "rgb888"],
+    ] {
+        let output = dir.join("icon.bmp");
+        run(
+            ico2img()
+                .arg(&ico)
+                .arg("-o")
+                .arg(&output)
+                .args(["-f", "bmp", "--verify-after-write"])
+                .args(extra),
+            0,
+        );
+        assert!(output.exists(), "{:?}", extra);
+    }
+}
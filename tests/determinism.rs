@@ -0,0 +1,70 @@
+mod common;
+
+use common::{ico2img, run, temp_dir, write_ico};
+use std::fs;
+
+#[test]
+fn palette_reduction_gives_identical_output_across_runs() {
+    let dir = temp_dir("determinism");
+    let input = write_ico(&dir.join("app.ico"), &[64]);
+
+    for depth in ["4", "8"] {
+        for palette in ["windows", "adaptive"] {
+            for dither in [None, Some("ordered"), Some("floyd-steinberg")] {
+                let outputs: Vec<Vec<u8>> = (0..2)
+                    .map(|run_index| {
+                        let output = dir.join(format!(
+                            "{}-{}-{}-{}.bmp",
+                            depth,
+                            palette,
+                            dither.unwrap_or("none"),
+                            run_index
+                        ));
+                        let mut command = ico2img();
+                        command.arg(&input).arg("-o").arg(&output).args([
+                            "-f",
+                            "bmp",
+                            "--depth",
+                            depth,
+                            "--palette",
+                            palette,
+                        ]);
+                        if let Some(dither) = dither {
+                            command.args(["--dither", dither]);
+                        }
+                        run(&mut command, 0);
+                        fs::read(&output).unwrap()
+                    })
+                    .collect();
+                assert_eq!(
+                    outputs[0], outputs[1],
+                    "--depth {} --palette {} --dither {:?}",
+                    depth, palette, dither
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn indexed_png_gives_identical_output_across_runs() {
+    let dir = temp_dir("determinism-png");
+    let input = write_ico(&dir.join("app.ico"), &[16]);
+
+    let outputs: Vec<Vec<u8>> = (0..3)
+        .map(|run_index| {
+            let output = dir.join(format!("{}.png", run_index));
+            run(
+                ico2img()
+                    .arg(&input)
+                    .arg("-o")
+                    .arg(&output)
+                    .arg("--png-indexed"),
+                0,
+            );
+            fs::read(&output).unwrap()
+        })
+        .collect();
+    assert_eq!(outputs[0], outputs[1]);
+    assert_eq!(outputs[0], outputs[2]);
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use common::{ico2img, run, temp_dir, write_ico};
+use std::{fs, path::Path};
+
+/// Dumps an ICO file of `count` entries named `app.ico` in `dir`, with the extra `args`, and
+/// returns the sorted names of the files written.
+fn dumped_names(dir: &Path, count: u32, args: &[&str]) -> Vec<String> {
+    let sizes: Vec<u32> = (1..=count).collect();
+    let input = write_ico(&dir.join("app.ico"), &sizes);
+    let output = dir.join("raw");
+
+    run(
+        ico2img()
+            .arg(&input)
+            .arg("--dump-raw")
+            .arg(&output)
+            .args(args),
+        0,
+    );
+
+    let mut names: Vec<String> = fs::read_dir(&output)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn indices_of_up_to_ten_entries_are_not_padded() {
+    let names = dumped_names(&temp_dir("dump-raw-ten"), 10, &[]);
+    let expected: Vec<String> = (0..10).map(|index| format!("app_{}.dib", index)).collect();
+    assert_eq!(names, expected);
+}
+
+#[test]
+fn indices_of_more_entries_are_padded_to_the_last_one() {
+    let names = dumped_names(&temp_dir("dump-raw-eleven"), 11, &[]);
+    let expected: Vec<String> = (0..11)
+        .map(|index| format!("app_{:02}.dib", index))
+        .collect();
+    assert_eq!(names, expected);
+}
+
+#[test]
+fn pad_index_sets_the_width() {
+    let names = dumped_names(&temp_dir("dump-raw-pad-index"), 3, &["--pad-index", "4"]);
+    assert_eq!(names, ["app_0000.dib", "app_0001.dib", "app_0002.dib"]);
+
+    // a width narrower than the indices doesn't truncate them
+    let names = dumped_names(
+        &temp_dir("dump-raw-pad-index-narrow"),
+        11,
+        &["--pad-index", "1"],
+    );
+    assert!(names.contains(&"app_0.dib".to_string()), "{:?}", names);
+    assert!(names.contains(&"app_10.dib".to_string()), "{:?}", names);
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use common::{ico2img, run, temp_dir, write_ico};
+use std::{fs, path::Path};
+
+/// Returns the JSON object that `--errors-json` printed to standard error for the failed
+/// conversion of `input`.
+fn error_json(input: &Path, dir: &Path) -> serde_json::Value {
+    let output = run(
+        ico2img()
+            .arg(input)
+            .arg("-o")
+            .arg(dir.join("icon.png"))
+            .arg("--errors-json"),
+        1,
+    );
+    serde_json::from_str(String::from_utf8_lossy(&output.stderr).trim()).unwrap()
+}
+
+#[test]
+fn missing_inputs_are_file_not_found() {
+    let dir = temp_dir("errors-json-missing");
+    let json = error_json(&dir.join("missing.ico"), &dir);
+    assert_eq!(json["code"], "file_not_found");
+    assert!(json["message"].as_str().unwrap().contains("missing.ico"));
+}
+
+#[test]
+fn other_files_are_ico_parse_errors() {
+    let dir = temp_dir("errors-json-parse");
+    let text = dir.join("notes.ico");
+    fs::write(&text, "not an icon").unwrap();
+    let json = error_json(&text, &dir);
+    assert_eq!(json["code"], "ico_parse_error");
+    assert!(json["message"]
+        .as_str()
+        .unwrap()
+        .contains("isn't an ICO or CUR file"));
+
+    // an ICO signature followed by a truncated directory
+    let truncated = dir.join("truncated.ico");
+    let data = fs::read(write_ico(&dir.join("icon.ico"), &[16])).unwrap();
+    fs::write(&truncated, &data[..10]).unwrap();
+    assert_eq!(error_json(&truncated, &dir)["code"], "ico_parse_error");
+}
@@ -0,0 +1,26 @@
+mod common;
+
+use common::{ico2img, run, temp_dir, write_ico};
+use std::fs;
+
+#[test]
+fn group_by_bpp_files_images_by_bit_depth() {
+    let dir = temp_dir("group-by-bpp");
+    let input = dir.join("input");
+    fs::create_dir(&input).unwrap();
+    write_ico(&input.join("a.ico"), &[16]);
+
+    run(
+        ico2img()
+            .arg(&input)
+            .arg("-o")
+            .arg(dir.join("grouped"))
+            .arg("--group-by-bpp"),
+        0,
+    );
+    assert!(dir.join("grouped/32bpp/a.png").exists());
+
+    run(ico2img().arg(&input).arg("-o").arg(dir.join("flat")), 0);
+    assert!(dir.join("flat/a.png").exists());
+    assert!(!dir.join("flat/32bpp").exists());
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use common::{ico2img, run, temp_dir, write_ico};
+use std::fs;
+
+#[test]
+fn info_shows_stored_sizes() {
+    let dir = temp_dir("info-256");
+    let ico = write_ico(&dir.join("e256.ico"), &[16, 256]);
+
+    let output = run(ico2img().arg(&ico).arg("--info"), 0);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let entries: Vec<&str> = stdout
+        .lines()
+        .filter(|line| line.starts_with("Entry"))
+        .collect();
+    assert_eq!(entries.len(), 2, "{}", stdout);
+    assert!(
+        entries[0].starts_with("Entry 0: 16x16 (stored as 16 by 16) "),
+        "{}",
+        entries[0]
+    );
+    assert!(
+        entries[1].starts_with("Entry 1: 256x256 (stored as 0 (256) by 0 (256)) "),
+        "{}",
+        entries[1]
+    );
+}
+
+#[test]
+fn info_notes_size_mismatches() {
+    let dir = temp_dir("info-mismatch");
+    let ico = write_ico(&dir.join("icon.ico"), &[16]);
+    // the width byte of the first directory entry
+    let mut data = fs::read(&ico).unwrap();
+    data[6] = 32;
+    fs::write(&ico, data).unwrap();
+
+    let output = run(ico2img().arg(&ico).arg("--info"), 0);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Entry 0: 16x16 (stored as 32 by 16, which doesn't match the image) "),
+        "{}",
+        stdout
+    );
+}
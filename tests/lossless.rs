@@ -0,0 +1,65 @@
+mod common;
+
+use common::{ico2img, run, temp_dir, write_ico};
+
+#[test]
+fn lossless_only_rejects_reduced_bmp() {
+    let dir = temp_dir("lossless-bmp");
+    let ico = write_ico(&dir.join("icon.ico"), &[16]);
+    let output = dir.join("icon.bmp");
+
+    for reduction in [&["--depth", "4"][..], &["--bmp-format", "rgb565"]] {
+        let result = run(
+            ico2img()
+                .arg(&ico)
+                .arg("-o")
+                .arg(&output)
+                .args(["-f", "bmp", "--lossless-only"])
+                .args(reduction),
+            1,
+        );
+        assert!(String::from_utf8_lossy(&result.stderr).contains(reduction[0]));
+        assert!(!output.exists());
+    }
+}
+
+#[test]
+fn lossless_only_accepts_lossless_formats() {
+    let dir = temp_dir("lossless-ok");
+    let ico = write_ico(&dir.join("icon.ico"), &[16]);
+
+    for (format, extra) in [
+        ("png", &[][..]),
+        ("webp", &[]),
+        ("bmp", &[]),
+        ("bmp", &["--bmp-format", "rgba8888"]),
+    ] {
+        let output = dir.join(format!("icon.{}", format));
+        run(
+            ico2img()
+                .arg(&ico)
+                .arg("-o")
+                .arg(&output)
+                .args(["-f", format, "--lossless-only"])
+                .args(extra),
+            0,
+        );
+        assert!(output.exists());
+    }
+}
+
+#[test]
+fn lossless_only_rejects_jpeg() {
+    let dir = temp_dir("lossless-jpeg");
+    let ico = write_ico(&dir.join("icon.ico"), &[16]);
+    let output = dir.join("icon.jpg");
+    run(
+        ico2img()
+            .arg(&ico)
+            .arg("-o")
+            .arg(&output)
+            .args(["-f", "jpeg", "--lossless-only"]),
+        1,
+    );
+    assert!(!output.exists());
+}
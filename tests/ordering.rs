@@ -0,0 +1,60 @@
+mod common;
+
+use common::{ico2img, run, temp_dir, write_ico};
+use std::fs;
+
+/// Returns the paths of the images written by a directory conversion with `threads` threads, in
+/// the order of the `entry_written` events, and the `Converted` lines it printed.
+fn convert(name: &str, threads: &str) -> (Vec<String>, Vec<String>) {
+    let dir = temp_dir(name);
+    let input = dir.join("input");
+    fs::create_dir(&input).unwrap();
+    // larger files first, so that the smaller ones later in order finish first
+    for (index, size) in [256, 128, 96, 64, 48, 40, 32, 24, 20, 16, 8, 4]
+        .into_iter()
+        .enumerate()
+    {
+        write_ico(&input.join(format!("icon_{:02}.ico", index)), &[size]);
+    }
+
+    let output = run(
+        ico2img()
+            .arg(&input)
+            .arg("-o")
+            .arg(dir.join("output"))
+            .args(["-v", "--progress-json", "--threads", threads]),
+        0,
+    );
+
+    let root = dir.display().to_string();
+    let written = String::from_utf8_lossy(&output.stderr)
+        .lines()
+        .filter(|line| line.contains("\"entry_written\""))
+        .map(|line| {
+            let value: serde_json::Value = serde_json::from_str(line).unwrap();
+            value["path"].as_str().unwrap().replace(&root, "")
+        })
+        .collect();
+    let converted = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| line.starts_with("Converted"))
+        .map(|line| line.replace(&root, ""))
+        .collect();
+    (written, converted)
+}
+
+#[test]
+fn directory_conversion_writes_files_in_order_with_threads() {
+    let expected: Vec<String> = (0..12)
+        .map(|index| format!("/output/icon_{:02}.png", index))
+        .collect();
+
+    let (written, converted) = convert("ordering-1", "1");
+    assert_eq!(written, expected);
+    for threads in ["2", "4", "8"] {
+        let (threaded_written, threaded_converted) =
+            convert(&format!("ordering-{}", threads), threads);
+        assert_eq!(threaded_written, expected);
+        assert_eq!(threaded_converted, converted);
+    }
+}
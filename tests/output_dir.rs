@@ -0,0 +1,40 @@
+mod common;
+
+use common::{ico2img, run, temp_dir, write_ico};
+use std::fs;
+
+/// Returns the names of the files in `dir`, sorted.
+fn file_names(dir: &std::path::Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn failed_batches_leave_only_images() {
+    let dir = temp_dir("output-dir-failure");
+    let input = dir.join("input");
+    fs::create_dir(&input).unwrap();
+    write_ico(&input.join("a.ico"), &[16]);
+    fs::write(input.join("b.ico"), b"\0\0\x01\0\x01\0not an icon").unwrap();
+    write_ico(&input.join("c.ico"), &[16]);
+
+    // the run stops at the corrupt file, after writing the image of the first one
+    let output = dir.join("output");
+    run(ico2img().arg(&input).arg("-o").arg(&output), 1);
+    assert_eq!(file_names(&output), ["a.png"]);
+
+    let output = dir.join("skipped");
+    run(
+        ico2img()
+            .arg(&input)
+            .arg("-o")
+            .arg(&output)
+            .args(["--skip-errors", "--threads", "2"]),
+        0,
+    );
+    assert_eq!(file_names(&output), ["a.png", "c.png"]);
+}
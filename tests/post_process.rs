@@ -0,0 +1,32 @@
+mod common;
+
+use common::{ico2img, run, temp_dir, write_ico};
+
+#[cfg(unix)]
+#[test]
+fn post_process_timeout_too_large() {
+    let dir = temp_dir("post-process-timeout");
+    let input = dir.join("icon.ico");
+    write_ico(&input, &[16]);
+
+    // Representable as a duration, but not as a deadline: it never expires.
+    run(
+        ico2img()
+            .arg(&input)
+            .arg("-o")
+            .arg(dir.join("output"))
+            .args(["--post-process", "true", "--post-process-timeout", "1e19"]),
+        0,
+    );
+    assert!(dir.join("output").exists());
+
+    let output = run(
+        ico2img()
+            .arg(&input)
+            .arg("-o")
+            .arg(dir.join("output"))
+            .args(["--post-process", "true", "--post-process-timeout", "1e300"]),
+        2,
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid duration"));
+}
@@ -0,0 +1,158 @@
+#![cfg(all(unix, feature = "serve"))]
+
+mod common;
+
+use common::{gradient, ico2img, ico_bytes, temp_dir};
+use serde_json::Value;
+use std::{
+    io::{Read, Write},
+    os::unix::net::UnixStream,
+    path::Path,
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Starts a server on `socket`, and waits for it to listen.
+fn start(socket: &Path) -> Child {
+    let child = ico2img()
+        .arg("--serve")
+        .arg(socket)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let start = Instant::now();
+    while !socket.exists() {
+        assert!(start.elapsed() < Duration::from_secs(10), "no socket");
+        thread::sleep(Duration::from_millis(10));
+    }
+    child
+}
+
+fn write_frame(stream: &mut UnixStream, frame: &[u8]) {
+    stream
+        .write_all(&(frame.len() as u32).to_le_bytes())
+        .unwrap();
+    stream.write_all(frame).unwrap();
+}
+
+fn read_frame(stream: &mut UnixStream) -> Vec<u8> {
+    let mut len = [0; 4];
+    stream.read_exact(&mut len).unwrap();
+    let mut frame = vec![0; u32::from_le_bytes(len) as usize];
+    stream.read_exact(&mut frame).unwrap();
+    frame
+}
+
+/// Sends SIGTERM to `server`, and checks that it exits successfully.
+fn terminate(server: &mut Child) {
+    let status = Command::new("kill")
+        .arg("-TERM")
+        .arg(server.id().to_string())
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(server.wait().unwrap().success());
+}
+
+/// Sends a request over `stream`, and returns the response header and payload.
+fn request(stream: &mut UnixStream, header: &str, data: &[u8]) -> (Value, Vec<u8>) {
+    write_frame(stream, header.as_bytes());
+    write_frame(stream, data);
+    let header = serde_json::from_slice(&read_frame(stream)).unwrap();
+    (header, read_frame(stream))
+}
+
+#[test]
+fn serve_converts_requests_and_survives_bad_ones() {
+    let dir = temp_dir("serve");
+    let socket = dir.join("ico2img.sock");
+    let mut server = start(&socket);
+    let ico = ico_bytes(&[gradient(16, 16), gradient(32, 32)]);
+
+    let mut stream = UnixStream::connect(&socket).unwrap();
+    let (header, image) = request(&mut stream, r#"{"index": 1, "format": "png"}"#, &ico);
+    assert_eq!(header["ok"], true);
+    assert_eq!(
+        (header["width"].as_u64(), header["height"].as_u64()),
+        (Some(32), Some(32))
+    );
+    let decoded = image::load_from_memory(&image).unwrap().to_rgba8();
+    assert_eq!(decoded, gradient(32, 32));
+
+    // errors are reported without closing the connection
+    let (header, image) = request(&mut stream, r#"{"index": 5}"#, &ico);
+    assert_eq!(header["ok"], false);
+    assert!(image.is_empty());
+    let (header, _) = request(&mut stream, "not json", &ico);
+    assert_eq!(header["ok"], false);
+    let (header, _) = request(&mut stream, "{}", b"not an ICO file");
+    assert_eq!(header["ok"], false);
+    let (header, _) = request(&mut stream, r#"{"resize": [8, 8]}"#, &ico);
+    assert_eq!(header["ok"], true);
+    assert_eq!(header["width"], 8);
+    drop(stream);
+
+    // a frame over the size limit closes its connection, but not the server
+    let mut stream = UnixStream::connect(&socket).unwrap();
+    stream.write_all(&u32::MAX.to_le_bytes()).unwrap();
+    let mut rest = Vec::new();
+    stream.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+
+    let mut stream = UnixStream::connect(&socket).unwrap();
+    let (header, _) = request(&mut stream, "{}", &ico);
+    assert_eq!(header["ok"], true);
+    drop(stream);
+
+    terminate(&mut server);
+    assert!(!socket.exists());
+}
+
+#[test]
+fn idle_connections_dont_hold_up_others() {
+    let dir = temp_dir("serve-idle");
+    let socket = dir.join("ico2img.sock");
+    let mut server = start(&socket);
+    let ico = ico_bytes(&[gradient(16, 16)]);
+
+    let mut idle = UnixStream::connect(&socket).unwrap();
+    let mut stream = UnixStream::connect(&socket).unwrap();
+    // well under the time the server waits for the idle client
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let (header, _) = request(&mut stream, "{}", &ico);
+    assert_eq!(header["ok"], true);
+
+    // the idle connection is still served once it sends a request
+    idle.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let (header, _) = request(&mut idle, "{}", &ico);
+    assert_eq!(header["ok"], true);
+
+    terminate(&mut server);
+}
+
+#[test]
+fn termination_closes_idle_connections() {
+    let dir = temp_dir("serve-terminate");
+    let socket = dir.join("ico2img.sock");
+    let mut server = start(&socket);
+
+    let mut idle = UnixStream::connect(&socket).unwrap();
+    // let the server accept the connection before it's asked to terminate
+    thread::sleep(Duration::from_millis(200));
+    let start = Instant::now();
+    terminate(&mut server);
+    assert!(
+        start.elapsed() < Duration::from_secs(5),
+        "{:?}",
+        start.elapsed()
+    );
+    assert!(!socket.exists());
+
+    let mut rest = Vec::new();
+    idle.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+}
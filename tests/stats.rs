@@ -0,0 +1,24 @@
+mod common;
+
+use common::{ico2img, run, temp_dir, write_ico};
+
+#[test]
+fn encoder_settings_only_apply_to_their_format() {
+    let dir = temp_dir("stats-encoder-settings");
+    let ico = write_ico(&dir.join("icon.ico"), &[16, 32]);
+
+    let output = run(
+        ico2img()
+            .arg(&ico)
+            .args(["--stats", "--jpeg-quality", "80", "--depth", "8"]),
+        0,
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    for format in ["png", "jpg", "bmp", "webp"] {
+        assert!(
+            stdout.lines().any(|line| line.starts_with(format)),
+            "{}",
+            stdout
+        );
+    }
+}
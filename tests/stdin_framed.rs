@@ -0,0 +1,171 @@
+mod common;
+
+use common::{gradient, ico2img, ico_bytes, temp_dir};
+use std::{
+    fs,
+    io::Write,
+    process::{Command, Output, Stdio},
+    thread,
+};
+
+/// Size of the largest frame accepted by `--stdin-framed`.
+const MAX_FRAME_SIZE: usize = 64 * 1024 * 1024;
+
+/// Returns `data` prefixed by its length, as a frame of `--stdin-framed`.
+fn frame(data: &[u8]) -> Vec<u8> {
+    let mut frame = (data.len() as u32).to_le_bytes().to_vec();
+    frame.extend_from_slice(data);
+    frame
+}
+
+/// Runs `command` with `input` as standard input, and returns its output after checking that it
+/// exited with `code`.
+fn run_with_input(command: &mut Command, input: Vec<u8>, code: i32) -> Output {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    // the input is written from another thread, so that the output can't fill up and block it
+    let writer = thread::spawn(move || {
+        let _ = stdin.write_all(&input);
+    });
+    let output = child.wait_with_output().unwrap();
+    writer.join().unwrap();
+    assert_eq!(
+        output.status.code(),
+        Some(code),
+        "stdout: {}\nstderr: {}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+#[test]
+fn frames_are_converted_and_listed_in_the_summary() {
+    let dir = temp_dir("stdin-framed");
+    let ico = ico_bytes(&[gradient(16, 16)]);
+    let mut input = frame(&ico);
+    input.extend(frame(b"not an icon"));
+    input.extend(frame(&ico));
+
+    let output = dir.join("output");
+    let summary = dir.join("summary.json");
+    run_with_input(
+        ico2img()
+            .arg("--stdin-framed")
+            .arg("-o")
+            .arg(&output)
+            .arg("--skip-errors")
+            .arg("--summary-json")
+            .arg(&summary),
+        input.clone(),
+        0,
+    );
+    assert!(output.join("stdin_0.png").exists());
+    assert!(!output.join("stdin_1.png").exists());
+    assert!(output.join("stdin_2.png").exists());
+
+    let summary: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&summary).unwrap()).unwrap();
+    let frames = summary["frames"].as_array().unwrap();
+    assert_eq!(frames.len(), 3);
+    assert_eq!(frames[0]["frame"], 0);
+    assert!(frames[0]["output"]
+        .as_str()
+        .unwrap()
+        .ends_with("stdin_0.png"));
+    assert_eq!(frames[1]["frame"], 1);
+    assert!(frames[1]["error"]
+        .as_str()
+        .unwrap()
+        .contains("stdin_1 isn't an ICO or CUR file"));
+
+    // without --skip-errors, the bad frame fails the run
+    let failed = run_with_input(
+        ico2img()
+            .arg("--stdin-framed")
+            .arg("-o")
+            .arg(dir.join("failed")),
+        input,
+        1,
+    );
+    assert!(String::from_utf8_lossy(&failed.stderr).contains("Failed to convert frame 1"));
+}
+
+#[test]
+fn oversized_frames_are_skipped() {
+    let dir = temp_dir("stdin-framed-oversized");
+    let ico = ico_bytes(&[gradient(16, 16)]);
+    let mut input = frame(&vec![0; MAX_FRAME_SIZE + 1]);
+    input.extend(frame(&ico));
+
+    let output = dir.join("output");
+    let result = run_with_input(
+        ico2img()
+            .arg("--stdin-framed")
+            .arg("-o")
+            .arg(&output)
+            .arg("--skip-errors"),
+        input,
+        0,
+    );
+    assert!(String::from_utf8_lossy(&result.stderr).contains("more than the 67108864 bytes"));
+    assert!(!output.join("stdin_0.png").exists());
+    assert!(output.join("stdin_1.png").exists());
+}
+
+#[test]
+fn truncated_streams_fail() {
+    let dir = temp_dir("stdin-framed-truncated");
+    let ico = ico_bytes(&[gradient(16, 16)]);
+
+    // the stream ends within the length of the second frame
+    let mut input = frame(&ico);
+    input.extend([1, 0]);
+    let result = run_with_input(
+        ico2img()
+            .arg("--stdin-framed")
+            .arg("-o")
+            .arg(dir.join("length"))
+            .arg("--skip-errors"),
+        input,
+        1,
+    );
+    assert!(String::from_utf8_lossy(&result.stderr).contains("2 of the 4 bytes"));
+    assert!(dir.join("length").join("stdin_0.png").exists());
+
+    // the stream ends within the data of the frame
+    let mut input = frame(&ico);
+    input.truncate(input.len() - 10);
+    let result = run_with_input(
+        ico2img()
+            .arg("--stdin-framed")
+            .arg("-o")
+            .arg(dir.join("data")),
+        input,
+        1,
+    );
+    assert!(String::from_utf8_lossy(&result.stderr).contains("The input ended after"));
+}
+
+#[test]
+fn frames_are_limited_to_max_entries_per_file() {
+    let dir = temp_dir("stdin-framed-entries");
+    let ico = ico_bytes(&[gradient(16, 16), gradient(32, 32)]);
+
+    let result = run_with_input(
+        ico2img()
+            .arg("--stdin-framed")
+            .arg("-o")
+            .arg(dir.join("output"))
+            .args(["-i", "1", "--max-entries-per-file", "1"]),
+        frame(&ico),
+        1,
+    );
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(stderr.contains("stdin_0 claims 2 entries"), "{}", stderr);
+}
@@ -0,0 +1,147 @@
+mod common;
+
+use common::{gradient, ico2img, run, temp_dir, write_ico};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Writes a mapping of `outputs` in `dir`, and returns its path.
+fn write_mapping(dir: &Path, outputs: &str) -> PathBuf {
+    let mapping = dir.join("favicons.toml");
+    fs::write(&mapping, format!("[outputs]\n{}", outputs)).unwrap();
+    mapping
+}
+
+#[test]
+fn invalid_mappings_are_rejected() {
+    let dir = temp_dir("sync-invalid");
+    let ico = write_ico(&dir.join("favicon.ico"), &[16]);
+
+    let cases = [
+        ("[sizes]\n", "The mapping has no [outputs] table."),
+        (
+            "[outputs]\n\"16\" = \"a.png\"\n",
+            "invalid size 16, expected WxH",
+        ),
+        ("[outputs]\n\"16xa\" = \"a.png\"\n", "invalid height a"),
+        (
+            "[outputs]\n\"16x16\" = 1\n",
+            "The output for 16x16 isn't a path.",
+        ),
+        ("[outputs\n", "TOML parse error"),
+    ];
+    for (contents, error) in cases {
+        let mapping = dir.join("favicons.toml");
+        fs::write(&mapping, contents).unwrap();
+        let output = run(ico2img().arg(&ico).arg("--sync").arg(&mapping), 1);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains(error), "{}: {}", contents, stderr);
+        assert!(!dir.join("a.png").exists());
+    }
+}
+
+#[test]
+fn only_changed_outputs_are_rewritten() {
+    let dir = temp_dir("sync-changed");
+    let ico = write_ico(&dir.join("favicon.ico"), &[16, 32]);
+    let mapping = write_mapping(
+        &dir,
+        "\"16x16\" = \"small.png\"\n\"64x64\" = \"large.png\"\n",
+    );
+
+    let output = run(ico2img().arg(&ico).arg("--sync").arg(&mapping), 1);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Updated: "), "{}", stdout);
+    let small = image::open(dir.join("small.png")).unwrap().to_rgba8();
+    assert_eq!(small, gradient(16, 16));
+    // sizes without an entry are resized from the largest one
+    let large = image::open(dir.join("large.png")).unwrap();
+    assert_eq!((large.width(), large.height()), (64, 64));
+
+    // nothing changed, so nothing is written and the exit code is 0
+    let output = run(ico2img().arg(&ico).arg("--sync").arg(&mapping), 0);
+    assert!(output.stdout.is_empty());
+
+    // an output whose pixels differ is rewritten, and only that one
+    image::RgbaImage::new(16, 16)
+        .save(dir.join("small.png"))
+        .unwrap();
+    let output = run(ico2img().arg(&ico).arg("--sync").arg(&mapping), 1);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.lines().count(), 1, "{}", stdout);
+    assert!(stdout.contains("small.png"), "{}", stdout);
+    let small = image::open(dir.join("small.png")).unwrap().to_rgba8();
+    assert_eq!(small, gradient(16, 16));
+}
+
+#[test]
+fn prune_removes_outputs_no_longer_mapped() {
+    let dir = temp_dir("sync-prune");
+    let ico = write_ico(&dir.join("favicon.ico"), &[16, 32]);
+    let mapping = write_mapping(
+        &dir,
+        "\"16x16\" = \"small.png\"\n\"32x32\" = \"large.png\"\n",
+    );
+    run(ico2img().arg(&ico).arg("--sync").arg(&mapping), 1);
+    assert!(dir.join("large.png").exists());
+
+    write_mapping(&dir, "\"16x16\" = \"small.png\"\n");
+    let output = run(
+        ico2img()
+            .arg(&ico)
+            .arg("--sync")
+            .arg(&mapping)
+            .arg("--prune"),
+        1,
+    );
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Removed: "));
+    assert!(!dir.join("large.png").exists());
+    assert!(dir.join("small.png").exists());
+}
+
+#[test]
+fn check_only_reports_without_writing() {
+    let dir = temp_dir("sync-check-only");
+    let ico = write_ico(&dir.join("favicon.ico"), &[16, 32]);
+    let mapping = write_mapping(
+        &dir,
+        "\"16x16\" = \"small.png\"\n\"32x32\" = \"large.png\"\n",
+    );
+
+    let output = run(
+        ico2img()
+            .arg(&ico)
+            .arg("--sync")
+            .arg(&mapping)
+            .arg("--check-only"),
+        1,
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.matches("Out of date: ").count(), 2, "{}", stdout);
+    assert!(!dir.join("small.png").exists());
+    assert!(!dir.join("favicons.synced").exists());
+
+    run(ico2img().arg(&ico).arg("--sync").arg(&mapping), 1);
+    run(
+        ico2img()
+            .arg(&ico)
+            .arg("--sync")
+            .arg(&mapping)
+            .arg("--check-only"),
+        0,
+    );
+
+    // outputs that would be pruned are reported, and kept
+    write_mapping(&dir, "\"16x16\" = \"small.png\"\n");
+    let output = run(
+        ico2img()
+            .arg(&ico)
+            .arg("--sync")
+            .arg(&mapping)
+            .args(["--prune", "--check-only"]),
+        1,
+    );
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Not mapped anymore: "));
+    assert!(dir.join("large.png").exists());
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use common::{ico2img, run, temp_dir, write_ico};
+use std::fs;
+
+#[test]
+fn time_limit_reports_skipped_files() {
+    for threads in ["1", "4"] {
+        let dir = temp_dir(&format!("time-limit-{}", threads));
+        let input = dir.join("input");
+        fs::create_dir(&input).unwrap();
+        for name in ["a", "b", "c"] {
+            write_ico(&input.join(format!("{}.ico", name)), &[16]);
+        }
+
+        let output = run(
+            ico2img()
+                .arg(&input)
+                .arg("-o")
+                .arg(dir.join("output"))
+                .args(["--time-limit", "0", "--threads", threads]),
+            7,
+        );
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("time limit of 0 seconds"), "{}", stderr);
+        for name in ["a.ico", "b.ico", "c.ico"] {
+            assert!(stderr.contains(name), "{}", stderr);
+        }
+    }
+}
+
+#[test]
+fn time_limit_not_exceeded() {
+    let dir = temp_dir("time-limit-ok");
+    let input = dir.join("input");
+    fs::create_dir(&input).unwrap();
+    write_ico(&input.join("a.ico"), &[16]);
+
+    run(
+        ico2img()
+            .arg(&input)
+            .arg("-o")
+            .arg(dir.join("output"))
+            .args(["--time-limit", "600", "--threads", "2"]),
+        0,
+    );
+    assert!(dir.join("output/a.png").exists());
+}
+
+#[test]
+fn time_limit_too_large_is_rejected() {
+    let dir = temp_dir("time-limit-large");
+    let input = dir.join("input");
+    fs::create_dir(&input).unwrap();
+    write_ico(&input.join("a.ico"), &[16]);
+
+    for limit in ["1e300", "-1", "inf", "NaN"] {
+        let output = run(
+            ico2img()
+                .arg(&input)
+                .arg("-o")
+                .arg(dir.join("output"))
+                .arg(format!("--time-limit={}", limit)),
+            2,
+        );
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("Invalid duration"), "{}", stderr);
+    }
+}
+
+#[test]
+fn time_limit_writes_the_summary_and_errors_json() {
+    let dir = temp_dir("time-limit-summary");
+    let input = dir.join("input");
+    fs::create_dir(&input).unwrap();
+    write_ico(&input.join("a.ico"), &[16]);
+
+    run(
+        ico2img()
+            .arg(&input)
+            .arg("-o")
+            .arg(dir.join("output"))
+            .args(["--time-limit", "0"])
+            .arg("--summary-json")
+            .arg(dir.join("summary.json"))
+            .arg("--errors-json=".to_string() + &dir.join("errors.json").display().to_string()),
+        7,
+    );
+    assert!(dir.join("summary.json").exists());
+    let errors: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(dir.join("errors.json")).unwrap()).unwrap();
+    assert_eq!(errors["code"], "time_limit_exceeded");
+
+    let output = run(
+        ico2img()
+            .arg(&input)
+            .arg("-o")
+            .arg(dir.join("output"))
+            .args(["--time-limit", "0", "--errors-json"]),
+        7,
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let errors: serde_json::Value = serde_json::from_str(stderr.trim()).unwrap();
+    assert_eq!(errors["code"], "time_limit_exceeded");
+}
@@ -0,0 +1,26 @@
+mod common;
+
+use common::{ico2img, run, temp_dir, write_ico};
+use std::fs;
+
+#[test]
+fn verification_is_timed() {
+    let dir = temp_dir("timings-verify");
+    let ico = write_ico(&dir.join("icon.ico"), &[16]);
+    let summary = dir.join("summary.json");
+
+    let output = run(
+        ico2img()
+            .arg(&ico)
+            .arg("-o")
+            .arg(dir.join("icon.png"))
+            .args(["--verify-after-write", "--timings", "--summary-json"])
+            .arg(&summary),
+        0,
+    );
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Total verify"));
+
+    let summary: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&summary).unwrap()).unwrap();
+    assert!(summary["profile"]["totals"]["verify"].is_number());
+}
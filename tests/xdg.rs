@@ -0,0 +1,61 @@
+mod common;
+
+use common::{gradient, ico2img, run, temp_dir, write_ico};
+
+#[test]
+fn xdg_install_writes_every_size_under_xdg_data_home() {
+    let dir = temp_dir("xdg");
+    let input = write_ico(&dir.join("app.ico"), &[16, 48, 256]);
+    let data_home = dir.join("data");
+
+    run(
+        ico2img()
+            .arg(&input)
+            .args(["--xdg-install", "hicolor", "my-app"])
+            .env("XDG_DATA_HOME", &data_home)
+            .env("HOME", dir.join("home"))
+            // keep gtk-update-icon-cache from running
+            .env("PATH", dir.join("no-bin")),
+        0,
+    );
+
+    let theme = data_home.join("icons").join("hicolor");
+    for size in [16, 22, 24, 32, 48, 64, 128, 256] {
+        let path = theme
+            .join(format!("{}x{}", size, size))
+            .join("apps")
+            .join("my-app.png");
+        let image = image::open(&path)
+            .unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+            .to_rgba8();
+        assert_eq!(image.dimensions(), (size, size));
+        if [16, 48, 256].contains(&size) {
+            assert_eq!(image, gradient(size, size), "{}", path.display());
+        }
+    }
+    // entries aren't scaled up
+    assert!(!theme.join("512x512").exists());
+    assert!(!dir.join("home").exists());
+}
+
+#[test]
+fn xdg_install_ignores_a_relative_xdg_data_home() {
+    let dir = temp_dir("xdg-relative");
+    let input = write_ico(&dir.join("app.ico"), &[16]);
+
+    run(
+        ico2img()
+            .arg(&input)
+            .args(["--xdg-install", "hicolor", "my-app"])
+            .env("XDG_DATA_HOME", "relative")
+            .env("HOME", dir.join("home"))
+            .env("PATH", dir.join("no-bin"))
+            .current_dir(&dir),
+        0,
+    );
+
+    assert!(dir
+        .join("home/.local/share/icons/hicolor/16x16/apps/my-app.png")
+        .exists());
+    assert!(!dir.join("relative").exists());
+}